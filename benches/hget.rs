@@ -0,0 +1,87 @@
+//! 统计通过 Service 执行一次 HGET 的内存分配次数和耗时，value 分为小的、大的字符串和二进制数据。
+//!
+//! 运行：cargo bench --bench hget
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use bytes::Bytes;
+use kv::{CommandRequest, MemTable, Service, ServiceInner, Storage, Value};
+
+// 统计内存分配次数和字节数的 allocator
+struct CountingAlloc;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+static ALLOC_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        ALLOC_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        ALLOC_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const ROUNDS: usize = 100_000;
+
+// 返回执行 f 期间的内存分配次数和字节数
+fn measure(f: impl FnOnce()) -> (usize, usize) {
+    let (allocs, bytes) = (
+        ALLOCS.load(Ordering::Relaxed),
+        ALLOC_BYTES.load(Ordering::Relaxed),
+    );
+    f();
+    (
+        ALLOCS.load(Ordering::Relaxed) - allocs,
+        ALLOC_BYTES.load(Ordering::Relaxed) - bytes,
+    )
+}
+
+fn main() {
+    let store = MemTable::new();
+    let values: [(&str, Value); 4] = [
+        ("small string", "v".repeat(16).into()),
+        ("large string", "v".repeat(64 * 1024).into()),
+        ("small binary", Bytes::from(vec![1u8; 16]).into()),
+        ("large binary", Bytes::from(vec![1u8; 64 * 1024]).into()),
+    ];
+    for (name, value) in &values {
+        store.set("bench", *name, value.clone()).unwrap();
+    }
+    let service: Service = ServiceInner::new(store).into();
+
+    for (name, _) in &values {
+        let cmd = CommandRequest::new_hget("bench", *name);
+        // 测量时需要减去 clone 命令本身的分配
+        let (cmd_allocs, cmd_bytes) = measure(|| drop(black_box(cmd.clone())));
+        let start = Instant::now();
+        let (allocs, bytes) = measure(|| {
+            for _ in 0..ROUNDS {
+                drop(black_box(service.execute(cmd.clone())));
+            }
+        });
+        let elapsed = start.elapsed();
+        let allocs = allocs / ROUNDS - cmd_allocs;
+        let bytes = bytes / ROUNDS - cmd_bytes;
+        println!(
+            "  {name:<14} allocs/op: {allocs:>3}  bytes/op: {bytes:>7}  time/op: {:>8.0?}",
+            elapsed / ROUNDS as u32
+        );
+    }
+}
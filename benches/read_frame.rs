@@ -0,0 +1,165 @@
+//! 对比不同的读取方式在两种负载（大量小命令、少量大 value）下读取 frame 时的内存分配次数和读取次数。
+//!
+//! 运行：cargo bench --bench read_frame
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use kv::{CommandRequest, CompressorType, FrameCoder, ProstStream, DEFAULT_READ_CHUNK};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
+
+// 统计内存分配次数的 allocator
+struct CountingAlloc;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+static READS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// 从内存中读取数据的 stream，记录读取的次数。读取本身不分配内存
+struct MemStream(Bytes);
+
+impl AsyncRead for MemStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let data = &mut self.get_mut().0;
+        let n = buf.remaining().min(data.len());
+        buf.put_slice(&data.split_to(n));
+        READS.fetch_add(1, Ordering::Relaxed);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for MemStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+// 把命令 encode 成连续的 frame
+fn encode(cmds: &[CommandRequest]) -> Bytes {
+    let mut buf = BytesMut::new();
+    for cmd in cmds {
+        let mut frame = BytesMut::new();
+        cmd.encode_frame_with_compressor(&mut frame, CompressorType::LZ4)
+            .unwrap();
+        buf.extend_from_slice(&frame);
+    }
+    buf.freeze()
+}
+
+// 伪随机的数据，避免被压缩
+fn random_bytes(len: usize, seed: u64) -> Vec<u8> {
+    let mut x = seed;
+    (0..len)
+        .map(|_| {
+            x = x
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (x >> 56) as u8
+        })
+        .collect()
+}
+
+// 旧的读取方式：每个 frame 先读 4 字节的头部再读剩下的内容，每个 frame 使用新的 buffer
+async fn read_per_frame(data: Bytes, frames: usize) {
+    let mut stream = MemStream(data);
+    for _ in 0..frames {
+        let header = stream.read_u32().await.unwrap();
+        // 最高 2 位是压缩标志
+        let len = header as usize & 0x3FFFFFFF;
+        let mut buf = BytesMut::with_capacity(4 + len);
+        buf.extend_from_slice(&header.to_be_bytes());
+        buf.resize(4 + len, 0);
+        stream.read_exact(&mut buf[4..]).await.unwrap();
+        CommandRequest::decode_frame(&mut buf).unwrap();
+    }
+}
+
+// ProstStream：按 read_chunk 读取，读缓存在 frame 之间复用
+async fn read_with_prost_stream(data: Bytes, read_chunk: usize) {
+    let mut stream = ProstStream::<_, CommandRequest, CommandRequest>::new(MemStream(data))
+        .with_read_chunk(read_chunk);
+    while let Some(cmd) = stream.next().await {
+        cmd.unwrap();
+    }
+}
+
+fn measure(rt: &tokio::runtime::Runtime, name: &str, fut: impl std::future::Future<Output = ()>) {
+    let allocs = ALLOCS.load(Ordering::Relaxed);
+    let reads = READS.load(Ordering::Relaxed);
+    rt.block_on(fut);
+    let allocs = ALLOCS.load(Ordering::Relaxed) - allocs;
+    let reads = READS.load(Ordering::Relaxed) - reads;
+    println!("  {name:<36} allocs: {allocs:>8}  reads: {reads:>8}");
+}
+
+fn main() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    let small: Vec<_> = (0..10_000)
+        .map(|i| CommandRequest::new_hget("table", format!("key{i}")))
+        .collect();
+    let large: Vec<_> = (0..32)
+        .map(|i| {
+            let value = Bytes::from(random_bytes(1024 * 1024, i));
+            CommandRequest::new_hset("table", format!("key{i}"), value)
+        })
+        .collect();
+
+    for (workload, cmds) in [("small commands", &small), ("large values", &large)] {
+        let data = encode(cmds);
+        println!("{workload}: {} frames, {} bytes", cmds.len(), data.len());
+        measure(
+            &rt,
+            "read header + body per frame (old)",
+            read_per_frame(data.clone(), cmds.len()),
+        );
+        for read_chunk in [1024, DEFAULT_READ_CHUNK, 64 * 1024, 1024 * 1024] {
+            measure(
+                &rt,
+                &format!("ProstStream read_chunk={read_chunk}"),
+                read_with_prost_stream(data.clone(), read_chunk),
+            );
+        }
+    }
+}
@@ -3,7 +3,11 @@ use std::process::Command;
 fn main() {
     let mut config = prost_build::Config::new();
     config.bytes(&["."]);
-    config.type_attribute(".", "#[derive(PartialOrd)]");
+    // prost 生成的枚举（比如 CompareOp）已经 derive 了 PartialOrd，只给 message 和 oneof 加上
+    config.message_attribute(".", "#[derive(PartialOrd)]");
+    for oneof in [".abi.CommandRequest.request_data", ".abi.Value.value"] {
+        config.type_attribute(oneof, "#[derive(PartialOrd)]");
+    }
     config
         .out_dir("src/pb")
         .compile_protos(&["abi.proto"], &["."])
@@ -38,7 +38,7 @@ fn create_ca() -> Result<CertPem> {
 
 fn create_cert(ca: &CA, domains: &[&str], cn: &str, is_client: bool) -> Result<CertPem> {
     let (days, cert_type) = if is_client {
-        (Some(365), CertType::Client)
+        (Some(10 * 365), CertType::Client)
     } else {
         (Some(5 * 365), CertType::Server)
     };
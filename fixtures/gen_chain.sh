@@ -0,0 +1,42 @@
+#!/bin/sh
+# 生成带中间 CA 的客户端证书链，用于测试客户端发送完整的证书链（certify 不支持签发中间 CA）：
+#   ca.cert -> intermediate_ca.cert -> client_chain.cert
+# client_chain.cert 中依次是客户端证书和中间 CA 证书。在项目根目录下运行：sh fixtures/gen_chain.sh
+set -e
+cd fixtures
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+
+# ca.key 是带公钥的 PKCS#8 v2 格式，openssl 读不了，先取出其中的 32 字节私钥转成 v1 格式
+{
+    printf '\060\056\002\001\000\060\005\006\003\053\145\160\004\042\004\040'
+    sed '1d;$d' ca.key | tr -d '\r' | base64 -d | dd bs=1 skip=16 count=32 2>/dev/null
+} > "$tmp/ca.der"
+openssl pkey -inform DER -in "$tmp/ca.der" -out "$tmp/ca.key"
+
+openssl genpkey -algorithm ED25519 -out intermediate_ca.key
+openssl req -new -key intermediate_ca.key -subj "/C=CN/O=Acme Inc./CN=Acme Intermediate CA" \
+    -out "$tmp/intermediate.csr"
+cat > "$tmp/intermediate.ext" <<EXT
+basicConstraints = critical, CA:TRUE, pathlen:0
+keyUsage = critical, keyCertSign, cRLSign
+subjectKeyIdentifier = hash
+authorityKeyIdentifier = keyid
+EXT
+openssl x509 -req -in "$tmp/intermediate.csr" -CA ca.cert -CAkey "$tmp/ca.key" -CAcreateserial \
+    -CAserial "$tmp/ca.srl" -days 3650 -extfile "$tmp/intermediate.ext" -out intermediate_ca.cert
+
+openssl genpkey -algorithm ED25519 -out client_chain.key
+openssl req -new -key client_chain.key -subj "/C=CN/O=Acme Inc./CN=chained-device-id" \
+    -out "$tmp/client.csr"
+cat > "$tmp/client.ext" <<EXT
+basicConstraints = CA:FALSE
+keyUsage = critical, digitalSignature
+extendedKeyUsage = clientAuth
+subjectKeyIdentifier = hash
+authorityKeyIdentifier = keyid
+EXT
+openssl x509 -req -in "$tmp/client.csr" -CA intermediate_ca.cert -CAkey intermediate_ca.key \
+    -CAcreateserial -CAserial "$tmp/intermediate.srl" -days 3650 -extfile "$tmp/client.ext" \
+    -out "$tmp/client.cert"
+cat "$tmp/client.cert" intermediate_ca.cert > client_chain.cert
@@ -18,6 +18,36 @@ pub enum KvError {
         key: String,
         error: String,
     },
+    #[error("Result of table {0} is larger than {1} bytes, use HSCAN for large tables")]
+    ResultTooLarge(String, usize),
+    #[error("{0} is {1} bytes, longer than the limit of {2} bytes")]
+    KeyTooLong(&'static str, usize, usize),
+    #[error("Integer overflow when adding {1} to {0}")]
+    IntegerOverflow(i64, i64),
+    #[error("Command quota of this connection is exhausted")]
+    QuotaExceeded,
+    #[error("Index of table {0} is incomplete since it exceeded {1} keys")]
+    IndexOverflow(String, usize),
+    #[error("Version of table {0} is {2}, expected {1}")]
+    VersionConflict(String, u64, u64),
+    #[error("Lock of table: {0}, key: {1} is held by another owner")]
+    LockHeld(String, String),
+    #[error("Timed out waiting for table: {0}, key: {1}")]
+    Timeout(String, String),
+    #[error("Deadline of the request is exceeded")]
+    DeadlineExceeded,
+    #[error("Number of tables exceeds the limit {0}")]
+    StorageFull(usize),
+    #[error("Server is overloaded, please retry later")]
+    Overloaded,
+    #[error("Table {0} is paused for maintenance, please retry later")]
+    TablePaused(String),
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("Server is going away, please reconnect")]
+    ServerGoingAway,
+    #[error("{0} is not supported by this storage")]
+    Unsupported(&'static str),
     #[error("Certificate parse error: error to load {0} {1}")]
     CertifcateParseError(&'static str, &'static str),
 
@@ -33,9 +63,83 @@ pub enum KvError {
     IoError(#[from] std::io::Error),
     #[error("tls error")]
     TlsError(#[from] tokio_rustls::rustls::Error),
+    #[error("TLS handshake failed: {0}")]
+    TlsHandshake(String),
     #[error("noise error")]
     NoiseError(#[from] snow::Error),
 
     #[error("Internal error: {0}")]
     Internal(String),
 }
+
+/// 所有的错误类别，也就是 KvError 中每个变体的名字，ERRORSTATS 按这个顺序返回每类错误的次数
+pub const ERROR_CATEGORIES: &[&str] = &[
+    "NotFound",
+    "FrameError",
+    "InvaildCommand",
+    "ConvertError",
+    "StorageError",
+    "ResultTooLarge",
+    "KeyTooLong",
+    "IntegerOverflow",
+    "QuotaExceeded",
+    "IndexOverflow",
+    "VersionConflict",
+    "LockHeld",
+    "Timeout",
+    "DeadlineExceeded",
+    "StorageFull",
+    "Overloaded",
+    "TablePaused",
+    "PermissionDenied",
+    "ServerGoingAway",
+    "Unsupported",
+    "CertifcateParseError",
+    "EncodeError",
+    "DecodeError",
+    "SeldError",
+    "RocksDBError",
+    "IoError",
+    "TlsError",
+    "TlsHandshake",
+    "NoiseError",
+    "Internal",
+];
+
+impl KvError {
+    /// 错误的类别，是 ERROR_CATEGORIES 中的一个
+    pub fn category(&self) -> &'static str {
+        match self {
+            KvError::NotFound(..) => "NotFound",
+            KvError::FrameError => "FrameError",
+            KvError::InvaildCommand(..) => "InvaildCommand",
+            KvError::ConvertError(..) => "ConvertError",
+            KvError::StorageError { .. } => "StorageError",
+            KvError::ResultTooLarge(..) => "ResultTooLarge",
+            KvError::KeyTooLong(..) => "KeyTooLong",
+            KvError::IntegerOverflow(..) => "IntegerOverflow",
+            KvError::QuotaExceeded => "QuotaExceeded",
+            KvError::IndexOverflow(..) => "IndexOverflow",
+            KvError::VersionConflict(..) => "VersionConflict",
+            KvError::LockHeld(..) => "LockHeld",
+            KvError::Timeout(..) => "Timeout",
+            KvError::DeadlineExceeded => "DeadlineExceeded",
+            KvError::StorageFull(..) => "StorageFull",
+            KvError::Overloaded => "Overloaded",
+            KvError::TablePaused(..) => "TablePaused",
+            KvError::PermissionDenied(..) => "PermissionDenied",
+            KvError::ServerGoingAway => "ServerGoingAway",
+            KvError::Unsupported(..) => "Unsupported",
+            KvError::CertifcateParseError(..) => "CertifcateParseError",
+            KvError::EncodeError(..) => "EncodeError",
+            KvError::DecodeError(..) => "DecodeError",
+            KvError::SeldError(..) => "SeldError",
+            KvError::RocksDBError(..) => "RocksDBError",
+            KvError::IoError(..) => "IoError",
+            KvError::TlsError(..) => "TlsError",
+            KvError::TlsHandshake(..) => "TlsHandshake",
+            KvError::NoiseError(..) => "NoiseError",
+            KvError::Internal(..) => "Internal",
+        }
+    }
+}
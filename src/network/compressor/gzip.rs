@@ -1,22 +1,26 @@
-use std::io::{Read, Write};
+use std::io::Write;
 
 use bytes::{BufMut, BytesMut};
 use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 
+use super::read_to_end_with_limit;
 use crate::{Compressor, KvError};
 
 pub struct Gzip;
 impl Compressor for Gzip {
-    fn compress(src: &[u8], dst: &mut BytesMut) -> Result<(), KvError> {
-        let mut encoder = GzEncoder::new(dst.writer(), Compression::default());
+    fn compress_with_level(
+        src: &[u8],
+        dst: &mut BytesMut,
+        level: Option<u32>,
+    ) -> Result<(), KvError> {
+        let level = level.map_or_else(Compression::default, |l| Compression::new(l.min(9)));
+        let mut encoder = GzEncoder::new(dst.writer(), level);
         encoder.write_all(&src[..])?;
         encoder.finish()?;
         Ok(())
     }
 
-    fn decompress(src: &[u8], dst: &mut Vec<u8>) -> Result<(), KvError> {
-        let mut decoder = GzDecoder::new(src);
-        decoder.read_to_end(dst)?;
-        Ok(())
+    fn decompress_with_limit(src: &[u8], dst: &mut Vec<u8>, limit: usize) -> Result<(), KvError> {
+        read_to_end_with_limit(GzDecoder::new(src), dst, limit)
     }
 }
@@ -9,8 +9,37 @@ use lz4::*;
 use zstd::*;
 
 pub trait Compressor {
-    fn compress(src: &[u8], dst: &mut BytesMut) -> Result<(), KvError>;
-    fn decompress(src: &[u8], dst: &mut Vec<u8>) -> Result<(), KvError>;
+    fn compress(src: &[u8], dst: &mut BytesMut) -> Result<(), KvError> {
+        Self::compress_with_level(src, dst, None)
+    }
+    /// 使用指定的压缩级别压缩，None 表示使用算法缺省的级别。超出算法支持范围的级别会被截断
+    fn compress_with_level(
+        src: &[u8],
+        dst: &mut BytesMut,
+        level: Option<u32>,
+    ) -> Result<(), KvError>;
+    fn decompress(src: &[u8], dst: &mut Vec<u8>) -> Result<(), KvError> {
+        Self::decompress_with_limit(src, dst, usize::MAX)
+    }
+    /// 解压缩，解压后的数据超过 limit 字节时返回 FrameError，避免很小的恶意数据解压后耗尽内存
+    fn decompress_with_limit(src: &[u8], dst: &mut Vec<u8>, limit: usize) -> Result<(), KvError>;
+}
+
+/// 从 reader 中最多读取 limit 字节，超过 limit 时返回 FrameError
+fn read_to_end_with_limit(
+    reader: impl std::io::Read,
+    dst: &mut Vec<u8>,
+    limit: usize,
+) -> Result<(), KvError> {
+    use std::io::Read;
+    // 多读一个字节用于判断是否超过 limit
+    let n = reader
+        .take((limit as u64).saturating_add(1))
+        .read_to_end(dst)?;
+    if n > limit {
+        return Err(KvError::FrameError);
+    }
+    Ok(())
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -22,10 +51,19 @@ pub enum CompressorType {
 }
 
 pub fn compress(compressor: CompressorType, src: &[u8], dst: &mut BytesMut) -> Result<(), KvError> {
+    compress_with_level(compressor, src, dst, None)
+}
+
+pub fn compress_with_level(
+    compressor: CompressorType,
+    src: &[u8],
+    dst: &mut BytesMut,
+    level: Option<u32>,
+) -> Result<(), KvError> {
     match compressor {
-        CompressorType::GZIP => Gzip::compress(src, dst),
-        CompressorType::LZ4 => Lz4::compress(src, dst),
-        CompressorType::ZSTD => Zstd::compress(src, dst),
+        CompressorType::GZIP => Gzip::compress_with_level(src, dst, level),
+        CompressorType::LZ4 => Lz4::compress_with_level(src, dst, level),
+        CompressorType::ZSTD => Zstd::compress_with_level(src, dst, level),
         CompressorType::None => Ok(()),
     }
 }
@@ -34,11 +72,20 @@ pub fn decompress(
     compressor: CompressorType,
     src: &[u8],
     dst: &mut Vec<u8>,
+) -> Result<(), KvError> {
+    decompress_with_limit(compressor, src, dst, usize::MAX)
+}
+
+pub fn decompress_with_limit(
+    compressor: CompressorType,
+    src: &[u8],
+    dst: &mut Vec<u8>,
+    limit: usize,
 ) -> Result<(), KvError> {
     match compressor {
-        CompressorType::GZIP => Gzip::decompress(src, dst),
-        CompressorType::LZ4 => Lz4::decompress(src, dst),
-        CompressorType::ZSTD => Zstd::decompress(src, dst),
+        CompressorType::GZIP => Gzip::decompress_with_limit(src, dst, limit),
+        CompressorType::LZ4 => Lz4::decompress_with_limit(src, dst, limit),
+        CompressorType::ZSTD => Zstd::decompress_with_limit(src, dst, limit),
         CompressorType::None => Ok(()),
     }
 }
@@ -63,6 +110,32 @@ mod tests {
         compressor_should_work(CompressorType::ZSTD);
     }
 
+    #[test]
+    fn decompress_over_limit_should_fail() {
+        let data = vec![0u8; 1024 * 1024];
+        for compressor_type in [
+            CompressorType::GZIP,
+            CompressorType::LZ4,
+            CompressorType::ZSTD,
+        ] {
+            let mut compressed = BytesMut::new();
+            compress(compressor_type, &data, &mut compressed).unwrap();
+            // 1M 的 0 压缩后很小，但解压后远超过 limit
+            assert!(compressed.len() < 1024 * 64);
+
+            let mut decompressed = Vec::new();
+            let res = decompress_with_limit(compressor_type, &compressed, &mut decompressed, 1024);
+            assert!(matches!(res, Err(KvError::FrameError)));
+            assert!(decompressed.len() <= 1025);
+
+            let mut decompressed = Vec::new();
+            let res =
+                decompress_with_limit(compressor_type, &compressed, &mut decompressed, data.len());
+            assert!(res.is_ok());
+            assert_eq!(decompressed, data);
+        }
+    }
+
     fn compressor_should_work(compressor_type: CompressorType) {
         let data = b"data that will be compressed.";
         let mut compressed = BytesMut::new();
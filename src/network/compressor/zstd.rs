@@ -1,18 +1,22 @@
-use zstd::{decode_all, encode_all};
+use zstd::{encode_all, stream::read::Decoder};
 
+use super::read_to_end_with_limit;
 use crate::{Compressor, KvError};
 
 pub struct Zstd;
 impl Compressor for Zstd {
-    fn compress(src: &[u8], dst: &mut bytes::BytesMut) -> Result<(), KvError> {
-        let compressed = encode_all(src, 0)?;
+    fn compress_with_level(
+        src: &[u8],
+        dst: &mut bytes::BytesMut,
+        level: Option<u32>,
+    ) -> Result<(), KvError> {
+        // 0 表示 zstd 缺省的级别
+        let compressed = encode_all(src, level.unwrap_or(0).min(22) as i32)?;
         dst.extend_from_slice(&compressed);
         Ok(())
     }
 
-    fn decompress(src: &[u8], dst: &mut Vec<u8>) -> Result<(), KvError> {
-        let decompressed = decode_all(src)?;
-        dst.extend_from_slice(&decompressed);
-        Ok(())
+    fn decompress_with_limit(src: &[u8], dst: &mut Vec<u8>, limit: usize) -> Result<(), KvError> {
+        read_to_end_with_limit(Decoder::new(src)?, dst, limit)
     }
 }
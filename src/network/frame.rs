@@ -1,14 +1,19 @@
 use bytes::{Buf, BufMut, BytesMut};
 use prost::Message;
-use tokio::io::{AsyncRead, AsyncReadExt};
+use std::fmt::Display;
 use tracing::debug;
 
-use crate::{compress, decompress, CommandRequest, CommandResponse, CompressorType, KvError};
+use crate::{
+    compress, decompress_with_limit, CommandRequest, CommandResponse, CompressorType, KvError,
+};
 
 /// Frame头的长度占 4 个字节
-const LEN_LEN: usize = 4;
+pub(crate) const LEN_LEN: usize = 4;
 /// 长度占30 bit，所以最大的 Frame 是 1G
 const MAX_FRAME: usize = 1024 * 1024 * 1024;
+/// 读取 Frame 时缺省允许的最大长度（解压前和解压后都不能超过），超过的 Frame 在分配内存前就会被拒绝。
+/// prost decode 时分配的内存和消息长度成正比，因此限制消息长度也就限制了 values/pairs 的数量
+pub const DEFAULT_MAX_FRAME: usize = 64 * 1024 * 1024;
 /// 如果 payload 长度超过 1436 字节，就做压缩。
 /// 以太网的 MTU 是 1500 字节，IP头、TCP头各占20字节，再除去IP头和TCP头可能包含的一些Option，我们预留 20 字节
 /// 还剩 1440 字节，再减去预留的 4 字节做帧长度。超过 1436 字节可能会导致分片，所以我们做压缩处理
@@ -18,79 +23,168 @@ const COMPRESSION_BIT: usize = 30;
 /// 用于消除最高2位的掩码
 const COMPRESSION_MASK: usize = 0x3FFFFFFF;
 
-// 处理 Frame 的 encode/decode
+/// 处理 Frame 的 encode/decode，ProstStream 通过它读写网络上的消息。
+///
+/// 实现者只需要提供 payload 的编码和解码，frame 的其它部分由缺省实现处理：每个 frame 以 4 字节大端的头部开始，
+/// 低 30 位是 payload 的长度，高 2 位是压缩算法；payload 超过 1436 字节时压缩。ProstStream 依靠头部切分 frame，
+/// 所以自己实现 encode_frame_with_compressor 和 decode_frame_with_limit 时也必须使用相同的头部。
+/// CommandRequest 和 CommandResponse 使用 protobuf 编码 payload
 pub trait FrameCoder
 where
-    Self: Message + Sized + Default,
+    Self: Sized,
 {
+    /// 把消息编码后追加到 buf 的末尾，buf 中已有的内容（frame 头部和之前的 frame）不能修改
+    fn encode_payload(&self, buf: &mut BytesMut) -> Result<(), KvError>;
+
+    /// 从完整的 payload（已经解压）中解码出消息
+    fn decode_payload(data: &[u8]) -> Result<Self, KvError>;
+
     fn encode_frame(&self, buf: &mut BytesMut) -> Result<(), KvError> {
         self.encode_frame_with_compressor(buf, CompressorType::GZIP)
     }
 
-    // 把一个 Message encode 成一个 Frame
+    /// 把一个 Message encode 成一个 Frame，追加到 buf 的末尾
     fn encode_frame_with_compressor(
         &self,
         buf: &mut BytesMut,
         compressor_type: CompressorType,
     ) -> Result<(), KvError> {
-        let size = self.encoded_len();
-
+        // 先留出头部，payload 写完之后再写入长度
+        let start = buf.len();
+        buf.put_u32(0);
+        if let Err(e) = self.encode_payload(buf) {
+            buf.truncate(start);
+            return Err(e);
+        }
+        let size = buf.len() - start - LEN_LEN;
         if size >= MAX_FRAME {
+            buf.truncate(start);
             return Err(KvError::FrameError);
         }
 
-        // 先写入长度，如果需要压缩，再重写压缩后的长度
-        buf.put_u32(size as _);
-
+        let mut header = size;
         if size > COMPRESSION_LIMIT {
-            let mut buf_tmp = Vec::with_capacity(size);
-            self.encode(&mut buf_tmp)?;
-
-            // 为了 Frame 头部拿走 4 个字节
-            let mut payload = buf.split_off(LEN_LEN);
-            buf.clear();
-
-            // 压缩
-            compress(compressor_type, &buf_tmp[..], &mut payload)?;
-            debug!("Encode a frame size: {size}({})", payload.len());
-
-            // 写入压缩后的长度，同时把最高位置 1 表示该组数据经过压缩
-            buf.put_u32((payload.len() | ((compressor_type as usize) << COMPRESSION_BIT)) as _);
-
-            // 合并 BytesMut
-            buf.unsplit(payload);
-
-            Ok(())
-        } else {
-            self.encode(buf)?;
-            Ok(())
+            // 压缩后的数据放回头部之后
+            let payload = buf.split_off(start + LEN_LEN);
+            if let Err(e) = compress(compressor_type, &payload[..], buf) {
+                buf.truncate(start);
+                return Err(e);
+            }
+            let compressed = buf.len() - start - LEN_LEN;
+            debug!("Encode a frame size: {size}({compressed})");
+
+            // 压缩后的长度，同时把最高位置 1 表示该组数据经过压缩
+            header = compressed | ((compressor_type as usize) << COMPRESSION_BIT);
         }
+        buf[start..start + LEN_LEN].copy_from_slice(&(header as u32).to_be_bytes());
+        Ok(())
     }
 
     /// 把一个完整的 frame decode 成一个 Message
     fn decode_frame(buf: &mut BytesMut) -> Result<Self, KvError> {
+        Self::decode_frame_with_limit(buf, MAX_FRAME)
+    }
+
+    /// 把一个完整的 frame decode 成一个 Message，消息（解压后）的长度超过 max_len 时返回 FrameError
+    fn decode_frame_with_limit(buf: &mut BytesMut, max_len: usize) -> Result<Self, KvError> {
         // 先取 4 字节，从中获得长度和 compression bit
         let header = buf.get_u32() as usize;
         let (len, compress_type) = decode_header(header);
         debug!("Got a frame: msg len: {len}, compress_type: {compress_type:?}");
 
+        if len > max_len || len > buf.len() {
+            return Err(KvError::FrameError);
+        }
+
         if compress_type != CompressorType::None {
-            // 解压缩
-            let mut buf_tmp = Vec::with_capacity(len * 2);
-            decompress(compress_type, &buf[..len], &mut buf_tmp)?;
+            // 解压缩，解压后的长度同样受 max_len 限制，防止解压炸弹
+            let mut buf_tmp = Vec::with_capacity(std::cmp::min(len * 2, max_len));
+            let res = decompress_with_limit(compress_type, &buf[..len], &mut buf_tmp, max_len);
             buf.advance(len);
+            res?;
 
-            Ok(Self::decode(&buf_tmp[..buf_tmp.len()])?)
+            Self::decode_payload(&buf_tmp[..])
         } else {
-            let msg = Self::decode(&buf[..len])?;
+            let msg = Self::decode_payload(&buf[..len])?;
             buf.advance(len);
             Ok(msg)
         }
     }
+
+    /// 把 Message encode 成完整的 frame 字节（包括 4 字节的头部），和网络上传输的内容完全一致，
+    /// 便于第三方客户端逐字节地比对自己的实现
+    fn encode_frame_to_vec(&self) -> Result<Vec<u8>, KvError> {
+        let mut buf = BytesMut::new();
+        self.encode_frame(&mut buf)?;
+        Ok(buf.to_vec())
+    }
+
+    /// 从一个完整的 frame 字节（包括 4 字节的头部）decode 出 Message，多余或者缺少的字节都会返回 FrameError
+    fn decode_frame_from_slice(data: &[u8]) -> Result<Self, KvError> {
+        if data.len() < LEN_LEN {
+            return Err(KvError::FrameError);
+        }
+        let mut buf = BytesMut::from(data);
+        let msg = Self::decode_frame(&mut buf)?;
+        if !buf.is_empty() {
+            return Err(KvError::FrameError);
+        }
+        Ok(msg)
+    }
 }
 
-impl FrameCoder for CommandRequest {}
-impl FrameCoder for CommandResponse {}
+impl FrameCoder for CommandRequest {
+    fn encode_payload(&self, buf: &mut BytesMut) -> Result<(), KvError> {
+        Ok(self.encode(buf)?)
+    }
+
+    fn decode_payload(data: &[u8]) -> Result<Self, KvError> {
+        Ok(Self::decode(data)?)
+    }
+}
+
+impl FrameCoder for CommandResponse {
+    fn encode_payload(&self, buf: &mut BytesMut) -> Result<(), KvError> {
+        Ok(self.encode(buf)?)
+    }
+
+    fn decode_payload(data: &[u8]) -> Result<Self, KvError> {
+        Ok(Self::decode(data)?)
+    }
+}
+
+/// 以 hexdump 的格式显示一段字节：每行 16 个字节，依次是偏移量、十六进制和可打印的 ASCII 字符
+pub struct HexDump<'a>(pub &'a [u8]);
+
+impl Display for HexDump<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, line) in self.0.chunks(16).enumerate() {
+            write!(f, "{:08x} ", i * 16)?;
+            for j in 0..16 {
+                // 每 8 个字节之间多空一格
+                if j % 8 == 0 {
+                    write!(f, " ")?;
+                }
+                match line.get(j) {
+                    Some(b) => write!(f, "{b:02x} ")?,
+                    None => write!(f, "   ")?,
+                }
+            }
+            let ascii: String = line
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            writeln!(f, " |{ascii}|")?;
+        }
+        Ok(())
+    }
+}
 
 fn decode_header(header: usize) -> (usize, CompressorType) {
     let len = header & COMPRESSION_MASK;
@@ -98,44 +192,45 @@ fn decode_header(header: usize) -> (usize, CompressorType) {
     (len, compress_type)
 }
 
-/// 从 stream 中读取一个完整的 frame
-pub async fn read_frame<S>(stream: &mut S, buf: &mut BytesMut) -> Result<(), KvError>
-where
-    S: AsyncRead + Unpin + Send,
-{
-    let header = stream.read_u32().await? as usize;
+/// 从 buf 开头的 frame 头部中取出整个 frame 的长度（包括 4 字节的头部），buf 不足 4 字节时返回 None
+pub(crate) fn peek_frame_len(buf: &[u8]) -> Option<usize> {
+    let header = buf.get(..LEN_LEN)?;
+    let header = u32::from_be_bytes(header.try_into().unwrap()) as usize;
     let (len, _compressed) = decode_header(header);
-    // 确保内存至少可以放下一个 Frame。reserve()仅修改容量，即capacit()
-    buf.reserve(LEN_LEN + len);
-    buf.put_u32(header as _);
-    // advance_mut 是 unsafe 的原因是，从当前位置 pos 到 pos + len，
-    // 这段内存目前没有初始化。我们就是为了 reserve 这段内存，然后从 stream
-    // 里读取，读取完，它就是初始化的。所以，我们这么用是安全的
-    // 通过advance_mut()将buf的长度增加，即len()。上面已经reserve()了，所以容量是够的
-    unsafe { buf.advance_mut(len) };
-    stream.read_exact(&mut buf[LEN_LEN..]).await?;
-    Ok(())
+    Some(LEN_LEN + len)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::utils::DummyStream;
     use crate::Value;
     use bytes::Bytes;
 
-    #[tokio::test]
-    async fn read_frame_should_work() {
+    #[test]
+    fn peek_frame_len_should_work() {
         let mut buf = BytesMut::new();
-        let cmd = CommandRequest::new_hdel("table", "key");
-        cmd.encode_frame(&mut buf).unwrap();
-        let mut stream = DummyStream { buf };
+        CommandRequest::new_hdel("table", "key")
+            .encode_frame(&mut buf)
+            .unwrap();
+        assert_eq!(peek_frame_len(&buf), Some(buf.len()));
+        assert_eq!(peek_frame_len(&buf[..LEN_LEN]), Some(buf.len()));
+        assert_eq!(peek_frame_len(&buf[..LEN_LEN - 1]), None);
+    }
 
-        let mut data = BytesMut::new();
-        read_frame(&mut stream, &mut data).await.unwrap();
+    #[test]
+    fn decode_compressed_frame_over_limit_should_fail() {
+        let mut buf = BytesMut::new();
 
-        let cmd_decoded = CommandRequest::decode_frame(&mut data).unwrap();
-        assert_eq!(cmd, cmd_decoded);
+        let value: Value = Bytes::from(vec![0u8; 1024 * 1024]).into();
+        let res: CommandResponse = value.into();
+        res.encode_frame_with_compressor(&mut buf, CompressorType::GZIP)
+            .unwrap();
+        assert!(buf.len() < 1024 * 64);
+
+        // 压缩后的 frame 很小，但解压后超过限制
+        let res = CommandResponse::decode_frame_with_limit(&mut buf, 1024 * 64);
+        assert!(matches!(res, Err(KvError::FrameError)));
+        assert!(buf.is_empty());
     }
 
     #[test]
@@ -184,6 +279,63 @@ mod tests {
         assert_eq!(res, res_decoded);
     }
 
+    #[test]
+    fn frame_to_vec_should_match_encode_frame() {
+        let cmd = CommandRequest::new_hget("t", "k");
+        let data = cmd.encode_frame_to_vec().unwrap();
+
+        let mut buf = BytesMut::new();
+        cmd.encode_frame(&mut buf).unwrap();
+        assert_eq!(data, &buf[..]);
+
+        // 头部是 4 字节大端的长度，之后是 protobuf 编码的内容
+        assert_eq!(&data[..4], &[0, 0, 0, 8]);
+        assert_eq!(
+            &data[4..],
+            &[0x0a, 0x06, 0x0a, 0x01, b't', 0x12, 0x01, b'k']
+        );
+
+        let cmd_decoded = CommandRequest::decode_frame_from_slice(&data).unwrap();
+        assert_eq!(cmd, cmd_decoded);
+    }
+
+    #[test]
+    fn compressed_frame_from_slice_should_work() {
+        let value: Value = Bytes::from(vec![0u8; COMPRESSION_LIMIT + 1]).into();
+        let res: CommandResponse = value.into();
+        let data = res.encode_frame_to_vec().unwrap();
+        assert!(is_compressed(&data));
+
+        let res_decoded = CommandResponse::decode_frame_from_slice(&data).unwrap();
+        assert_eq!(res, res_decoded);
+    }
+
+    #[test]
+    fn decode_frame_from_bad_slice_should_fail() {
+        let data = CommandRequest::new_hget("t", "k")
+            .encode_frame_to_vec()
+            .unwrap();
+
+        // 多余的字节
+        let mut longer = data.clone();
+        longer.push(0);
+        assert!(CommandRequest::decode_frame_from_slice(&longer).is_err());
+
+        // 缺少的字节
+        assert!(CommandRequest::decode_frame_from_slice(&data[..data.len() - 1]).is_err());
+        assert!(CommandRequest::decode_frame_from_slice(&data[..2]).is_err());
+    }
+
+    #[test]
+    fn hex_dump_should_work() {
+        let data = b"\x00\x00\x00\x08hello, world!\x01";
+        assert_eq!(
+            HexDump(data).to_string(),
+            "00000000  00 00 00 08 68 65 6c 6c  6f 2c 20 77 6f 72 6c 64  |....hello, world|\n\
+             00000010  21 01                                             |!.|\n"
+        );
+    }
+
     fn is_compressed(data: &[u8]) -> bool {
         if let &[v] = &data[..1] {
             v >> 6 != 0b00
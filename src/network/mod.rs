@@ -5,49 +5,243 @@ mod security;
 mod stream;
 
 pub use compressor::*;
-pub use frame::FrameCoder;
+pub use frame::{FrameCoder, HexDump};
 pub use security::*;
-use stream::*;
-
-use futures::{SinkExt, StreamExt};
-use tokio::io::{AsyncRead, AsyncWrite};
+pub use stream::*;
+
+use futures::{future, SinkExt, StreamExt};
+use prost::Message;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::watch,
+};
 use tracing::info;
 
-use crate::{CommandRequest, CommandResponse, KvError, Service};
+use crate::{
+    command_request::RequestData, CommandRequest, CommandResponse, KvError, Kvpair, MemTable,
+    Service, Storage, Value,
+};
 
 // 处理服务端某个 accept 下来的 socket 的读写
-pub struct ProstServerStream<S> {
+pub struct ProstServerStream<S, Store = MemTable> {
     inner: ProstStream<S, CommandRequest, CommandResponse>,
-    service: Service,
+    service: Service<Store>,
+    // 服务器关闭信号，收到 true 时通知客户端并关闭连接
+    shutdown: Option<watch::Receiver<bool>>,
+    // 这个连接剩余可以执行的命令数，None 表示不限制
+    remaining_quota: Option<u64>,
+    // 配额用完后是否关闭连接
+    close_on_quota_exceeded: bool,
+    // 通过 mTLS 认证的客户端身份，None 表示匿名
+    peer: Option<PeerIdentity>,
+    // MULTI 之后排队等待 EXEC 的命令，None 表示不在事务中
+    transaction: Option<Vec<CommandRequest>>,
 }
 
+/// 一个事务中最多可以排队的命令数，超过时放弃整个事务
+pub const MAX_QUEUED_COMMANDS: usize = 1024;
+
 // 处理客户端 socket 的读写
 pub struct ProstClientStream<S> {
     inner: ProstStream<S, CommandResponse, CommandRequest>,
 }
 
-impl<S> ProstServerStream<S>
+impl<S, Store> ProstServerStream<S, Store>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send,
+    Store: Storage,
 {
-    pub fn new(stream: S, service: Service) -> Self {
+    pub fn new(stream: S, service: Service<Store>) -> Self {
         Self {
             inner: ProstStream::new(stream),
             service,
+            shutdown: None,
+            remaining_quota: None,
+            close_on_quota_exceeded: false,
+            peer: None,
+            transaction: None,
         }
     }
 
+    /// 监听服务器的关闭信号。服务器开始关闭时，会给客户端发送一个 410 的 CommandResponse，
+    /// 不再处理这个连接上新的命令，然后关闭连接
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// 限制这个连接最多可以执行 quota 个命令，每个响应都会带上剩余的配额。配额用完后的命令
+    /// 不再执行，直接返回 429；如果 close_on_exceeded 为 true，返回 429 之后关闭连接
+    pub fn with_quota(mut self, quota: u64, close_on_exceeded: bool) -> Self {
+        self.remaining_quota = Some(quota);
+        self.close_on_quota_exceeded = close_on_exceeded;
+        self
+    }
+
+    /// 设置这个连接上客户端的身份，WHOAMI 会返回它
+    pub fn with_peer_identity(mut self, peer: Option<PeerIdentity>) -> Self {
+        self.peer = peer;
+        self
+    }
+
     pub async fn process(mut self) -> Result<(), KvError> {
+        self.service.metrics().connection_opened();
+        let result = self.serve().await;
+        self.service.metrics().connection_closed();
+        result
+    }
+
+    async fn serve(&mut self) -> Result<(), KvError> {
         let stream = &mut self.inner;
-        while let Some(Ok(cmd)) = stream.next().await {
-            info!("Got a new command: {cmd:?}");
-            let res = self.service.execute(cmd);
-            stream.send(res).await?;
+        loop {
+            let cmd = tokio::select! {
+                // 优先处理关闭信号，避免关闭后依旧执行新的命令
+                biased;
+                _ = shutdown_signaled(&mut self.shutdown) => {
+                    info!("Server is going away, draining connection");
+                    let res: CommandResponse = KvError::ServerGoingAway.into();
+                    stream.send(res).await?;
+                    stream.close().await?;
+                    return Ok(());
+                }
+                cmd = stream.next() => cmd,
+            };
+
+            match cmd {
+                Some(Ok(cmd)) => {
+                    info!("Got a new command: {}", self.service.format_request(&cmd));
+                    let metrics = self.service.metrics();
+                    metrics.bytes_received(cmd.encoded_len());
+                    let quit = matches!(cmd.request_data, Some(RequestData::Quit(_)));
+                    let (res, exceeded) = match &mut self.remaining_quota {
+                        Some(0) => (KvError::QuotaExceeded.into(), true),
+                        Some(remaining) => {
+                            *remaining -= 1;
+                            let mut res =
+                                execute(&self.service, &self.peer, &mut self.transaction, cmd)
+                                    .await;
+                            res.remaining_quota = Some(*remaining);
+                            (res, false)
+                        }
+                        None => (
+                            execute(&self.service, &self.peer, &mut self.transaction, cmd).await,
+                            false,
+                        ),
+                    };
+                    metrics.bytes_sent(res.encoded_len());
+                    stream.send(res).await?;
+                    if quit {
+                        info!("Client quit, closing connection");
+                        stream.close().await?;
+                        return Ok(());
+                    }
+                    if exceeded && self.close_on_quota_exceeded {
+                        info!("Command quota exhausted, closing connection");
+                        stream.close().await?;
+                        return Ok(());
+                    }
+                }
+                _ => break,
+            }
         }
         Ok(())
     }
 }
 
+// 执行一个命令。事务、WHOAMI 和管理员命令的权限检查需要连接的信息，在这里处理，其它命令交给 Service
+async fn execute<Store: Storage>(
+    service: &Service<Store>,
+    peer: &Option<PeerIdentity>,
+    transaction: &mut Option<Vec<CommandRequest>>,
+    cmd: CommandRequest,
+) -> CommandResponse {
+    // QUIT 不排队，关闭连接时放弃事务
+    if let Some(queued) = transaction {
+        if !matches!(
+            cmd.request_data,
+            Some(RequestData::Exec(_) | RequestData::Discard(_) | RequestData::Quit(_))
+        ) {
+            return match check_queueable(&cmd, queued.len()) {
+                Ok(()) => {
+                    queued.push(cmd);
+                    Value::from("QUEUED").into()
+                }
+                Err(e) => {
+                    *transaction = None;
+                    e.into()
+                }
+            };
+        }
+    }
+    match cmd.request_data {
+        Some(RequestData::Multi(_)) => {
+            *transaction = Some(Vec::new());
+            Value::from("OK").into()
+        }
+        Some(RequestData::Exec(_)) => match transaction.take() {
+            Some(cmds) => service.execute_transaction(cmds),
+            None => KvError::InvaildCommand("EXEC without MULTI".into()).into(),
+        },
+        Some(RequestData::Discard(_)) => match transaction.take() {
+            Some(_) => Value::from("OK").into(),
+            None => KvError::InvaildCommand("DISCARD without MULTI".into()).into(),
+        },
+        Some(RequestData::Whoami(_)) => {
+            let pairs = match peer {
+                Some(peer) => vec![
+                    Kvpair::new("authenticated", true),
+                    Kvpair::new("fingerprint", peer.fingerprint.as_str()),
+                    Kvpair::new("subject", peer.subject.as_str()),
+                ],
+                None => vec![Kvpair::new("authenticated", false)],
+            };
+            pairs.into()
+        }
+        Some(
+            RequestData::Pausetable(_)
+            | RequestData::Resumetable(_)
+            | RequestData::Delmatch(_)
+            | RequestData::Flushtable(_),
+        ) if !service.is_admin(peer.as_ref().map(|p| p.fingerprint.as_str())) => {
+            KvError::PermissionDenied("control commands require an admin certificate".into()).into()
+        }
+        _ => service.execute_async(cmd).await,
+    }
+}
+
+// 需要连接的信息或者会改变连接状态的命令不能在事务中排队，遇到时放弃整个事务
+fn check_queueable(cmd: &CommandRequest, queued: usize) -> Result<(), KvError> {
+    let name = match &cmd.request_data {
+        None => "empty command",
+        Some(RequestData::Multi(_)) => "MULTI",
+        Some(RequestData::Whoami(_)) => "WHOAMI",
+        Some(RequestData::Pausetable(_)) => "PAUSETABLE",
+        Some(RequestData::Resumetable(_)) => "RESUMETABLE",
+        // 事务中的命令不经过权限检查
+        Some(RequestData::Delmatch(_)) => "DELMATCH",
+        Some(RequestData::Flushtable(_)) => "FLUSHTABLE",
+        Some(_) if queued >= MAX_QUEUED_COMMANDS => {
+            return Err(KvError::InvaildCommand(format!(
+                "Transaction has more than {MAX_QUEUED_COMMANDS} commands, discarded"
+            )))
+        }
+        Some(_) => return Ok(()),
+    };
+    Err(KvError::InvaildCommand(format!(
+        "{name} can not be used in a transaction, discarded"
+    )))
+}
+
+// 等待关闭信号，没有设置关闭信号或者发送端已经释放时永远不会返回
+async fn shutdown_signaled(shutdown: &mut Option<watch::Receiver<bool>>) {
+    if let Some(rx) = shutdown {
+        if rx.wait_for(|v| *v).await.is_ok() {
+            return;
+        }
+    }
+    future::pending::<()>().await
+}
+
 impl<S> ProstClientStream<S>
 where
     S: AsyncRead + AsyncWrite + Unpin + Send,
@@ -77,7 +271,7 @@ mod tests {
 
     use tokio::net::{TcpListener, TcpStream};
 
-    use crate::{assert_res_ok, MemTable, ServiceInner, Value};
+    use crate::{assert_res_error, assert_res_ok, MemTable, ServiceInner, Value};
 
     use super::*;
 
@@ -122,7 +316,212 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn client_should_be_notified_when_server_going_away() -> anyhow::Result<()> {
+        let (tx, rx) = watch::channel(false);
+        let addr = start_server_with_shutdown(rx).await?;
+
+        let stream = TcpStream::connect(addr).await?;
+        let mut client = ProstClientStream::new(stream);
+
+        let cmd = CommandRequest::new_hset("table", "key", "value");
+        let res = client.execute(cmd).await.unwrap();
+        assert_res_ok(res, &[Value::default()], &[]);
+
+        // 服务器开始关闭，客户端会收到一个 going away 的响应
+        tx.send(true)?;
+        let cmd = CommandRequest::new_hget("table", "key");
+        let res = client.execute(cmd).await.unwrap();
+        assert!(res.is_going_away());
+
+        // 之后连接被关闭，命令不会再被处理
+        let cmd = CommandRequest::new_hget("table", "key");
+        assert!(client.execute(cmd).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn connection_should_be_closed_after_quit() -> anyhow::Result<()> {
+        let addr = start_server().await?;
+
+        let stream = TcpStream::connect(addr).await?;
+        let mut client = ProstClientStream::new(stream);
+
+        let res = client.execute(CommandRequest::new_quit()).await.unwrap();
+        assert_res_ok(res, &[], &[]);
+
+        // 服务器在响应之后关闭了连接
+        let cmd = CommandRequest::new_hget("table", "key");
+        assert!(client.execute(cmd).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn whoami_should_return_anonymous_without_client_cert() -> anyhow::Result<()> {
+        let addr = start_server().await?;
+
+        let stream = TcpStream::connect(addr).await?;
+        let mut client = ProstClientStream::new(stream);
+
+        let res = client.execute(CommandRequest::new_whoami()).await.unwrap();
+        assert_res_ok(res, &[], &[Kvpair::new("authenticated", false)]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn control_commands_should_require_admin() {
+        let service: Service = ServiceInner::new(MemTable::new())
+            .with_admins(["abcd"])
+            .into();
+        let cmd = CommandRequest::new_pausetable("t1");
+        let res = execute(&service, &None, &mut None, cmd.clone()).await;
+        assert_eq!(res.status, 403);
+
+        let peer = PeerIdentity {
+            subject: "CN=admin".into(),
+            fingerprint: "abcd".into(),
+        };
+        let res = execute(&service, &Some(peer.clone()), &mut None, cmd).await;
+        assert_res_ok(res, &["t1".into()], &[]);
+
+        // DELMATCH 和 FLUSHTABLE 会删除大量数据，同样需要管理员证书，也不能在事务中绕过检查
+        service.execute(CommandRequest::new_hset("t2", "k", "v"));
+        let cmd = CommandRequest::new_delmatch("t2", "*");
+        let res = execute(&service, &None, &mut None, cmd.clone()).await;
+        assert_eq!(res.status, 403);
+        let res = execute(&service, &None, &mut Some(Vec::new()), cmd.clone()).await;
+        assert_eq!(res.status, 400);
+        let res = execute(&service, &Some(peer.clone()), &mut None, cmd).await;
+        assert_res_ok(res, &[Value::from(1)], &[]);
+
+        service.execute(CommandRequest::new_hset("t2", "k", "v"));
+        let cmd = CommandRequest::new_flushtable("t2");
+        let res = execute(&service, &None, &mut None, cmd.clone()).await;
+        assert_eq!(res.status, 403);
+        let res = execute(&service, &None, &mut Some(Vec::new()), cmd.clone()).await;
+        assert_eq!(res.status, 400);
+        let res = execute(&service, &Some(peer), &mut None, cmd).await;
+        assert_res_ok(res, &[Value::from(1)], &[]);
+    }
+
+    #[tokio::test]
+    async fn transaction_should_queue_until_exec() -> anyhow::Result<()> {
+        let addr = start_server().await?;
+        let mut client = ProstClientStream::new(TcpStream::connect(addr).await?);
+
+        let res = client.execute(CommandRequest::new_multi()).await?;
+        assert_res_ok(res, &["OK".into()], &[]);
+        let cmd = CommandRequest::new_hset("table", "key", "value");
+        let res = client.execute(cmd).await?;
+        assert_res_ok(res, &["QUEUED".into()], &[]);
+        let res = client
+            .execute(CommandRequest::new_hget("table", "key"))
+            .await?;
+        assert_res_ok(res, &["QUEUED".into()], &[]);
+
+        let res = client.execute(CommandRequest::new_exec()).await?;
+        assert_eq!(res.status, 200);
+        assert_eq!(res.responses.len(), 2);
+        assert_res_ok(res.responses[0].clone(), &[Value::default()], &[]);
+        assert_res_ok(res.responses[1].clone(), &["value".into()], &[]);
+
+        // EXEC 之后回到正常的模式
+        let res = client
+            .execute(CommandRequest::new_hget("table", "key"))
+            .await?;
+        assert_res_ok(res, &["value".into()], &[]);
+        let res = client.execute(CommandRequest::new_exec()).await?;
+        assert_res_error(res, 400, "EXEC without MULTI");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transaction_should_be_discarded() -> anyhow::Result<()> {
+        let addr = start_server().await?;
+        let mut client = ProstClientStream::new(TcpStream::connect(addr).await?);
+
+        client.execute(CommandRequest::new_multi()).await?;
+        let cmd = CommandRequest::new_hset("table", "key", "value");
+        client.execute(cmd).await?;
+        let res = client.execute(CommandRequest::new_discard()).await?;
+        assert_res_ok(res, &["OK".into()], &[]);
+        let res = client
+            .execute(CommandRequest::new_hget("table", "key"))
+            .await?;
+        assert_eq!(res.status, 404);
+
+        // 排队时遇到不能在事务中执行的命令，放弃整个事务
+        client.execute(CommandRequest::new_multi()).await?;
+        let cmd = CommandRequest::new_hset("table", "key", "value");
+        client.execute(cmd).await?;
+        let res = client.execute(CommandRequest::new_whoami()).await?;
+        assert_res_error(res, 400, "WHOAMI can not be used in a transaction");
+        let res = client.execute(CommandRequest::new_exec()).await?;
+        assert_res_error(res, 400, "EXEC without MULTI");
+        let res = client
+            .execute(CommandRequest::new_hget("table", "key"))
+            .await?;
+        assert_eq!(res.status, 404);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn command_quota_should_work() -> anyhow::Result<()> {
+        let addr = start_server_with(|server| server.with_quota(2, false)).await?;
+
+        let stream = TcpStream::connect(addr).await?;
+        let mut client = ProstClientStream::new(stream);
+
+        let cmd = CommandRequest::new_hset("table", "key", "value");
+        let res = client.execute(cmd).await.unwrap();
+        assert_eq!(res.remaining_quota, Some(1));
+        let cmd = CommandRequest::new_hget("table", "key");
+        let res = client.execute(cmd).await.unwrap();
+        assert_eq!(res.remaining_quota, Some(0));
+
+        // 配额用完，命令不再被执行，但连接依旧可用
+        for _ in 0..2 {
+            let cmd = CommandRequest::new_hget("table", "key");
+            let res = client.execute(cmd).await.unwrap();
+            assert_eq!(res.status, 429);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn connection_should_be_closed_when_quota_exceeded() -> anyhow::Result<()> {
+        let addr = start_server_with(|server| server.with_quota(0, true)).await?;
+
+        let stream = TcpStream::connect(addr).await?;
+        let mut client = ProstClientStream::new(stream);
+
+        let cmd = CommandRequest::new_hget("table", "key");
+        let res = client.execute(cmd).await.unwrap();
+        assert_eq!(res.status, 429);
+
+        let cmd = CommandRequest::new_hget("table", "key");
+        assert!(client.execute(cmd).await.is_err());
+
+        Ok(())
+    }
+
     async fn start_server() -> Result<SocketAddr> {
+        start_server_with(|server| server).await
+    }
+
+    async fn start_server_with_shutdown(shutdown: watch::Receiver<bool>) -> Result<SocketAddr> {
+        start_server_with(move |server| server.with_shutdown(shutdown.clone())).await
+    }
+
+    // 启动一个测试服务器，每个新连接的 ProstServerStream 都先经过 configure 配置
+    async fn start_server_with<F>(configure: F) -> Result<SocketAddr>
+    where
+        F: Fn(ProstServerStream<TcpStream>) -> ProstServerStream<TcpStream> + Send + 'static,
+    {
         let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
         let addr = listener.local_addr().unwrap();
 
@@ -130,7 +529,7 @@ mod tests {
             loop {
                 let (stream, _) = listener.accept().await.unwrap();
                 let service: Service = ServiceInner::new(MemTable::new()).into();
-                let server = ProstServerStream::new(stream, service);
+                let server = configure(ProstServerStream::new(stream, service));
                 tokio::spawn(server.process());
             }
         });
@@ -157,7 +556,7 @@ pub mod utils {
             buf: &mut tokio::io::ReadBuf<'_>,
         ) -> std::task::Poll<std::io::Result<()>> {
             // 看看 Reader 需要多大的数据
-            let len = buf.capacity();
+            let len = buf.remaining().min(self.buf.len());
 
             // split 出这么大的数据
             let data = self.get_mut().buf.split_to(len);
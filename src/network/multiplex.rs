@@ -145,13 +145,20 @@ mod tests {
         let addr = listener.local_addr().unwrap();
         let service: Service = ServiceInner::new(store).into();
 
+        let f = Arc::new(f);
         tokio::spawn(async move {
             loop {
                 match listener.accept().await {
-                    Ok((stream, _addr)) => match tls.accept(stream).await {
-                        Ok(stream) => f(stream, service.clone()),
-                        Err(e) => warn!("Failed to process secure stream: {e:?}"),
-                    },
+                    Ok((stream, _addr)) => {
+                        let (tls, service, f) = (tls.clone(), service.clone(), f.clone());
+                        // 握手放到单独的 task 中，避免握手很慢的客户端阻塞 accept
+                        tokio::spawn(async move {
+                            match tls.accept(stream).await {
+                                Ok(stream) => f(stream, service),
+                                Err(e) => warn!("Failed to process secure stream: {e:?}"),
+                            }
+                        });
+                    }
                     Err(e) => warn!("Failed to process tcp {e:?}"),
                 }
             }
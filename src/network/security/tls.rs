@@ -6,16 +6,22 @@ use tokio_rustls::{client::TlsStream as ClientTlsStream, server::TlsStream as Se
 use tokio_rustls::{TlsAcceptor, TlsConnector};
 
 use crate::KvError;
+use sha2::{Digest, Sha256};
 use std::io::Cursor;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// KV Server 自己的 ALPN (Application-Layer Protocol Negotiation)
 const ALPN_KV: &str = "kv";
 
+/// 缺省的 TLS 握手超时时间
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// 存放 TLS ServerConfig 并提供方法 accept 把底层的协议转换成 TLS
 #[derive(Clone)]
 pub struct TlsServerAcceptor {
     inner: Arc<ServerConfig>,
+    handshake_timeout: Duration,
 }
 
 /// 存放 TLS Client 并提供方法 connect 把底层的协议转换成 TLS
@@ -27,9 +33,37 @@ pub struct TlsClientConnector {
 
 pub struct TlsStream;
 
+/// 通过 mTLS 认证的客户端身份
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeerIdentity {
+    /// 客户端证书的 subject，比如 `C=CN, O=Acme Inc., CN=awesome-device-id`
+    pub subject: String,
+    /// 客户端证书（DER 编码）的 SHA-256 指纹，小写十六进制
+    pub fingerprint: String,
+}
+
+impl PeerIdentity {
+    /// 从 DER 编码的证书中解析客户端身份
+    pub fn from_certificate(der: &[u8]) -> Result<Self, KvError> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der)
+            .map_err(|_| KvError::CertifcateParseError("client", "cert"))?;
+        Ok(Self {
+            subject: cert.subject().to_string(),
+            fingerprint: format!("{:x}", Sha256::digest(der)),
+        })
+    }
+
+    /// 握手完成后从 TLS stream 中获取客户端身份，客户端没有提供证书时返回 None
+    pub fn from_tls<S>(stream: &ServerTlsStream<S>) -> Option<Self> {
+        let cert = stream.get_ref().1.peer_certificates()?.first()?;
+        Self::from_certificate(cert).ok()
+    }
+}
+
 impl TlsClientConnector {
     /// 加载 client cert / CA cert，生成 ClientConfig
-    /// server_ca 选项应传递根证书
+    /// server_ca 选项应传递根证书。identity 中的 cert 可以是一条证书链：客户端证书在前，之后依次是签发它的中间 CA 证书，
+    /// 握手时会把整条证书链发给服务器，这样服务器只需要信任根证书。顺序不对时返回错误
     pub fn new(
         domain: impl Into<String>,
         identity: Option<(&str, &str)>,
@@ -54,6 +88,7 @@ impl TlsClientConnector {
         let config = match identity {
             Some((cert, key)) => {
                 let certs = load_certs(cert)?;
+                check_chain(&certs)?;
                 let key = load_key(key)?;
                 ClientConfig::builder()
                     .with_root_certificates(root_cert_store)
@@ -83,7 +118,8 @@ impl TlsClientConnector {
 
         let stream = TlsConnector::from(self.config.clone())
             .connect(dns.to_owned(), stream)
-            .await?;
+            .await
+            .map_err(handshake_error)?;
 
         Ok(stream)
     }
@@ -122,16 +158,65 @@ impl TlsServerAcceptor {
 
         Ok(Self {
             inner: Arc::new(config),
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
         })
     }
 
+    /// 设置 TLS 握手的超时时间，缺省为 DEFAULT_HANDSHAKE_TIMEOUT
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// 触发 TLS 协议，把底层的 stream 转换成 TLS stream。
+    /// 客户端没有在超时时间内完成握手时返回错误并关闭连接，避免只建立 TCP 连接的客户端一直占用资源
     pub async fn accept<S>(&self, stream: S) -> Result<ServerTlsStream<S>, KvError>
     where
         S: AsyncRead + AsyncWrite + Unpin + Send,
     {
         let acceptor = TlsAcceptor::from(self.inner.clone());
-        Ok(acceptor.accept(stream).await?)
+        match tokio::time::timeout(self.handshake_timeout, acceptor.accept(stream)).await {
+            Ok(result) => result.map_err(handshake_error),
+            Err(_) => Err(KvError::TlsHandshake(format!(
+                "timed out after {:?}",
+                self.handshake_timeout
+            ))),
+        }
+    }
+}
+
+// tokio-rustls 把握手失败的原因（证书过期、未知的 CA、没有提供证书等）包装在 io::Error 中，
+// 把它取出来，这样日志里能看到握手失败的具体原因。单纯的 IO 错误保持不变
+fn handshake_error(e: std::io::Error) -> KvError {
+    let reason = e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<tokio_rustls::rustls::Error>())
+        .map(|e| e.to_string());
+    match reason {
+        Some(reason) => KvError::TlsHandshake(reason),
+        None => e.into(),
+    }
+}
+
+// 检查证书链的顺序：第一个是终端实体证书（不是 CA），之后每个证书都是前一个证书的签发者。
+// rustls 按原样发送证书链，顺序错误时服务器只会报告无法验证，很难看出原因
+fn check_chain(certs: &[CertificateDer]) -> Result<(), KvError> {
+    let err = || KvError::CertifcateParseError("client", "cert chain");
+    let certs = certs
+        .iter()
+        .map(|der| x509_parser::parse_x509_certificate(der).map(|(_, cert)| cert))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| err())?;
+    match certs.first() {
+        Some(leaf) if !leaf.is_ca() => {}
+        _ => return Err(err()),
     }
+    for pair in certs.windows(2) {
+        if pair[0].issuer().as_raw() != pair[1].subject().as_raw() {
+            return Err(err());
+        }
+    }
+    Ok(())
 }
 
 fn load_certs(cert: &str) -> Result<Vec<CertificateDer>, KvError> {
@@ -219,7 +304,9 @@ mod tests {
         let stream = TcpStream::connect(addr).await?;
         let result = connector.connect(stream).await;
 
-        assert!(result.is_err());
+        assert!(
+            matches!(result, Err(KvError::TlsHandshake(reason)) if reason.contains("certificate"))
+        );
 
         Ok(())
     }
@@ -241,6 +328,117 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn peer_identity_should_work() -> Result<()> {
+        let acceptor = tls_acceptor(true)?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let stream = acceptor.accept(stream).await.unwrap();
+            PeerIdentity::from_tls(&stream)
+        });
+
+        let connector = tls_connector(true)?;
+        let stream = TcpStream::connect(addr).await?;
+        let _stream = connector.connect(stream).await?;
+
+        let identity = server.await?.unwrap();
+        let cert = &load_certs(tls_utils::CLIENT_CERT)?[0];
+        assert_eq!(identity, PeerIdentity::from_certificate(cert)?);
+        assert_eq!(identity.fingerprint.len(), 64);
+        assert!(identity.subject.contains("CN="));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handshake_error_should_tell_the_reason() -> Result<()> {
+        let acceptor = tls_acceptor(true)?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            acceptor.accept(stream).await.err()
+        });
+
+        // 服务器要求客户端证书，但客户端没有提供
+        let connector = tls_connector(false)?;
+        let stream = TcpStream::connect(addr).await?;
+        let _stream = connector.connect(stream).await;
+
+        let err = server.await?.unwrap();
+        assert!(matches!(err, KvError::TlsHandshake(_)));
+        assert!(err.to_string().contains("TLS handshake failed"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn handshake_should_time_out() -> Result<()> {
+        let acceptor = tls_acceptor(false)?.with_handshake_timeout(Duration::from_millis(100));
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            acceptor.accept(stream).await.err()
+        });
+
+        // 只建立 TCP 连接，不发送任何 TLS 数据
+        let mut stream = TcpStream::connect(addr).await?;
+        let err = tokio::time::timeout(Duration::from_secs(5), server).await??;
+        assert!(matches!(err, Some(KvError::TlsHandshake(reason)) if reason.contains("timed out")));
+
+        // 服务器关闭了连接
+        let mut buf = [0; 1];
+        assert_eq!(stream.read(&mut buf).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tls_with_client_cert_chain_should_work() -> Result<()> {
+        // 服务器只信任根证书，客户端需要发送中间 CA 证书
+        let addr = start_server(true).await?;
+        let connector = tls_utils::tls_chain_connector(tls_utils::CLIENT_CHAIN_CERT)?;
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect(stream).await?;
+        stream.write_all(b"hello world!").await?;
+        let mut buf = [0; 12];
+        stream.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"hello world!");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tls_with_client_cert_without_intermediate_should_not_work() -> Result<()> {
+        let addr = start_server(true).await?;
+        // 只发送证书链中的第一个证书
+        let chain = tls_utils::CLIENT_CHAIN_CERT;
+        let leaf = &chain[..chain[1..].find("-----BEGIN").unwrap() + 1];
+        let connector = tls_utils::tls_chain_connector(leaf)?;
+        let stream = TcpStream::connect(addr).await?;
+        let mut stream = connector.connect(stream).await?;
+
+        let mut buf = [0; 12];
+        assert!(stream.read_exact(&mut buf).await.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn client_cert_chain_in_wrong_order_should_be_rejected() {
+        let chain = tls_utils::CLIENT_CHAIN_CERT;
+        let i = chain[1..].find("-----BEGIN").unwrap() + 1;
+        let reversed = format!("{}{}", &chain[i..], &chain[..i]);
+        let result = tls_utils::tls_chain_connector(&reversed);
+        assert!(matches!(
+            result,
+            Err(KvError::CertifcateParseError("client", "cert chain"))
+        ));
+    }
+
     async fn start_server(client_cert: bool) -> Result<SocketAddr> {
         let acceptor = tls_acceptor(client_cert)?;
 
@@ -265,10 +463,13 @@ pub mod tls_utils {
     use crate::{KvError, TlsClientConnector, TlsServerAcceptor};
 
     const CA_CERT: &str = include_str!("../../../fixtures/ca.cert");
-    const CLIENT_CERT: &str = include_str!("../../../fixtures/client.cert");
+    pub const CLIENT_CERT: &str = include_str!("../../../fixtures/client.cert");
     const CLIENT_KEY: &str = include_str!("../../../fixtures/client.key");
     const SERVER_CERT: &str = include_str!("../../../fixtures/server.cert");
     const SERVER_KEY: &str = include_str!("../../../fixtures/server.key");
+    // 由中间 CA 签发的客户端证书，后面跟着中间 CA 证书，见 fixtures/gen_chain.sh
+    pub const CLIENT_CHAIN_CERT: &str = include_str!("../../../fixtures/client_chain.cert");
+    const CLIENT_CHAIN_KEY: &str = include_str!("../../../fixtures/client_chain.key");
 
     pub fn tls_connector(client_cert: bool) -> Result<TlsClientConnector, KvError> {
         let ca = Some(CA_CERT);
@@ -280,6 +481,12 @@ pub mod tls_utils {
         }
     }
 
+    /// 使用 fixtures/client_chain.key 和给定的证书链作为客户端身份
+    pub fn tls_chain_connector(chain: &str) -> Result<TlsClientConnector, KvError> {
+        let identity = Some((chain, CLIENT_CHAIN_KEY));
+        TlsClientConnector::new("kvserver.acme.inc", identity, Some(CA_CERT))
+    }
+
     pub fn tls_acceptor(client_cert: bool) -> Result<TlsServerAcceptor, KvError> {
         let ca = Some(CA_CERT);
         match client_cert {
@@ -1,12 +1,22 @@
 use std::{marker::PhantomData, pin::Pin, task::Poll};
 
-use bytes::BytesMut;
-use futures::{ready, FutureExt, Sink, Stream};
+use bytes::{BufMut, BytesMut};
+use futures::{ready, Sink, Stream};
 use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::io::poll_read_buf;
 
-use crate::{network::frame::read_frame, FrameCoder, KvError};
+use crate::{
+    network::frame::{peek_frame_len, DEFAULT_MAX_FRAME, LEN_LEN},
+    FrameCoder, KvError,
+};
 
-// 处理 KV server prost frame 的 stream
+/// 每次从 stream 中读取数据时缺省最多读取的字节数
+pub const DEFAULT_READ_CHUNK: usize = 8 * 1024;
+
+/// 处理 KV server prost frame 的 stream
+///
+/// 读缓存在 frame 之间复用：一个 frame decode 完之后它占用的空间会被回收，剩下的数据移动到缓存的开头，
+/// 只有缓存放不下下一个 frame 时才会重新分配内存
 pub struct ProstStream<S, In, Out> {
     // inner stream
     stream: S,
@@ -14,8 +24,14 @@ pub struct ProstStream<S, In, Out> {
     wbuf: BytesMut,
     // 写入了多少字节
     written: usize,
+    // 写入出错，frame 可能只写入了一部分，之后的写入都直接返回错误
+    write_failed: bool,
     // 读缓存
     rbuf: BytesMut,
+    // 允许读取的最大 frame 长度
+    max_frame: usize,
+    // 每次从 stream 中最多读取的字节数
+    read_chunk: usize,
 
     _in: PhantomData<In>,
     _out: PhantomData<Out>,
@@ -29,12 +45,50 @@ where
         Self {
             stream,
             written: 0,
+            write_failed: false,
             wbuf: BytesMut::new(),
             rbuf: BytesMut::new(),
+            max_frame: DEFAULT_MAX_FRAME,
+            read_chunk: DEFAULT_READ_CHUNK,
             _in: PhantomData::default(),
             _out: PhantomData::default(),
         }
     }
+
+    /// 设置允许读取的最大 frame 长度（解压前和解压后），超过的 frame 会在分配内存前被拒绝
+    pub fn with_max_frame(mut self, max_frame: usize) -> Self {
+        self.max_frame = max_frame;
+        self
+    }
+
+    /// 设置每次从 stream 中最多读取的字节数。小命令多的场景可以调小以减少内存占用，
+    /// 大 value 多的场景可以调大以减少系统调用的次数
+    pub fn with_read_chunk(mut self, read_chunk: usize) -> Self {
+        self.read_chunk = read_chunk.max(LEN_LEN);
+        self
+    }
+}
+
+impl<S, In, Out> ProstStream<S, In, Out> {
+    // 对端收到的可能是写了一半的 frame，无法再和它对齐 frame 的边界，
+    // 丢掉没有写入的数据，并且不再写入，避免继续发送的数据被对端当成上一个 frame 的剩余部分
+    fn write_failed(&mut self, e: std::io::Error) -> KvError {
+        self.write_failed = true;
+        self.wbuf.clear();
+        self.written = 0;
+        e.into()
+    }
+
+    fn check_writable(&self) -> Result<(), KvError> {
+        match self.write_failed {
+            true => Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "Stream is unusable after a failed write",
+            )
+            .into()),
+            false => Ok(()),
+        }
+    }
 }
 
 impl<S, Req, Res> Unpin for ProstStream<S, Req, Res> where S: Unpin {}
@@ -49,24 +103,43 @@ where
     type Item = Result<In, KvError>;
 
     fn poll_next(
-        mut self: std::pin::Pin<&mut Self>,
+        self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        // 上次调用结束后 rbuf 应该为空
-        assert!(self.rbuf.is_empty());
-
-        // 从 rbuf 中分理出 rest （摆脱对 self 的引用）
-        let mut rest = self.rbuf.split_off(0);
-
-        // 使用 read_frame 来读取数据
-        let fut = read_frame(&mut self.stream, &mut rest);
-        ready!(Box::pin(fut).poll_unpin(cx))?;
+        let this = self.get_mut();
 
-        // 拿到一个 frame 的数据后，把 buffer 合并回去
-        self.rbuf.unsplit(rest);
+        // 读到的数据都保存在 rbuf 中，因此 future 在读取到一半时被取消（比如在 select! 中）也不会丢失数据
+        loop {
+            let mut remaining = 0;
+            if let Some(len) = peek_frame_len(&this.rbuf) {
+                // 在分配内存前拒绝过大的 frame
+                if len - LEN_LEN > this.max_frame {
+                    return Poll::Ready(Some(Err(KvError::FrameError)));
+                }
+                if this.rbuf.len() >= len {
+                    // frame 释放后，它占用的空间在下次 reserve 时被回收
+                    let mut frame = this.rbuf.split_to(len);
+                    return Poll::Ready(Some(In::decode_frame_with_limit(
+                        &mut frame,
+                        this.max_frame,
+                    )));
+                }
+                remaining = len - this.rbuf.len();
+            }
 
-        // 调用 decode_frame 获取解包后的数据
-        Poll::Ready(Some(In::decode_frame(&mut self.rbuf)))
+            // 一次为整个 frame 预留好内存，避免大 frame 在读取过程中多次重新分配
+            this.rbuf.reserve(remaining.max(this.read_chunk));
+            let mut dst = (&mut this.rbuf).limit(this.read_chunk);
+            let n = ready!(poll_read_buf(Pin::new(&mut this.stream), cx, &mut dst))?;
+            if n == 0 {
+                // 在两个 frame 之间关闭连接是正常结束，frame 读取到一半时关闭是错误
+                if this.rbuf.is_empty() {
+                    return Poll::Ready(None);
+                }
+                let e = std::io::Error::from(std::io::ErrorKind::UnexpectedEof);
+                return Poll::Ready(Some(Err(e.into())));
+            }
+        }
     }
 }
 
@@ -84,11 +157,12 @@ where
         _cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Result<(), Self::Error>> {
         // 暂时不考虑在网络层做背压处理，依赖操作系统层的协议栈做背压处理
-        Poll::Ready(Ok(()))
+        Poll::Ready(self.check_writable())
     }
 
     fn start_send(self: std::pin::Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
         let this = self.get_mut();
+        this.check_writable()?;
         item.encode_frame(&mut this.wbuf)?;
 
         Ok(())
@@ -99,11 +173,19 @@ where
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Result<(), Self::Error>> {
         let this = self.get_mut();
+        this.check_writable()?;
 
         // 循环写入 stream 中
         while this.written != this.wbuf.len() {
-            let n = ready!(Pin::new(&mut this.stream).poll_write(cx, &this.wbuf[this.written..]))?;
-            this.written += n;
+            let buf = &this.wbuf[this.written..];
+            match ready!(Pin::new(&mut this.stream).poll_write(cx, buf)) {
+                Ok(0) => {
+                    let e = std::io::ErrorKind::WriteZero.into();
+                    return Poll::Ready(Err(this.write_failed(e)));
+                }
+                Ok(n) => this.written += n,
+                Err(e) => return Poll::Ready(Err(this.write_failed(e))),
+            }
         }
 
         // 清除 buf
@@ -111,7 +193,9 @@ where
         this.written = 0;
 
         // 调用 stream 的 poll_flush 确保写入
-        ready!(Pin::new(&mut this.stream).poll_flush(cx))?;
+        if let Err(e) = ready!(Pin::new(&mut this.stream).poll_flush(cx)) {
+            return Poll::Ready(Err(this.write_failed(e)));
+        }
         Poll::Ready(Ok(()))
     }
 
@@ -148,4 +232,201 @@ mod tests {
         }
         Ok(())
     }
+
+    // 以 UTF-8 文本作为 payload 的消息
+    #[derive(Debug, PartialEq)]
+    struct Text(String);
+
+    impl FrameCoder for Text {
+        fn encode_payload(&self, buf: &mut BytesMut) -> Result<(), KvError> {
+            buf.extend_from_slice(self.0.as_bytes());
+            Ok(())
+        }
+
+        fn decode_payload(data: &[u8]) -> Result<Self, KvError> {
+            let text = String::from_utf8(data.to_vec()).map_err(|_| KvError::FrameError)?;
+            Ok(Text(text))
+        }
+    }
+
+    #[tokio::test]
+    async fn prost_stream_should_work_with_custom_codec() -> Result<()> {
+        let mut stream = ProstStream::<_, Text, Text>::new(DummyStream::default());
+        // 超过 1436 字节的 payload 会被压缩
+        let texts = ["hello".to_string(), "hello ".repeat(1000)];
+        for text in &texts {
+            stream.send(Text(text.clone())).await?;
+            assert_eq!(stream.next().await.unwrap()?, Text(text.clone()));
+        }
+
+        // 一次写入多个 frame
+        for text in &texts {
+            stream.feed(Text(text.clone())).await?;
+        }
+        stream.flush().await?;
+        for text in &texts {
+            assert_eq!(stream.next().await.unwrap()?, Text(text.clone()));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prost_stream_should_reject_oversized_frame() -> Result<()> {
+        let stream = DummyStream::default();
+        let mut stream =
+            ProstStream::<_, CommandRequest, CommandRequest>::new(stream).with_max_frame(16);
+        let cmd = CommandRequest::new_hset("table", "key", "a value longer than 16 bytes");
+        stream.send(cmd).await?;
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(KvError::FrameError))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prost_stream_with_oversized_header_should_fail() -> Result<()> {
+        // 伪造一个声明长度接近 1G 的 frame，但实际只有几个字节
+        let mut buf = BytesMut::new();
+        buf.put_u32((1024 * 1024 * 1024 - 1) as _);
+        buf.put_slice(b"hello");
+        let stream = DummyStream { buf };
+        let mut stream = ProstStream::<_, CommandRequest, CommandRequest>::new(stream);
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(KvError::FrameError))
+        ));
+        // 没有为伪造的长度分配内存
+        assert!(stream.rbuf.capacity() < DEFAULT_MAX_FRAME);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prost_stream_should_read_frames_in_small_chunks() -> Result<()> {
+        let stream = DummyStream::default();
+        let mut stream =
+            ProstStream::<_, CommandRequest, CommandRequest>::new(stream).with_read_chunk(5);
+        let cmds = [
+            CommandRequest::new_hget("table", "key"),
+            CommandRequest::new_hset("table", "key", "v".repeat(4096)),
+            CommandRequest::new_hdel("table", "key"),
+        ];
+        for cmd in cmds.iter() {
+            stream.send(cmd.clone()).await?;
+        }
+        for cmd in cmds {
+            assert_eq!(stream.next().await.unwrap()?, cmd);
+        }
+        assert!(stream.next().await.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prost_stream_should_reuse_read_buffer() -> Result<()> {
+        let stream = DummyStream::default();
+        let mut stream = ProstStream::<_, CommandRequest, CommandRequest>::new(stream);
+        let cmd = CommandRequest::new_hget("table", "key");
+        let len = cmd.encode_frame_to_vec()?.len();
+        stream.send(cmd.clone()).await?;
+        stream.next().await.unwrap()?;
+
+        // 第一个 frame 从新分配的内存的开头开始
+        let start = stream.rbuf.as_ptr() as usize - len;
+        let end = start + len + stream.rbuf.capacity();
+
+        // 读取的总数据量远大于缓存的容量，但一直使用同一块内存
+        for _ in 0..1000 {
+            stream.send(cmd.clone()).await?;
+            assert_eq!(stream.next().await.unwrap()?, cmd);
+            let ptr = stream.rbuf.as_ptr() as usize;
+            assert!(ptr >= start && ptr <= end);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prost_stream_with_truncated_frame_should_fail() -> Result<()> {
+        let mut buf = BytesMut::new();
+        CommandRequest::new_hget("table", "key").encode_frame(&mut buf)?;
+        buf.truncate(buf.len() - 1);
+        let stream = DummyStream { buf };
+        let mut stream = ProstStream::<_, CommandRequest, CommandRequest>::new(stream);
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(KvError::IoError(_)))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prost_stream_should_fail_after_partial_write() -> Result<()> {
+        let cmd = CommandRequest::new_hset("table", "key", "value");
+        let len = cmd.encode_frame_to_vec()?.len();
+        let stream = HalfWriteStream {
+            written: Vec::new(),
+            fail_at: Some(len / 2),
+        };
+        let mut stream = ProstStream::<_, CommandRequest, CommandRequest>::new(stream);
+        assert!(matches!(
+            stream.send(cmd.clone()).await,
+            Err(KvError::IoError(_))
+        ));
+        assert_eq!(stream.stream.written.len(), len / 2);
+
+        // 底层的 stream 恢复了，也不再写入任何数据，而不是把剩下的半个 frame 和新的 frame 发出去
+        assert!(matches!(stream.send(cmd).await, Err(KvError::IoError(_))));
+        assert_eq!(stream.stream.written.len(), len / 2);
+        Ok(())
+    }
+
+    // 写入到 fail_at 字节时失败一次，之后恢复正常
+    struct HalfWriteStream {
+        written: Vec<u8>,
+        fail_at: Option<usize>,
+    }
+
+    impl tokio::io::AsyncRead for HalfWriteStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            _buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl tokio::io::AsyncWrite for HalfWriteStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            let this = self.get_mut();
+            let n = match this.fail_at {
+                Some(at) if this.written.len() == at => {
+                    this.fail_at = None;
+                    let e = std::io::ErrorKind::ConnectionReset.into();
+                    return Poll::Ready(Err(e));
+                }
+                Some(at) => buf.len().min(at - this.written.len()),
+                None => buf.len(),
+            };
+            this.written.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(
+            self: Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
 }
@@ -4,9 +4,14 @@
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CommandRequest {
+    /// 客户端愿意等待的毫秒数，从服务器收到请求开始计算。超过之后服务器不再开始执行，
+    /// 遍历 table 的命令在遍历过程中放弃，返回 504。已经开始的单个读写不会被中断。
+    /// tag 和命令的 tag 分开，新增命令不会和它冲突
+    #[prost(uint64, optional, tag = "1000")]
+    pub deadline_ms: ::core::option::Option<u64>,
     #[prost(
         oneof = "command_request::RequestData",
-        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9"
+        tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64"
     )]
     pub request_data: ::core::option::Option<command_request::RequestData>,
 }
@@ -34,6 +39,116 @@ pub mod command_request {
         Hexist(super::Hexist),
         #[prost(message, tag = "9")]
         Hmexist(super::Hmexist),
+        #[prost(message, tag = "10")]
+        Compact(super::Compact),
+        #[prost(message, tag = "11")]
+        Metrics(super::Metrics),
+        #[prost(message, tag = "12")]
+        Hmove(super::Hmove),
+        #[prost(message, tag = "13")]
+        Hmerge(super::Hmerge),
+        #[prost(message, tag = "14")]
+        Hdeleq(super::Hdeleq),
+        #[prost(message, tag = "15")]
+        Hmtime(super::Hmtime),
+        #[prost(message, tag = "16")]
+        Findbyvalue(super::Findbyvalue),
+        #[prost(message, tag = "17")]
+        Hincrfield(super::Hincrfield),
+        #[prost(message, tag = "18")]
+        Fieldget(super::Fieldget),
+        #[prost(message, tag = "19")]
+        Fieldset(super::Fieldset),
+        #[prost(message, tag = "20")]
+        Memusage(super::Memusage),
+        #[prost(message, tag = "21")]
+        Hsetchunk(super::Hsetchunk),
+        #[prost(message, tag = "22")]
+        Hwait(super::Hwait),
+        #[prost(message, tag = "23")]
+        Quit(super::Quit),
+        #[prost(message, tag = "24")]
+        Selftest(super::Selftest),
+        #[prost(message, tag = "25")]
+        Whoami(super::Whoami),
+        #[prost(message, tag = "26")]
+        Hsetif(super::Hsetif),
+        #[prost(message, tag = "27")]
+        Commands(super::Commands),
+        #[prost(message, tag = "28")]
+        Hgetset(super::Hgetset),
+        #[prost(message, tag = "29")]
+        Pausetable(super::Pausetable),
+        #[prost(message, tag = "30")]
+        Resumetable(super::Resumetable),
+        #[prost(message, tag = "31")]
+        Replacetable(super::Replacetable),
+        #[prost(message, tag = "32")]
+        Difftables(super::Difftables),
+        #[prost(message, tag = "33")]
+        Fieldmerge(super::Fieldmerge),
+        #[prost(message, tag = "34")]
+        Hmatch(super::Hmatch),
+        #[prost(message, tag = "35")]
+        Tableversion(super::Tableversion),
+        #[prost(message, tag = "36")]
+        Replacetableif(super::Replacetableif),
+        #[prost(message, tag = "37")]
+        Time(super::Time),
+        #[prost(message, tag = "38")]
+        Multi(super::Multi),
+        #[prost(message, tag = "39")]
+        Exec(super::Exec),
+        #[prost(message, tag = "40")]
+        Discard(super::Discard),
+        #[prost(message, tag = "41")]
+        Fieldmget(super::Fieldmget),
+        #[prost(message, tag = "42")]
+        Hmincr(super::Hmincr),
+        #[prost(message, tag = "43")]
+        Lock(super::Lock),
+        #[prost(message, tag = "44")]
+        Unlock(super::Unlock),
+        #[prost(message, tag = "45")]
+        Errorstats(super::Errorstats),
+        #[prost(message, tag = "46")]
+        Delmatch(super::Delmatch),
+        #[prost(message, tag = "47")]
+        Hexpire(super::Hexpire),
+        #[prost(message, tag = "48")]
+        Hincrinit(super::Hincrinit),
+        #[prost(message, tag = "49")]
+        Hincr(super::Hincr),
+        #[prost(message, tag = "50")]
+        Keyinfo(super::Keyinfo),
+        #[prost(message, tag = "51")]
+        Hincrfloat(super::Hincrfloat),
+        #[prost(message, tag = "52")]
+        Lpush(super::Lpush),
+        #[prost(message, tag = "53")]
+        Rpush(super::Rpush),
+        #[prost(message, tag = "54")]
+        Lrange(super::Lrange),
+        #[prost(message, tag = "55")]
+        Hkeys(super::Hkeys),
+        #[prost(message, tag = "56")]
+        Hvals(super::Hvals),
+        #[prost(message, tag = "57")]
+        Hlen(super::Hlen),
+        #[prost(message, tag = "58")]
+        Hsetnx(super::Hsetnx),
+        #[prost(message, tag = "59")]
+        Hcas(super::Hcas),
+        #[prost(message, tag = "60")]
+        Transaction(super::Transaction),
+        #[prost(message, tag = "61")]
+        Hrename(super::Hrename),
+        #[prost(message, tag = "62")]
+        Copytable(super::Copytable),
+        #[prost(message, tag = "63")]
+        Flushtable(super::Flushtable),
+        #[prost(message, tag = "64")]
+        Tables(super::Tables),
     }
 }
 /// 服务器的响应
@@ -53,6 +168,16 @@ pub struct CommandResponse {
     /// 成功返回的 kv pairs
     #[prost(message, repeated, tag = "4")]
     pub pairs: ::prost::alloc::vec::Vec<Kvpair>,
+    /// 如果连接设置了命令配额，这里是剩余可以执行的命令数
+    #[prost(uint64, optional, tag = "5")]
+    pub remaining_quota: ::core::option::Option<u64>,
+    /// 结果不完整（比如超过了结果大小的限制），status 为 206，message 说明原因，
+    /// values 和 pairs 中是已经得到的部分结果
+    #[prost(bool, tag = "6")]
+    pub partial: bool,
+    /// EXEC 返回的事务中每个命令的结果，顺序和命令排队的顺序相同
+    #[prost(message, repeated, tag = "7")]
+    pub responses: ::prost::alloc::vec::Vec<CommandResponse>,
 }
 /// 从 table 中获取一个 key，返回 value
 #[derive(PartialOrd)]
@@ -87,7 +212,11 @@ pub struct Hmget {
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Value {
-    #[prost(oneof = "value::Value", tags = "1, 2, 3, 4, 5")]
+    /// 可选的内容类型，比如 application/json。服务器只保存，不解释它，
+    /// 客户端据此决定如何解码 binary。老的数据没有这个字段，读出来是空
+    #[prost(string, optional, tag = "8")]
+    pub content_type: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(oneof = "value::Value", tags = "1, 2, 3, 4, 5, 6, 7, 9, 10")]
     pub value: ::core::option::Option<value::Value>,
 }
 /// Nested message and enum types in `Value`.
@@ -106,8 +235,26 @@ pub mod value {
         Float(f64),
         #[prost(bool, tag = "5")]
         Bool(bool),
+        #[prost(message, tag = "6")]
+        Register(super::Register),
+        #[prost(message, tag = "7")]
+        Map(super::Map),
+        #[prost(message, tag = "9")]
+        Timestamp(super::Timestamp),
+        #[prost(message, tag = "10")]
+        List(super::List),
     }
 }
+/// UNIX 时间戳：从 1970-01-01T00:00:00Z 开始的秒数，加上不足一秒的纳秒数（0 到 999999999）
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Timestamp {
+    #[prost(int64, tag = "1")]
+    pub seconds: i64,
+    #[prost(int32, tag = "2")]
+    pub nanos: i32,
+}
 /// 返回的 kvpair
 #[derive(PartialOrd)]
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -119,7 +266,8 @@ pub struct Kvpair {
     pub value: ::core::option::Option<Value>,
 }
 /// 往 table 里存一个 kvpair，
-/// 如果 table 不存在就创建这个 table
+/// 如果 table 不存在就创建这个 table。ttl_secs 不为 0 时 key 在 ttl_secs 秒后过期，
+/// 为 0 时去掉 key 原来的过期时间。value 和过期时间一起写入，存储不支持过期时返回 501，什么都不写入
 #[derive(PartialOrd)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -128,9 +276,12 @@ pub struct Hset {
     pub table: ::prost::alloc::string::String,
     #[prost(message, optional, tag = "2")]
     pub pair: ::core::option::Option<Kvpair>,
+    #[prost(uint64, tag = "3")]
+    pub ttl_secs: u64,
 }
 /// 往 table 中存一组 kvpair，
-/// 如果 table 不存在就创建这个 table
+/// 如果 table 不存在就创建这个 table。pairs 中有重复的 key 时按 duplicates 处理，
+/// 返回去重后每个 key 之前的 value，顺序是 key 第一次出现的顺序
 #[derive(PartialOrd)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -139,6 +290,8 @@ pub struct Hmset {
     pub table: ::prost::alloc::string::String,
     #[prost(message, repeated, tag = "2")]
     pub pairs: ::prost::alloc::vec::Vec<Kvpair>,
+    #[prost(enumeration = "DuplicatePolicy", tag = "3")]
+    pub duplicates: i32,
 }
 /// 从 table 中删除一个 key，返回它之前的值
 #[derive(PartialOrd)]
@@ -180,3 +333,737 @@ pub struct Hmexist {
     #[prost(string, repeated, tag = "2")]
     pub keys: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
+/// 整理存储后端，回收陈旧数据占用的磁盘空间，返回回收的字节数
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Compact {}
+/// 获取服务器内部的统计数据，以 metric 名称和数值的 kvpair 返回
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Metrics {}
+/// 把一个 key 从 from_table 原子地移动到 to_table，返回被移动的 value
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hmove {
+    #[prost(string, tag = "1")]
+    pub from_table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub to_table: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub key: ::prost::alloc::string::String,
+}
+/// 向量时钟中一个节点的计数
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ClockEntry {
+    #[prost(string, tag = "1")]
+    pub node: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub counter: u64,
+}
+/// 带向量时钟的多值寄存器，values 是所有并发写入（互相冲突）的值，clock 是它们合并后的向量时钟
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Register {
+    #[prost(message, repeated, tag = "1")]
+    pub values: ::prost::alloc::vec::Vec<Value>,
+    #[prost(message, repeated, tag = "2")]
+    pub clock: ::prost::alloc::vec::Vec<ClockEntry>,
+}
+/// 把带有向量时钟 clock 的 value 合并到 table 的 key 中，返回合并后的 Register
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hmerge {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "3")]
+    pub value: ::core::option::Option<Value>,
+    #[prost(message, repeated, tag = "4")]
+    pub clock: ::prost::alloc::vec::Vec<ClockEntry>,
+}
+/// 只有 key 当前的值等于 expected 时才删除它，返回是否删除
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hdeleq {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "3")]
+    pub expected: ::core::option::Option<Value>,
+}
+/// 获取 key 最后一次被写入的时间，返回毫秒级的 UNIX 时间戳
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hmtime {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+}
+/// 查找 table 中所有值等于 value 的 key
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Findbyvalue {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub value: ::core::option::Option<Value>,
+}
+/// 嵌套在 value 中的 map，每个 field 是一个 kvpair
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Map {
+    #[prost(message, repeated, tag = "1")]
+    pub fields: ::prost::alloc::vec::Vec<Kvpair>,
+}
+/// 嵌套在 value 中的列表，LPUSH / RPUSH 在两端添加元素，LRANGE 读取其中的一段
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct List {
+    #[prost(message, repeated, tag = "1")]
+    pub values: ::prost::alloc::vec::Vec<Value>,
+}
+/// 原子地给 table 中 key 的 map 里的整数 field 加上 delta，field 不存在时从 0 开始，返回新的值。
+/// 结果溢出 i64 时默认返回错误并保持原来的值不变；saturating 为 true 时结果截断到 i64::MIN / i64::MAX
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hincrfield {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub field: ::prost::alloc::string::String,
+    #[prost(int64, tag = "4")]
+    pub delta: i64,
+    #[prost(bool, tag = "5")]
+    pub saturating: bool,
+}
+/// 获取 table 中 key 的 map 里的一个 field
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Fieldget {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub field: ::prost::alloc::string::String,
+}
+/// 原子地设置 table 中 key 的 map 里的一个 field，其它 field 保持不变，返回 field 之前的值
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Fieldset {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub field: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "4")]
+    pub value: ::core::option::Option<Value>,
+}
+/// 估算 table 占用的字节数，返回的是一个近似值
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Memusage {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+}
+/// 分块上传一个大的 value，所有 chunk 都收到后才原子地写入 table 中的 key。
+/// chunk 数或者数据大小超过服务器的限制时返回 400，同时进行的上传太多时返回 503（见 UploadLimits）
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hsetchunk {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    /// 标识一次上传，同一次上传的所有 chunk 使用相同的 upload_id
+    #[prost(string, tag = "3")]
+    pub upload_id: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "4")]
+    pub chunk_index: u32,
+    #[prost(uint32, tag = "5")]
+    pub total_chunks: u32,
+    #[prost(bytes = "bytes", tag = "6")]
+    pub data: ::prost::bytes::Bytes,
+}
+/// 等待 table 中的 key 被写入：key 已经存在时立即返回它的值，否则一直等到 key 被写入，
+/// 或者超过 timeout_ms 毫秒后返回 504
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hwait {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub timeout_ms: u64,
+}
+/// 结束连接：服务器返回一个 OK 的 CommandResponse 之后关闭连接
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Quit {}
+/// 自检：在保留的 table 中写入、读取、删除一个探测用的 key，返回每一步是否成功以及耗时（微秒）
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Selftest {}
+/// 返回服务器看到的客户端身份：通过 mTLS 认证时返回客户端证书的 subject 和 SHA-256 指纹
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Whoami {}
+/// 只有新的 value 和 key 当前的 value 的比较结果满足 op 时才写入，返回是否写入。
+/// key 不存在时视为满足条件，所以第一次写入总会成功
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hsetif {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "3")]
+    pub value: ::core::option::Option<Value>,
+    #[prost(enumeration = "CompareOp", tag = "4")]
+    pub op: i32,
+}
+/// 返回服务器支持的所有命令的名字
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Commands {}
+/// 写入新的 value 并返回 key 之前的 value（key 不存在时返回空的 value），写入和读取之前的 value 是原子的
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hgetset {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "3")]
+    pub value: ::core::option::Option<Value>,
+}
+/// 暂停 table 的写入，读取不受影响。被暂停的 table 的写入命令返回 503，返回暂停中的所有 table
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Pausetable {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+}
+/// 恢复 table 的写入，返回暂停中的所有 table
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Resumetable {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+}
+/// 用 pairs 原子地替换 table 中的所有数据，读者不会看到替换了一半的 table。
+/// pairs 中重复的 key 以最后一个为准，返回删除的 key 数 removed 和写入的 key 数 inserted
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Replacetable {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "2")]
+    pub pairs: ::prost::alloc::vec::Vec<Kvpair>,
+}
+/// 比较两个 table，返回有差异的 key：只在 table_a 中、只在 table_b 中、两边都有但 value 不同。
+/// 每个差异是一个 Kvpair，value 是 map：diff 是差异的类型 only_a、only_b 或 changed，
+/// a 和 b 是两边的 value（没有的一边不出现）。结果按 key 排序
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Difftables {
+    #[prost(string, tag = "1")]
+    pub table_a: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub table_b: ::prost::alloc::string::String,
+}
+/// 原子地把 fields 合并到 table 中 key 的 map 里：key 不存在时创建 map，已有的 field 被覆盖，
+/// 没有提到的 field 保持不变。返回合并后 map 中 field 的数量
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Fieldmerge {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "3")]
+    pub fields: ::prost::alloc::vec::Vec<Kvpair>,
+}
+/// 返回 table 中匹配 glob 模式 pattern 的 key：* 匹配任意个字符，? 匹配一个字符，
+/// \[abc\]、[a-z] 匹配其中一个字符，\[!abc\] 匹配不在其中的字符，\\ 转义下一个字符。
+/// 结果按 key 排序，with_values 为 true 时以 pairs 返回 key 和 value，否则以 values 返回 key。
+/// 需要遍历整个 table，大的 table 上代价很高
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hmatch {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub pattern: ::prost::alloc::string::String,
+    #[prost(bool, tag = "3")]
+    pub with_values: bool,
+}
+/// 返回 table 的版本号：table 每被修改一次版本号加一，没有被修改过的 table 版本号为 0。
+/// 版本号只保存在服务器的内存中，重启后从 0 开始
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Tableversion {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+}
+/// table 的版本号等于 expected_version 时才像 REPLACETABLE 一样替换 table 中的所有数据，
+/// 检查和替换之间不会有其它写入。返回 inserted、removed 和替换后的版本号 version，
+/// 版本号不一致时返回 409，message 中是当前的版本号
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Replacetableif {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub expected_version: u64,
+    #[prost(message, repeated, tag = "3")]
+    pub pairs: ::prost::alloc::vec::Vec<Kvpair>,
+}
+/// 返回服务器当前的时间（Timestamp）。这是服务器的墙上时钟，不是单调时钟：
+/// 服务器校时后可能跳变，两次 TIME 的结果不一定递增。客户端计算绝对的过期时间时应该以它为准，
+/// 而不是自己的时钟，避免客户端和服务器之间的时钟偏差
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Time {}
+/// 开始一个连接上的事务：之后的命令不再立即执行，而是排队并返回 QUEUED，直到 EXEC 或者 DISCARD。
+/// 排队时遇到不能在事务中执行的命令（MULTI、WHOAMI、PAUSETABLE 等）会放弃整个事务，返回 400。
+/// QUIT 不排队，直接关闭连接
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Multi {}
+/// 执行 MULTI 之后排队的所有命令，每个命令的结果按顺序放在 responses 中。
+/// 执行期间其它连接不能写入这些命令修改的 table，但读取不受影响。某个命令失败时后面的命令依旧执行，不会回滚
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Exec {}
+/// 放弃 MULTI 之后排队的所有命令
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Discard {}
+/// 获取 table 中 key 的 map 里的一组 field，按 fields 的顺序返回它们的值，不存在的 field 返回空值
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Fieldmget {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, repeated, tag = "3")]
+    pub fields: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// 给 table 中的一组整数 key 分别加上 delta，key 不存在时从 0 开始，按 pairs 的顺序返回新的值，
+/// 同一个 key 出现多次时依次累加。某个 key 不是整数或者结果溢出时：all_or_nothing 为 false 时
+/// 这个 key 保持不变并返回空值，其它 key 照常修改，返回 206 和出错的 key；为 true 时所有 key 都不修改，
+/// 返回错误。saturating 为 true 时溢出的结果截断到 i64::MIN / i64::MAX，不算出错。
+/// 通过 Service 执行时持有 table 的独占锁，其它写入不会穿插在中间
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hmincr {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "2")]
+    pub pairs: ::prost::alloc::vec::Vec<KeyDelta>,
+    #[prost(bool, tag = "3")]
+    pub all_or_nothing: bool,
+    #[prost(bool, tag = "4")]
+    pub saturating: bool,
+}
+/// HMINCR 中的一个 key 和要加上的 delta
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct KeyDelta {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(int64, tag = "2")]
+    pub delta: i64,
+}
+/// 获取 table 中 key 上的锁：锁空闲、已经过期或者属于 owner 时获取成功，返回 fencing token。
+/// 每次获取空闲或者过期的锁时 token 加一，owner 再次获取自己持有的锁只更新过期时间，token 不变。
+/// 锁在 ttl_ms 毫秒后过期，为 0 时不过期；被其它 owner 持有时返回 409。
+/// 锁以 map 保存在 key 中，包括 owner、token 和 expires_at（毫秒级的 UNIX 时间戳，0 表示不过期）
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Lock {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub owner: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "4")]
+    pub ttl_ms: u64,
+}
+/// 释放 owner 持有的 table 中 key 上的锁，返回是否释放。锁不属于 owner 或者已经过期时不做修改。
+/// 释放后 token 依旧保存在 key 中，之后获取锁时继续递增
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Unlock {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub owner: ::prost::alloc::string::String,
+}
+/// 返回每类错误（KvError 的变体名）发生的次数，reset 为 true 时同时清零。每个 Service 单独计数
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Errorstats {
+    #[prost(bool, tag = "1")]
+    pub reset: bool,
+}
+/// 删除 table 中所有匹配 glob 模式 pattern 的 key，返回删除的 key 数，模式的语法和 HMATCH 相同。
+/// 匹配的 key 在一个批次中原子地删除，读者要么看到所有的 key，要么一个也看不到。
+/// 需要遍历整个 table，大的 table 上代价很高。设置了管理员时只有管理员可以执行
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Delmatch {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub pattern: ::prost::alloc::string::String,
+}
+/// 设置 table 中的 key 在 ttl_secs 秒后过期，返回 key 是否存在。ttl_secs 为 0 时 key 立即过期。
+/// 过期的 key 和不存在的 key 一样，HGET 返回 404。存储不支持过期时返回 501
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hexpire {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub ttl_secs: u64,
+}
+/// 给 table 中的整数 key 加上 delta，返回新的值。key 不存在时创建为 delta，init_ttl_ms 不为 0 时
+/// 在 init_ttl_ms 毫秒后过期；key 已经存在时只增加，不改变过期时间。用于按时间窗口计数：
+/// 计数器在第一次创建时开始计时，之后的增加不会延长它的寿命。通过 Service 执行时持有 table 的独占锁，
+/// 其它写入不会穿插在创建和设置过期时间之间。溢出时和 HINCR 一样处理
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hincrinit {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(int64, tag = "3")]
+    pub delta: i64,
+    #[prost(uint64, tag = "4")]
+    pub init_ttl_ms: u64,
+    #[prost(bool, tag = "5")]
+    pub saturating: bool,
+}
+/// 给 table 中的整数 key 加上 delta，返回新的值。key 不存在时从 0 开始，value 不是整数时返回错误。
+/// 读取和写入是原子的，并发的 HINCR 不会丢失修改。结果溢出 i64 时默认返回错误并保持原来的值不变；
+/// saturating 为 true 时结果截断到 i64::MIN / i64::MAX
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hincr {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(int64, tag = "3")]
+    pub delta: i64,
+    #[prost(bool, tag = "4")]
+    pub saturating: bool,
+}
+/// 查看 key 的元数据，不返回 value 本身。按顺序返回 pairs：type（value 的类型名）、
+/// size（value 编码后的字节数），以及存储记录了的 ttl_ms（剩余的存活毫秒数，没有过期时间时不返回）、
+/// mtime（最后修改时间）和 token（key 是 LOCK 创建的锁时的 fencing token）。key 不存在时返回 404
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Keyinfo {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+}
+/// 给 table 中的浮点数 key 加上 delta，返回新的值。key 不存在时从 0.0 开始，value 不是浮点数时返回错误，
+/// 结果是 NaN 或者无穷大时不修改并返回错误。和 HINCR 一样读取和写入是原子的
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hincrfloat {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(double, tag = "3")]
+    pub delta: f64,
+}
+/// 在 table 中 key 的列表头部依次插入 values，所以 values 在列表中是倒序的（和 Redis 相同）。
+/// key 不存在时创建一个新的列表，value 不是列表时返回错误。return_list 为 false 时返回列表的新长度，
+/// 为 true 时按顺序返回插入之后的整个列表
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Lpush {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "3")]
+    pub values: ::prost::alloc::vec::Vec<Value>,
+    #[prost(bool, tag = "4")]
+    pub return_list: bool,
+}
+/// 在 table 中 key 的列表尾部依次追加 values，其它和 LPUSH 相同
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Rpush {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "3")]
+    pub values: ::prost::alloc::vec::Vec<Value>,
+    #[prost(bool, tag = "4")]
+    pub return_list: bool,
+}
+/// 返回 table 中 key 的列表从 start 到 stop（包括 stop）的元素。和 Redis 一样，负数表示从尾部开始数，
+/// -1 是最后一个元素；超出范围的下标截断到列表的两端。key 不存在时返回空的结果，value 不是列表时返回错误
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Lrange {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(int64, tag = "3")]
+    pub start: i64,
+    #[prost(int64, tag = "4")]
+    pub stop: i64,
+}
+/// 返回 table 中所有的 key，不返回 value。顺序和遍历 table 的顺序相同，
+/// 和 HGETALL 一样结果太大时返回 206 和部分结果
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hkeys {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+}
+/// 返回 table 中所有的 value，不返回 key，其它和 HKEYS 相同
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hvals {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+}
+/// 返回 table 中 key 的个数，不传输 key 和 value
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hlen {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+}
+/// 只有 key 不存在时才写入 pair，返回是否写入。检查和写入是原子的，
+/// 多个客户端同时对同一个 key 执行 HSETNX 时只有一个成功
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hsetnx {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub pair: ::core::option::Option<Kvpair>,
+}
+/// 只有 key 当前的值等于 expected 时才写入 new（compare-and-swap），没有 expected 表示只有 key 不存在时才写入，
+/// 和 HSETNX 相同。返回两个 value：是否写入，以及写入之前 key 的值（key 不存在时为空的 value），
+/// 没有写入时就是 key 当前的值。比较和写入是原子的
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hcas {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "3")]
+    pub expected: ::core::option::Option<Value>,
+    #[prost(message, optional, tag = "4")]
+    pub new: ::core::option::Option<Value>,
+}
+/// 原子地执行一组命令，每个命令的结果按顺序放在 responses 中。执行期间持有这些命令要修改的所有 table 的独占锁，
+/// 命令的修改先暂存起来，后面的命令能读到前面命令的修改，全部成功后用存储的 write_batch 一次性写入。
+/// 某个命令失败（状态码不是 2xx，比如 HGET 不存在的 key）时不再执行后面的命令，什么都不写入：返回失败的命令的状态码，
+/// pairs 中的 failed_index 是它的下标，responses 中是已经执行的命令的结果。
+/// 提交之前其它读取看不到任何修改，提交之后才增加 table 的版本号、唤醒等待这些 key 的 HWAIT，被写入的 key 的修改时间是提交的时间。
+/// MemTable、SledDb 的 write_batch 是原子的，服务器崩溃时要么全部写入要么都没有写入；
+/// RocksDB 不支持在其中设置过期时间，自定义存储没有实现 write_batch 时缺省的实现逐个写入，不是原子的。
+/// 需要连接的信息、改变连接状态或者保存在 Service 中的命令（MULTI、WHOAMI、PAUSETABLE、HSETCHUNK、HWAIT 等）不能在其中执行
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Transaction {
+    #[prost(message, repeated, tag = "1")]
+    pub commands: ::prost::alloc::vec::Vec<CommandRequest>,
+}
+/// 把 table 中 from 的 value 原子地移动到 to 并删除 from，覆盖 to 中已有的值，返回被覆盖的 value
+/// （to 不存在时为空的 value）。from 不存在时返回 404，修改时间和过期时间和 value 一起移动
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Hrename {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub from: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub to: ::prost::alloc::string::String,
+}
+/// 把 src 中的所有 key 复制到 dst，overwrite 为 false 时跳过 dst 中已有的 key，返回复制的 key 数。
+/// 复制的 key 和 HSET 一样没有过期时间。复制不是原子的，也不会唤醒等待 dst 中的 key 的 HWAIT
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Copytable {
+    #[prost(string, tag = "1")]
+    pub src: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub dst: ::prost::alloc::string::String,
+    #[prost(bool, tag = "3")]
+    pub overwrite: bool,
+}
+/// 删除 table 中的所有 key，返回删除的 key 数，不存在的 table 返回 0。
+/// 所有 key 原子地删除，读者要么看到所有的 key，要么一个也看不到。设置了管理员时只有管理员可以执行
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Flushtable {
+    #[prost(string, tag = "1")]
+    pub table: ::prost::alloc::string::String,
+}
+/// 列出所有至少包含一个 key 的 table 的名字，按名字排序
+#[derive(PartialOrd)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Tables {}
+/// HMSET 中重复的 key 的处理方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum DuplicatePolicy {
+    /// 使用最后一个 value
+    LastWins = 0,
+    /// 使用第一个 value
+    FirstWins = 1,
+    /// 返回 400，不写入任何数据
+    Reject = 2,
+}
+impl DuplicatePolicy {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::LastWins => "LAST_WINS",
+            DuplicatePolicy::FirstWins => "FIRST_WINS",
+            DuplicatePolicy::Reject => "REJECT",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "LAST_WINS" => Some(Self::LastWins),
+            "FIRST_WINS" => Some(Self::FirstWins),
+            "REJECT" => Some(Self::Reject),
+            _ => None,
+        }
+    }
+}
+/// HSETIF 使用的比较方式：用新的 value 和 key 当前的 value 比较
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum CompareOp {
+    Gt = 0,
+    Lt = 1,
+    Gte = 2,
+    Lte = 3,
+    Ne = 4,
+}
+impl CompareOp {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            CompareOp::Gt => "GT",
+            CompareOp::Lt => "LT",
+            CompareOp::Gte => "GTE",
+            CompareOp::Lte => "LTE",
+            CompareOp::Ne => "NE",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "GT" => Some(Self::Gt),
+            "LT" => Some(Self::Lt),
+            "GTE" => Some(Self::Gte),
+            "LTE" => Some(Self::Lte),
+            "NE" => Some(Self::Ne),
+            _ => None,
+        }
+    }
+}
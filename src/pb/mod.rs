@@ -4,104 +4,1270 @@ use abi::{command_request::RequestData, *};
 use bytes::Bytes;
 use http::StatusCode;
 use prost::Message;
-use std::fmt::Display;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::Display,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use crate::KvError;
+use crate::{ErrorStats, KvError};
 
 impl CommandRequest {
+    /// 服务器支持的所有命令的名字，顺序和 abi.proto 中 CommandRequest 的 oneof 一致。
+    /// 新增命令时需要同时加到这里，测试会检查它和 abi.proto 是否一致
+    pub const COMMANDS: &'static [&'static str] = &[
+        "HGET",
+        "HGETALL",
+        "HMGET",
+        "HSET",
+        "HMSET",
+        "HDEL",
+        "HMDEL",
+        "HEXIST",
+        "HMEXIST",
+        "COMPACT",
+        "METRICS",
+        "HMOVE",
+        "HMERGE",
+        "HDELEQ",
+        "HMTIME",
+        "FINDBYVALUE",
+        "HINCRFIELD",
+        "FIELDGET",
+        "FIELDSET",
+        "MEMUSAGE",
+        "HSETCHUNK",
+        "HWAIT",
+        "QUIT",
+        "SELFTEST",
+        "WHOAMI",
+        "HSETIF",
+        "COMMANDS",
+        "HGETSET",
+        "PAUSETABLE",
+        "RESUMETABLE",
+        "REPLACETABLE",
+        "DIFFTABLES",
+        "FIELDMERGE",
+        "HMATCH",
+        "TABLEVERSION",
+        "REPLACETABLEIF",
+        "TIME",
+        "MULTI",
+        "EXEC",
+        "DISCARD",
+        "FIELDMGET",
+        "HMINCR",
+        "LOCK",
+        "UNLOCK",
+        "ERRORSTATS",
+        "DELMATCH",
+        "HEXPIRE",
+        "HINCRINIT",
+        "HINCR",
+        "KEYINFO",
+        "HINCRFLOAT",
+        "LPUSH",
+        "RPUSH",
+        "LRANGE",
+        "HKEYS",
+        "HVALS",
+        "HLEN",
+        "HSETNX",
+        "HCAS",
+        "TRANSACTION",
+        "HRENAME",
+        "COPYTABLE",
+        "FLUSHTABLE",
+        "TABLES",
+    ];
+
+    /// 设置请求的 deadline：服务器收到请求 timeout 之后还没有完成时放弃执行，返回 504
+    pub fn with_deadline(mut self, timeout: Duration) -> Self {
+        self.deadline_ms = Some(timeout.as_millis() as u64);
+        self
+    }
+
     /// 创建 HGET 命令
     pub fn new_hget(table: impl Into<String>, key: impl Into<String>) -> Self {
         Self {
-            request_data: Some(RequestData::Hget(Hget {
+            request_data: Some(RequestData::Hget(Hget {
+                table: table.into(),
+                key: key.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HGETALL 命令
+    pub fn new_hgetall(table: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::Hgetall(Hgetall {
+                table: table.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HSET 命令
+    pub fn new_hset(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hset(Hset {
+                table: table.into(),
+                pair: Some(Kvpair::new(key, value)),
+                ttl_secs: 0,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 ttl_secs 秒后过期的 HSET 命令
+    pub fn new_hset_ex(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+        ttl_secs: u64,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hset(Hset {
+                table: table.into(),
+                pair: Some(Kvpair::new(key, value)),
+                ttl_secs,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HDEL 命令
+    pub fn new_hdel(table: impl Into<String>, key: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::Hdel(Hdel {
+                table: table.into(),
+                key: key.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HEXIST 命令
+    pub fn new_hexist(table: impl Into<String>, key: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::Hexist(Hexist {
+                table: table.into(),
+                key: key.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HMGET 命令
+    pub fn new_hmget(table: impl Into<String>, keys: Vec<impl Into<String>>) -> Self {
+        Self {
+            request_data: Some(RequestData::Hmget(Hmget {
+                table: table.into(),
+                keys: keys.into_iter().map(|key| key.into()).collect(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HMSET 命令
+    pub fn new_hmset(table: impl Into<String>, pairs: Vec<impl Into<Kvpair>>) -> Self {
+        Self::new_hmset_with_policy(table, pairs, DuplicatePolicy::LastWins)
+    }
+    /// 创建 HMSET 命令，指定重复的 key 的处理方式
+    pub fn new_hmset_with_policy(
+        table: impl Into<String>,
+        pairs: Vec<impl Into<Kvpair>>,
+        duplicates: DuplicatePolicy,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hmset(Hmset {
+                table: table.into(),
+                pairs: pairs.into_iter().map(|pair| pair.into()).collect(),
+                duplicates: duplicates as i32,
+            })),
+            ..Default::default()
+        }
+    }
+    /// 创建 HMDEL 命令
+    pub fn new_hmdel(table: impl Into<String>, keys: Vec<impl Into<String>>) -> Self {
+        Self {
+            request_data: Some(RequestData::Hmdel(Hmdel {
+                table: table.into(),
+                keys: keys.into_iter().map(|key| key.into()).collect(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HMEXIST 命令
+    pub fn new_hmexist(table: impl Into<String>, keys: Vec<impl Into<String>>) -> Self {
+        Self {
+            request_data: Some(RequestData::Hmexist(Hmexist {
+                table: table.into(),
+                keys: keys.into_iter().map(|key| key.into()).collect(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 COMPACT 命令
+    pub fn new_compact() -> Self {
+        Self {
+            request_data: Some(RequestData::Compact(Compact {})),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 METRICS 命令
+    pub fn new_metrics() -> Self {
+        Self {
+            request_data: Some(RequestData::Metrics(Metrics {})),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HMOVE 命令
+    pub fn new_hmove(
+        from_table: impl Into<String>,
+        to_table: impl Into<String>,
+        key: impl Into<String>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hmove(Hmove {
+                from_table: from_table.into(),
+                to_table: to_table.into(),
+                key: key.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HMERGE 命令
+    pub fn new_hmerge(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+        clock: Vec<ClockEntry>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hmerge(Hmerge {
+                table: table.into(),
+                key: key.into(),
+                value: Some(value.into()),
+                clock,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HDELEQ 命令
+    pub fn new_hdeleq(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        expected: impl Into<Value>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hdeleq(Hdeleq {
+                table: table.into(),
+                key: key.into(),
+                expected: Some(expected.into()),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HMTIME 命令
+    pub fn new_hmtime(table: impl Into<String>, key: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::Hmtime(Hmtime {
+                table: table.into(),
+                key: key.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 FINDBYVALUE 命令
+    pub fn new_findbyvalue(table: impl Into<String>, value: impl Into<Value>) -> Self {
+        Self {
+            request_data: Some(RequestData::Findbyvalue(Findbyvalue {
+                table: table.into(),
+                value: Some(value.into()),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HINCRFIELD 命令
+    pub fn new_hincrfield(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        field: impl Into<String>,
+        delta: i64,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hincrfield(Hincrfield {
+                table: table.into(),
+                key: key.into(),
+                field: field.into(),
+                delta,
+                saturating: false,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建结果溢出时截断到 i64::MIN / i64::MAX 的 HINCRFIELD 命令
+    pub fn new_hincrfield_saturating(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        field: impl Into<String>,
+        delta: i64,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hincrfield(Hincrfield {
+                table: table.into(),
+                key: key.into(),
+                field: field.into(),
+                delta,
+                saturating: true,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 FIELDGET 命令
+    pub fn new_fieldget(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        field: impl Into<String>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Fieldget(Fieldget {
+                table: table.into(),
+                key: key.into(),
+                field: field.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 FIELDSET 命令
+    pub fn new_fieldset(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        field: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Fieldset(Fieldset {
+                table: table.into(),
+                key: key.into(),
+                field: field.into(),
+                value: Some(value.into()),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 MEMUSAGE 命令
+    pub fn new_memusage(table: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::Memusage(Memusage {
+                table: table.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HSETCHUNK 命令
+    pub fn new_hsetchunk(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        upload_id: impl Into<String>,
+        chunk_index: u32,
+        total_chunks: u32,
+        data: impl Into<Bytes>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hsetchunk(Hsetchunk {
+                table: table.into(),
+                key: key.into(),
+                upload_id: upload_id.into(),
+                chunk_index,
+                total_chunks,
+                data: data.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HWAIT 命令
+    pub fn new_hwait(table: impl Into<String>, key: impl Into<String>, timeout_ms: u64) -> Self {
+        Self {
+            request_data: Some(RequestData::Hwait(Hwait {
+                table: table.into(),
+                key: key.into(),
+                timeout_ms,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 QUIT 命令
+    pub fn new_quit() -> Self {
+        Self {
+            request_data: Some(RequestData::Quit(Quit {})),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 SELFTEST 命令
+    pub fn new_selftest() -> Self {
+        Self {
+            request_data: Some(RequestData::Selftest(Selftest {})),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 WHOAMI 命令
+    pub fn new_whoami() -> Self {
+        Self {
+            request_data: Some(RequestData::Whoami(Whoami {})),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HSETIF 命令
+    pub fn new_hsetif(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+        op: CompareOp,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hsetif(Hsetif {
+                table: table.into(),
+                key: key.into(),
+                value: Some(value.into()),
+                op: op as i32,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 COMMANDS 命令
+    pub fn new_commands() -> Self {
+        Self {
+            request_data: Some(RequestData::Commands(Commands {})),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HGETSET 命令
+    pub fn new_hgetset(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hgetset(Hgetset {
+                table: table.into(),
+                key: key.into(),
+                value: Some(value.into()),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 PAUSETABLE 命令
+    pub fn new_pausetable(table: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::Pausetable(Pausetable {
+                table: table.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 RESUMETABLE 命令
+    pub fn new_resumetable(table: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::Resumetable(Resumetable {
+                table: table.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 REPLACETABLE 命令
+    pub fn new_replacetable(table: impl Into<String>, pairs: Vec<Kvpair>) -> Self {
+        Self {
+            request_data: Some(RequestData::Replacetable(Replacetable {
+                table: table.into(),
+                pairs,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 DIFFTABLES 命令
+    pub fn new_difftables(table_a: impl Into<String>, table_b: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::Difftables(Difftables {
+                table_a: table_a.into(),
+                table_b: table_b.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 FIELDMERGE 命令
+    pub fn new_fieldmerge(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        fields: Vec<Kvpair>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Fieldmerge(Fieldmerge {
+                table: table.into(),
+                key: key.into(),
+                fields,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HMATCH 命令
+    pub fn new_hmatch(
+        table: impl Into<String>,
+        pattern: impl Into<String>,
+        with_values: bool,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hmatch(Hmatch {
+                table: table.into(),
+                pattern: pattern.into(),
+                with_values,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 TABLEVERSION 命令
+    pub fn new_tableversion(table: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::Tableversion(Tableversion {
+                table: table.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 REPLACETABLEIF 命令
+    pub fn new_replacetableif(
+        table: impl Into<String>,
+        expected_version: u64,
+        pairs: Vec<impl Into<Kvpair>>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Replacetableif(Replacetableif {
+                table: table.into(),
+                expected_version,
+                pairs: pairs.into_iter().map(Into::into).collect(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 TIME 命令
+    pub fn new_time() -> Self {
+        Self {
+            request_data: Some(RequestData::Time(Time {})),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 MULTI 命令
+    pub fn new_multi() -> Self {
+        Self {
+            request_data: Some(RequestData::Multi(Multi {})),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 EXEC 命令
+    pub fn new_exec() -> Self {
+        Self {
+            request_data: Some(RequestData::Exec(Exec {})),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 DISCARD 命令
+    pub fn new_discard() -> Self {
+        Self {
+            request_data: Some(RequestData::Discard(Discard {})),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 FIELDMGET 命令
+    pub fn new_fieldmget(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        fields: Vec<impl Into<String>>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Fieldmget(Fieldmget {
+                table: table.into(),
+                key: key.into(),
+                fields: fields.into_iter().map(|field| field.into()).collect(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HMINCR 命令，出错的 key 不影响其它 key
+    pub fn new_hmincr(table: impl Into<String>, pairs: Vec<(impl Into<String>, i64)>) -> Self {
+        Self::hmincr(table, pairs, false, false)
+    }
+
+    /// 创建有一个 key 出错时所有 key 都不修改的 HMINCR 命令
+    pub fn new_hmincr_all_or_nothing(
+        table: impl Into<String>,
+        pairs: Vec<(impl Into<String>, i64)>,
+    ) -> Self {
+        Self::hmincr(table, pairs, true, false)
+    }
+
+    /// 创建结果溢出时截断到 i64::MIN / i64::MAX 的 HMINCR 命令
+    pub fn new_hmincr_saturating(
+        table: impl Into<String>,
+        pairs: Vec<(impl Into<String>, i64)>,
+    ) -> Self {
+        Self::hmincr(table, pairs, false, true)
+    }
+
+    fn hmincr(
+        table: impl Into<String>,
+        pairs: Vec<(impl Into<String>, i64)>,
+        all_or_nothing: bool,
+        saturating: bool,
+    ) -> Self {
+        let pairs = pairs.into_iter().map(|(key, delta)| KeyDelta {
+            key: key.into(),
+            delta,
+        });
+        Self {
+            request_data: Some(RequestData::Hmincr(Hmincr {
+                table: table.into(),
+                pairs: pairs.collect(),
+                all_or_nothing,
+                saturating,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 LOCK 命令，ttl_ms 为 0 时锁不过期
+    pub fn new_lock(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        owner: impl Into<String>,
+        ttl_ms: u64,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Lock(Lock {
+                table: table.into(),
+                key: key.into(),
+                owner: owner.into(),
+                ttl_ms,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 UNLOCK 命令
+    pub fn new_unlock(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        owner: impl Into<String>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Unlock(Unlock {
+                table: table.into(),
+                key: key.into(),
+                owner: owner.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 ERRORSTATS 命令，reset 为 true 时返回之后清零
+    pub fn new_errorstats(reset: bool) -> Self {
+        Self {
+            request_data: Some(RequestData::Errorstats(Errorstats { reset })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 DELMATCH 命令
+    pub fn new_delmatch(table: impl Into<String>, pattern: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::Delmatch(Delmatch {
+                table: table.into(),
+                pattern: pattern.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HEXPIRE 命令
+    pub fn new_hexpire(table: impl Into<String>, key: impl Into<String>, ttl_secs: u64) -> Self {
+        Self {
+            request_data: Some(RequestData::Hexpire(Hexpire {
+                table: table.into(),
+                key: key.into(),
+                ttl_secs,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HINCRINIT 命令，init_ttl_ms 为 0 时创建的 key 不过期
+    pub fn new_hincrinit(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        delta: i64,
+        init_ttl_ms: u64,
+    ) -> Self {
+        Self::hincrinit(table, key, delta, init_ttl_ms, false)
+    }
+
+    /// 创建结果溢出时截断到 i64::MIN / i64::MAX 的 HINCRINIT 命令
+    pub fn new_hincrinit_saturating(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        delta: i64,
+        init_ttl_ms: u64,
+    ) -> Self {
+        Self::hincrinit(table, key, delta, init_ttl_ms, true)
+    }
+
+    fn hincrinit(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        delta: i64,
+        init_ttl_ms: u64,
+        saturating: bool,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hincrinit(Hincrinit {
+                table: table.into(),
+                key: key.into(),
+                delta,
+                init_ttl_ms,
+                saturating,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HINCR 命令
+    pub fn new_hincr(table: impl Into<String>, key: impl Into<String>, delta: i64) -> Self {
+        Self::hincr(table, key, delta, false)
+    }
+
+    /// 创建结果溢出时截断到 i64::MIN / i64::MAX 的 HINCR 命令
+    pub fn new_hincr_saturating(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        delta: i64,
+    ) -> Self {
+        Self::hincr(table, key, delta, true)
+    }
+
+    fn hincr(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        delta: i64,
+        saturating: bool,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hincr(Hincr {
+                table: table.into(),
+                key: key.into(),
+                delta,
+                saturating,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 KEYINFO 命令
+    pub fn new_keyinfo(table: impl Into<String>, key: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::Keyinfo(Keyinfo {
                 table: table.into(),
                 key: key.into(),
             })),
+            ..Default::default()
         }
     }
 
-    /// 创建 HGETALL 命令
-    pub fn new_hgetall(table: impl Into<String>) -> Self {
+    /// 创建 HINCRFLOAT 命令
+    pub fn new_hincrfloat(table: impl Into<String>, key: impl Into<String>, delta: f64) -> Self {
         Self {
-            request_data: Some(RequestData::Hgetall(Hgetall {
+            request_data: Some(RequestData::Hincrfloat(Hincrfloat {
                 table: table.into(),
+                key: key.into(),
+                delta,
             })),
+            ..Default::default()
         }
     }
 
-    /// 创建 HSET 命令
-    pub fn new_hset(
+    /// 创建 LPUSH 命令，返回列表的新长度
+    pub fn new_lpush(
         table: impl Into<String>,
         key: impl Into<String>,
-        value: impl Into<Value>,
+        values: Vec<impl Into<Value>>,
     ) -> Self {
         Self {
-            request_data: Some(RequestData::Hset(Hset {
+            request_data: Some(RequestData::Lpush(Lpush {
                 table: table.into(),
-                pair: Some(Kvpair::new(key, value)),
+                key: key.into(),
+                values: values.into_iter().map(|v| v.into()).collect(),
+                return_list: false,
             })),
+            ..Default::default()
         }
     }
 
-    /// 创建 HDEL 命令
-    pub fn new_hdel(table: impl Into<String>, key: impl Into<String>) -> Self {
+    /// 创建 RPUSH 命令，返回列表的新长度
+    pub fn new_rpush(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        values: Vec<impl Into<Value>>,
+    ) -> Self {
         Self {
-            request_data: Some(RequestData::Hdel(Hdel {
+            request_data: Some(RequestData::Rpush(Rpush {
                 table: table.into(),
                 key: key.into(),
+                values: values.into_iter().map(|v| v.into()).collect(),
+                return_list: false,
             })),
+            ..Default::default()
         }
     }
 
-    /// 创建 HEXIST 命令
-    pub fn new_hexist(table: impl Into<String>, key: impl Into<String>) -> Self {
+    /// 创建 LRANGE 命令，start 和 stop 为负数时从尾部开始数
+    pub fn new_lrange(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        start: i64,
+        stop: i64,
+    ) -> Self {
         Self {
-            request_data: Some(RequestData::Hexist(Hexist {
+            request_data: Some(RequestData::Lrange(Lrange {
                 table: table.into(),
                 key: key.into(),
+                start,
+                stop,
             })),
+            ..Default::default()
         }
     }
 
-    /// 创建 HMGET 命令
-    pub fn new_hmget(table: impl Into<String>, keys: Vec<impl Into<String>>) -> Self {
+    /// 创建 HKEYS 命令
+    pub fn new_hkeys(table: impl Into<String>) -> Self {
         Self {
-            request_data: Some(RequestData::Hmget(Hmget {
+            request_data: Some(RequestData::Hkeys(Hkeys {
                 table: table.into(),
-                keys: keys.into_iter().map(|key| key.into()).collect(),
             })),
+            ..Default::default()
         }
     }
 
-    /// 创建 HMSET 命令
-    pub fn new_hmset(table: impl Into<String>, pairs: Vec<impl Into<Kvpair>>) -> Self {
+    /// 创建 HVALS 命令
+    pub fn new_hvals(table: impl Into<String>) -> Self {
         Self {
-            request_data: Some(RequestData::Hmset(Hmset {
+            request_data: Some(RequestData::Hvals(Hvals {
                 table: table.into(),
-                pairs: pairs.into_iter().map(|pair| pair.into()).collect(),
             })),
+            ..Default::default()
         }
     }
-    /// 创建 HMDEL 命令
-    pub fn new_hmdel(table: impl Into<String>, keys: Vec<impl Into<String>>) -> Self {
+
+    /// 创建 HLEN 命令
+    pub fn new_hlen(table: impl Into<String>) -> Self {
         Self {
-            request_data: Some(RequestData::Hmdel(Hmdel {
+            request_data: Some(RequestData::Hlen(Hlen {
                 table: table.into(),
-                keys: keys.into_iter().map(|key| key.into()).collect(),
             })),
+            ..Default::default()
         }
     }
 
-    /// 创建 HMEXIST 命令
-    pub fn new_hmexist(table: impl Into<String>, keys: Vec<impl Into<String>>) -> Self {
+    /// 创建 HSETNX 命令
+    pub fn new_hsetnx(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Self {
         Self {
-            request_data: Some(RequestData::Hmexist(Hmexist {
+            request_data: Some(RequestData::Hsetnx(Hsetnx {
                 table: table.into(),
-                keys: keys.into_iter().map(|key| key.into()).collect(),
+                pair: Some(Kvpair::new(key, value)),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HCAS 命令，expected 为 None 时只有 key 不存在才写入
+    pub fn new_hcas(
+        table: impl Into<String>,
+        key: impl Into<String>,
+        expected: Option<Value>,
+        new: impl Into<Value>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hcas(Hcas {
+                table: table.into(),
+                key: key.into(),
+                expected,
+                new: Some(new.into()),
             })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 TRANSACTION 命令
+    pub fn new_transaction(commands: Vec<CommandRequest>) -> Self {
+        Self {
+            request_data: Some(RequestData::Transaction(Transaction { commands })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 HRENAME 命令
+    pub fn new_hrename(
+        table: impl Into<String>,
+        from: impl Into<String>,
+        to: impl Into<String>,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Hrename(Hrename {
+                table: table.into(),
+                from: from.into(),
+                to: to.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 COPYTABLE 命令
+    pub fn new_copytable(src: impl Into<String>, dst: impl Into<String>, overwrite: bool) -> Self {
+        Self {
+            request_data: Some(RequestData::Copytable(Copytable {
+                src: src.into(),
+                dst: dst.into(),
+                overwrite,
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 FLUSHTABLE 命令
+    pub fn new_flushtable(table: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::Flushtable(Flushtable {
+                table: table.into(),
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// 创建 TABLES 命令
+    pub fn new_tables() -> Self {
+        Self {
+            request_data: Some(RequestData::Tables(Tables {})),
+            ..Default::default()
+        }
+    }
+}
+
+impl Value {
+    /// 给 value 加上内容类型，比如 `Value::from(json).with_content_type("application/json")`
+    pub fn with_content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// 比较两个 value 是否完全相同。和 derive 出来的 PartialEq 不同，浮点数按二进制位比较，
+    /// 因此 NaN 等于它自己，而 0.0 和 -0.0 不相等；不同类型的 value 永远不相等。
+    /// 只比较 value 的内容，不比较 content_type
+    pub fn deep_eq(&self, other: &Value) -> bool {
+        use value::Value::*;
+        match (&self.value, &other.value) {
+            (Some(Float(a)), Some(Float(b))) => a.to_bits() == b.to_bits(),
+            (Some(Register(a)), Some(Register(b))) => {
+                a.clock == b.clock
+                    && a.values.len() == b.values.len()
+                    && a.values.iter().zip(&b.values).all(|(a, b)| a.deep_eq(b))
+            }
+            (Some(List(a)), Some(List(b))) => {
+                a.values.len() == b.values.len()
+                    && a.values.iter().zip(&b.values).all(|(a, b)| a.deep_eq(b))
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    /// 比较两个同类型的标量 value：字符串和二进制按字节序，整数、浮点数按数值，false 小于 true，
+    /// 时间戳按时间的先后。
+    /// 类型不同、浮点数为 NaN，或者是 register、map 这样的复合类型时无法比较，返回 None
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        use value::Value::*;
+        match (self.value.as_ref()?, other.value.as_ref()?) {
+            (String(a), String(b)) => Some(a.cmp(b)),
+            (Binary(a), Binary(b)) => Some(a.cmp(b)),
+            (Integer(a), Integer(b)) => Some(a.cmp(b)),
+            (Float(a), Float(b)) => a.partial_cmp(b),
+            (Bool(a), Bool(b)) => Some(a.cmp(b)),
+            (Timestamp(a), Timestamp(b)) => Some((a.seconds, a.nanos).cmp(&(b.seconds, b.nanos))),
+            _ => None,
+        }
+    }
+}
+
+impl CompareOp {
+    /// ordering 是新的 value 和当前的 value 比较的结果，返回它是否满足这个比较方式
+    pub fn matches(self, ordering: Ordering) -> bool {
+        match self {
+            CompareOp::Gt => ordering.is_gt(),
+            CompareOp::Lt => ordering.is_lt(),
+            CompareOp::Gte => ordering.is_ge(),
+            CompareOp::Lte => ordering.is_le(),
+            CompareOp::Ne => ordering.is_ne(),
+        }
+    }
+}
+
+impl ClockEntry {
+    pub fn new(node: impl Into<String>, counter: u64) -> Self {
+        Self {
+            node: node.into(),
+            counter,
+        }
+    }
+}
+
+/// 两个向量时钟之间的因果关系
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Causality {
+    Before,
+    After,
+    Equal,
+    Concurrent,
+}
+
+impl Register {
+    /// 把一个带向量时钟的写入合并到寄存器中，冲突处理的策略是：
+    /// - 写入的时钟在因果上晚于寄存器的时钟，用写入的值替换掉所有的值
+    /// - 写入的时钟早于或者等于寄存器的时钟，这是一个过时或者重复的写入，忽略它
+    /// - 两个时钟互相并发，保留所有的值，由读者决定如何解决冲突，时钟取两者逐个节点的最大值
+    pub fn merge(&mut self, value: Value, clock: Vec<ClockEntry>) {
+        match compare_clock(&clock, &self.clock) {
+            Causality::After => {
+                self.values = vec![value];
+                self.clock = clock;
+            }
+            Causality::Before | Causality::Equal => {}
+            Causality::Concurrent => {
+                if !self.values.contains(&value) {
+                    self.values.push(value);
+                }
+                for entry in clock {
+                    match self.clock.iter_mut().find(|e| e.node == entry.node) {
+                        Some(e) => e.counter = e.counter.max(entry.counter),
+                        None => self.clock.push(entry),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl From<Value> for Register {
+    /// 普通的 value 可以看作是时钟为空的寄存器，任何带时钟的写入都会覆盖它
+    fn from(v: Value) -> Self {
+        match v.value {
+            Some(value::Value::Register(r)) => r,
+            _ => Self {
+                values: vec![v],
+                clock: vec![],
+            },
+        }
+    }
+}
+
+impl From<Register> for Value {
+    fn from(r: Register) -> Self {
+        Self {
+            value: Some(value::Value::Register(r)),
+            content_type: None,
+        }
+    }
+}
+
+// 计算时钟 a 相对于时钟 b 的因果关系，时钟里没有出现的节点计数为 0
+fn compare_clock(a: &[ClockEntry], b: &[ClockEntry]) -> Causality {
+    let counter = |clock: &[ClockEntry], node: &str| {
+        clock
+            .iter()
+            .find(|e| e.node == node)
+            .map_or(0, |e| e.counter)
+    };
+    let (mut less, mut greater) = (false, false);
+    for node in a.iter().chain(b).map(|e| e.node.as_str()) {
+        match counter(a, node).cmp(&counter(b, node)) {
+            std::cmp::Ordering::Less => less = true,
+            std::cmp::Ordering::Greater => greater = true,
+            std::cmp::Ordering::Equal => {}
+        }
+    }
+    match (less, greater) {
+        (false, false) => Causality::Equal,
+        (false, true) => Causality::After,
+        (true, false) => Causality::Before,
+        (true, true) => Causality::Concurrent,
+    }
+}
+
+impl Map {
+    /// 获取 field 的值
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|f| f.key == field)
+            .and_then(|f| f.value.as_ref())
+    }
+
+    /// 设置 field 的值，返回旧的值，其它 field 保持不变
+    pub fn set(&mut self, field: impl Into<String>, value: impl Into<Value>) -> Option<Value> {
+        let (field, value) = (field.into(), value.into());
+        match self.fields.iter_mut().find(|f| f.key == field) {
+            Some(f) => f.value.replace(value),
+            None => {
+                self.fields.push(Kvpair::new(field, value));
+                None
+            }
+        }
+    }
+
+    /// 给整数 field 加上 delta，field 不存在时从 0 开始，返回新的值。
+    /// 结果溢出时，saturating 为 true 则截断到 i64::MIN / i64::MAX，否则返回错误且不修改 field
+    pub fn incr(&mut self, field: &str, delta: i64, saturating: bool) -> Result<i64, KvError> {
+        let current = match self.get(field) {
+            Some(v) => i64::try_from(v.clone())?,
+            None => 0,
+        };
+        let value = match current.checked_add(delta) {
+            Some(value) => value,
+            None if saturating => current.saturating_add(delta),
+            None => return Err(KvError::IntegerOverflow(current, delta)),
+        };
+        self.set(field, value);
+        Ok(value)
+    }
+}
+
+impl From<Map> for Value {
+    fn from(map: Map) -> Self {
+        Self {
+            value: Some(value::Value::Map(map)),
+            content_type: None,
+        }
+    }
+}
+
+impl TryFrom<Value> for Map {
+    type Error = KvError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v.value {
+            Some(value::Value::Map(map)) => Ok(map),
+            _ => Err(KvError::ConvertError(v, "Map")),
+        }
+    }
+}
+
+impl From<List> for Value {
+    fn from(list: List) -> Self {
+        Self {
+            value: Some(value::Value::List(list)),
+            content_type: None,
+        }
+    }
+}
+
+impl TryFrom<Value> for List {
+    type Error = KvError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        match v.value {
+            Some(value::Value::List(list)) => Ok(list),
+            _ => Err(KvError::ConvertError(v, "List")),
         }
     }
 }
 
+/// 从HashMap转成Value，field 按名字排序
+impl From<HashMap<String, Value>> for Value {
+    fn from(map: HashMap<String, Value>) -> Self {
+        let mut fields: Vec<_> = map.into_iter().map(Kvpair::from).collect();
+        fields.sort_by(|a, b| a.key.cmp(&b.key));
+        Map { fields }.into()
+    }
+}
+
+impl TryFrom<Value> for HashMap<String, Value> {
+    type Error = KvError;
+
+    fn try_from(v: Value) -> Result<Self, Self::Error> {
+        let map = Map::try_from(v)?;
+        Ok(map
+            .fields
+            .into_iter()
+            .map(|f| (f.key, f.value.unwrap_or_default()))
+            .collect())
+    }
+}
+
+impl CommandResponse {
+    /// 服务器即将关闭当前连接，客户端应当重新连接到其它服务器
+    pub fn is_going_away(&self) -> bool {
+        self.status == StatusCode::GONE.as_u16() as u32
+    }
+
+    /// 把响应标记为不完整的结果，客户端可以根据 reason 决定是否继续获取剩下的数据
+    pub fn into_partial(mut self, reason: impl Into<String>) -> Self {
+        self.status = StatusCode::PARTIAL_CONTENT.as_u16() as _;
+        self.message = reason.into();
+        self.partial = true;
+        self
+    }
+}
+
 impl Kvpair {
     // 创建一个新的 kv pair
     pub fn new(key: impl Into<String>, value: impl Into<Value>) -> Self {
@@ -119,11 +1285,46 @@ impl From<(String, Value)> for Kvpair {
     }
 }
 
+/// 从 SystemTime 转成 Timestamp，早于 1970 年的时间 nanos 依旧是非负的
+impl From<SystemTime> for Timestamp {
+    fn from(t: SystemTime) -> Self {
+        let (seconds, nanos) = match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => (d.as_secs() as i64, d.subsec_nanos() as i32),
+            Err(e) => {
+                let d = e.duration();
+                match d.subsec_nanos() {
+                    0 => (-(d.as_secs() as i64), 0),
+                    n => (-(d.as_secs() as i64) - 1, 1_000_000_000 - n as i32),
+                }
+            }
+        };
+        Self { seconds, nanos }
+    }
+}
+
+/// 从 Timestamp 转成 Value
+impl From<Timestamp> for Value {
+    fn from(t: Timestamp) -> Self {
+        Self {
+            value: Some(value::Value::Timestamp(t)),
+            content_type: None,
+        }
+    }
+}
+
+/// 从 SystemTime 转成 Value
+impl From<SystemTime> for Value {
+    fn from(t: SystemTime) -> Self {
+        Timestamp::from(t).into()
+    }
+}
+
 /// 从bool转成Value
 impl From<bool> for Value {
     fn from(v: bool) -> Self {
         Self {
             value: Some(value::Value::Bool(v)),
+            content_type: None,
         }
     }
 }
@@ -133,6 +1334,7 @@ impl From<String> for Value {
     fn from(s: String) -> Self {
         Self {
             value: Some(value::Value::String(s)),
+            content_type: None,
         }
     }
 }
@@ -142,6 +1344,7 @@ impl From<&str> for Value {
     fn from(s: &str) -> Self {
         Self {
             value: Some(value::Value::String(s.into())),
+            content_type: None,
         }
     }
 }
@@ -151,6 +1354,17 @@ impl From<i64> for Value {
     fn from(i: i64) -> Self {
         Self {
             value: Some(value::Value::Integer(i.into())),
+            content_type: None,
+        }
+    }
+}
+
+/// 从f64转成Value
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Self {
+            value: Some(value::Value::Float(f)),
+            content_type: None,
         }
     }
 }
@@ -180,16 +1394,38 @@ impl From<Vec<Kvpair>> for CommandResponse {
 /// 从KvError 转换成 CommandResponse
 impl From<KvError> for CommandResponse {
     fn from(e: KvError) -> Self {
+        ErrorStats::record_current(&e);
         let mut result = Self {
             status: StatusCode::INTERNAL_SERVER_ERROR.as_u16() as _,
             message: e.to_string(),
-            values: vec![],
-            pairs: vec![],
+            ..Default::default()
         };
 
         match e {
             KvError::NotFound(_, _) => result.status = StatusCode::NOT_FOUND.as_u16() as _,
-            KvError::InvaildCommand(_) => result.status = StatusCode::BAD_REQUEST.as_u16() as _,
+            KvError::InvaildCommand(_) | KvError::KeyTooLong(_, _, _) => {
+                result.status = StatusCode::BAD_REQUEST.as_u16() as _
+            }
+            KvError::ResultTooLarge(_, _) => {
+                result.status = StatusCode::PAYLOAD_TOO_LARGE.as_u16() as _
+            }
+            KvError::IntegerOverflow(_, _) => result.status = StatusCode::BAD_REQUEST.as_u16() as _,
+            KvError::QuotaExceeded => result.status = StatusCode::TOO_MANY_REQUESTS.as_u16() as _,
+            KvError::Timeout(_, _) | KvError::DeadlineExceeded => {
+                result.status = StatusCode::GATEWAY_TIMEOUT.as_u16() as _
+            }
+            KvError::StorageFull(_) => {
+                result.status = StatusCode::INSUFFICIENT_STORAGE.as_u16() as _
+            }
+            KvError::Overloaded | KvError::TablePaused(_) => {
+                result.status = StatusCode::SERVICE_UNAVAILABLE.as_u16() as _
+            }
+            KvError::PermissionDenied(_) => result.status = StatusCode::FORBIDDEN.as_u16() as _,
+            KvError::ServerGoingAway => result.status = StatusCode::GONE.as_u16() as _,
+            KvError::Unsupported(_) => result.status = StatusCode::NOT_IMPLEMENTED.as_u16() as _,
+            KvError::VersionConflict(_, _, _) | KvError::LockHeld(_, _) => {
+                result.status = StatusCode::CONFLICT.as_u16() as _
+            }
             _ => {}
         };
 
@@ -271,15 +1507,6 @@ impl TryFrom<&[u8]> for Value {
     }
 }
 
-impl From<(Box<[u8]>, Box<[u8]>)> for Kvpair {
-    fn from(pair: (Box<[u8]>, Box<[u8]>)) -> Self {
-        Kvpair::new(
-            std::str::from_utf8(pair.0.as_ref()).unwrap(),
-            TryInto::<Value>::try_into(pair.1.as_ref()).unwrap(),
-        )
-    }
-}
-
 impl<const N: usize> From<&[u8; N]> for Value {
     fn from(buf: &[u8; N]) -> Self {
         Bytes::copy_from_slice(&buf[..]).into()
@@ -290,19 +1517,97 @@ impl From<Bytes> for Value {
     fn from(buf: Bytes) -> Self {
         Self {
             value: Some(value::Value::Binary(buf)),
+            content_type: None,
+        }
+    }
+}
+
+/// Display 时 binary value 缺省最多显示的字节数
+const BINARY_PREVIEW_LEN: usize = 32;
+
+/// binary value 只显示开头的一部分，避免大块的二进制数据刷屏。显示的字节数可以通过精度指定，
+/// 比如 `{:.64}`；需要完整的内容时使用 `{:?}`
+impl Value {
+    /// value 的类型名：string、binary、integer、float、bool、register、map、timestamp、list，空的 value 是 null。
+    /// 客户端可以据此区分 value 的类型，不需要匹配 prost 生成的枚举
+    pub fn type_name(&self) -> &'static str {
+        match &self.value {
+            Some(value::Value::String(_)) => "string",
+            Some(value::Value::Binary(_)) => "binary",
+            Some(value::Value::Integer(_)) => "integer",
+            Some(value::Value::Float(_)) => "float",
+            Some(value::Value::Bool(_)) => "bool",
+            Some(value::Value::Register(_)) => "register",
+            Some(value::Value::Map(_)) => "map",
+            Some(value::Value::Timestamp(_)) => "timestamp",
+            Some(value::Value::List(_)) => "list",
+            None => "null",
         }
     }
 }
 
+/// 显示为 `类型名(内容)`，比如 `integer(42)`、`string("hello")`，空的 value 显示为 `null`
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &self.value {
-            Some(value) => write!(f, "{:?}", value),
-            None => Ok({}),
+        let Some(value) = &self.value else {
+            return write!(f, "null");
+        };
+        write!(f, "{}(", self.type_name())?;
+        match value {
+            value::Value::String(s) => write!(f, "{s:?}")?,
+            value::Value::Binary(data) => {
+                let limit = f.precision().unwrap_or(BINARY_PREVIEW_LEN);
+                let preview = &data[..data.len().min(limit)];
+                // 全部是可打印的 ASCII 字符时按字符串显示
+                if preview.iter().all(|&c| c.is_ascii_graphic() || c == b' ') {
+                    write!(f, "{:?}", String::from_utf8_lossy(preview))?;
+                } else {
+                    write!(f, "{:?}", preview)?;
+                }
+                if data.len() > limit {
+                    write!(f, "...({} bytes)", data.len())?;
+                }
+            }
+            value::Value::Integer(i) => write!(f, "{i}")?,
+            value::Value::Float(v) => write!(f, "{v:?}")?,
+            value::Value::Bool(b) => write!(f, "{b}")?,
+            value::Value::Register(r) => {
+                write_list(f, ("[", "]"), r.values.iter(), |f, v| write!(f, "{v}"))?
+            }
+            value::Value::Map(m) => {
+                write_list(f, ("{", "}"), m.fields.iter(), |f, pair| {
+                    match &pair.value {
+                        Some(v) => write!(f, "{:?}: {v}", pair.key),
+                        None => write!(f, "{:?}: null", pair.key),
+                    }
+                })?
+            }
+            value::Value::Timestamp(t) => write!(f, "{}.{:09}", t.seconds, t.nanos)?,
+            value::Value::List(l) => {
+                write_list(f, ("[", "]"), l.values.iter(), |f, v| write!(f, "{v}"))?
+            }
         }
+        write!(f, ")")
     }
 }
 
+// 以 `[a, b, c]`（open 和 close 为 `[` 和 `]` 时）的形式显示一组数据
+fn write_list<T>(
+    f: &mut std::fmt::Formatter<'_>,
+    (open, close): (&str, &str),
+    items: impl Iterator<Item = T>,
+    mut write_item: impl FnMut(&mut std::fmt::Formatter<'_>, T) -> std::fmt::Result,
+) -> std::fmt::Result {
+    f.write_str(open)?;
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write_item(f, item)?;
+    }
+    f.write_str(close)
+}
+
 impl Display for Kvpair {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.value.is_some() {
@@ -340,6 +1645,89 @@ impl Display for CommandResponse {
             }
         }
 
+        for (i, res) in self.responses.iter().enumerate() {
+            writeln!(f, "Response {i}:")?;
+            for line in res.to_string().lines() {
+                writeln!(f, "  {line}")?;
+            }
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_names_should_match_proto() {
+        let proto = include_str!("../../abi.proto");
+        let start = proto.find("oneof request_data {").unwrap();
+        let end = start + proto[start..].find('}').unwrap();
+        let names: Vec<_> = proto[start..end]
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().next())
+            .map(|name| name.to_uppercase())
+            .collect();
+        assert_eq!(CommandRequest::COMMANDS, names);
+    }
+
+    #[test]
+    fn binary_value_display_should_be_truncated() {
+        let value: Value = Bytes::from(vec![0u8; 1024]).into();
+        let s = value.to_string();
+        assert!(s.ends_with("...(1024 bytes))"));
+        assert!(s.len() < 200);
+
+        // 可以通过精度指定显示的字节数
+        let value: Value = b"hello world".into();
+        assert_eq!(format!("{value:.5}"), r#"binary("hello"...(11 bytes))"#);
+        assert_eq!(value.to_string(), r#"binary("hello world")"#);
+
+        let value: Value = b"\x00\x01".into();
+        assert_eq!(value.to_string(), "binary([0, 1])");
+
+        // Debug 依旧显示完整的内容
+        let value: Value = Bytes::from(vec![0u8; 1024]).into();
+        assert!(format!("{value:?}").len() > 1024);
+    }
+
+    #[test]
+    fn value_display_should_include_type() {
+        assert_eq!(Value::from(42).to_string(), "integer(42)");
+        assert_eq!(Value::from(1.0).to_string(), "float(1.0)");
+        assert_eq!(Value::from(true).to_string(), "bool(true)");
+        assert_eq!(Value::from("hi").to_string(), r#"string("hi")"#);
+        assert_eq!(Value::default().to_string(), "null");
+        assert_eq!(Value::default().type_name(), "null");
+
+        let mut map = Map::default();
+        map.set("n", 1);
+        let value: Value = map.into();
+        assert_eq!(value.type_name(), "map");
+        assert_eq!(value.to_string(), r#"map({"n": integer(1)})"#);
+
+        let pair = Kvpair::new("k", "v");
+        assert_eq!(pair.to_string(), r#"key: k, value: string("v")"#);
+    }
+
+    #[test]
+    fn timestamp_should_work() {
+        let t = UNIX_EPOCH + Duration::new(1_700_000_000, 5);
+        let value = Value::from(t);
+        assert_eq!(value.type_name(), "timestamp");
+        assert_eq!(value.to_string(), "timestamp(1700000000.000000005)");
+
+        // 早于 1970 年的时间
+        let t = Timestamp::from(UNIX_EPOCH - Duration::from_millis(1500));
+        assert_eq!((t.seconds, t.nanos), (-2, 500_000_000));
+        let t = Timestamp::from(UNIX_EPOCH - Duration::from_secs(1));
+        assert_eq!((t.seconds, t.nanos), (-1, 0));
+
+        // 按时间的先后比较
+        let later = Value::from(UNIX_EPOCH + Duration::new(1_700_000_000, 6));
+        assert_eq!(value.compare(&later), Some(Ordering::Less));
+    }
+}
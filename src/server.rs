@@ -1,7 +1,10 @@
 use anyhow::Result;
-use kv::{MemTable, ProstServerStream, Service, ServiceInner, TlsServerAcceptor};
-use tokio::net::TcpListener;
-use tracing::info;
+use kv::{
+    BoxedStorage, LimitedStore, PeerIdentity, ProstServerStream, Service, ServiceInner,
+    TlsServerAcceptor,
+};
+use tokio::{net::TcpListener, signal, sync::watch};
+use tracing::{info, warn};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -13,15 +16,48 @@ async fn main() -> Result<()> {
     let ca_cert = Some(include_str!("../fixtures/ca.cert"));
 
     let acceptor = TlsServerAcceptor::new(server_cert, server_key, ca_cert)?;
-    let service: Service = ServiceInner::new(MemTable::new()).into();
+    // 通过 KV_STORAGE 选择存储：memory（缺省）、sled:<path> 或者 rocksdb:<path>
+    let storage = std::env::var("KV_STORAGE").unwrap_or_else(|_| "memory".into());
+    let storage = LimitedStore::new(BoxedStorage::open(&storage)?);
+    let service: Service<LimitedStore<BoxedStorage>> = ServiceInner::new(storage).into();
     let listener = TcpListener::bind(addr).await?;
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
     info!("Starting listening on {addr}");
     loop {
-        let tls = acceptor.clone();
-        let (stream, addr) = listener.accept().await?;
-        info!("Client {addr:?} connected");
-        let stream = tls.accept(stream).await?;
-        let stream = ProstServerStream::new(stream, service.clone());
-        tokio::spawn(async move { stream.process().await });
+        tokio::select! {
+            accepted = listener.accept() => {
+                let tls = acceptor.clone();
+                let (stream, addr) = accepted?;
+                info!("Client {addr:?} connected");
+                let service = service.clone();
+                let shutdown_rx = shutdown_rx.clone();
+                // 在单独的 task 中握手，握手很慢的客户端不会阻塞 accept，超时后 task 结束
+                tokio::spawn(async move {
+                    let stream = match tls.accept(stream).await {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            warn!("Failed to accept client {addr:?}: {e}");
+                            return Ok(());
+                        }
+                    };
+                    let peer = PeerIdentity::from_tls(&stream);
+                    ProstServerStream::new(stream, service)
+                        .with_shutdown(shutdown_rx)
+                        .with_peer_identity(peer)
+                        .process()
+                        .await
+                });
+            }
+            _ = signal::ctrl_c() => {
+                info!("Shutting down, notifying all connections");
+                shutdown_tx.send(true)?;
+                break;
+            }
+        }
     }
+
+    // 等待所有连接处理完关闭信号
+    drop(shutdown_rx);
+    shutdown_tx.closed().await;
+    Ok(())
 }
@@ -0,0 +1,85 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+use crate::command_request::RequestData;
+
+/// 命令别名表：已经废弃的命令，以及替代它的命令。
+///
+/// 修改一个命令的行为时，新增一个命令（比如 HgetV2）承载新的行为，旧的命令加入这张表，
+/// 并在 `resolve` 中把旧命令的参数转换成新命令，这样老的客户端不需要任何修改。
+/// 目前还没有废弃的命令
+pub const DEPRECATED_COMMANDS: &[(&str, &str)] = &[];
+
+/// 同一个废弃命令的警告最多每隔这么久打印一次，避免刷屏
+const WARN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// 在 dispatch 之前把废弃的命令转换成替代它的命令，其它命令原样返回
+pub(crate) fn resolve(data: RequestData) -> RequestData {
+    // 新增别名时在这里加上转换，比如：
+    // RequestData::Hget(v) => {
+    //     warner().warn("Hget", "HgetV2");
+    //     RequestData::HgetV2(v.into())
+    // }
+    data
+}
+
+// 目前没有废弃的命令，暂时没有用到
+#[allow(dead_code)]
+fn warner() -> &'static DeprecationWarner {
+    static WARNER: OnceLock<DeprecationWarner> = OnceLock::new();
+    WARNER.get_or_init(|| DeprecationWarner::new(WARN_INTERVAL))
+}
+
+/// 限制废弃警告的频率：每个废弃命令在 interval 内最多打印一次警告
+#[derive(Debug)]
+pub struct DeprecationWarner {
+    interval: Duration,
+    last_warned: Mutex<HashMap<&'static str, Instant>>,
+}
+
+impl DeprecationWarner {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_warned: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 打印 deprecated 命令已经废弃的警告，返回这次是否真的打印了
+    pub fn warn(&self, deprecated: &'static str, replacement: &'static str) -> bool {
+        let mut last_warned = self.last_warned.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        match last_warned.get(deprecated) {
+            Some(last) if now.duration_since(*last) < self.interval => false,
+            _ => {
+                last_warned.insert(deprecated, now);
+                warn!("Command {deprecated} is deprecated, please use {replacement} instead");
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn deprecation_warning_should_be_rate_limited() {
+        let warner = DeprecationWarner::new(Duration::from_millis(20));
+        assert!(warner.warn("Hget", "HgetV2"));
+        assert!(!warner.warn("Hget", "HgetV2"));
+        // 不同的命令分别计算
+        assert!(warner.warn("Hset", "HsetV2"));
+
+        thread::sleep(Duration::from_millis(30));
+        assert!(warner.warn("Hget", "HgetV2"));
+    }
+}
@@ -1,4 +1,12 @@
-use crate::*;
+use crate::{storage::now_millis, *};
+use http::StatusCode;
+use prost::Message;
+use std::{
+    cmp,
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant, SystemTime},
+};
 
 impl CommandService for Hget {
     fn execute(self, store: &impl Storage) -> CommandResponse {
@@ -12,42 +20,183 @@ impl CommandService for Hget {
 
 impl CommandService for Hmget {
     fn execute(self, store: &impl Storage) -> CommandResponse {
-        self.keys
+        match store.get_batch(&self.table, &self.keys) {
+            Ok(values) => values
+                .into_iter()
+                .map(Option::unwrap_or_default)
+                .collect::<Vec<_>>()
+                .into(),
+            Err(e) => self.execute_each(store, e),
+        }
+    }
+}
+
+impl Hmget {
+    // 一次读取失败后逐个读取，只要有 key 读取成功，就返回部分结果，读取失败的 key 返回空的 value
+    fn execute_each(self, store: &impl Storage, e: KvError) -> CommandResponse {
+        let results: Vec<_> = self
+            .keys
             .iter()
-            .map(|key| match store.get(&self.table, key) {
-                Ok(Some(v)) => v,
-                _ => Value::default(),
-            })
-            .collect::<Vec<_>>()
-            .into()
+            .map(|key| store.get(&self.table, key))
+            .collect();
+        if results.iter().all(Result::is_err) {
+            return e.into();
+        }
+        let mut failed = Vec::new();
+        let mut values = Vec::with_capacity(results.len());
+        for (key, result) in self.keys.into_iter().zip(results) {
+            match result {
+                Ok(v) => values.push(v.unwrap_or_default()),
+                Err(_) => {
+                    failed.push(key);
+                    values.push(Value::default());
+                }
+            }
+        }
+        CommandResponse::from(values).into_partial(format!(
+            "Failed to read keys of table {}: {}",
+            self.table,
+            failed.join(", ")
+        ))
     }
 }
 
 impl CommandService for Hgetall {
     fn execute(self, store: &impl Storage) -> CommandResponse {
-        match store.get_all(&self.table) {
-            Ok(v) => v.into(),
-            Err(e) => e.into(),
+        self.execute_with_limit(store, DEFAULT_MAX_RESULT_SIZE, None)
+    }
+}
+
+// 遍历 table 的命令每处理这么多个 key 检查一次 deadline
+const DEADLINE_CHECK_INTERVAL: usize = 256;
+
+// 是否已经超过了请求的 deadline
+fn deadline_exceeded(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|d| Instant::now() >= d)
+}
+
+impl Hgetall {
+    /// 边遍历 table 边构建结果，编码后的结果一旦超过 limit 字节就停止，返回 206 和已经得到的部分结果，
+    /// 避免为一个巨大的 table 构建出远超 frame 大小限制的响应。遍历时超过 deadline 返回 504
+    pub fn execute_with_limit(
+        self,
+        store: &impl Storage,
+        limit: usize,
+        deadline: Option<Instant>,
+    ) -> CommandResponse {
+        let iter = match store.get_iter(&self.table) {
+            Ok(iter) => iter,
+            Err(e) => return e.into(),
+        };
+        let mut size = 0;
+        let mut pairs = Vec::new();
+        for (i, pair) in iter.enumerate() {
+            if i % DEADLINE_CHECK_INTERVAL == 0 && deadline_exceeded(deadline) {
+                return KvError::DeadlineExceeded.into();
+            }
+            size += pair.encoded_len();
+            if size > limit {
+                return CommandResponse::from(pairs)
+                    .into_partial(KvError::ResultTooLarge(self.table.clone(), limit).to_string());
+            }
+            pairs.push(pair);
+        }
+        pairs.into()
+    }
+}
+
+impl CommandService for Hkeys {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        self.execute_with_limit(store, DEFAULT_MAX_RESULT_SIZE, None)
+    }
+}
+
+impl Hkeys {
+    /// 和 HGETALL 一样限制结果的大小，超过 deadline 时返回 504
+    pub fn execute_with_limit(
+        self,
+        store: &impl Storage,
+        limit: usize,
+        deadline: Option<Instant>,
+    ) -> CommandResponse {
+        collect_values(store, self.table, limit, deadline, |pair| pair.key.into())
+    }
+}
+
+impl CommandService for Hvals {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        self.execute_with_limit(store, DEFAULT_MAX_RESULT_SIZE, None)
+    }
+}
+
+impl Hvals {
+    /// 和 HGETALL 一样限制结果的大小，超过 deadline 时返回 504
+    pub fn execute_with_limit(
+        self,
+        store: &impl Storage,
+        limit: usize,
+        deadline: Option<Instant>,
+    ) -> CommandResponse {
+        collect_values(store, self.table, limit, deadline, |pair| {
+            pair.value.unwrap_or_default()
+        })
+    }
+}
+
+// 遍历 table，把每个 kv pair 转换成一个 value，编码后的结果超过 limit 字节时返回 206 和部分结果
+fn collect_values(
+    store: &impl Storage,
+    table: String,
+    limit: usize,
+    deadline: Option<Instant>,
+    f: impl Fn(Kvpair) -> Value,
+) -> CommandResponse {
+    let iter = match store.get_iter(&table) {
+        Ok(iter) => iter,
+        Err(e) => return e.into(),
+    };
+    let mut size = 0;
+    let mut values = Vec::new();
+    for (i, pair) in iter.enumerate() {
+        if i % DEADLINE_CHECK_INTERVAL == 0 && deadline_exceeded(deadline) {
+            return KvError::DeadlineExceeded.into();
+        }
+        let value = f(pair);
+        size += value.encoded_len();
+        if size > limit {
+            return CommandResponse::from(values)
+                .into_partial(KvError::ResultTooLarge(table.clone(), limit).to_string());
         }
+        values.push(value);
     }
+    values.into()
 }
 
 impl CommandService for Hset {
     fn execute(self, store: &impl Storage) -> CommandResponse {
-        match self.pair {
-            Some(v) => match store.set(&self.table, v.key, v.value.unwrap_or_default()) {
-                Ok(Some(v)) => v.into(),
-                Ok(None) => Value::default().into(),
-                Err(e) => e.into(),
-            },
-            None => Value::default().into(),
+        let Some(pair) = self.pair else {
+            return Value::default().into();
+        };
+        let value = pair.value.unwrap_or_default();
+        // value 和过期时间一起写入，存储不支持过期时什么都不写入
+        let result = match self.ttl_secs {
+            0 => store.set(&self.table, pair.key, value),
+            secs => store.set_with_ttl(&self.table, pair.key, value, Duration::from_secs(secs)),
+        };
+        match result {
+            Ok(old) => old.unwrap_or_default().into(),
+            Err(e) => e.into(),
         }
     }
 }
 
 impl CommandService for Hmset {
     fn execute(self, store: &impl Storage) -> CommandResponse {
-        let pairs = self.pairs;
+        let duplicates = self.duplicates();
+        let pairs = match dedup_hmset_pairs(self.pairs, duplicates) {
+            Ok(pairs) => pairs,
+            Err(e) => return e.into(),
+        };
         let table = self.table;
         pairs
             .into_iter()
@@ -62,6 +211,41 @@ impl CommandService for Hmset {
     }
 }
 
+// 按 policy 去掉重复的 key，保留每个 key 第一次出现的位置。REJECT 在写入之前就返回错误
+fn dedup_hmset_pairs(pairs: Vec<Kvpair>, policy: DuplicatePolicy) -> Result<Vec<Kvpair>, KvError> {
+    let mut positions = HashMap::new();
+    let mut result: Vec<Kvpair> = Vec::with_capacity(pairs.len());
+    for pair in pairs {
+        match positions.get(&pair.key) {
+            None => {
+                positions.insert(pair.key.clone(), result.len());
+                result.push(pair);
+            }
+            Some(&i) => match policy {
+                DuplicatePolicy::LastWins => result[i].value = pair.value,
+                DuplicatePolicy::FirstWins => {}
+                DuplicatePolicy::Reject => {
+                    return Err(KvError::InvaildCommand(format!(
+                        "Duplicate key {} in HMSET",
+                        pair.key
+                    )))
+                }
+            },
+        }
+    }
+    Ok(result)
+}
+
+impl CommandService for Hexpire {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let ttl = Duration::from_secs(self.ttl_secs);
+        match store.expire(&self.table, &self.key, ttl) {
+            Ok(exists) => Value::from(exists).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
 impl CommandService for Hdel {
     fn execute(self, store: &impl Storage) -> CommandResponse {
         match store.del(&self.table, &self.key) {
@@ -107,181 +291,2642 @@ impl CommandService for Hmexist {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{assert_res_error, assert_res_ok, command_request::RequestData};
+impl CommandService for Compact {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.compact() {
+            Ok(n) => Value::from(n as i64).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
 
-    #[test]
-    fn hset_should_work() {
-        let store = MemTable::new();
-        let cmd = CommandRequest::new_hset("table", "hello", "world");
-        let res = dispatch(cmd.clone(), &store);
-        // 第一次插入返回之前的值为空
-        assert_res_ok(res, &[Value::default()], &[]);
+impl CommandService for Hmove {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.move_key(&self.from_table, &self.to_table, &self.key) {
+            Ok(Some(v)) => v.into(),
+            Ok(None) => KvError::NotFound(self.from_table, self.key).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
 
-        let res = dispatch(cmd, &store);
-        // 再次插入返回之前的值
-        assert_res_ok(res, &["world".into()], &[]);
+impl CommandService for Hmerge {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let value = self.value.unwrap_or_default();
+        let result = store.update(&self.table, &self.key, |old| {
+            let mut register = match old {
+                Some(v) => Register::from(v.clone()),
+                None => Register::default(),
+            };
+            register.merge(value.clone(), self.clock.clone());
+            Some(register.into())
+        });
+        match result {
+            Ok(v) => v.unwrap_or_default().into(),
+            Err(e) => e.into(),
+        }
     }
+}
 
-    #[test]
-    fn hget_should_work() {
-        let store = MemTable::new();
-        let cmd = CommandRequest::new_hset("score", "u1", 10);
-        dispatch(cmd, &store);
-        let cmd = CommandRequest::new_hget("score", "u1");
-        let res = dispatch(cmd, &store);
-        assert_res_ok(res, &[10.into()], &[]);
+impl CommandService for Hdeleq {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let expected = self.expected.unwrap_or_default();
+        let mut deleted = false;
+        // 比较和删除在同一个原子操作中完成，不会误删别人刚刚更新的值
+        let result = store.update(&self.table, &self.key, |old| {
+            deleted = old.is_some_and(|v| v.deep_eq(&expected));
+            match deleted {
+                true => None,
+                false => old.cloned(),
+            }
+        });
+        match result {
+            Ok(_) => Value::from(deleted).into(),
+            Err(e) => e.into(),
+        }
     }
+}
 
-    #[test]
-    fn hdel_should_work() {
-        let store = MemTable::new();
-        let cmd = CommandRequest::new_hset("table", "key", 10);
-        dispatch(cmd, &store);
+impl CommandService for Hmtime {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.mtime(&self.table, &self.key) {
+            Ok(Some(v)) => Value::from(v).into(),
+            Ok(None) => KvError::NotFound(self.table, self.key).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
 
-        let cmd = CommandRequest::new_hdel("table", "key");
-        let res = dispatch(cmd, &store);
-        assert_res_ok(res, &[10.into()], &[]);
+impl CommandService for Findbyvalue {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.find_by_value(&self.table, &self.value.unwrap_or_default()) {
+            Ok(keys) => keys.into_iter().map(Value::from).collect::<Vec<_>>().into(),
+            Err(e) => e.into(),
+        }
+    }
+}
 
-        let cmd = CommandRequest::new_hexist("table", "key");
-        let res = dispatch(cmd, &store);
-        assert_res_ok(res, &[false.into()], &[]);
+impl CommandService for Hincrfield {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let mut result = Ok(0);
+        let updated = store.update(&self.table, &self.key, |old| {
+            let map = match old {
+                Some(v) => Map::try_from(v.clone()),
+                None => Ok(Map::default()),
+            };
+            let incremented = map.and_then(|mut map| {
+                let value = map.incr(&self.field, self.delta, self.saturating)?;
+                Ok((value, map))
+            });
+            // 类型不匹配时保持原来的值不变，把错误带出来
+            match incremented {
+                Ok((value, map)) => {
+                    result = Ok(value);
+                    Some(map.into())
+                }
+                Err(e) => {
+                    result = Err(e);
+                    old.cloned()
+                }
+            }
+        });
+        match updated.and(result) {
+            Ok(v) => Value::from(v).into(),
+            Err(e) => e.into(),
+        }
     }
+}
 
-    #[test]
-    fn hexist_should_work() {
-        let store = MemTable::new();
-        // 不存在的 key 应返回 false
-        let cmd = CommandRequest::new_hexist("table", "key");
-        let res = dispatch(cmd, &store);
-        assert_res_ok(res, &[false.into()], &[]);
+impl CommandService for Hmincr {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match self.all_or_nothing {
+            true => self.incr_all(store),
+            false => self.incr_each(store),
+        }
+    }
+}
 
-        // 存在的 key 返回 true
-        let cmd = CommandRequest::new_hset("table", "key", 10);
-        dispatch(cmd, &store);
-        let cmd = CommandRequest::new_hexist("table", "key");
-        let res = dispatch(cmd, &store);
-        assert_res_ok(res, &[true.into()], &[]);
+impl Hmincr {
+    // 每个 key 单独修改，出错的 key 返回空的 value。所有 key 都出错时返回第一个错误
+    fn incr_each(self, store: &impl Storage) -> CommandResponse {
+        let mut values = Vec::with_capacity(self.pairs.len());
+        let mut failed = Vec::new();
+        let mut error = None;
+        for pair in &self.pairs {
+            let mut result = Ok(0);
+            let updated = store.update(&self.table, &pair.key, |old| {
+                result = incr_value(old, pair.delta, self.saturating);
+                // 出错时保持原来的值不变
+                match &result {
+                    Ok(v) => Some((*v).into()),
+                    Err(_) => old.cloned(),
+                }
+            });
+            match updated.and(result) {
+                Ok(v) => values.push(v.into()),
+                Err(e) => {
+                    values.push(Value::default());
+                    failed.push(pair.key.as_str());
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        match error {
+            None => values.into(),
+            Some(e) if failed.len() == self.pairs.len() => e.into(),
+            Some(e) => CommandResponse::from(values).into_partial(format!(
+                "Failed to increment keys of table {}: {} ({e})",
+                self.table,
+                failed.join(", ")
+            )),
+        }
     }
 
-    #[test]
-    fn hget_with_non_exist_should_return_404() {
-        let store = MemTable::new();
-        let cmd = CommandRequest::new_hget("non exist table", "non exist key");
-        let res = dispatch(cmd, &store);
-        assert_res_error(res, 404, "Not found");
+    // 先算出所有 key 的新值，都没有出错才写入
+    fn incr_all(self, store: &impl Storage) -> CommandResponse {
+        let keys: Vec<_> = self.pairs.iter().map(|pair| pair.key.as_str()).collect();
+        let olds = match store.get_batch(&self.table, &keys) {
+            Ok(olds) => olds,
+            Err(e) => return e.into(),
+        };
+        let mut current: HashMap<&str, i64> = HashMap::new();
+        let mut values: Vec<Value> = Vec::with_capacity(keys.len());
+        for ((key, pair), old) in keys.iter().zip(&self.pairs).zip(olds) {
+            // 同一个 key 出现多次时在前一次的结果上累加
+            let old = match current.get(key) {
+                Some(&v) => Some(Value::from(v)),
+                None => old,
+            };
+            match incr_value(old.as_ref(), pair.delta, self.saturating) {
+                Ok(v) => {
+                    current.insert(*key, v);
+                    values.push(v.into());
+                }
+                Err(e) => return e.into(),
+            }
+        }
+        // 用 update 写入算好的值，保留 key 原来的过期时间
+        for (key, value) in current {
+            if let Err(e) = store.update(&self.table, key, |_| Some(value.into())) {
+                return e.into();
+            }
+        }
+        values.into()
     }
+}
 
-    #[test]
-    fn hgetall_should_work() {
-        let store = MemTable::new();
-        let cmds = vec![
-            CommandRequest::new_hset("score", "u1", 10),
-            CommandRequest::new_hset("score", "u2", 9),
-            CommandRequest::new_hset("score", "u3", 23),
-            CommandRequest::new_hset("score", "u1", 5),
-        ];
-        for cmd in cmds {
-            dispatch(cmd, &store);
+impl CommandService for Hincr {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let mut result = Ok(0);
+        // 在 update 中读取和写入，其它写入不会穿插在中间
+        let updated = store.update(&self.table, &self.key, |old| {
+            result = incr_value(old, self.delta, self.saturating);
+            match &result {
+                Ok(v) => Some((*v).into()),
+                Err(_) => old.cloned(),
+            }
+        });
+        match updated.and(result) {
+            Ok(v) => Value::from(v).into(),
+            Err(e) => e.into(),
         }
+    }
+}
 
-        let cmd = CommandRequest::new_hgetall("score");
-        let res = dispatch(cmd, &store);
-        let pairs = &[
-            Kvpair::new("u1", 5),
-            Kvpair::new("u2", 9),
-            Kvpair::new("u3", 23),
-        ];
-        assert_res_ok(res, &[], pairs);
+impl CommandService for Hincrfloat {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let mut result = Ok(0.0);
+        let updated = store.update(&self.table, &self.key, |old| {
+            result = incr_float(old, self.delta);
+            match &result {
+                Ok(v) => Some((*v).into()),
+                Err(_) => old.cloned(),
+            }
+        });
+        match updated.and(result) {
+            Ok(v) => Value::from(v).into(),
+            Err(e) => e.into(),
+        }
     }
+}
 
-    #[test]
-    fn hmget_should_work() {
-        let store = MemTable::new();
-        let cmds = vec![
-            CommandRequest::new_hset("table", "key1", 1),
-            CommandRequest::new_hset("table", "key2", 2),
-            CommandRequest::new_hset("table", "key3", 3),
-            CommandRequest::new_hset("table", "key4", 4),
-        ];
-        for cmd in cmds {
-            dispatch(cmd, &store);
+impl CommandService for Hincrinit {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let mut created = false;
+        let mut result = Ok(0);
+        // update 保留已有的 key 的过期时间
+        let updated = store.update(&self.table, &self.key, |old| {
+            created = old.is_none();
+            result = incr_value(old, self.delta, self.saturating);
+            match &result {
+                Ok(v) => Some((*v).into()),
+                Err(_) => old.cloned(),
+            }
+        });
+        let value = match updated.and(result) {
+            Ok(v) => v,
+            Err(e) => return e.into(),
+        };
+        if created && self.init_ttl_ms > 0 {
+            let ttl = Duration::from_millis(self.init_ttl_ms);
+            if let Err(e) = store.expire(&self.table, &self.key, ttl) {
+                // 存储不支持过期时删除刚创建的 key，不留下一个不会过期的计数器
+                let _ = store.del(&self.table, &self.key);
+                return e.into();
+            }
         }
+        Value::from(value).into()
+    }
+}
 
-        let cmd = CommandRequest::new_hmget(
-            "table",
-            vec!["key1", "key2", "not exist key", "key3", "key4"],
-        );
-        let res = dispatch(cmd, &store);
-        assert_res_ok(
-            res,
-            &[
-                1.into(),
-                2.into(),
-                pb::abi::Value { value: None },
-                3.into(),
-                4.into(),
-            ],
-            &[],
-        );
+// 整数 value 加上 delta，value 不存在时从 0 开始。溢出时 saturating 为 true 则截断到 i64::MIN / i64::MAX，
+// 否则返回错误
+fn incr_value(old: Option<&Value>, delta: i64, saturating: bool) -> Result<i64, KvError> {
+    let current = match old {
+        Some(v) => i64::try_from(v.clone())?,
+        None => 0,
+    };
+    match current.checked_add(delta) {
+        Some(v) => Ok(v),
+        None if saturating => Ok(current.saturating_add(delta)),
+        None => Err(KvError::IntegerOverflow(current, delta)),
     }
+}
 
-    #[test]
-    fn hmset_should_work() {
-        let store = MemTable::new();
-        let pairs = &[
-            Kvpair::new("key1", 1),
-            Kvpair::new("key2", 2),
-            Kvpair::new("key3", 3),
-            Kvpair::new("key4", 4),
-        ];
+// 浮点数 value 加上 delta，value 不存在时从 0.0 开始，结果不是有限的数时返回错误
+fn incr_float(old: Option<&Value>, delta: f64) -> Result<f64, KvError> {
+    let current = match old {
+        Some(v) => f64::try_from(v.clone())?,
+        None => 0.0,
+    };
+    let result = current + delta;
+    if !result.is_finite() {
+        return Err(KvError::InvaildCommand(format!(
+            "Adding {delta} to {current} is not a finite number"
+        )));
+    }
+    Ok(result)
+}
 
-        let cmd = CommandRequest::new_hmset("table", pairs.into());
-        dispatch(cmd, &store);
+impl CommandService for Lpush {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        push(
+            store,
+            &self.table,
+            &self.key,
+            self.values,
+            true,
+            self.return_list,
+        )
+    }
+}
 
-        let cmd = CommandRequest::new_hgetall("table");
-        let res = dispatch(cmd, &store);
-        assert_res_ok(res, &[], pairs);
+impl CommandService for Rpush {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        push(
+            store,
+            &self.table,
+            &self.key,
+            self.values,
+            false,
+            self.return_list,
+        )
     }
+}
 
-    #[test]
-    fn hmdel_should_work() {
-        let store = MemTable::new();
-        let cmds = vec![
-            CommandRequest::new_hset("table", "key1", 1),
-            CommandRequest::new_hset("table", "key2", 2),
-            CommandRequest::new_hset("table", "key3", 3),
-            CommandRequest::new_hset("table", "key4", 4),
-        ];
-        for cmd in cmds {
-            dispatch(cmd, &store);
+// 在 update 中修改列表，并发的插入不会丢失。front 为 true 时依次插入到头部
+fn push(
+    store: &impl Storage,
+    table: &str,
+    key: &str,
+    values: Vec<Value>,
+    front: bool,
+    return_list: bool,
+) -> CommandResponse {
+    let mut result = Ok(List::default());
+    let updated = store.update(table, key, |old| {
+        result = match old {
+            Some(v) => List::try_from(v.clone()),
+            None => Ok(List::default()),
+        };
+        let Ok(list) = &mut result else {
+            return old.cloned();
+        };
+        if front {
+            list.values.splice(0..0, values.iter().rev().cloned());
+        } else {
+            list.values.extend(values.iter().cloned());
         }
-
-        let cmd = CommandRequest::new_hmdel("table", vec!["key1", "key2", "key3", "key4"]);
-        let res = dispatch(cmd, &store);
-        assert_res_ok(res, &[1.into(), 2.into(), 3.into(), 4.into()], &[]);
+        Some(list.clone().into())
+    });
+    match updated.and(result) {
+        Ok(list) if return_list => list.values.into(),
+        Ok(list) => Value::from(list.values.len() as i64).into(),
+        Err(e) => e.into(),
     }
+}
 
-    #[test]
-    fn hmexist_should_work() {
-        let store = MemTable::new();
-        let cmds = vec![
-            CommandRequest::new_hset("table", "key1", 1),
-            CommandRequest::new_hset("table", "key3", 3),
-        ];
-        for cmd in cmds {
-            dispatch(cmd, &store);
+impl CommandService for Lrange {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let list = match store.get(&self.table, &self.key) {
+            Ok(Some(v)) => List::try_from(v),
+            Ok(None) => Ok(List::default()),
+            Err(e) => Err(e),
+        };
+        match list {
+            Ok(mut list) => {
+                let range = list_range(list.values.len(), self.start, self.stop);
+                list.values.drain(range).collect::<Vec<_>>().into()
+            }
+            Err(e) => e.into(),
+        }
+    }
+}
+
+// LRANGE 的 start 和 stop 对应的下标范围，负数从尾部开始数，超出范围时截断，start 在 stop 之后时为空
+fn list_range(len: usize, start: i64, stop: i64) -> std::ops::Range<usize> {
+    let len = len as i64;
+    let index = |i: i64| if i < 0 { len + i } else { i };
+    let start = index(start).max(0);
+    let stop = index(stop).min(len - 1);
+    if start > stop {
+        return 0..0;
+    }
+    start as usize..stop as usize + 1
+}
+
+impl CommandService for Fieldget {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let map = match store.get(&self.table, &self.key) {
+            Ok(Some(v)) => Map::try_from(v),
+            Ok(None) => Err(KvError::NotFound(self.table, self.key)),
+            Err(e) => Err(e),
+        };
+        match map.map(|map| map.get(&self.field).cloned()) {
+            Ok(Some(v)) => v.into(),
+            Ok(None) => Value::default().into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Fieldmget {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let map = match store.get(&self.table, &self.key) {
+            Ok(Some(v)) => Map::try_from(v),
+            Ok(None) => Err(KvError::NotFound(self.table, self.key)),
+            Err(e) => Err(e),
+        };
+        match map {
+            Ok(map) => self
+                .fields
+                .iter()
+                .map(|field| map.get(field).cloned().unwrap_or_default())
+                .collect::<Vec<_>>()
+                .into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Fieldset {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let value = self.value.unwrap_or_default();
+        let mut result = Ok(None);
+        let updated = store.update(&self.table, &self.key, |old| {
+            let map = match old {
+                Some(v) => Map::try_from(v.clone()),
+                None => Ok(Map::default()),
+            };
+            // 不是 map 时保持原来的值不变，把错误带出来
+            match map {
+                Ok(mut map) => {
+                    result = Ok(map.set(&self.field, value.clone()));
+                    Some(map.into())
+                }
+                Err(e) => {
+                    result = Err(e);
+                    old.cloned()
+                }
+            }
+        });
+        match updated.and(result) {
+            Ok(Some(v)) => v.into(),
+            Ok(None) => Value::default().into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Fieldmerge {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let mut result = Ok(0);
+        let updated = store.update(&self.table, &self.key, |old| {
+            let map = match old {
+                Some(v) => Map::try_from(v.clone()),
+                None => Ok(Map::default()),
+            };
+            // 和 FIELDSET 一样，不是 map 时保持原来的值不变
+            match map {
+                Ok(mut map) => {
+                    for field in &self.fields {
+                        map.set(&field.key, field.value.clone().unwrap_or_default());
+                    }
+                    result = Ok(map.fields.len() as i64);
+                    Some(map.into())
+                }
+                Err(e) => {
+                    result = Err(e);
+                    old.cloned()
+                }
+            }
+        });
+        match updated.and(result) {
+            Ok(n) => Value::from(n).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Lock {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        // 空的 owner 表示锁是空闲的
+        if self.owner.is_empty() {
+            return KvError::InvaildCommand("Owner of a lock can not be empty".into()).into();
+        }
+        let now = now_millis();
+        let mut result = Ok(0);
+        let updated = store.update(&self.table, &self.key, |old| {
+            let mut lock = match LockState::try_from(old) {
+                Ok(lock) => lock,
+                Err(e) => {
+                    result = Err(e);
+                    return old.cloned();
+                }
+            };
+            if lock.is_held(now) && lock.owner != self.owner {
+                result = Err(KvError::LockHeld(self.table.clone(), self.key.clone()));
+                return old.cloned();
+            }
+            // 持有者再次获取时只更新过期时间
+            if !lock.is_held(now) {
+                lock.owner = self.owner.clone();
+                lock.token += 1;
+            }
+            lock.expires_at = match self.ttl_ms {
+                0 => 0,
+                ttl => now.saturating_add(ttl as i64),
+            };
+            result = Ok(lock.token);
+            Some(lock.into())
+        });
+        match updated.and(result) {
+            Ok(token) => Value::from(token).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Unlock {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let now = now_millis();
+        let mut result = Ok(false);
+        let updated = store.update(&self.table, &self.key, |old| {
+            let mut lock = match LockState::try_from(old) {
+                Ok(lock) => lock,
+                Err(e) => {
+                    result = Err(e);
+                    return old.cloned();
+                }
+            };
+            if !lock.is_held(now) || lock.owner != self.owner {
+                return old.cloned();
+            }
+            // 保留 token，之后获取锁时继续递增
+            lock.owner.clear();
+            lock.expires_at = 0;
+            result = Ok(true);
+            Some(lock.into())
+        });
+        match updated.and(result) {
+            Ok(released) => Value::from(released).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+// LOCK 保存在 key 中的 map 的 field
+const LOCK_OWNER: &str = "owner";
+const LOCK_TOKEN: &str = "token";
+const LOCK_EXPIRES_AT: &str = "expires_at";
+
+// 锁的状态，key 不存在时是一个从没有被获取过的锁
+#[derive(Default)]
+struct LockState {
+    owner: String,
+    token: i64,
+    expires_at: i64,
+}
+
+impl LockState {
+    fn is_held(&self, now: i64) -> bool {
+        !self.owner.is_empty() && (self.expires_at == 0 || self.expires_at > now)
+    }
+}
+
+impl TryFrom<Option<&Value>> for LockState {
+    type Error = KvError;
+
+    fn try_from(v: Option<&Value>) -> Result<Self, Self::Error> {
+        let Some(v) = v else {
+            return Ok(Self::default());
+        };
+        let map = Map::try_from(v.clone())?;
+        let owner = match map.get(LOCK_OWNER).and_then(|v| v.value.as_ref()) {
+            Some(value::Value::String(owner)) => owner.clone(),
+            _ => return Err(KvError::ConvertError(v.clone(), "Lock")),
+        };
+        let integer = |field| match map.get(field) {
+            Some(v) => i64::try_from(v.clone()),
+            None => Err(KvError::ConvertError(Value::default(), "Lock")),
+        };
+        Ok(Self {
+            owner,
+            token: integer(LOCK_TOKEN)?,
+            expires_at: integer(LOCK_EXPIRES_AT)?,
+        })
+    }
+}
+
+impl From<LockState> for Value {
+    fn from(lock: LockState) -> Self {
+        let mut map = Map::default();
+        map.set(LOCK_OWNER, lock.owner);
+        map.set(LOCK_TOKEN, lock.token);
+        map.set(LOCK_EXPIRES_AT, lock.expires_at);
+        map.into()
+    }
+}
+
+impl CommandService for Keyinfo {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match self.info(store) {
+            Ok(pairs) => pairs.into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl Keyinfo {
+    // 只返回存储记录了的元数据
+    fn info(self, store: &impl Storage) -> Result<Vec<Kvpair>, KvError> {
+        let Some(value) = store.get(&self.table, &self.key)? else {
+            return Err(KvError::NotFound(self.table, self.key));
+        };
+        let mut pairs = vec![
+            Kvpair::new("type", value.type_name()),
+            Kvpair::new("size", value.encoded_len() as i64),
+        ];
+        if let Some(ttl) = store.ttl(&self.table, &self.key)? {
+            pairs.push(Kvpair::new("ttl_ms", ttl.as_millis() as i64));
+        }
+        if let Some(mtime) = store.mtime(&self.table, &self.key)? {
+            pairs.push(Kvpair::new("mtime", mtime));
+        }
+        if let Ok(lock) = LockState::try_from(Some(&value)) {
+            pairs.push(Kvpair::new("token", lock.token));
+        }
+        Ok(pairs)
+    }
+}
+
+impl CommandService for Memusage {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.approximate_size(&self.table) {
+            Ok(n) => Value::from(n as i64).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Hlen {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.len(&self.table) {
+            Ok(n) => Value::from(n as i64).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Quit {
+    // 连接由 ProstServerStream 在发送响应之后关闭
+    fn execute(self, _store: &impl Storage) -> CommandResponse {
+        Vec::<Value>::new().into()
+    }
+}
+
+/// SELFTEST 使用的 table，用户的数据不应该写入这个 table
+pub const SELFTEST_TABLE: &str = "__kv_selftest__";
+
+impl CommandService for Selftest {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        static PROBES: AtomicU64 = AtomicU64::new(0);
+        let key = format!("probe-{}", PROBES.fetch_add(1, Ordering::Relaxed));
+        let expected = Value::from(key.as_str());
+
+        let mut pairs = Vec::new();
+        let mut errors = Vec::new();
+        let mut step = |name: &str, f: &dyn Fn() -> Result<bool, KvError>| {
+            let start = Instant::now();
+            let result = f();
+            let micros = start.elapsed().as_micros() as i64;
+            let ok = match result {
+                Ok(true) => true,
+                Ok(false) => {
+                    errors.push(format!("{name}: unexpected result"));
+                    false
+                }
+                Err(e) => {
+                    errors.push(format!("{name}: {e}"));
+                    false
+                }
+            };
+            pairs.push(Kvpair::new(name, ok));
+            pairs.push(Kvpair::new(format!("{name}_micros"), micros));
+        };
+        step("set", &|| {
+            store.set(SELFTEST_TABLE, key.clone(), expected.clone())?;
+            Ok(true)
+        });
+        step("get", &|| {
+            Ok(store.get(SELFTEST_TABLE, &key)?.as_ref() == Some(&expected))
+        });
+        // 前面的步骤失败时也要删除，不在存储中留下探测用的 key
+        step("del", &|| {
+            store.del(SELFTEST_TABLE, &key)?;
+            Ok(!store.contains(SELFTEST_TABLE, &key)?)
+        });
+
+        let mut res: CommandResponse = pairs.into();
+        if !errors.is_empty() {
+            res.status = StatusCode::INTERNAL_SERVER_ERROR.as_u16() as _;
+            res.message = errors.join("; ");
+        }
+        res
+    }
+}
+
+impl CommandService for Hsetif {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let op = self.op();
+        let value = self.value.unwrap_or_default();
+        let (mut written, mut error) = (false, None);
+        // 比较和写入在同一个原子操作中完成，f 可能被调用多次，每次都重新计算结果
+        let result = store.update(&self.table, &self.key, |old| {
+            (written, error) = (false, None);
+            let Some(old) = old else {
+                written = true;
+                return Some(value.clone());
+            };
+            match value.compare(old) {
+                Some(ordering) if op.matches(ordering) => {
+                    written = true;
+                    Some(value.clone())
+                }
+                Some(_) => Some(old.clone()),
+                None => {
+                    error = Some(KvError::ConvertError(old.clone(), "comparable value"));
+                    Some(old.clone())
+                }
+            }
+        });
+        match (result, error) {
+            (Err(e), _) | (Ok(_), Some(e)) => e.into(),
+            (Ok(_), None) => Value::from(written).into(),
+        }
+    }
+}
+
+impl CommandService for Hsetnx {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let Some(pair) = self.pair else {
+            return KvError::InvaildCommand("HSETNX has no pair".into()).into();
+        };
+        let value = pair.value.unwrap_or_default();
+        let mut written = false;
+        // 检查和写入在同一个原子操作中完成，key 已经存在时保持不变
+        let result = store.update(&self.table, &pair.key, |old| {
+            written = old.is_none();
+            Some(old.cloned().unwrap_or_else(|| value.clone()))
+        });
+        match result {
+            Ok(_) => Value::from(written).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Hcas {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let new = self.new.unwrap_or_default();
+        let (mut swapped, mut previous) = (false, None);
+        // 比较和写入在同一个原子操作中完成，f 可能被调用多次，每次都重新计算结果
+        let result = store.update(&self.table, &self.key, |old| {
+            swapped = match (old, &self.expected) {
+                (None, None) => true,
+                (Some(old), Some(expected)) => old.deep_eq(expected),
+                _ => false,
+            };
+            previous = old.cloned();
+            match swapped {
+                true => Some(new.clone()),
+                false => old.cloned(),
+            }
+        });
+        match result {
+            Ok(_) => vec![swapped.into(), previous.unwrap_or_default()].into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Commands {
+    fn execute(self, _store: &impl Storage) -> CommandResponse {
+        let names = CommandRequest::COMMANDS.iter().map(|&name| name.into());
+        names.collect::<Vec<Value>>().into()
+    }
+}
+
+impl CommandService for Hgetset {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        // 所有的 Storage 都保证 set 返回的是被这次写入覆盖的 value
+        match store.set(&self.table, self.key, self.value.unwrap_or_default()) {
+            Ok(old) => old.unwrap_or_default().into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Replacetable {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.replace_table(&self.table, self.pairs) {
+            Ok((removed, inserted)) => vec![
+                Kvpair::new("inserted", inserted as i64),
+                Kvpair::new("removed", removed as i64),
+            ]
+            .into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Difftables {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        self.execute_with_limit(store, DEFAULT_MAX_RESULT_SIZE, None)
+    }
+}
+
+impl Difftables {
+    /// 两个 table 按 key 排序后做 merge join。两个 table 是先后遍历的，不是同一时刻的快照：
+    /// 比较期间写入的数据可能出现在结果中，也可能不出现，需要精确的结果时应该先 PAUSETABLE。
+    /// 和 HGETALL 一样，编码后的结果超过 limit 字节时返回 206 和按 key 排在前面的部分结果，超过 deadline 时返回 504
+    pub fn execute_with_limit(
+        self,
+        store: &impl Storage,
+        limit: usize,
+        deadline: Option<Instant>,
+    ) -> CommandResponse {
+        let (a, b) = match (
+            sorted_pairs(store, &self.table_a),
+            sorted_pairs(store, &self.table_b),
+        ) {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(e), _) | (_, Err(e)) => return e.into(),
+        };
+        let (mut a, mut b) = (a.into_iter().peekable(), b.into_iter().peekable());
+        let mut size = 0;
+        let mut pairs = Vec::new();
+        for i in 0.. {
+            if i % DEADLINE_CHECK_INTERVAL == 0 && deadline_exceeded(deadline) {
+                return KvError::DeadlineExceeded.into();
+            }
+            let ordering = match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) => x.key.cmp(&y.key),
+                (Some(_), None) => cmp::Ordering::Less,
+                (None, Some(_)) => cmp::Ordering::Greater,
+                (None, None) => break,
+            };
+            let pair = match ordering {
+                cmp::Ordering::Less => {
+                    let x = a.next().unwrap();
+                    diff_entry(x.key, "only_a", x.value, None)
+                }
+                cmp::Ordering::Greater => {
+                    let y = b.next().unwrap();
+                    diff_entry(y.key, "only_b", None, y.value)
+                }
+                cmp::Ordering::Equal => {
+                    let (x, y) = (a.next().unwrap(), b.next().unwrap());
+                    let (vx, vy) = (x.value.unwrap_or_default(), y.value.unwrap_or_default());
+                    if vx.deep_eq(&vy) {
+                        continue;
+                    }
+                    diff_entry(x.key, "changed", Some(vx), Some(vy))
+                }
+            };
+            size += pair.encoded_len();
+            if size > limit {
+                return CommandResponse::from(pairs)
+                    .into_partial(KvError::ResultTooLarge(self.table_a, limit).to_string());
+            }
+            pairs.push(pair);
+        }
+        pairs.into()
+    }
+}
+
+fn sorted_pairs(store: &impl Storage, table: &str) -> Result<Vec<Kvpair>, KvError> {
+    let mut pairs: Vec<_> = store.get_iter(table)?.collect();
+    pairs.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+    Ok(pairs)
+}
+
+// 一个差异：value 是包含 a、b 和 diff 的 map，没有的一边不出现
+fn diff_entry(key: String, diff: &str, a: Option<Value>, b: Option<Value>) -> Kvpair {
+    let mut fields = Vec::new();
+    for (name, value) in [("a", a), ("b", b)] {
+        if let Some(value) = value {
+            fields.push(Kvpair::new(name, value));
+        }
+    }
+    fields.push(Kvpair::new("diff", diff));
+    Kvpair::new(key, Map { fields })
+}
+
+impl CommandService for Hmatch {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        self.execute_with_limit(store, DEFAULT_MAX_RESULT_SIZE, None)
+    }
+}
+
+impl Hmatch {
+    /// 遍历 table 时就过滤 key，只有匹配的 key 计入结果的大小。
+    /// 和 HGETALL 一样，结果超过 limit 字节时返回 206 和已经匹配的部分 key，超过 deadline 时返回 504
+    pub fn execute_with_limit(
+        self,
+        store: &impl Storage,
+        limit: usize,
+        deadline: Option<Instant>,
+    ) -> CommandResponse {
+        let iter = match store.get_iter(&self.table) {
+            Ok(iter) => iter,
+            Err(e) => return e.into(),
+        };
+        let pattern: Vec<char> = self.pattern.chars().collect();
+        let mut size = 0;
+        let mut pairs = Vec::new();
+        let mut truncated = false;
+        for (i, mut pair) in iter.enumerate() {
+            if i % DEADLINE_CHECK_INTERVAL == 0 && deadline_exceeded(deadline) {
+                return KvError::DeadlineExceeded.into();
+            }
+            if !glob_match(&pattern, &pair.key) {
+                continue;
+            }
+            if !self.with_values {
+                pair.value = None;
+            }
+            size += pair.encoded_len();
+            if size > limit {
+                truncated = true;
+                break;
+            }
+            pairs.push(pair);
+        }
+        pairs.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+        let res: CommandResponse = match self.with_values {
+            true => pairs.into(),
+            false => pairs
+                .into_iter()
+                .map(|pair| Value::from(pair.key))
+                .collect::<Vec<_>>()
+                .into(),
+        };
+        match truncated {
+            true => res.into_partial(KvError::ResultTooLarge(self.table, limit).to_string()),
+            false => res,
+        }
+    }
+}
+
+impl CommandService for Delmatch {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        self.execute_with_deadline(store, None)
+    }
+}
+
+impl Delmatch {
+    /// 遍历 table 找出匹配的 key，再用 del_batch 一次删除。遍历超过 deadline 时返回 504，不删除任何 key
+    pub fn execute_with_deadline(
+        self,
+        store: &impl Storage,
+        deadline: Option<Instant>,
+    ) -> CommandResponse {
+        let iter = match store.get_iter(&self.table) {
+            Ok(iter) => iter,
+            Err(e) => return e.into(),
+        };
+        let pattern: Vec<char> = self.pattern.chars().collect();
+        let mut keys = Vec::new();
+        for (i, pair) in iter.enumerate() {
+            if i % DEADLINE_CHECK_INTERVAL == 0 && deadline_exceeded(deadline) {
+                return KvError::DeadlineExceeded.into();
+            }
+            if glob_match(&pattern, &pair.key) {
+                keys.push(pair.key);
+            }
+        }
+        // 遍历之后被其它命令删除的 key 不计入删除的数量
+        match store.del_batch(&self.table, &keys) {
+            Ok(removed) => Value::from(removed.iter().flatten().count() as i64).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+// glob 匹配，* 匹配失败时回退到上一个 * 多匹配一个字符
+fn glob_match(pattern: &[char], text: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // 上一个 * 之后的位置，以及这个 * 已经匹配到的 text 位置
+    let mut star = None;
+    while t < text.len() {
+        if pattern.get(p) == Some(&'*') {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if let Some(next) = match_one(pattern, p, text[t]) {
+            p = next;
+            t += 1;
+        } else if let Some((sp, st)) = star {
+            star = Some((sp, st + 1));
+            p = sp;
+            t = st + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// pattern 在 p 处的一个元素（不是 *）能否匹配字符 c，能匹配时返回下一个元素的位置
+fn match_one(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match *pattern.get(p)? {
+        '?' => Some(p + 1),
+        '\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        '[' => match match_class(pattern, p, c) {
+            Some((matched, next)) => matched.then_some(next),
+            // 没有闭合的 [ 按普通字符处理
+            None => (c == '[').then_some(p + 1),
+        },
+        x => (x == c).then_some(p + 1),
+    }
+}
+
+// 匹配 p 处的字符集合 [...]，返回是否匹配以及 ] 之后的位置，没有闭合时返回 None。
+// 紧跟在 [ 或者 [! 之后的 ] 是普通字符
+fn match_class(pattern: &[char], p: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = p + 1;
+    let negate = matches!(pattern.get(i), Some('!' | '^'));
+    if negate {
+        i += 1;
+    }
+    let (mut matched, mut first) = (false, true);
+    loop {
+        let &lo = pattern.get(i)?;
+        if lo == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        let lo = match lo {
+            '\\' => {
+                i += 1;
+                *pattern.get(i)?
+            }
+            lo => lo,
+        };
+        match (pattern.get(i + 1), pattern.get(i + 2)) {
+            (Some('-'), Some(&hi)) if hi != ']' => {
+                matched |= lo <= c && c <= hi;
+                i += 3;
+            }
+            _ => {
+                matched |= lo == c;
+                i += 1;
+            }
+        }
+        first = false;
+    }
+}
+
+impl CommandService for Time {
+    fn execute(self, _store: &impl Storage) -> CommandResponse {
+        Value::from(SystemTime::now()).into()
+    }
+}
+
+impl CommandService for Hrename {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.rename_key(&self.table, &self.from, &self.to) {
+            Ok(Some(old)) => old.unwrap_or_default().into(),
+            Ok(None) => KvError::NotFound(self.table, self.from).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Copytable {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.copy_table(&self.src, &self.dst, self.overwrite) {
+            Ok(copied) => Value::from(copied as i64).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Flushtable {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.clear_table(&self.table) {
+            Ok(removed) => Value::from(removed as i64).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Tables {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.tables() {
+            Ok(names) => names
+                .into_iter()
+                .map(Value::from)
+                .collect::<Vec<_>>()
+                .into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{assert_res_error, assert_res_ok, command_request::RequestData};
+
+    #[test]
+    fn hset_should_work() {
+        let store = MemTable::new();
+        let cmd = CommandRequest::new_hset("table", "hello", "world");
+        let res = dispatch(cmd.clone(), &store);
+        // 第一次插入返回之前的值为空
+        assert_res_ok(res, &[Value::default()], &[]);
+
+        let res = dispatch(cmd, &store);
+        // 再次插入返回之前的值
+        assert_res_ok(res, &["world".into()], &[]);
+    }
+
+    #[test]
+    fn hget_should_work() {
+        let store = MemTable::new();
+        let cmd = CommandRequest::new_hset("score", "u1", 10);
+        dispatch(cmd, &store);
+        let cmd = CommandRequest::new_hget("score", "u1");
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[10.into()], &[]);
+    }
+
+    #[test]
+    fn hdel_should_work() {
+        let store = MemTable::new();
+        let cmd = CommandRequest::new_hset("table", "key", 10);
+        dispatch(cmd, &store);
+
+        let cmd = CommandRequest::new_hdel("table", "key");
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[10.into()], &[]);
+
+        let cmd = CommandRequest::new_hexist("table", "key");
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[false.into()], &[]);
+    }
+
+    #[test]
+    fn hexist_should_work() {
+        let store = MemTable::new();
+        // 不存在的 key 应返回 false
+        let cmd = CommandRequest::new_hexist("table", "key");
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[false.into()], &[]);
+
+        // 存在的 key 返回 true
+        let cmd = CommandRequest::new_hset("table", "key", 10);
+        dispatch(cmd, &store);
+        let cmd = CommandRequest::new_hexist("table", "key");
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[true.into()], &[]);
+    }
+
+    #[test]
+    fn hget_with_non_exist_should_return_404() {
+        let store = MemTable::new();
+        let cmd = CommandRequest::new_hget("non exist table", "non exist key");
+        let res = dispatch(cmd, &store);
+        assert_res_error(res, 404, "Not found");
+    }
+
+    #[test]
+    fn hgetall_should_work() {
+        let store = MemTable::new();
+        let cmds = vec![
+            CommandRequest::new_hset("score", "u1", 10),
+            CommandRequest::new_hset("score", "u2", 9),
+            CommandRequest::new_hset("score", "u3", 23),
+            CommandRequest::new_hset("score", "u1", 5),
+        ];
+        for cmd in cmds {
+            dispatch(cmd, &store);
+        }
+
+        let cmd = CommandRequest::new_hgetall("score");
+        let res = dispatch(cmd, &store);
+        let pairs = &[
+            Kvpair::new("u1", 5),
+            Kvpair::new("u2", 9),
+            Kvpair::new("u3", 23),
+        ];
+        assert_res_ok(res, &[], pairs);
+    }
+
+    #[test]
+    fn hmget_should_work() {
+        let store = MemTable::new();
+        let cmds = vec![
+            CommandRequest::new_hset("table", "key1", 1),
+            CommandRequest::new_hset("table", "key2", 2),
+            CommandRequest::new_hset("table", "key3", 3),
+            CommandRequest::new_hset("table", "key4", 4),
+        ];
+        for cmd in cmds {
+            dispatch(cmd, &store);
+        }
+
+        let cmd = CommandRequest::new_hmget(
+            "table",
+            vec!["key1", "key2", "not exist key", "key3", "key4"],
+        );
+        let res = dispatch(cmd, &store);
+        assert_res_ok(
+            res,
+            &[
+                1.into(),
+                2.into(),
+                pb::abi::Value::default(),
+                3.into(),
+                4.into(),
+            ],
+            &[],
+        );
+    }
+
+    #[test]
+    fn hmset_should_work() {
+        let store = MemTable::new();
+        let pairs = &[
+            Kvpair::new("key1", 1),
+            Kvpair::new("key2", 2),
+            Kvpair::new("key3", 3),
+            Kvpair::new("key4", 4),
+        ];
+
+        let cmd = CommandRequest::new_hmset("table", pairs.into());
+        dispatch(cmd, &store);
+
+        let cmd = CommandRequest::new_hgetall("table");
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[], pairs);
+    }
+
+    #[test]
+    fn hmdel_should_work() {
+        let store = MemTable::new();
+        let cmds = vec![
+            CommandRequest::new_hset("table", "key1", 1),
+            CommandRequest::new_hset("table", "key2", 2),
+            CommandRequest::new_hset("table", "key3", 3),
+            CommandRequest::new_hset("table", "key4", 4),
+        ];
+        for cmd in cmds {
+            dispatch(cmd, &store);
+        }
+
+        let cmd = CommandRequest::new_hmdel("table", vec!["key1", "key2", "key3", "key4"]);
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[1.into(), 2.into(), 3.into(), 4.into()], &[]);
+    }
+
+    #[test]
+    fn hmexist_should_work() {
+        let store = MemTable::new();
+        let cmds = vec![
+            CommandRequest::new_hset("table", "key1", 1),
+            CommandRequest::new_hset("table", "key3", 3),
+        ];
+        for cmd in cmds {
+            dispatch(cmd, &store);
         }
 
         let cmd = CommandRequest::new_hmexist("table", vec!["key1", "key2", "key3", "key4"]);
         let res = dispatch(cmd, &store);
         assert_res_ok(
-            res,
-            &[true.into(), false.into(), true.into(), false.into()],
+            res,
+            &[true.into(), false.into(), true.into(), false.into()],
+            &[],
+        );
+    }
+
+    #[test]
+    fn compact_should_work() {
+        let store = MemTable::new();
+        let cmd = CommandRequest::new_compact();
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[0.into()], &[]);
+    }
+
+    #[test]
+    fn hmove_should_work() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("pending", "task", 1), &store);
+
+        let cmd = CommandRequest::new_hmove("pending", "done", "task");
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[1.into()], &[]);
+
+        let res = dispatch(CommandRequest::new_hexist("pending", "task"), &store);
+        assert_res_ok(res, &[false.into()], &[]);
+        let res = dispatch(CommandRequest::new_hget("done", "task"), &store);
+        assert_res_ok(res, &[1.into()], &[]);
+    }
+
+    #[test]
+    fn hmove_with_non_exist_key_should_return_404() {
+        let store = MemTable::new();
+        let cmd = CommandRequest::new_hmove("pending", "done", "task");
+        let res = dispatch(cmd, &store);
+        assert_res_error(res, 404, "Not found");
+    }
+
+    #[test]
+    fn hmerge_should_keep_causally_later_value() {
+        let store = MemTable::new();
+        let clock = vec![ClockEntry::new("a", 1)];
+        let cmd = CommandRequest::new_hmerge("t", "k", "v1", clock.clone());
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[register(&["v1"], &clock)], &[]);
+
+        // 晚于当前时钟的写入覆盖旧值
+        let later = vec![ClockEntry::new("a", 2)];
+        let cmd = CommandRequest::new_hmerge("t", "k", "v2", later.clone());
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[register(&["v2"], &later)], &[]);
+
+        // 过时的写入被忽略
+        let cmd = CommandRequest::new_hmerge("t", "k", "v3", clock);
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[register(&["v2"], &later)], &[]);
+    }
+
+    #[test]
+    fn hmerge_should_keep_both_values_on_conflict() {
+        let store = MemTable::new();
+        let cmd = CommandRequest::new_hmerge("t", "k", "v1", vec![ClockEntry::new("a", 1)]);
+        dispatch(cmd, &store);
+
+        // 两个节点并发写入，保留两个值，时钟取最大值
+        let cmd = CommandRequest::new_hmerge("t", "k", "v2", vec![ClockEntry::new("b", 1)]);
+        let res = dispatch(cmd, &store);
+        let merged = vec![ClockEntry::new("a", 1), ClockEntry::new("b", 1)];
+        assert_res_ok(res, &[register(&["v1", "v2"], &merged)], &[]);
+
+        // 看到了两个值之后的写入解决冲突
+        let resolved = vec![ClockEntry::new("a", 2), ClockEntry::new("b", 1)];
+        let cmd = CommandRequest::new_hmerge("t", "k", "v3", resolved.clone());
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[register(&["v3"], &resolved)], &[]);
+    }
+
+    #[test]
+    fn hmerge_should_overwrite_plain_value() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("t", "k", "plain"), &store);
+        let clock = vec![ClockEntry::new("a", 1)];
+        let cmd = CommandRequest::new_hmerge("t", "k", "v1", clock.clone());
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[register(&["v1"], &clock)], &[]);
+    }
+
+    fn register(values: &[&str], clock: &[ClockEntry]) -> Value {
+        Register {
+            values: values.iter().map(|&v| v.into()).collect(),
+            clock: clock.to_vec(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn hgetall_over_result_limit_should_return_partial() {
+        let store = MemTable::new();
+        for i in 0..1000i64 {
+            dispatch(
+                CommandRequest::new_hset("score", format!("u{i}"), i),
+                &store,
+            );
+        }
+
+        let cmd = CommandRequest::new_hgetall("score");
+        let res = match cmd.request_data.unwrap() {
+            RequestData::Hgetall(v) => v.execute_with_limit(&store, 1024, None),
+            _ => unreachable!(),
+        };
+        // 返回不超过限制的部分结果
+        assert_eq!(res.status, 206);
+        assert!(res.partial);
+        assert!(res.message.contains("use HSCAN"));
+        assert!(!res.pairs.is_empty());
+        let size: usize = res.pairs.iter().map(|pair| pair.encoded_len()).sum();
+        assert!(size <= 1024);
+        for pair in res.pairs {
+            assert_eq!(store.get("score", &pair.key).unwrap(), pair.value);
+        }
+
+        // 遍历时超过 deadline
+        let param = Hgetall {
+            table: "score".into(),
+        };
+        let res = param.execute_with_limit(&store, usize::MAX, Some(Instant::now()));
+        assert_res_error(res, 504, "Deadline");
+
+        // 缺省的限制足够大
+        let res = dispatch(CommandRequest::new_hgetall("score"), &store);
+        assert_eq!(res.status, 200);
+        assert!(!res.partial);
+        assert_eq!(res.pairs.len(), 1000);
+    }
+
+    #[test]
+    fn hdeleq_should_work() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("t", "k", "v1"), &store);
+
+        // 值不同，不删除
+        let res = dispatch(CommandRequest::new_hdeleq("t", "k", "v2"), &store);
+        assert_res_ok(res, &[false.into()], &[]);
+        // 类型不同，不删除
+        let res = dispatch(CommandRequest::new_hdeleq("t", "k", b"v1"), &store);
+        assert_res_ok(res, &[false.into()], &[]);
+        let res = dispatch(CommandRequest::new_hget("t", "k"), &store);
+        assert_res_ok(res, &["v1".into()], &[]);
+
+        let res = dispatch(CommandRequest::new_hdeleq("t", "k", "v1"), &store);
+        assert_res_ok(res, &[true.into()], &[]);
+        let res = dispatch(CommandRequest::new_hexist("t", "k"), &store);
+        assert_res_ok(res, &[false.into()], &[]);
+
+        // key 不存在
+        let res = dispatch(CommandRequest::new_hdeleq("t", "k", "v1"), &store);
+        assert_res_ok(res, &[false.into()], &[]);
+    }
+
+    #[test]
+    fn hdeleq_should_compare_float_by_bits() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("t", "nan", f64::NAN), &store);
+        dispatch(CommandRequest::new_hset("t", "zero", 0.0), &store);
+
+        let res = dispatch(CommandRequest::new_hdeleq("t", "zero", -0.0), &store);
+        assert_res_ok(res, &[false.into()], &[]);
+        let res = dispatch(CommandRequest::new_hdeleq("t", "nan", f64::NAN), &store);
+        assert_res_ok(res, &[true.into()], &[]);
+    }
+
+    #[test]
+    fn hmtime_should_work() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("t", "k", "v"), &store);
+        let res = dispatch(CommandRequest::new_hmtime("t", "k"), &store);
+        assert_eq!(res.status, 200);
+        let mtime: i64 = res.values[0].clone().try_into().unwrap();
+        assert!(mtime > 0);
+
+        let res = dispatch(CommandRequest::new_hmtime("t", "not exist key"), &store);
+        assert_res_error(res, 404, "Not found");
+    }
+
+    #[test]
+    fn findbyvalue_should_work() {
+        let store = IndexedStore::new(MemTable::new())
+            .with_index("user", 100)
+            .unwrap();
+        dispatch(CommandRequest::new_hset("user", "bob", "admin"), &store);
+        dispatch(CommandRequest::new_hset("user", "alice", "admin"), &store);
+        dispatch(CommandRequest::new_hset("user", "tyr", "guest"), &store);
+
+        let res = dispatch(CommandRequest::new_findbyvalue("user", "admin"), &store);
+        assert_res_ok(res, &["alice".into(), "bob".into()], &[]);
+
+        // 没有索引的 table 遍历查找
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("user", "tyr", "guest"), &store);
+        let res = dispatch(CommandRequest::new_findbyvalue("user", "guest"), &store);
+        assert_res_ok(res, &["tyr".into()], &[]);
+    }
+
+    #[test]
+    fn multi_commands_with_empty_keys_should_return_empty_result() {
+        let store = MemTable::new();
+        let keys: Vec<String> = vec![];
+        let cmds = [
+            CommandRequest::new_hmget("table", keys.clone()),
+            CommandRequest::new_hmdel("table", keys.clone()),
+            CommandRequest::new_hmexist("table", keys),
+            CommandRequest::new_hmset("table", Vec::<Kvpair>::new()),
+        ];
+        for cmd in cmds {
+            let res = dispatch(cmd, &store);
+            assert_res_ok(res, &[], &[]);
+        }
+    }
+
+    #[test]
+    fn hgetall_with_non_exist_table_should_return_empty_result() {
+        let store = MemTable::new();
+        let res = dispatch(CommandRequest::new_hgetall("not exist table"), &store);
+        assert_res_ok(res, &[], &[]);
+    }
+
+    #[test]
+    fn hincrfield_should_create_and_update_field() {
+        let store = MemTable::new();
+        let res = dispatch(
+            CommandRequest::new_hincrfield("t", "doc", "views", 1),
+            &store,
+        );
+        assert_res_ok(res, &[1.into()], &[]);
+        let res = dispatch(
+            CommandRequest::new_hincrfield("t", "doc", "views", 10),
+            &store,
+        );
+        assert_res_ok(res, &[11.into()], &[]);
+        let res = dispatch(
+            CommandRequest::new_hincrfield("t", "doc", "likes", -1),
+            &store,
+        );
+        assert_res_ok(res, &[(-1).into()], &[]);
+
+        let res = dispatch(CommandRequest::new_hget("t", "doc"), &store);
+        let map = Map::try_from(res.values[0].clone()).unwrap();
+        assert_eq!(map.get("views"), Some(&11.into()));
+        assert_eq!(map.get("likes"), Some(&(-1).into()));
+    }
+
+    #[test]
+    fn hincrfield_with_mismatched_type_should_fail() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("t", "plain", "value"), &store);
+        let res = dispatch(
+            CommandRequest::new_hincrfield("t", "plain", "views", 1),
+            &store,
+        );
+        assert_res_error(res, 500, "Cannot convert");
+        // 原来的值保持不变
+        let res = dispatch(CommandRequest::new_hget("t", "plain"), &store);
+        assert_res_ok(res, &["value".into()], &[]);
+
+        let mut map = Map::default();
+        map.set("name", "tyr");
+        dispatch(CommandRequest::new_hset("t", "doc", map), &store);
+        let res = dispatch(
+            CommandRequest::new_hincrfield("t", "doc", "name", 1),
+            &store,
+        );
+        assert_res_error(res, 500, "Cannot convert");
+    }
+
+    #[test]
+    fn hincrfield_overflow_should_fail_or_saturate() {
+        let store = MemTable::new();
+        dispatch(
+            CommandRequest::new_hincrfield("t", "doc", "max", i64::MAX),
+            &store,
+        );
+        dispatch(
+            CommandRequest::new_hincrfield("t", "doc", "min", i64::MIN),
+            &store,
+        );
+
+        // 默认溢出时返回错误，原来的值保持不变
+        let res = dispatch(CommandRequest::new_hincrfield("t", "doc", "max", 1), &store);
+        assert_res_error(res, 400, "Integer overflow");
+        let res = dispatch(
+            CommandRequest::new_hincrfield("t", "doc", "min", -1),
+            &store,
+        );
+        assert_res_error(res, 400, "Integer overflow");
+        let res = dispatch(CommandRequest::new_hincrfield("t", "doc", "max", 0), &store);
+        assert_res_ok(res, &[i64::MAX.into()], &[]);
+
+        // saturating 时截断到边界
+        let cmd = CommandRequest::new_hincrfield_saturating("t", "doc", "max", 1);
+        assert_res_ok(dispatch(cmd, &store), &[i64::MAX.into()], &[]);
+        let cmd = CommandRequest::new_hincrfield_saturating("t", "doc", "min", i64::MIN);
+        assert_res_ok(dispatch(cmd, &store), &[i64::MIN.into()], &[]);
+        let cmd = CommandRequest::new_hincrfield_saturating("t", "doc", "max", -1);
+        assert_res_ok(dispatch(cmd, &store), &[(i64::MAX - 1).into()], &[]);
+    }
+
+    #[test]
+    fn hincr_overflow_should_fail_or_saturate() {
+        let store = MemTable::new();
+        store.set("t", "max", i64::MAX).unwrap();
+        store.set("t", "min", i64::MIN).unwrap();
+
+        let res = dispatch(CommandRequest::new_hincr("t", "max", 1), &store);
+        assert_res_error(res, 400, "Integer overflow");
+        assert_eq!(store.get("t", "max").unwrap(), Some(i64::MAX.into()));
+
+        let cmd = CommandRequest::new_hincr_saturating("t", "max", 1);
+        assert_res_ok(dispatch(cmd, &store), &[i64::MAX.into()], &[]);
+        let cmd = CommandRequest::new_hincr_saturating("t", "min", i64::MIN);
+        assert_res_ok(dispatch(cmd, &store), &[i64::MIN.into()], &[]);
+        let cmd = CommandRequest::new_hincr_saturating("t", "max", -1);
+        assert_res_ok(dispatch(cmd, &store), &[(i64::MAX - 1).into()], &[]);
+        // 不是整数时依旧返回错误
+        store.set("t", "s", "text").unwrap();
+        let res = dispatch(CommandRequest::new_hincr_saturating("t", "s", 1), &store);
+        assert_res_error(res, 500, "Cannot convert");
+    }
+
+    #[test]
+    fn hmincr_overflow_should_fail_or_saturate() {
+        let store = MemTable::new();
+        store.set("t", "max", i64::MAX).unwrap();
+        store.set("t", "min", i64::MIN).unwrap();
+
+        let cmd = CommandRequest::new_hmincr("t", vec![("max", 1), ("min", 1)]);
+        let res = dispatch(cmd, &store);
+        assert_eq!(res.status, 206);
+        assert_eq!(res.values, [Value::default(), (i64::MIN + 1).into()]);
+
+        // 同一个 key 多次累加时在截断后的值上继续累加
+        let pairs = vec![("max", 1), ("max", -1), ("min", i64::MIN)];
+        let cmd = CommandRequest::new_hmincr_saturating("t", pairs);
+        let values = [i64::MAX.into(), (i64::MAX - 1).into(), i64::MIN.into()];
+        assert_res_ok(dispatch(cmd, &store), &values, &[]);
+        assert_eq!(store.get("t", "max").unwrap(), Some((i64::MAX - 1).into()));
+    }
+
+    #[test]
+    fn hincrinit_overflow_should_fail_or_saturate() {
+        let store = MemTable::new();
+        store.set("t", "max", i64::MAX).unwrap();
+
+        let res = dispatch(CommandRequest::new_hincrinit("t", "max", 1, 0), &store);
+        assert_res_error(res, 400, "Integer overflow");
+        let cmd = CommandRequest::new_hincrinit_saturating("t", "max", 1, 0);
+        assert_res_ok(dispatch(cmd, &store), &[i64::MAX.into()], &[]);
+        let cmd = CommandRequest::new_hincrinit_saturating("t", "new", i64::MIN, 60_000);
+        assert_res_ok(dispatch(cmd, &store), &[i64::MIN.into()], &[]);
+        assert!(store.ttl("t", "new").unwrap().is_some());
+    }
+
+    #[test]
+    fn fieldset_and_fieldget_should_work() {
+        let store = MemTable::new();
+        let cmd = CommandRequest::new_fieldset("t", "doc", "name", "tyr");
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[Value::default()], &[]);
+        dispatch(CommandRequest::new_fieldset("t", "doc", "age", 10), &store);
+
+        // 只修改一个 field，其它 field 保持不变
+        let res = dispatch(CommandRequest::new_fieldset("t", "doc", "age", 11), &store);
+        assert_res_ok(res, &[10.into()], &[]);
+        let res = dispatch(CommandRequest::new_fieldget("t", "doc", "name"), &store);
+        assert_res_ok(res, &["tyr".into()], &[]);
+        let res = dispatch(CommandRequest::new_fieldget("t", "doc", "age"), &store);
+        assert_res_ok(res, &[11.into()], &[]);
+
+        // field 不存在
+        let res = dispatch(CommandRequest::new_fieldget("t", "doc", "email"), &store);
+        assert_res_ok(res, &[Value::default()], &[]);
+        // key 不存在
+        let res = dispatch(CommandRequest::new_fieldget("t", "no doc", "name"), &store);
+        assert_res_error(res, 404, "Not found");
+    }
+
+    #[test]
+    fn fieldmget_should_return_fields_in_order() {
+        let store = MemTable::new();
+        dispatch(
+            CommandRequest::new_fieldset("t", "doc", "name", "tyr"),
+            &store,
+        );
+        dispatch(CommandRequest::new_fieldset("t", "doc", "age", 10), &store);
+
+        // 不存在的 field 在对应的位置返回空值
+        let cmd = CommandRequest::new_fieldmget("t", "doc", vec!["age", "email", "name"]);
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[10.into(), Value::default(), "tyr".into()], &[]);
+        let res = dispatch(
+            CommandRequest::new_fieldmget("t", "doc", vec!["email"]),
+            &store,
+        );
+        assert_res_ok(res, &[Value::default()], &[]);
+
+        // key 不存在
+        let cmd = CommandRequest::new_fieldmget("t", "no doc", vec!["name"]);
+        assert_res_error(dispatch(cmd, &store), 404, "Not found");
+        // 不是 map
+        dispatch(CommandRequest::new_hset("t", "plain", "value"), &store);
+        let cmd = CommandRequest::new_fieldmget("t", "plain", vec!["name"]);
+        assert_res_error(dispatch(cmd, &store), 500, "Cannot convert");
+    }
+
+    #[test]
+    fn field_commands_on_non_map_value_should_fail() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("t", "plain", "value"), &store);
+        let res = dispatch(CommandRequest::new_fieldget("t", "plain", "name"), &store);
+        assert_res_error(res, 500, "Cannot convert");
+        let res = dispatch(
+            CommandRequest::new_fieldset("t", "plain", "name", 1),
+            &store,
+        );
+        assert_res_error(res, 500, "Cannot convert");
+        let res = dispatch(CommandRequest::new_hget("t", "plain"), &store);
+        assert_res_ok(res, &["value".into()], &[]);
+    }
+
+    #[test]
+    fn hashmap_value_conversion_should_work() {
+        let map = HashMap::from([("b".to_string(), 2.into()), ("a".to_string(), "1".into())]);
+        let value: Value = map.clone().into();
+        assert_eq!(
+            Map::try_from(value.clone()).unwrap().fields,
+            vec![Kvpair::new("a", "1"), Kvpair::new("b", 2)]
+        );
+        assert_eq!(HashMap::<String, Value>::try_from(value).unwrap(), map);
+    }
+
+    #[test]
+    fn memusage_should_work() {
+        let store = MemTable::new();
+        let res = dispatch(CommandRequest::new_memusage("t"), &store);
+        assert_res_ok(res, &[0.into()], &[]);
+
+        dispatch(CommandRequest::new_hset("t", "key", "value"), &store);
+        let value: Value = "value".into();
+        let expected = "key".len() + value.encoded_len();
+        let res = dispatch(CommandRequest::new_memusage("t"), &store);
+        assert_res_ok(res, &[(expected as i64).into()], &[]);
+    }
+
+    #[test]
+    fn selftest_should_work() {
+        let store = MemTable::new();
+        let res = dispatch(CommandRequest::new_selftest(), &store);
+        assert_eq!(res.status, 200);
+        for step in ["set", "get", "del"] {
+            assert!(res.pairs.contains(&Kvpair::new(step, true)));
+            assert!(res.pairs.iter().any(|p| p.key == format!("{step}_micros")));
+        }
+        // 不留下探测用的 key
+        assert!(store.get_all(SELFTEST_TABLE).unwrap().is_empty());
+    }
+
+    #[test]
+    fn hsetif_should_work() {
+        let store = MemTable::new();
+        // key 不存在时总是写入
+        let cmd = CommandRequest::new_hsetif("t1", "max", 10, CompareOp::Gt);
+        assert_res_ok(dispatch(cmd, &store), &[true.into()], &[]);
+
+        let cmd = CommandRequest::new_hsetif("t1", "max", 5, CompareOp::Gt);
+        assert_res_ok(dispatch(cmd, &store), &[false.into()], &[]);
+        let cmd = CommandRequest::new_hsetif("t1", "max", 10, CompareOp::Gte);
+        assert_res_ok(dispatch(cmd, &store), &[true.into()], &[]);
+        let cmd = CommandRequest::new_hsetif("t1", "max", 10, CompareOp::Ne);
+        assert_res_ok(dispatch(cmd, &store), &[false.into()], &[]);
+        let cmd = CommandRequest::new_hsetif("t1", "max", 5, CompareOp::Lt);
+        assert_res_ok(dispatch(cmd, &store), &[true.into()], &[]);
+        assert_eq!(store.get("t1", "max").unwrap(), Some(5.into()));
+    }
+
+    #[test]
+    fn hsetif_with_incomparable_value_should_fail() {
+        let store = MemTable::new();
+        store.set("t1", "max", 10).unwrap();
+        let cmd = CommandRequest::new_hsetif("t1", "max", "hello", CompareOp::Gt);
+        let res = dispatch(cmd, &store);
+        assert_res_error(res, 500, "comparable value");
+        assert_eq!(store.get("t1", "max").unwrap(), Some(10.into()));
+    }
+
+    #[test]
+    fn commands_should_work() {
+        let store = MemTable::new();
+        let res = dispatch(CommandRequest::new_commands(), &store);
+        assert_eq!(res.status, 200);
+        assert_eq!(res.values.len(), CommandRequest::COMMANDS.len());
+        assert!(res.values.contains(&"HGET".into()));
+        assert!(res.values.contains(&"COMMANDS".into()));
+    }
+
+    #[test]
+    fn hgetset_should_return_previous_value() {
+        let store = MemTable::new();
+        // 第一次写入之前没有值，返回空的 value
+        let res = dispatch(CommandRequest::new_hgetset("t", "k", "v1"), &store);
+        assert_res_ok(res, &[Value::default()], &[]);
+        let res = dispatch(CommandRequest::new_hgetset("t", "k", 2), &store);
+        assert_res_ok(res, &["v1".into()], &[]);
+        let res = dispatch(CommandRequest::new_hget("t", "k"), &store);
+        assert_res_ok(res, &[2.into()], &[]);
+    }
+
+    #[test]
+    fn replacetable_should_replace_all_keys() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("config", "old", 1), &store);
+        dispatch(CommandRequest::new_hset("config", "kept", 1), &store);
+
+        let pairs = vec![Kvpair::new("kept", 2), Kvpair::new("new", 3)];
+        let res = dispatch(CommandRequest::new_replacetable("config", pairs), &store);
+        let expected = [Kvpair::new("inserted", 2), Kvpair::new("removed", 2)];
+        assert_res_ok(res, &[], &expected);
+
+        let res = dispatch(CommandRequest::new_hget("config", "old"), &store);
+        assert_res_error(res, 404, "Not found");
+        let res = dispatch(CommandRequest::new_hget("config", "kept"), &store);
+        assert_res_ok(res, &[2.into()], &[]);
+    }
+
+    #[test]
+    fn hset_should_keep_content_type() {
+        let store = MemTable::new();
+        let value = Value::from(b"\x08\x01").with_content_type("application/x-protobuf");
+        dispatch(CommandRequest::new_hset("blob", "k", value.clone()), &store);
+        let res = dispatch(CommandRequest::new_hget("blob", "k"), &store);
+        assert_res_ok(res, &[value], &[]);
+    }
+
+    #[test]
+    fn difftables_should_work() {
+        let store = MemTable::new();
+        let cmds = vec![
+            CommandRequest::new_hset("a", "same", 1),
+            CommandRequest::new_hset("b", "same", 1),
+            CommandRequest::new_hset("a", "changed", 1),
+            CommandRequest::new_hset("b", "changed", 2),
+            CommandRequest::new_hset("a", "x", 1),
+            CommandRequest::new_hset("b", "y", 2),
+        ];
+        for cmd in cmds {
+            dispatch(cmd, &store);
+        }
+
+        let res = dispatch(CommandRequest::new_difftables("a", "b"), &store);
+        let entry = |key: &str, diff: &str, a: Option<i64>, b: Option<i64>| {
+            let mut fields = Vec::new();
+            fields.extend(a.map(|v| Kvpair::new("a", v)));
+            fields.extend(b.map(|v| Kvpair::new("b", v)));
+            fields.push(Kvpair::new("diff", diff));
+            Kvpair::new(key, Map { fields })
+        };
+        let expected = [
+            entry("changed", "changed", Some(1), Some(2)),
+            entry("x", "only_a", Some(1), None),
+            entry("y", "only_b", None, Some(2)),
+        ];
+        assert_res_ok(res, &[], &expected);
+
+        let res = dispatch(CommandRequest::new_difftables("a", "a"), &store);
+        assert_res_ok(res, &[], &[]);
+    }
+
+    #[test]
+    fn hmset_should_follow_duplicate_policy() {
+        let pairs = || {
+            vec![
+                Kvpair::new("k", 1),
+                Kvpair::new("other", 0),
+                Kvpair::new("k", 2),
+            ]
+        };
+        for (policy, expected) in [
+            (DuplicatePolicy::LastWins, 2),
+            (DuplicatePolicy::FirstWins, 1),
+        ] {
+            let store = MemTable::new();
+            let cmd = CommandRequest::new_hmset_with_policy("t", pairs(), policy);
+            let res = dispatch(cmd, &store);
+            // 每个 key 只返回一个之前的 value
+            assert_res_ok(res, &[Value::default(), Value::default()], &[]);
+            let res = dispatch(CommandRequest::new_hget("t", "k"), &store);
+            assert_res_ok(res, &[expected.into()], &[]);
+        }
+
+        let store = MemTable::new();
+        let cmd = CommandRequest::new_hmset_with_policy("t", pairs(), DuplicatePolicy::Reject);
+        let res = dispatch(cmd, &store);
+        assert_res_error(res, 400, "Duplicate key k");
+        let res = dispatch(CommandRequest::new_hget("t", "other"), &store);
+        assert_res_error(res, 404, "Not found");
+    }
+
+    #[test]
+    fn fieldmerge_should_work() {
+        let store = MemTable::new();
+        let fields = vec![Kvpair::new("name", "tyr"), Kvpair::new("age", 10)];
+        let res = dispatch(CommandRequest::new_fieldmerge("user", "u1", fields), &store);
+        assert_res_ok(res, &[2.into()], &[]);
+
+        let fields = vec![Kvpair::new("age", 11), Kvpair::new("city", "sh")];
+        let res = dispatch(CommandRequest::new_fieldmerge("user", "u1", fields), &store);
+        assert_res_ok(res, &[3.into()], &[]);
+        let res = dispatch(CommandRequest::new_fieldget("user", "u1", "age"), &store);
+        assert_res_ok(res, &[11.into()], &[]);
+        let res = dispatch(CommandRequest::new_fieldget("user", "u1", "name"), &store);
+        assert_res_ok(res, &["tyr".into()], &[]);
+
+        // 不是 map 时返回错误，原来的值不变
+        dispatch(CommandRequest::new_hset("user", "u2", 1), &store);
+        let fields = vec![Kvpair::new("age", 1)];
+        let res = dispatch(CommandRequest::new_fieldmerge("user", "u2", fields), &store);
+        assert_eq!(res.status, 500);
+        let res = dispatch(CommandRequest::new_hget("user", "u2"), &store);
+        assert_res_ok(res, &[1.into()], &[]);
+    }
+
+    #[test]
+    fn hget_binary_should_share_stored_bytes() {
+        // value 只在从 Storage 中读出时 clone 一次，binary 的 clone 只增加引用计数，不复制数据
+        let store = MemTable::new();
+        let data = bytes::Bytes::from(vec![1u8; 1024]);
+        store.set("t", "k", data.clone()).unwrap();
+        let res = dispatch(CommandRequest::new_hget("t", "k"), &store);
+        let Some(value::Value::Binary(returned)) = &res.values[0].value else {
+            panic!("expect binary value");
+        };
+        assert_eq!(returned.as_ptr(), data.as_ptr());
+    }
+
+    #[test]
+    fn hmatch_should_work() {
+        let store = MemTable::new();
+        for key in ["user:1", "user:2", "user:10", "admin:1", "user:a"] {
+            dispatch(CommandRequest::new_hset("t", key, key), &store);
+        }
+        let keys = |pattern: &str| {
+            let res = dispatch(CommandRequest::new_hmatch("t", pattern, false), &store);
+            assert_eq!(res.status, 200);
+            res.values
+        };
+        let expect = |keys: &[&str]| keys.iter().map(|&k| Value::from(k)).collect::<Vec<_>>();
+        assert_eq!(
+            keys("user:*"),
+            expect(&["user:1", "user:10", "user:2", "user:a"])
+        );
+        assert_eq!(keys("user:?"), expect(&["user:1", "user:2", "user:a"]));
+        assert_eq!(keys("*:1*"), expect(&["admin:1", "user:1", "user:10"]));
+        assert_eq!(keys("user:[0-9]"), expect(&["user:1", "user:2"]));
+        assert_eq!(keys("user:[!0-9]"), expect(&["user:a"]));
+        assert_eq!(keys("*:[a1]"), expect(&["admin:1", "user:1", "user:a"]));
+        assert!(keys("guest:*").is_empty());
+        assert!(keys("user").is_empty());
+
+        let res = dispatch(CommandRequest::new_hmatch("t", "admin:*", true), &store);
+        assert_res_ok(res, &[], &[Kvpair::new("admin:1", "admin:1")]);
+    }
+
+    #[test]
+    fn delmatch_should_delete_matching_keys() {
+        let store = MemTable::new();
+        for key in ["user:1", "user:2", "user:10", "admin:1"] {
+            dispatch(CommandRequest::new_hset("t", key, key), &store);
+        }
+        dispatch(CommandRequest::new_hset("t2", "user:1", "other"), &store);
+        let delmatch = |pattern: &str| {
+            let res = dispatch(CommandRequest::new_delmatch("t", pattern), &store);
+            assert_eq!(res.status, 200);
+            res.values
+        };
+
+        // 没有匹配的 key 时什么也不删除
+        assert_eq!(delmatch("guest:*"), [Value::from(0)]);
+        assert_eq!(store.get_all("t").unwrap().len(), 4);
+
+        // 只删除匹配的 key，其它 key 和其它 table 中同名的 key 保留
+        assert_eq!(delmatch("user:?"), [Value::from(2)]);
+        let mut keys: Vec<_> = store.get_all("t").unwrap();
+        keys.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(
+            keys,
+            [
+                Kvpair::new("admin:1", "admin:1"),
+                Kvpair::new("user:10", "user:10")
+            ]
+        );
+        assert_eq!(store.get("t2", "user:1").unwrap(), Some("other".into()));
+
+        // 匹配所有的 key
+        assert_eq!(delmatch("*"), [Value::from(2)]);
+        assert!(store.get_all("t").unwrap().is_empty());
+        assert_eq!(delmatch("*"), [Value::from(0)]);
+    }
+
+    #[test]
+    fn glob_should_handle_escape_and_brackets() {
+        let matches = |pattern: &str, text: &str| {
+            let pattern: Vec<char> = pattern.chars().collect();
+            glob_match(&pattern, text)
+        };
+        assert!(matches("a\\*b", "a*b"));
+        assert!(!matches("a\\*b", "axb"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("a[", "a["));
+        assert!(matches("**", ""));
+        assert!(matches("*a*b*c", "xxaxxbxxc"));
+        assert!(!matches("*a*b*c", "xxaxxcxxb"));
+    }
+
+    #[test]
+    fn hmget_should_return_partial_when_some_keys_fail() {
+        let store = FlakyStore(MemTable::new());
+        store.set("t", "k1", "v1").unwrap();
+        store.set("t", "bad1", "v").unwrap();
+
+        let cmd = CommandRequest::new_hmget("t", vec!["k1", "bad1", "k2"]);
+        let res = dispatch(cmd, &store);
+        assert_eq!(res.status, 206);
+        assert!(res.partial);
+        assert!(res.message.contains("bad1"));
+        assert!(!res.message.contains("k1"));
+        assert_eq!(
+            res.values,
+            [Value::from("v1"), Value::default(), Value::default()]
+        );
+
+        // 所有的 key 都读取失败时返回错误
+        let res = dispatch(CommandRequest::new_hmget("t", vec!["bad1", "bad2"]), &store);
+        assert_res_error(res, 500, "bad1");
+
+        // 没有失败时和之前一样
+        let res = dispatch(CommandRequest::new_hmget("t", vec!["k1"]), &store);
+        assert_res_ok(res, &["v1".into()], &[]);
+    }
+
+    // 读取以 bad 开头的 key 时失败的存储
+    struct FlakyStore(MemTable);
+
+    impl Storage for FlakyStore {
+        fn get(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+            match key.starts_with("bad") {
+                true => Err(KvError::Internal(format!("Failed to read {key}"))),
+                false => self.0.get(table, key),
+            }
+        }
+
+        fn set(
+            &self,
+            table: &str,
+            key: impl Into<String>,
+            value: impl Into<Value>,
+        ) -> Result<Option<Value>, KvError> {
+            self.0.set(table, key, value)
+        }
+
+        fn contains(&self, table: &str, key: &str) -> Result<bool, KvError> {
+            self.0.contains(table, key)
+        }
+
+        fn del(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+            self.0.del(table, key)
+        }
+
+        fn mtime(&self, table: &str, key: &str) -> Result<Option<i64>, KvError> {
+            self.0.mtime(table, key)
+        }
+
+        fn tables(&self) -> Result<Vec<String>, KvError> {
+            self.0.tables()
+        }
+
+        fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError> {
+            self.0.get_all(table)
+        }
+
+        fn get_iter(&self, table: &str) -> Result<impl Iterator<Item = Kvpair>, KvError> {
+            self.0.get_iter(table)
+        }
+    }
+
+    #[test]
+    fn time_should_return_server_time() {
+        let store = MemTable::new();
+        let before = Timestamp::from(SystemTime::now());
+        let res = dispatch(CommandRequest::new_time(), &store);
+        let after = Timestamp::from(SystemTime::now());
+        assert_eq!(res.status, 200);
+        let Some(value::Value::Timestamp(t)) = &res.values[0].value else {
+            panic!("expect a timestamp, got {:?}", res.values);
+        };
+        assert!((0..1_000_000_000).contains(&t.nanos));
+        let key = |t: &Timestamp| (t.seconds, t.nanos);
+        assert!(key(&before) <= key(t) && key(t) <= key(&after));
+    }
+
+    #[test]
+    fn hmincr_should_work() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("t", "a", 10), &store);
+        // 新的 key 从 0 开始，重复的 key 依次累加
+        let cmd = CommandRequest::new_hmincr("t", vec![("a", 1), ("b", 2), ("a", 3)]);
+        assert_res_ok(
+            dispatch(cmd, &store),
+            &[11.into(), 2.into(), 14.into()],
+            &[],
+        );
+        let cmd =
+            CommandRequest::new_hmincr_all_or_nothing("t", vec![("c", -1), ("b", 1), ("b", 1)]);
+        assert_res_ok(
+            dispatch(cmd, &store),
+            &[(-1).into(), 3.into(), 4.into()],
+            &[],
+        );
+        let cmd = CommandRequest::new_hmget("t", vec!["a", "b", "c"]);
+        assert_res_ok(
+            dispatch(cmd, &store),
+            &[14.into(), 4.into(), (-1).into()],
+            &[],
+        );
+    }
+
+    #[test]
+    fn hmincr_should_handle_invalid_keys() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("t", "a", 1), &store);
+        dispatch(CommandRequest::new_hset("t", "s", "text"), &store);
+        dispatch(CommandRequest::new_hset("t", "max", i64::MAX), &store);
+
+        // 出错的 key 保持不变，其它 key 照常修改
+        let cmd = CommandRequest::new_hmincr("t", vec![("a", 1), ("s", 1), ("max", 1), ("n", 5)]);
+        let res = dispatch(cmd, &store);
+        assert_eq!(res.status, 206);
+        assert!(res.partial);
+        assert!(res.message.contains("s, max"), "{}", res.message);
+        let expected = [2.into(), Value::default(), Value::default(), 5.into()];
+        assert_eq!(res.values, expected);
+        let res = dispatch(CommandRequest::new_hget("t", "s"), &store);
+        assert_res_ok(res, &["text".into()], &[]);
+
+        // 所有 key 都出错时返回错误
+        let cmd = CommandRequest::new_hmincr("t", vec![("s", 1)]);
+        assert_res_error(dispatch(cmd, &store), 500, "Cannot convert");
+
+        // all_or_nothing 时一个 key 出错所有 key 都不修改
+        let cmd = CommandRequest::new_hmincr_all_or_nothing("t", vec![("a", 1), ("max", 1)]);
+        assert_res_error(dispatch(cmd, &store), 400, "overflow");
+        let cmd = CommandRequest::new_hmget("t", vec!["a", "max"]);
+        assert_res_ok(dispatch(cmd, &store), &[2.into(), i64::MAX.into()], &[]);
+    }
+
+    #[test]
+    fn hmincr_all_or_nothing_should_keep_ttl() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("t", "n", 1), &store);
+        store.expire("t", "n", Duration::from_secs(60)).unwrap();
+        let cmd = CommandRequest::new_hmincr_all_or_nothing("t", vec![("n", 1), ("m", 1)]);
+        assert_res_ok(dispatch(cmd, &store), &[2.into(), 1.into()], &[]);
+        assert!(store.ttl("t", "n").unwrap().is_some());
+        assert_eq!(store.ttl("t", "m").unwrap(), None);
+    }
+
+    #[test]
+    fn lock_should_return_increasing_tokens() {
+        let store = MemTable::new();
+        let res = dispatch(CommandRequest::new_lock("locks", "job", "a", 0), &store);
+        assert_res_ok(res, &[1.into()], &[]);
+        // 持有者再次获取时 token 不变，其它 owner 获取失败
+        let res = dispatch(CommandRequest::new_lock("locks", "job", "a", 0), &store);
+        assert_res_ok(res, &[1.into()], &[]);
+        let res = dispatch(CommandRequest::new_lock("locks", "job", "b", 0), &store);
+        assert_res_error(res, 409, "held by another owner");
+
+        // 只有持有者可以释放
+        let res = dispatch(CommandRequest::new_unlock("locks", "job", "b"), &store);
+        assert_res_ok(res, &[false.into()], &[]);
+        let res = dispatch(CommandRequest::new_unlock("locks", "job", "a"), &store);
+        assert_res_ok(res, &[true.into()], &[]);
+        let res = dispatch(CommandRequest::new_unlock("locks", "job", "a"), &store);
+        assert_res_ok(res, &[false.into()], &[]);
+
+        // 释放后重新获取，token 继续递增
+        let res = dispatch(CommandRequest::new_lock("locks", "job", "b", 0), &store);
+        assert_res_ok(res, &[2.into()], &[]);
+        let res = dispatch(CommandRequest::new_lock("locks", "other", "b", 0), &store);
+        assert_res_ok(res, &[1.into()], &[]);
+    }
+
+    #[test]
+    fn expired_lock_should_be_acquired_by_others() {
+        let store = MemTable::new();
+        let res = dispatch(CommandRequest::new_lock("locks", "job", "a", 10), &store);
+        assert_res_ok(res, &[1.into()], &[]);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+
+        // 过期的锁不能再被原来的持有者释放
+        let res = dispatch(CommandRequest::new_lock("locks", "job", "b", 1000), &store);
+        assert_res_ok(res, &[2.into()], &[]);
+        let res = dispatch(CommandRequest::new_unlock("locks", "job", "a"), &store);
+        assert_res_ok(res, &[false.into()], &[]);
+        let res = dispatch(CommandRequest::new_lock("locks", "job", "a", 1000), &store);
+        assert_res_error(res, 409, "held by another owner");
+    }
+
+    #[test]
+    fn lock_on_invalid_value_should_fail() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("locks", "plain", "value"), &store);
+        let res = dispatch(CommandRequest::new_lock("locks", "plain", "a", 0), &store);
+        assert_res_error(res, 500, "Cannot convert");
+        let res = dispatch(CommandRequest::new_unlock("locks", "plain", "a"), &store);
+        assert_res_error(res, 500, "Cannot convert");
+        let res = dispatch(CommandRequest::new_lock("locks", "job", "", 0), &store);
+        assert_res_error(res, 400, "can not be empty");
+    }
+
+    #[test]
+    fn error_stats_should_reset() {
+        let stats = ErrorStats::new();
+        stats.record(&KvError::NotFound("t".into(), "k".into()));
+        stats.record(&KvError::NotFound("t".into(), "k".into()));
+        stats.record(&KvError::Overloaded);
+        let count = |pairs: &[Kvpair], name| {
+            let pair = pairs.iter().find(|p| p.key == name).unwrap();
+            i64::try_from(pair.value.clone().unwrap()).unwrap()
+        };
+        let pairs = stats.to_pairs(true);
+        assert_eq!(count(&pairs, "NotFound"), 2);
+        assert_eq!(count(&pairs, "Overloaded"), 1);
+        assert_eq!(count(&pairs, "Timeout"), 0);
+        // 清零之后重新计数
+        let pairs = stats.to_pairs(false);
+        assert!(pairs.iter().all(|p| p.value == Some(0.into())));
+    }
+
+    #[test]
+    fn hexpire_should_work() {
+        let store = MemTable::new();
+        let res = dispatch(CommandRequest::new_hexpire("t", "k", 60), &store);
+        assert_res_ok(res, &[false.into()], &[]);
+
+        dispatch(CommandRequest::new_hset_ex("t", "k", "v", 60), &store);
+        let res = dispatch(CommandRequest::new_hget("t", "k"), &store);
+        assert_res_ok(res, &["v".into()], &[]);
+        let res = dispatch(CommandRequest::new_hexpire("t", "k", 0), &store);
+        assert_res_ok(res, &[true.into()], &[]);
+        let res = dispatch(CommandRequest::new_hget("t", "k"), &store);
+        assert_res_error(res, 404, "Not found");
+        let res = dispatch(CommandRequest::new_hgetall("t"), &store);
+        assert_res_ok(res, &[], &[]);
+    }
+
+    #[test]
+    fn hset_ex_should_fail_without_expiration_support() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RocksDB::new(dir);
+        dispatch(CommandRequest::new_hset("t", "k", "old"), &store);
+        let mtime = store.mtime("t", "k").unwrap();
+        let res = dispatch(CommandRequest::new_hset_ex("t", "k", "new", 60), &store);
+        assert_eq!(res.status, 501);
+        // 失败时什么都不写入，保留原来的值和修改时间
+        assert_eq!(store.get("t", "k").unwrap(), Some("old".into()));
+        assert_eq!(store.mtime("t", "k").unwrap(), mtime);
+        let res = dispatch(
+            CommandRequest::new_hset_ex("t", "missing", "new", 60),
+            &store,
+        );
+        assert_eq!(res.status, 501);
+        assert_eq!(store.get("t", "missing").unwrap(), None);
+        let res = dispatch(CommandRequest::new_hexpire("t", "k", 60), &store);
+        assert_eq!(res.status, 501);
+    }
+
+    #[test]
+    fn hincrinit_should_set_ttl_only_on_creation() {
+        let store = MemTable::new();
+        let incr = |key: &str, delta: i64, ttl_ms: u64| {
+            dispatch(
+                CommandRequest::new_hincrinit("t", key, delta, ttl_ms),
+                &store,
+            )
+        };
+        // 创建时设置过期时间，之后的增加不延长过期时间
+        assert_res_ok(incr("window", 5, 100), &[5.into()], &[]);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_res_ok(incr("window", 2, 100), &[7.into()], &[]);
+        std::thread::sleep(Duration::from_millis(80));
+        let res = dispatch(CommandRequest::new_hget("t", "window"), &store);
+        assert_res_error(res, 404, "Not found");
+        // 过期之后重新开始计数
+        assert_res_ok(incr("window", 1, 0), &[1.into()], &[]);
+
+        // 已经存在的 key 不会被设置过期时间
+        dispatch(CommandRequest::new_hset("t", "total", 10), &store);
+        assert_res_ok(incr("total", 1, 10), &[11.into()], &[]);
+        std::thread::sleep(Duration::from_millis(30));
+        let res = dispatch(CommandRequest::new_hget("t", "total"), &store);
+        assert_res_ok(res, &[11.into()], &[]);
+
+        dispatch(CommandRequest::new_hset("t", "name", "v"), &store);
+        let res = incr("name", 1, 10);
+        assert_eq!(res.status, 500);
+        assert_eq!(store.get("t", "name").unwrap(), Some("v".into()));
+    }
+
+    #[test]
+    fn hincr_should_work() {
+        let store = MemTable::new();
+        let incr =
+            |key: &str, delta: i64| dispatch(CommandRequest::new_hincr("t", key, delta), &store);
+        // 不存在的 key 从 0 开始
+        assert_res_ok(incr("n", 5), &[5.into()], &[]);
+        assert_res_ok(incr("n", -7), &[(-2).into()], &[]);
+        let res = dispatch(CommandRequest::new_hget("t", "n"), &store);
+        assert_res_ok(res, &[(-2).into()], &[]);
+
+        // 不是整数的 value 和溢出时保持不变
+        dispatch(CommandRequest::new_hset("t", "s", "text"), &store);
+        assert_res_error(incr("s", 1), 500, "Cannot convert");
+        let res = dispatch(CommandRequest::new_hget("t", "s"), &store);
+        assert_res_ok(res, &["text".into()], &[]);
+        dispatch(CommandRequest::new_hset("t", "max", i64::MAX), &store);
+        assert_res_error(incr("max", 1), 400, "overflow");
+    }
+
+    #[test]
+    fn hincrfloat_should_work() {
+        let store = MemTable::new();
+        let incr = |key: &str, delta: f64| {
+            dispatch(CommandRequest::new_hincrfloat("t", key, delta), &store)
+        };
+        // 不存在的 key 从 0.0 开始
+        assert_res_ok(incr("sum", 1.5), &[1.5.into()], &[]);
+        assert_res_ok(incr("sum", -0.25), &[1.25.into()], &[]);
+
+        // 整数和字符串都不是浮点数，出错时保持不变
+        dispatch(CommandRequest::new_hset("t", "int", 1), &store);
+        assert_res_error(incr("int", 1.0), 500, "Cannot convert");
+        let res = dispatch(CommandRequest::new_hget("t", "int"), &store);
+        assert_res_ok(res, &[1.into()], &[]);
+        assert_res_error(incr("sum", f64::INFINITY), 400, "not a finite number");
+        assert_res_error(incr("sum", f64::NAN), 400, "not a finite number");
+        let res = dispatch(CommandRequest::new_hget("t", "sum"), &store);
+        assert_res_ok(res, &[1.25.into()], &[]);
+    }
+
+    #[test]
+    fn concurrent_hincr_should_not_lose_updates() {
+        // 多个线程同时增加同一个 key，每次增加都不丢失
+        fn run(store: &(impl Storage + Sync)) {
+            std::thread::scope(|s| {
+                for _ in 0..4 {
+                    s.spawn(|| {
+                        for _ in 0..100 {
+                            let res = dispatch(CommandRequest::new_hincr("t", "n", 1), store);
+                            assert_eq!(res.status, 200);
+                            let cmd = CommandRequest::new_hincrfloat("t", "f", 0.5);
+                            assert_eq!(dispatch(cmd, store).status, 200);
+                        }
+                    });
+                }
+            });
+            assert_eq!(store.get("t", "n").unwrap(), Some(400.into()));
+            assert_eq!(store.get("t", "f").unwrap(), Some(200.0.into()));
+        }
+        run(&MemTable::new());
+        let dir = tempfile::tempdir().unwrap();
+        run(&SledDb::new(dir.path()));
+    }
+
+    #[test]
+    fn keyinfo_should_return_metadata() {
+        let store = MemTable::new();
+        let info = |key: &str| {
+            let res = dispatch(CommandRequest::new_keyinfo("t", key), &store);
+            assert_eq!(res.status, 200, "{}", res.message);
+            assert!(res.values.is_empty());
+            res.pairs
+                .into_iter()
+                .map(|pair| (pair.key, pair.value.unwrap()))
+                .collect::<HashMap<_, _>>()
+        };
+        let before = now_millis();
+        dispatch(CommandRequest::new_hset("t", "plain", "hello"), &store);
+        let pairs = info("plain");
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(pairs["type"], "string".into());
+        assert_eq!(
+            pairs["size"],
+            (Value::from("hello").encoded_len() as i64).into()
+        );
+        assert!(i64::try_from(pairs["mtime"].clone()).unwrap() >= before);
+
+        // 设置了过期时间的 key 返回剩余的毫秒数
+        dispatch(CommandRequest::new_hset_ex("t", "temp", 1, 60), &store);
+        let pairs = info("temp");
+        assert_eq!(pairs["type"], "integer".into());
+        let ttl = i64::try_from(pairs["ttl_ms"].clone()).unwrap();
+        assert!(ttl > 0 && ttl <= 60_000, "{ttl}");
+
+        // 锁返回 fencing token
+        dispatch(CommandRequest::new_lock("t", "lock", "a", 0), &store);
+        dispatch(CommandRequest::new_unlock("t", "lock", "a"), &store);
+        dispatch(CommandRequest::new_lock("t", "lock", "b", 0), &store);
+        let pairs = info("lock");
+        assert_eq!(pairs["type"], "map".into());
+        assert_eq!(pairs["token"], 2.into());
+
+        let res = dispatch(CommandRequest::new_keyinfo("t", "missing"), &store);
+        assert_res_error(res, 404, "Not found");
+    }
+
+    #[test]
+    fn push_and_lrange_should_work() {
+        let store = MemTable::new();
+        let res = dispatch(CommandRequest::new_rpush("t", "q", vec!["b", "c"]), &store);
+        assert_res_ok(res, &[2.into()], &[]);
+        // LPUSH 依次插入到头部，插入的元素是倒序的
+        let res = dispatch(CommandRequest::new_lpush("t", "q", vec![1, 2]), &store);
+        assert_res_ok(res, &[4.into()], &[]);
+        let mut cmd = CommandRequest::new_rpush("t", "q", vec![true]);
+        if let Some(RequestData::Rpush(v)) = &mut cmd.request_data {
+            v.return_list = true;
+        }
+        let list = [2.into(), 1.into(), "b".into(), "c".into(), true.into()];
+        assert_res_ok(dispatch(cmd, &store), &list, &[]);
+
+        let lrange =
+            |start, stop| dispatch(CommandRequest::new_lrange("t", "q", start, stop), &store);
+        assert_res_ok(lrange(0, -1), &list, &[]);
+        assert_res_ok(lrange(1, 2), &list[1..3], &[]);
+        assert_res_ok(lrange(-2, 100), &list[3..], &[]);
+        assert_res_ok(lrange(-100, 0), &list[..1], &[]);
+        assert_res_ok(lrange(3, 1), &[], &[]);
+        assert_res_ok(lrange(5, 10), &[], &[]);
+        let res = dispatch(CommandRequest::new_lrange("t", "missing", 0, -1), &store);
+        assert_res_ok(res, &[], &[]);
+
+        // 不是列表的 value 保持不变
+        dispatch(CommandRequest::new_hset("t", "s", "text"), &store);
+        let res = dispatch(CommandRequest::new_lpush("t", "s", vec!["v"]), &store);
+        assert_res_error(res, 500, "Cannot convert");
+        let res = dispatch(CommandRequest::new_lrange("t", "s", 0, -1), &store);
+        assert_res_error(res, 500, "Cannot convert");
+        let res = dispatch(CommandRequest::new_hget("t", "s"), &store);
+        assert_res_ok(res, &["text".into()], &[]);
+    }
+
+    #[test]
+    fn list_should_round_trip_in_sled() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SledDb::new(dir.path());
+        dispatch(CommandRequest::new_rpush("t", "q", vec!["a", "b"]), &store);
+        let expected = List {
+            values: vec!["a".into(), "b".into()],
+        };
+        assert_eq!(store.get("t", "q").unwrap(), Some(expected.into()));
+    }
+
+    #[test]
+    fn hkeys_and_hvals_should_work() {
+        let store = MemTable::new();
+        assert_res_ok(dispatch(CommandRequest::new_hkeys("t"), &store), &[], &[]);
+        assert_res_ok(dispatch(CommandRequest::new_hvals("t"), &store), &[], &[]);
+
+        let cmd = CommandRequest::new_hmset("t", vec![Kvpair::new("a", 1), Kvpair::new("b", "v")]);
+        dispatch(cmd, &store);
+        dispatch(CommandRequest::new_hset("other", "c", 3), &store);
+        let mut res = dispatch(CommandRequest::new_hkeys("t"), &store);
+        assert!(res.pairs.is_empty());
+        res.values.sort_by(|a, b| a.compare(b).unwrap());
+        assert_res_ok(res, &["a".into(), "b".into()], &[]);
+        let mut res = dispatch(CommandRequest::new_hvals("t"), &store);
+        res.values.sort_by_key(|v| v.type_name());
+        assert_res_ok(res, &[1.into(), "v".into()], &[]);
+    }
+
+    #[test]
+    fn hkeys_should_be_limited() {
+        let store = MemTable::new();
+        for i in 0..10 {
+            store.set("t", format!("key{i}"), "v".repeat(100)).unwrap();
+        }
+        // 只有 key 计入结果的大小
+        let res = Hkeys { table: "t".into() }.execute_with_limit(&store, 1000, None);
+        assert_eq!(res.status, 200);
+        assert_eq!(res.values.len(), 10);
+        let res = Hvals { table: "t".into() }.execute_with_limit(&store, 500, None);
+        assert_eq!(res.status, 206);
+        assert!(res.partial);
+        assert_eq!(res.values.len(), 4);
+    }
+
+    #[test]
+    fn hlen_should_work() {
+        let store = MemTable::new();
+        assert_res_ok(
+            dispatch(CommandRequest::new_hlen("t"), &store),
+            &[0.into()],
+            &[],
+        );
+        for i in 0..3 {
+            dispatch(CommandRequest::new_hset("t", format!("k{i}"), i), &store);
+        }
+        dispatch(CommandRequest::new_hset("other", "k", 1), &store);
+        dispatch(CommandRequest::new_hdel("t", "k0"), &store);
+        assert_res_ok(
+            dispatch(CommandRequest::new_hlen("t"), &store),
+            &[2.into()],
+            &[],
+        );
+    }
+
+    #[test]
+    fn hsetnx_should_only_set_absent_keys() {
+        let store = MemTable::new();
+        let res = dispatch(CommandRequest::new_hsetnx("t", "k", "v1"), &store);
+        assert_res_ok(res, &[true.into()], &[]);
+        let mtime = dispatch(CommandRequest::new_hmtime("t", "k"), &store);
+        // key 已经存在时什么都不写入，修改时间不变
+        std::thread::sleep(Duration::from_millis(5));
+        let res = dispatch(CommandRequest::new_hsetnx("t", "k", "v2"), &store);
+        assert_res_ok(res, &[false.into()], &[]);
+        let res = dispatch(CommandRequest::new_hget("t", "k"), &store);
+        assert_res_ok(res, &["v1".into()], &[]);
+        let res = dispatch(CommandRequest::new_hmtime("t", "k"), &store);
+        assert_eq!(res.values, mtime.values);
+        // 删除之后可以重新写入
+        dispatch(CommandRequest::new_hdel("t", "k"), &store);
+        let res = dispatch(CommandRequest::new_hsetnx("t", "k", "v3"), &store);
+        assert_res_ok(res, &[true.into()], &[]);
+    }
+
+    #[test]
+    fn concurrent_hsetnx_should_succeed_once() {
+        fn run(store: &(impl Storage + Sync)) {
+            for i in 0..20 {
+                let key = format!("lock{i}");
+                let written: Vec<bool> = std::thread::scope(|s| {
+                    let handles: Vec<_> = (0..2)
+                        .map(|owner| {
+                            let cmd = CommandRequest::new_hsetnx("t", &key, owner);
+                            s.spawn(move || dispatch(cmd, store).values[0] == Value::from(true))
+                        })
+                        .collect();
+                    handles.into_iter().map(|h| h.join().unwrap()).collect()
+                });
+                assert_eq!(written.iter().filter(|&&w| w).count(), 1, "{key}");
+            }
+        }
+        run(&MemTable::new());
+        let dir = tempfile::tempdir().unwrap();
+        run(&SledDb::new(dir.path()));
+    }
+
+    #[test]
+    fn hcas_should_swap_only_on_match() {
+        let store = MemTable::new();
+        let cas = |expected: Option<Value>, new: &str| {
+            dispatch(CommandRequest::new_hcas("t", "k", expected, new), &store)
+        };
+        // 没有 expected 时只有 key 不存在才写入
+        assert_res_ok(cas(None, "v1"), &[true.into(), Value::default()], &[]);
+        let mtime = dispatch(CommandRequest::new_hmtime("t", "k"), &store);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_res_ok(cas(None, "v2"), &[false.into(), "v1".into()], &[]);
+        // 比较失败时什么都不写入，修改时间不变
+        let res = dispatch(CommandRequest::new_hmtime("t", "k"), &store);
+        assert_eq!(res.values, mtime.values);
+
+        // 不相等时返回 key 当前的值
+        assert_res_ok(
+            cas(Some("v0".into()), "v2"),
+            &[false.into(), "v1".into()],
+            &[],
+        );
+        assert_res_ok(
+            cas(Some("v1".into()), "v2"),
+            &[true.into(), "v1".into()],
+            &[],
+        );
+        let res = dispatch(CommandRequest::new_hget("t", "k"), &store);
+        assert_res_ok(res, &["v2".into()], &[]);
+
+        // 类型不同的 value 不相等
+        dispatch(CommandRequest::new_hset("t", "n", 1), &store);
+        let cmd = CommandRequest::new_hcas("t", "n", Some(1.0.into()), 2);
+        assert_res_ok(dispatch(cmd, &store), &[false.into(), 1.into()], &[]);
+        let cmd = CommandRequest::new_hcas("t", "missing", Some(1.into()), 2);
+        assert_res_ok(
+            dispatch(cmd, &store),
+            &[false.into(), Value::default()],
             &[],
         );
+        let res = dispatch(CommandRequest::new_hget("t", "missing"), &store);
+        assert_res_error(res, 404, "Not found");
+    }
+
+    #[test]
+    fn hrename_should_work() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("t", "staging", "v2"), &store);
+        dispatch(CommandRequest::new_hset("t", "prod", "v1"), &store);
+
+        // 覆盖 to 时返回被覆盖的值，from 被删除
+        let cmd = CommandRequest::new_hrename("t", "staging", "prod");
+        assert_res_ok(dispatch(cmd, &store), &["v1".into()], &[]);
+        let res = dispatch(CommandRequest::new_hexist("t", "staging"), &store);
+        assert_res_ok(res, &[false.into()], &[]);
+        let res = dispatch(CommandRequest::new_hget("t", "prod"), &store);
+        assert_res_ok(res, &["v2".into()], &[]);
+
+        // to 不存在时返回空的 value，from 不存在时返回 404
+        let cmd = CommandRequest::new_hrename("t", "prod", "new");
+        assert_res_ok(dispatch(cmd.clone(), &store), &[Value::default()], &[]);
+        assert_res_error(dispatch(cmd, &store), 404, "Not found");
+    }
+
+    #[test]
+    fn copytable_should_work() {
+        let store = MemTable::new();
+        dispatch(CommandRequest::new_hset("blue", "a", 1), &store);
+        dispatch(CommandRequest::new_hset("blue", "b", 2), &store);
+        dispatch(CommandRequest::new_hset("green", "a", "old"), &store);
+
+        let cmd = CommandRequest::new_copytable("blue", "green", false);
+        assert_res_ok(dispatch(cmd, &store), &[1.into()], &[]);
+        let res = dispatch(CommandRequest::new_hget("green", "a"), &store);
+        assert_res_ok(res, &["old".into()], &[]);
+
+        let cmd = CommandRequest::new_copytable("blue", "green", true);
+        assert_res_ok(dispatch(cmd, &store), &[2.into()], &[]);
+        let res = dispatch(CommandRequest::new_hget("green", "a"), &store);
+        assert_res_ok(res, &[1.into()], &[]);
+    }
+
+    #[test]
+    fn flushtable_should_work() {
+        let store = MemTable::new();
+        for i in 0..3 {
+            dispatch(CommandRequest::new_hset("t", format!("k{i}"), i), &store);
+        }
+        dispatch(CommandRequest::new_hset("other", "k0", 0), &store);
+
+        let res = dispatch(CommandRequest::new_flushtable("t"), &store);
+        assert_res_ok(res, &[3.into()], &[]);
+        let res = dispatch(CommandRequest::new_hlen("t"), &store);
+        assert_res_ok(res, &[0.into()], &[]);
+        let res = dispatch(CommandRequest::new_hget("other", "k0"), &store);
+        assert_res_ok(res, &[0.into()], &[]);
+
+        // 不存在的 table 返回 0
+        let res = dispatch(CommandRequest::new_flushtable("missing"), &store);
+        assert_res_ok(res, &[0.into()], &[]);
+    }
+
+    #[test]
+    fn tables_should_list_sorted_names() {
+        let store = MemTable::new();
+        let res = dispatch(CommandRequest::new_tables(), &store);
+        assert_res_ok(res, &[], &[]);
+
+        for table in ["users", "orders", "users", "a:b"] {
+            dispatch(CommandRequest::new_hset(table, "k", "v"), &store);
+        }
+        // 删除了所有 key 的 table 不再出现
+        dispatch(CommandRequest::new_hset("empty", "k", "v"), &store);
+        dispatch(CommandRequest::new_hdel("empty", "k"), &store);
+        let res = dispatch(CommandRequest::new_tables(), &store);
+        assert_res_ok(res, &["a:b".into(), "orders".into(), "users".into()], &[]);
     }
 
     // 从 Request 中获得 Responese 目前只处理 HGET/HSET/HGETALL
@@ -296,6 +2941,61 @@ mod tests {
             RequestData::Hmdel(v) => v.execute(store),
             RequestData::Hmexist(v) => v.execute(store),
             RequestData::Hgetall(v) => v.execute(store),
+            RequestData::Compact(v) => v.execute(store),
+            RequestData::Metrics(_) => unreachable!("Metrics is executed by Service"),
+            RequestData::Hmove(v) => v.execute(store),
+            RequestData::Hmerge(v) => v.execute(store),
+            RequestData::Hdeleq(v) => v.execute(store),
+            RequestData::Hmtime(v) => v.execute(store),
+            RequestData::Findbyvalue(v) => v.execute(store),
+            RequestData::Hincrfield(v) => v.execute(store),
+            RequestData::Fieldget(v) => v.execute(store),
+            RequestData::Fieldset(v) => v.execute(store),
+            RequestData::Memusage(v) => v.execute(store),
+            RequestData::Hsetchunk(_) => unreachable!("Hsetchunk is executed by Service"),
+            RequestData::Hwait(_) => unreachable!("Hwait is executed by Service"),
+            RequestData::Quit(v) => v.execute(store),
+            RequestData::Selftest(v) => v.execute(store),
+            RequestData::Whoami(_) => unreachable!("Whoami is executed by ProstServerStream"),
+            RequestData::Hsetif(v) => v.execute(store),
+            RequestData::Commands(v) => v.execute(store),
+            RequestData::Hgetset(v) => v.execute(store),
+            RequestData::Pausetable(_) => unreachable!("Pausetable is executed by Service"),
+            RequestData::Resumetable(_) => unreachable!("Resumetable is executed by Service"),
+            RequestData::Replacetable(v) => v.execute(store),
+            RequestData::Difftables(v) => v.execute(store),
+            RequestData::Fieldmerge(v) => v.execute(store),
+            RequestData::Hmatch(v) => v.execute(store),
+            RequestData::Tableversion(_) => unreachable!("Tableversion is executed by Service"),
+            RequestData::Replacetableif(_) => unreachable!("Replacetableif is executed by Service"),
+            RequestData::Time(v) => v.execute(store),
+            RequestData::Multi(_) => unreachable!("Multi is executed by ProstServerStream"),
+            RequestData::Exec(_) => unreachable!("Exec is executed by ProstServerStream"),
+            RequestData::Discard(_) => unreachable!("Discard is executed by ProstServerStream"),
+            RequestData::Fieldmget(v) => v.execute(store),
+            RequestData::Hmincr(v) => v.execute(store),
+            RequestData::Lock(v) => v.execute(store),
+            RequestData::Unlock(v) => v.execute(store),
+            RequestData::Errorstats(_) => unreachable!("Errorstats is executed by Service"),
+            RequestData::Delmatch(v) => v.execute(store),
+            RequestData::Hexpire(v) => v.execute(store),
+            RequestData::Hincrinit(v) => v.execute(store),
+            RequestData::Hincr(v) => v.execute(store),
+            RequestData::Keyinfo(v) => v.execute(store),
+            RequestData::Hincrfloat(v) => v.execute(store),
+            RequestData::Lpush(v) => v.execute(store),
+            RequestData::Rpush(v) => v.execute(store),
+            RequestData::Lrange(v) => v.execute(store),
+            RequestData::Hkeys(v) => v.execute(store),
+            RequestData::Hvals(v) => v.execute(store),
+            RequestData::Hlen(v) => v.execute(store),
+            RequestData::Hsetnx(v) => v.execute(store),
+            RequestData::Hcas(v) => v.execute(store),
+            RequestData::Transaction(_) => unreachable!("Transaction is executed by Service"),
+            RequestData::Hrename(v) => v.execute(store),
+            RequestData::Copytable(v) => v.execute(store),
+            RequestData::Flushtable(v) => v.execute(store),
+            RequestData::Tables(v) => v.execute(store),
         }
     }
 }
@@ -0,0 +1,463 @@
+use std::fmt::{self, Write};
+
+use crate::{command_request::RequestData, value, CommandRequest, CommandResponse, Kvpair, Value};
+
+/// 日志中 string 和 binary 的 value 缺省保留的长度（字符数或者字节数）
+pub const DEFAULT_LOG_VALUE_LEN: usize = 64;
+
+/// 替换需要隐藏的 value 的内容
+pub const REDACTED: &str = "***";
+
+/// 日志中记录请求和响应的详细程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogPolicy {
+    /// 只记录命令的类型和响应的状态码
+    CommandOnly,
+    /// 记录完整的请求和响应，超过给定长度的 string 和 binary 被截断
+    Truncated(usize),
+    /// 原样记录完整的请求和响应，value 可能很大或者包含敏感数据，只应该在开发环境中使用
+    Full,
+}
+
+impl Default for LogPolicy {
+    fn default() -> Self {
+        Self::Truncated(DEFAULT_LOG_VALUE_LEN)
+    }
+}
+
+/// 判断 table 中 key 的 value 是否需要在日志中显示为 `***`。
+/// 不属于某个 key 的 value（比如 FINDBYVALUE 查找的 value）key 为空字符串
+pub type Redactor = fn(table: &str, key: &str) -> bool;
+
+/// 按照 LogPolicy 和 Redactor 生成请求和响应在日志中的内容
+#[derive(Debug, Default)]
+pub struct RequestLog {
+    policy: LogPolicy,
+    redactor: Option<Redactor>,
+}
+
+/// 请求读写的 table 和 key，用来判断对应的响应中哪些 value 需要隐藏
+#[derive(Debug, Default)]
+pub struct LogScope {
+    // 请求中的某个 key 需要隐藏时，响应中所有的 value 都隐藏
+    redacted: bool,
+    // HGETALL 等返回整个 table 的命令，按 pair 的 key 逐个判断
+    tables: Vec<String>,
+}
+
+impl RequestLog {
+    pub fn set_policy(&mut self, policy: LogPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn set_redactor(&mut self, redactor: Redactor) {
+        self.redactor = Some(redactor);
+    }
+
+    /// 请求在日志中的内容
+    pub fn request(&self, cmd: &CommandRequest) -> String {
+        let Some(data) = &cmd.request_data else {
+            return format!("{cmd:?}");
+        };
+        if self.policy == LogPolicy::CommandOnly {
+            return command_name(data);
+        }
+        if self.policy == LogPolicy::Full && self.redactor.is_none() {
+            return format!("{cmd:?}");
+        }
+        let mut cmd = cmd.clone();
+        if let Some(data) = &mut cmd.request_data {
+            for_each_value(data, |table, key, value| {
+                self.hide(self.redacts(table, key), value)
+            });
+            // 分块上传的数据不是 Value，单独处理
+            if let RequestData::Hsetchunk(v) = data {
+                if self.redacts(&v.table, &v.key) {
+                    v.data = REDACTED.into();
+                } else if let LogPolicy::Truncated(len) = self.policy {
+                    v.data.truncate(len);
+                }
+            }
+        }
+        format!("{cmd:?}")
+    }
+
+    /// 请求对应的 LogScope，没有设置 Redactor 时不需要记录
+    pub fn scope(&self, cmd: &CommandRequest) -> LogScope {
+        let (Some(redactor), Some(data)) = (self.redactor, &cmd.request_data) else {
+            return LogScope::default();
+        };
+        let (tables, keys) = touched(data);
+        let redacted = tables
+            .iter()
+            .any(|table| keys.iter().any(|key| redactor(table, key)));
+        LogScope {
+            redacted,
+            tables: tables.into_iter().map(String::from).collect(),
+        }
+    }
+
+    /// 响应在日志中的内容，scope 来自响应对应的请求
+    pub fn response(&self, res: &CommandResponse, scope: &LogScope) -> String {
+        match self.policy {
+            LogPolicy::CommandOnly => format!("status: {}", res.status),
+            LogPolicy::Full if self.redactor.is_none() => format!("{res:?}"),
+            _ => {
+                let mut res = res.clone();
+                self.hide_response(&mut res, scope);
+                format!("{res:?}")
+            }
+        }
+    }
+
+    fn hide_response(&self, res: &mut CommandResponse, scope: &LogScope) {
+        for value in &mut res.values {
+            self.hide(scope.redacted, value);
+        }
+        for pair in &mut res.pairs {
+            let redacted = scope.redacted
+                || scope
+                    .tables
+                    .iter()
+                    .any(|table| self.redacts(table, &pair.key));
+            if let Some(value) = &mut pair.value {
+                self.hide(redacted, value);
+            }
+        }
+        for res in &mut res.responses {
+            self.hide_response(res, scope);
+        }
+    }
+
+    fn redacts(&self, table: &str, key: &str) -> bool {
+        self.redactor.is_some_and(|f| f(table, key))
+    }
+
+    fn hide(&self, redacted: bool, value: &mut Value) {
+        if redacted {
+            *value = REDACTED.into();
+        } else if let LogPolicy::Truncated(len) = self.policy {
+            truncate(value, len);
+        }
+    }
+}
+
+// 截断 value 中过长的 string 和 binary，包括嵌套在 map、register 和 list 中的 value
+fn truncate(value: &mut Value, len: usize) {
+    match &mut value.value {
+        Some(value::Value::String(s)) => {
+            if let Some((i, _)) = s.char_indices().nth(len) {
+                s.truncate(i);
+                s.push_str("...");
+            }
+        }
+        Some(value::Value::Binary(data)) => data.truncate(len),
+        Some(value::Value::Map(map)) => {
+            for value in map.fields.iter_mut().filter_map(|f| f.value.as_mut()) {
+                truncate(value, len);
+            }
+        }
+        Some(value::Value::Register(register)) => {
+            for value in &mut register.values {
+                truncate(value, len);
+            }
+        }
+        Some(value::Value::List(list)) => {
+            for value in &mut list.values {
+                truncate(value, len);
+            }
+        }
+        _ => {}
+    }
+}
+
+// 命令的类型，也就是 RequestData 的 Debug 输出中 `(` 之前的部分。
+// 写到 `(` 时返回错误结束格式化，不会格式化命令的内容
+fn command_name(data: &RequestData) -> String {
+    struct Name(String);
+
+    impl Write for Name {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            match s.find('(') {
+                Some(i) => {
+                    self.0.push_str(&s[..i]);
+                    Err(fmt::Error)
+                }
+                None => {
+                    self.0.push_str(s);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    let mut name = Name(String::new());
+    let _ = write!(name, "{data:?}");
+    name.0
+}
+
+// 依次访问请求中的每个 value 和它所属的 table、key
+fn for_each_value(data: &mut RequestData, mut f: impl FnMut(&str, &str, &mut Value)) {
+    visit_values(data, &mut f)
+}
+
+// TRANSACTION 中的命令递归访问，使用 dyn 避免泛型无限展开
+fn visit_values(data: &mut RequestData, f: &mut dyn FnMut(&str, &str, &mut Value)) {
+    let (table, values): (&str, Vec<(&str, &mut Value)>) = match data {
+        RequestData::Hset(v) => (&v.table, pair_values(v.pair.as_mut_slice())),
+        RequestData::Hsetnx(v) => (&v.table, pair_values(v.pair.as_mut_slice())),
+        RequestData::Hmset(v) => (&v.table, pair_values(&mut v.pairs)),
+        RequestData::Replacetable(v) => (&v.table, pair_values(&mut v.pairs)),
+        RequestData::Replacetableif(v) => (&v.table, pair_values(&mut v.pairs)),
+        // map 中的 field 属于 key
+        RequestData::Fieldmerge(v) => {
+            let key = v.key.as_str();
+            let values = v
+                .fields
+                .iter_mut()
+                .filter_map(|p| Some((key, p.value.as_mut()?)));
+            (&v.table, values.collect())
+        }
+        RequestData::Hmerge(v) => (&v.table, one_value(&v.key, &mut v.value)),
+        RequestData::Hdeleq(v) => (&v.table, one_value(&v.key, &mut v.expected)),
+        RequestData::Findbyvalue(v) => (&v.table, one_value("", &mut v.value)),
+        RequestData::Fieldset(v) => (&v.table, one_value(&v.key, &mut v.value)),
+        RequestData::Hsetif(v) => (&v.table, one_value(&v.key, &mut v.value)),
+        RequestData::Hgetset(v) => (&v.table, one_value(&v.key, &mut v.value)),
+        RequestData::Hcas(v) => {
+            let values = v.expected.iter_mut().chain(v.new.iter_mut());
+            (
+                &v.table,
+                values.map(|value| (v.key.as_str(), value)).collect(),
+            )
+        }
+        // 列表中的元素属于 key
+        RequestData::Lpush(v) => (&v.table, list_values(&v.key, &mut v.values)),
+        RequestData::Rpush(v) => (&v.table, list_values(&v.key, &mut v.values)),
+        RequestData::Transaction(v) => {
+            for data in v
+                .commands
+                .iter_mut()
+                .filter_map(|c| c.request_data.as_mut())
+            {
+                visit_values(data, f);
+            }
+            return;
+        }
+        _ => return,
+    };
+    for (key, value) in values {
+        f(table, key, value);
+    }
+}
+
+fn pair_values(pairs: &mut [Kvpair]) -> Vec<(&str, &mut Value)> {
+    let values = pairs
+        .iter_mut()
+        .filter_map(|Kvpair { key, value }| Some((key.as_str(), value.as_mut()?)));
+    values.collect()
+}
+
+fn one_value<'a>(key: &'a str, value: &'a mut Option<Value>) -> Vec<(&'a str, &'a mut Value)> {
+    value.iter_mut().map(|value| (key, value)).collect()
+}
+
+fn list_values<'a>(key: &'a str, values: &'a mut [Value]) -> Vec<(&'a str, &'a mut Value)> {
+    values.iter_mut().map(|value| (key, value)).collect()
+}
+
+// 请求读写的 table 和 key，key 属于所有的 table。只涉及 table 的命令 key 为空
+fn touched(data: &RequestData) -> (Vec<&str>, Vec<&str>) {
+    match data {
+        RequestData::Hget(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hgetall(v) => (vec![&v.table], vec![]),
+        RequestData::Hmget(v) => (vec![&v.table], keys(&v.keys)),
+        RequestData::Hset(v) => (vec![&v.table], pair_keys(v.pair.as_slice())),
+        RequestData::Hmset(v) => (vec![&v.table], pair_keys(&v.pairs)),
+        RequestData::Hdel(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hmdel(v) => (vec![&v.table], keys(&v.keys)),
+        RequestData::Hexist(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hmexist(v) => (vec![&v.table], keys(&v.keys)),
+        RequestData::Hmove(v) => (vec![&v.from_table, &v.to_table], vec![&v.key]),
+        RequestData::Hrename(v) => (vec![&v.table], vec![&v.from, &v.to]),
+        RequestData::Copytable(v) => (vec![&v.src, &v.dst], vec![]),
+        RequestData::Flushtable(v) => (vec![&v.table], vec![]),
+        RequestData::Hmerge(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hdeleq(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hmtime(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Findbyvalue(v) => (vec![&v.table], vec![]),
+        RequestData::Hincrfield(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Fieldget(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Fieldset(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hsetchunk(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hwait(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hsetif(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hgetset(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Replacetable(v) => (vec![&v.table], pair_keys(&v.pairs)),
+        RequestData::Difftables(v) => (vec![&v.table_a, &v.table_b], vec![]),
+        RequestData::Fieldmerge(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hmatch(v) => (vec![&v.table], vec![]),
+        RequestData::Replacetableif(v) => (vec![&v.table], pair_keys(&v.pairs)),
+        RequestData::Fieldmget(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Delmatch(v) => (vec![&v.table], vec![]),
+        RequestData::Hexpire(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hincrinit(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hincr(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Keyinfo(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hincrfloat(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Lpush(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Rpush(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Lrange(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Hkeys(v) => (vec![&v.table], vec![]),
+        RequestData::Hvals(v) => (vec![&v.table], vec![]),
+        RequestData::Hlen(v) => (vec![&v.table], vec![]),
+        RequestData::Hsetnx(v) => (vec![&v.table], pair_keys(v.pair.as_slice())),
+        RequestData::Hcas(v) => (vec![&v.table], vec![&v.key]),
+        RequestData::Transaction(v) => {
+            let (mut tables, mut keys) = (Vec::new(), Vec::new());
+            for data in v.commands.iter().filter_map(|c| c.request_data.as_ref()) {
+                let (t, k) = touched(data);
+                tables.extend(t);
+                keys.extend(k);
+            }
+            (tables, keys)
+        }
+        _ => (vec![], vec![]),
+    }
+}
+
+fn keys(keys: &[String]) -> Vec<&str> {
+    keys.iter().map(String::as_str).collect()
+}
+
+fn pair_keys(pairs: &[Kvpair]) -> Vec<&str> {
+    pairs.iter().map(|p| p.key.as_str()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{MemTable, Service, ServiceInner};
+
+    fn secret(_table: &str, key: &str) -> bool {
+        key.starts_with("password")
+    }
+
+    fn redacting_log(policy: LogPolicy) -> RequestLog {
+        let mut log = RequestLog::default();
+        log.set_policy(policy);
+        log.set_redactor(secret);
+        log
+    }
+
+    #[test]
+    fn command_only_should_not_log_values() {
+        let log = redacting_log(LogPolicy::CommandOnly);
+        let cmd = CommandRequest::new_hset("t", "k", "visible");
+        assert_eq!(log.request(&cmd), "Hset");
+        let res: CommandResponse = Value::from("visible").into();
+        assert_eq!(log.response(&res, &log.scope(&cmd)), "status: 200");
+    }
+
+    #[test]
+    fn truncated_should_shorten_long_values() {
+        let log = redacting_log(LogPolicy::Truncated(4));
+        let cmd = CommandRequest::new_hset("t", "k", "abcdefgh");
+        let logged = log.request(&cmd);
+        assert!(logged.contains("\"abcd...\""), "{logged}");
+        assert!(!logged.contains("abcdefgh"));
+
+        let res: CommandResponse = vec![Kvpair::new("k", "中文中文中文")].into();
+        let logged = log.response(&res, &log.scope(&cmd));
+        assert!(logged.contains("中文中文..."), "{logged}");
+
+        // Full 原样记录
+        let mut log = RequestLog::default();
+        log.set_policy(LogPolicy::Full);
+        assert!(log.request(&cmd).contains("abcdefgh"));
+    }
+
+    #[test]
+    fn redacted_values_should_be_hidden() {
+        for policy in [LogPolicy::Truncated(64), LogPolicy::Full] {
+            let log = redacting_log(policy);
+            let pairs = vec![
+                Kvpair::new("password", "s3cret"),
+                Kvpair::new("name", "tyr"),
+            ];
+            let cmd = CommandRequest::new_hmset("t", pairs.clone());
+            let logged = log.request(&cmd);
+            assert!(!logged.contains("s3cret"), "{logged}");
+            assert!(logged.contains(REDACTED) && logged.contains("tyr"));
+
+            // 插入列表的元素属于列表的 key
+            let cmd = CommandRequest::new_rpush("t", "passwords", vec!["s3cret", "old"]);
+            let logged = log.request(&cmd);
+            assert!(
+                !logged.contains("s3cret") && !logged.contains("old"),
+                "{logged}"
+            );
+
+            // 返回整个 table 的命令按 pair 的 key 隐藏
+            let cmd = CommandRequest::new_hgetall("t");
+            let logged = log.response(&pairs.clone().into(), &log.scope(&cmd));
+            assert!(!logged.contains("s3cret"), "{logged}");
+            assert!(logged.contains(REDACTED) && logged.contains("tyr"));
+
+            // 请求中的 key 需要隐藏时，响应中的 value 都隐藏
+            let cmd = CommandRequest::new_hmget("t", vec!["name", "password"]);
+            let res: CommandResponse = vec![Value::from("tyr"), Value::from("s3cret")].into();
+            let logged = log.response(&res, &log.scope(&cmd));
+            assert!(
+                !logged.contains("s3cret") && !logged.contains("tyr"),
+                "{logged}"
+            );
+        }
+    }
+
+    // 把日志写到内存中，测试结束后检查日志的内容
+    #[derive(Clone, Default)]
+    struct Logs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn redacted_values_should_not_appear_in_service_logs() {
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let service: Service = ServiceInner::new(MemTable::new())
+            .with_log_policy(LogPolicy::Full)
+            .with_log_redactor(secret)
+            .into();
+        tracing::subscriber::with_default(subscriber, || {
+            service.execute(CommandRequest::new_hset("t", "password", "s3cret"));
+            service.execute(CommandRequest::new_hset("t", "name", "tyr"));
+            let res = service.execute(CommandRequest::new_hget("t", "password"));
+            assert_eq!(res.values, [Value::from("s3cret")]);
+            service.execute(CommandRequest::new_hgetall("t"));
+            let cmd = CommandRequest::new_hget("t", "password");
+            tracing::info!("Got a new command: {}", service.format_request(&cmd));
+        });
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("Executed response"), "{logs}");
+        assert!(logs.contains("tyr"));
+        assert!(!logs.contains("s3cret"), "{logs}");
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::{KvError, Kvpair, ERROR_CATEGORIES};
+
+/// Service 运行过程中的统计数据，全部使用原子变量，读写开销都很小
+#[derive(Debug, Default)]
+pub struct ServiceMetrics {
+    // 收到的命令总数
+    commands: AtomicU64,
+    // 返回非 2xx 状态码的命令数
+    errors: AtomicU64,
+    // 当前活跃的连接数
+    active_connections: AtomicU64,
+    // 累计连接数
+    total_connections: AtomicU64,
+    // 收到的命令字节数（压缩前）
+    bytes_received: AtomicU64,
+    // 发送的响应字节数（压缩前）
+    bytes_sent: AtomicU64,
+    // 正在执行的命令数
+    in_flight: AtomicU64,
+    // 因为过载而被拒绝的命令数
+    shed: AtomicU64,
+    // 每类错误的次数
+    error_stats: Arc<ErrorStats>,
+}
+
+/// 一个正在执行的命令，释放时减少正在执行的命令数
+#[derive(Debug)]
+pub struct InFlightGuard<'a>(&'a ServiceMetrics);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ServiceMetrics {
+    pub fn command_received(&self) {
+        self.commands.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn command_failed(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_opened(&self) {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        self.total_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_closed(&self) {
+        self.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn bytes_received(&self, n: usize) {
+        self.bytes_received.fetch_add(n as _, Ordering::Relaxed);
+    }
+
+    pub fn bytes_sent(&self, n: usize) {
+        self.bytes_sent.fetch_add(n as _, Ordering::Relaxed);
+    }
+
+    /// 开始执行一个命令。正在执行的命令数已经达到 limit 时拒绝执行，返回 None 并计入被拒绝的命令数
+    pub fn try_start_command(&self, limit: Option<u64>) -> Option<InFlightGuard<'_>> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::Relaxed);
+        let guard = InFlightGuard(self);
+        if limit.is_some_and(|limit| in_flight >= limit) {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(guard)
+    }
+
+    /// 每类错误发生的次数
+    pub fn error_stats(&self) -> &ErrorStats {
+        &self.error_stats
+    }
+
+    /// 执行 f，期间当前线程中转换成 CommandResponse 的错误都计入这个 Service 的 error_stats
+    pub fn count_errors<R>(&self, f: impl FnOnce() -> R) -> R {
+        let previous = CURRENT_ERROR_STATS.replace(Some(Arc::clone(&self.error_stats)));
+        let _restore = RestoreErrorStats(previous);
+        f()
+    }
+
+    /// 把所有统计数据转换成 metric 名称到数值的 kv pair
+    pub fn to_pairs(&self) -> Vec<Kvpair> {
+        [
+            ("commands_total", &self.commands),
+            ("errors_total", &self.errors),
+            ("connections_active", &self.active_connections),
+            ("connections_total", &self.total_connections),
+            ("bytes_received_total", &self.bytes_received),
+            ("bytes_sent_total", &self.bytes_sent),
+            ("commands_in_flight", &self.in_flight),
+            ("commands_shed_total", &self.shed),
+        ]
+        .into_iter()
+        .map(|(name, v)| Kvpair::new(name, v.load(Ordering::Relaxed) as i64))
+        .collect()
+    }
+}
+
+thread_local! {
+    // 当前线程正在为哪个 Service 执行命令，见 ServiceMetrics::count_errors
+    static CURRENT_ERROR_STATS: RefCell<Option<Arc<ErrorStats>>> = const { RefCell::new(None) };
+}
+
+// count_errors 结束（包括 panic）时恢复之前的 error_stats，嵌套的 count_errors 互不影响
+struct RestoreErrorStats(Option<Arc<ErrorStats>>);
+
+impl Drop for RestoreErrorStats {
+    fn drop(&mut self) {
+        CURRENT_ERROR_STATS.set(self.0.take());
+    }
+}
+
+/// 每类错误（见 ERROR_CATEGORIES）发生的次数
+#[derive(Debug)]
+pub struct ErrorStats([AtomicU64; ERROR_CATEGORIES.len()]);
+
+impl Default for ErrorStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ErrorStats {
+    pub const fn new() -> Self {
+        Self([const { AtomicU64::new(0) }; ERROR_CATEGORIES.len()])
+    }
+
+    pub fn record(&self, e: &KvError) {
+        let category = e.category();
+        if let Some(i) = ERROR_CATEGORIES.iter().position(|&c| c == category) {
+            self.0[i].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 计入当前线程正在执行命令的 Service，不在 Service 中执行时不计数
+    pub(crate) fn record_current(e: &KvError) {
+        CURRENT_ERROR_STATS.with_borrow(|stats| {
+            if let Some(stats) = stats {
+                stats.record(e);
+            }
+        });
+    }
+
+    /// 把每类错误的次数转换成类别到次数的 kv pair。reset 为 true 时同时清零，
+    /// 每个计数器的读取和清零是一个原子操作，读取之后发生的错误会计入下一次的结果，不会丢失
+    pub fn to_pairs(&self, reset: bool) -> Vec<Kvpair> {
+        ERROR_CATEGORIES
+            .iter()
+            .zip(&self.0)
+            .map(|(&name, v)| {
+                let n = match reset {
+                    true => v.swap(0, Ordering::Relaxed),
+                    false => v.load(Ordering::Relaxed),
+                };
+                Kvpair::new(name, n as i64)
+            })
+            .collect()
+    }
+}
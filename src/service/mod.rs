@@ -1,10 +1,32 @@
 use crate::{
-    command_request::RequestData, CommandRequest, CommandResponse, KvError, MemTable, Storage,
+    command_request::RequestData, CommandRequest, CommandResponse, Hsetchunk, Hwait, KvError,
+    Kvpair, MemTable, Replacetable, Replacetableif, Staged, Storage, Value,
 };
-use std::sync::Arc;
-use tracing::debug;
+use http::StatusCode;
+use std::{collections::HashSet, sync::Arc, time::Duration};
+use tokio::time::{timeout_at, Instant};
+use tracing::{debug, enabled, Level};
 
+mod alias;
 mod command_service;
+mod logging;
+mod metrics;
+mod tables;
+mod upload;
+mod watch;
+
+pub use alias::{DeprecationWarner, DEPRECATED_COMMANDS};
+pub use command_service::SELFTEST_TABLE;
+pub use logging::{LogPolicy, Redactor, DEFAULT_LOG_VALUE_LEN, REDACTED};
+pub use metrics::{ErrorStats, InFlightGuard, ServiceMetrics};
+pub use tables::{PausedTables, TableLimit, TableVersions};
+pub use upload::{UploadLimits, UploadProgress, UploadSessions, DEFAULT_UPLOAD_TIMEOUT};
+pub use watch::{KeyWatch, KeyWatchers};
+
+use logging::{LogScope, RequestLog};
+
+/// HGETALL 等返回整个 table 的命令，缺省允许的最大结果大小（编码后的字节数）
+pub const DEFAULT_MAX_RESULT_SIZE: usize = 16 * 1024 * 1024;
 
 /// 对command的处理的抽象
 pub trait CommandService {
@@ -12,6 +34,13 @@ pub trait CommandService {
     fn execute(self, store: &impl Storage) -> CommandResponse;
 }
 
+/// 针对某些命令的中间件，在命令执行之前检查或者修改命令。返回 Some 时命令不再执行，
+/// 直接把返回的 CommandResponse 作为结果
+pub type Middleware = fn(&mut CommandRequest) -> Option<CommandResponse>;
+
+/// 选择中间件作用的命令，比如 `|data| matches!(data, RequestData::Hset(_))`
+pub type CommandMatcher = fn(&RequestData) -> bool;
+
 /// 事件通知（不可变事件）
 pub trait Notify<Arg> {
     fn notify(&self, arg: &Arg);
@@ -46,6 +75,14 @@ pub struct Service<Store = MemTable> {
     inner: Arc<ServiceInner<Store>>,
 }
 
+// 命令成功写入之后要做的事情：增加 table 的版本号、唤醒等待这些 key 的 HWAIT。
+// TRANSACTION 中的命令在提交之后才做
+#[derive(Debug, Default)]
+struct Pending {
+    tables: Vec<String>,
+    keys: Vec<(String, String)>,
+}
+
 impl<Store> Clone for Service<Store> {
     fn clone(&self) -> Self {
         Self {
@@ -55,24 +92,477 @@ impl<Store> Clone for Service<Store> {
 }
 
 impl<Store: Storage> Service<Store> {
+    /// 执行命令。HWAIT 在这里不会等待，key 不存在时立即返回 504，需要等待时使用 execute_async
     pub fn execute(&self, cmd: CommandRequest) -> CommandResponse {
-        debug!("Got request: {:?}", cmd);
-        self.inner.on_received.notify(&cmd);
-        let mut res = dispatch(cmd, &self.inner.store);
-        debug!("Executed response: {:?}", res);
+        self.execute_with(cmd, false)
+    }
+
+    /// 依次执行事务（MULTI 和 EXEC 之间排队）中的命令，把每个命令的结果按顺序放在 responses 中。
+    /// 执行期间持有这些命令要修改的所有 table 的独占锁，其它写入这些 table 的命令不会穿插在中间，
+    /// 但读取不受影响，可能看到只执行了一部分的事务。某个命令失败时后面的命令依旧执行，不会回滚。
+    /// 事务中的 HWAIT 不等待，key 不存在时立即返回 504
+    pub fn execute_transaction(&self, cmds: Vec<CommandRequest>) -> CommandResponse {
+        let tables: Vec<String> = cmds
+            .iter()
+            .filter_map(|cmd| cmd.request_data.as_ref())
+            .flat_map(modified_tables)
+            .map(String::from)
+            .collect();
+        let tables: Vec<_> = tables.iter().map(String::as_str).collect();
+        let responses = self.inner.versions.exclusive(&tables, || {
+            cmds.into_iter()
+                .map(|cmd| self.execute_with(cmd, true))
+                .collect()
+        });
+        CommandResponse {
+            status: StatusCode::OK.as_u16() as _,
+            responses,
+            ..Default::default()
+        }
+    }
+
+    // locked 为 true 时调用者已经持有了命令要修改的 table 的独占锁
+    fn execute_with(&self, mut cmd: CommandRequest, locked: bool) -> CommandResponse {
+        let metrics = &self.inner.metrics;
+        let deadline = deadline_of(&cmd);
+        let scope = self.received(&cmd);
+        let Some(_guard) = self.admit(&cmd) else {
+            return self.executed(metrics.count_errors(|| KvError::Overloaded.into()), &scope);
+        };
+        let res = metrics.count_errors(|| match self.run_middlewares(&mut cmd) {
+            Some(res) => res,
+            None => self.execute_command(cmd, deadline, locked),
+        });
+        self.executed(res, &scope)
+    }
+
+    /// 执行命令，HWAIT 会在异步运行时上等待 key 被写入，而不会阻塞线程
+    pub async fn execute_async(&self, mut cmd: CommandRequest) -> CommandResponse {
+        let metrics = &self.inner.metrics;
+        let deadline = deadline_of(&cmd);
+        let scope = self.received(&cmd);
+        let Some(_guard) = self.admit(&cmd) else {
+            return self.executed(metrics.count_errors(|| KvError::Overloaded.into()), &scope);
+        };
+        if let Some(res) = metrics.count_errors(|| self.run_middlewares(&mut cmd)) {
+            return self.executed(res, &scope);
+        }
+        // 等待期间可能换到别的线程上执行，只在同步执行的部分计数错误
+        let res = match cmd.request_data {
+            Some(RequestData::Hwait(param)) => match self.wait(param, deadline).await {
+                Ok(v) => v.into(),
+                Err(e) => metrics.count_errors(|| e.into()),
+            },
+            request_data => metrics.count_errors(|| {
+                let cmd = CommandRequest {
+                    request_data,
+                    ..cmd
+                };
+                self.execute_command(cmd, deadline, false)
+            }),
+        };
+        self.executed(res, &scope)
+    }
+
+    // 返回的 LogScope 用于在日志中隐藏响应里对应的 value，不输出 debug 日志时不需要计算
+    fn received(&self, cmd: &CommandRequest) -> LogScope {
+        debug!("Got request: {}", self.inner.log.request(cmd));
+        self.inner.metrics.command_received();
+        self.inner.on_received.notify(cmd);
+        match enabled!(Level::DEBUG) {
+            true => self.inner.log.scope(cmd),
+            false => LogScope::default(),
+        }
+    }
+
+    // 正在执行的命令太多时拒绝新的命令，保证已经接受的命令的延迟。
+    // METRICS 不受限制，过载时依旧可以查看统计数据
+    fn admit(&self, cmd: &CommandRequest) -> Option<InFlightGuard<'_>> {
+        let limit = match cmd.request_data {
+            Some(RequestData::Metrics(_)) => None,
+            _ => self.inner.max_in_flight,
+        };
+        self.inner.metrics.try_start_command(limit)
+    }
+
+    // 按注册的顺序执行匹配的中间件，每个中间件看到的是前面的中间件修改之后的命令。
+    // 某个中间件返回 Some 时，后面的中间件和命令本身都不再执行
+    fn run_middlewares(&self, cmd: &mut CommandRequest) -> Option<CommandResponse> {
+        for (matches, middleware) in &self.inner.middlewares {
+            if cmd.request_data.as_ref().is_some_and(matches) {
+                if let Some(res) = middleware(cmd) {
+                    return Some(res);
+                }
+            }
+        }
+        None
+    }
+
+    fn execute_command(
+        &self,
+        cmd: CommandRequest,
+        deadline: Option<std::time::Instant>,
+        locked: bool,
+    ) -> CommandResponse {
+        let mut pending = Pending::default();
+        let res = self.execute_in(cmd, deadline, locked, &self.inner.store, &mut pending);
+        let tables: Vec<_> = pending.tables.iter().map(String::as_str).collect();
+        self.inner.versions.bump(&tables);
+        for (table, key) in pending.keys {
+            self.inner.watchers.notify(&table, &key);
+        }
+        res
+    }
+
+    // 在 store 上执行命令，locked 时要增加的版本号和要唤醒的 HWAIT 记在 pending 中，由调用者处理
+    fn execute_in(
+        &self,
+        cmd: CommandRequest,
+        deadline: Option<std::time::Instant>,
+        locked: bool,
+        store: &impl Storage,
+        pending: &mut Pending,
+    ) -> CommandResponse {
+        // 在客户端放弃之前还没有开始执行的命令不再执行
+        if deadline.is_some_and(|d| std::time::Instant::now() >= d) {
+            return KvError::DeadlineExceeded.into();
+        }
+        let tables: Vec<String> = match &cmd.request_data {
+            Some(data) => modified_tables(data)
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            None => Vec::new(),
+        };
+        if let Err(e) = self.inner.paused.check(tables.iter().map(String::as_str)) {
+            return e.into();
+        }
+        let written = cmd
+            .request_data
+            .as_ref()
+            .map(written_keys)
+            .unwrap_or_default();
+        // key 已经存在的 HSETNX 和比较失败的 HCAS 什么都没有写入，不改变版本号也不唤醒 HWAIT
+        let conditional = matches!(
+            cmd.request_data,
+            Some(RequestData::Hsetnx(_) | RequestData::Hcas(_))
+        );
+        let has_written = |res: &CommandResponse| {
+            is_success(res) && !(conditional && res.values.first() == Some(&false.into()))
+        };
+        if let Some(limit) = &self.inner.table_limit {
+            let mut tables: Vec<_> = written.iter().map(|(table, _)| table.as_str()).collect();
+            // 分块上传在收到第一个 chunk 时就占用名额，不会在最后一个 chunk 时才失败。
+            // COPYTABLE 写入的 key 事先不知道，同样按 table 占用名额
+            match &cmd.request_data {
+                Some(RequestData::Hsetchunk(v)) => tables.push(&v.table),
+                Some(RequestData::Copytable(v)) => tables.push(&v.dst),
+                _ => {}
+            }
+            if let Err(e) = limit.reserve(&self.inner.store, tables) {
+                return e.into();
+            }
+        }
+        let res = match cmd.request_data {
+            Some(RequestData::Replacetableif(param)) => {
+                self.replace_table_if(param, locked, store, pending)
+            }
+            Some(RequestData::Transaction(param)) => {
+                let tables: Vec<_> = tables.iter().map(String::as_str).collect();
+                match locked {
+                    true => self.execute_batch(param.commands, pending),
+                    false => self
+                        .inner
+                        .versions
+                        .exclusive(&tables, || self.execute_batch(param.commands, pending)),
+                }
+            }
+            // 持有独占锁，其它写入不会穿插在读取和写入所有 key 之间，或者创建 key 和设置过期时间之间
+            Some(data @ (RequestData::Hmincr(_) | RequestData::Hincrinit(_))) if !locked => {
+                let tables: Vec<_> = tables.iter().map(String::as_str).collect();
+                self.inner.versions.exclusive(&tables, || {
+                    let res = dispatch(
+                        CommandRequest {
+                            request_data: Some(data),
+                            ..cmd
+                        },
+                        store,
+                    );
+                    if is_success(&res) {
+                        self.inner.versions.bump(&tables);
+                    }
+                    res
+                })
+            }
+            request_data => {
+                let tables: Vec<_> = tables.iter().map(String::as_str).collect();
+                let cmd = CommandRequest {
+                    request_data,
+                    ..cmd
+                };
+                if locked {
+                    let res = self.run_command(cmd, deadline, store);
+                    if has_written(&res) {
+                        pending.tables.extend(tables.into_iter().map(String::from));
+                    }
+                    res
+                } else {
+                    let run = || self.run_command(cmd, deadline, store);
+                    self.inner.versions.modify(&tables, run, has_written)
+                }
+            }
+        };
+        if has_written(&res) {
+            pending.keys.extend(written);
+        }
+        res
+    }
+
+    fn run_command(
+        &self,
+        cmd: CommandRequest,
+        deadline: Option<std::time::Instant>,
+        store: &impl Storage,
+    ) -> CommandResponse {
+        match cmd.request_data {
+            // 统计数据保存在 Service 中，不经过 Storage
+            Some(RequestData::Metrics(_)) => {
+                let mut pairs = self.inner.metrics.to_pairs();
+                let paused = self.inner.paused.names().len();
+                pairs.push(Kvpair::new("tables_paused", paused as i64));
+                pairs.into()
+            }
+            // 暂停状态保存在 Service 中，返回暂停中的所有 table
+            Some(RequestData::Pausetable(param)) => {
+                self.inner.paused.pause(&param.table);
+                self.paused_tables()
+            }
+            Some(RequestData::Resumetable(param)) => {
+                self.inner.paused.resume(&param.table);
+                self.paused_tables()
+            }
+            // 上传会话保存在 Service 中，所有 chunk 都收到后才写入 Storage
+            Some(RequestData::Hsetchunk(param)) => self.receive_chunk(param),
+            Some(RequestData::Hgetall(param)) => {
+                param.execute_with_limit(store, self.inner.max_result_size, deadline)
+            }
+            Some(RequestData::Difftables(param)) => {
+                param.execute_with_limit(store, self.inner.max_result_size, deadline)
+            }
+            Some(RequestData::Hmatch(param)) => {
+                param.execute_with_limit(store, self.inner.max_result_size, deadline)
+            }
+            Some(RequestData::Delmatch(param)) => param.execute_with_deadline(store, deadline),
+            Some(RequestData::Hkeys(param)) => {
+                param.execute_with_limit(store, self.inner.max_result_size, deadline)
+            }
+            Some(RequestData::Hvals(param)) => {
+                param.execute_with_limit(store, self.inner.max_result_size, deadline)
+            }
+            Some(RequestData::Hwait(param)) => self.wait_now(param),
+            // 版本号保存在 Service 中
+            Some(RequestData::Tableversion(param)) => {
+                Value::from(self.inner.versions.get(&param.table) as i64).into()
+            }
+            // 错误统计和其它统计数据一样保存在 Service 中
+            Some(RequestData::Errorstats(param)) => {
+                let stats = self.inner.metrics.error_stats();
+                stats.to_pairs(param.reset).into()
+            }
+            _ => dispatch(cmd, store),
+        }
+    }
+
+    // 持有 TRANSACTION 要修改的所有 table 的独占锁时，在暂存的修改上依次执行其中的命令，都成功后一次性提交。
+    // 某个命令失败时什么都不写入，返回它的下标。提交之后才增加版本号，要唤醒的 HWAIT 记在 pending 中
+    fn execute_batch(&self, cmds: Vec<CommandRequest>, pending: &mut Pending) -> CommandResponse {
+        if let Some(e) = cmds.iter().find_map(|cmd| check_batchable(cmd).err()) {
+            return e.into();
+        }
+        let staged = Staged::new(&self.inner.store);
+        let mut batch = Pending::default();
+        let mut responses = Vec::with_capacity(cmds.len());
+        for (i, cmd) in cmds.into_iter().enumerate() {
+            // TRANSACTION 本身已经计数、占用了名额，其中的命令直接执行
+            let deadline = deadline_of(&cmd);
+            let res = self.execute_in(cmd, deadline, true, &staged, &mut batch);
+            if is_success(&res) {
+                responses.push(res);
+                continue;
+            }
+            let (status, message) = (res.status, res.message.clone());
+            responses.push(res);
+            return CommandResponse {
+                status,
+                message: format!(
+                    "Command {i} of the transaction failed, nothing was written: {message}"
+                ),
+                pairs: vec![Kvpair::new("failed_index", i as i64)],
+                responses,
+                ..Default::default()
+            };
+        }
+        if let Err(e) = staged.commit() {
+            return e.into();
+        }
+        let tables: Vec<_> = batch.tables.iter().map(String::as_str).collect();
+        self.inner.versions.bump(&tables);
+        pending.keys.append(&mut batch.keys);
+        CommandResponse {
+            status: StatusCode::OK.as_u16() as _,
+            responses,
+            ..Default::default()
+        }
+    }
+
+    // 持有 table 的独占锁时检查版本号并替换，成功时在结果中附上替换后的版本号。
+    // locked 时调用者已经持有了独占锁，处理 pending 时才增加版本号
+    fn replace_table_if(
+        &self,
+        param: Replacetableif,
+        locked: bool,
+        store: &impl Storage,
+        pending: &mut Pending,
+    ) -> CommandResponse {
+        let Replacetableif {
+            table,
+            expected_version,
+            pairs,
+        } = param;
+        let replace = || {
+            let replace = Replacetable {
+                table: table.clone(),
+                pairs,
+            };
+            let mut res = replace.execute(store);
+            if is_success(&res) {
+                let version = expected_version + 1;
+                res.pairs.push(Kvpair::new("version", version as i64));
+            }
+            res
+        };
+        let versions = &self.inner.versions;
+        if !locked {
+            return versions.modify_if(&table, expected_version, replace);
+        }
+        let current = versions.get(&table);
+        if current != expected_version {
+            return KvError::VersionConflict(table, expected_version, current).into();
+        }
+        let res = replace();
+        if is_success(&res) {
+            pending.tables.push(table);
+        }
+        res
+    }
+
+    fn paused_tables(&self) -> CommandResponse {
+        let names = self.inner.paused.names().into_iter().map(Value::from);
+        names.collect::<Vec<_>>().into()
+    }
+
+    fn executed(&self, mut res: CommandResponse, scope: &LogScope) -> CommandResponse {
+        if !is_success(&res) {
+            self.inner.metrics.command_failed();
+        }
+        debug!(
+            "Executed response: {}",
+            self.inner.log.response(&res, scope)
+        );
         self.inner.on_executed.notify(&res);
         self.inner.on_before_send.notify(&mut res);
         if !self.inner.on_before_send.is_empty() {
-            debug!("Modified response: {:?}", res);
+            debug!(
+                "Modified response: {}",
+                self.inner.log.response(&res, scope)
+            );
         }
 
         res
     }
+
+    fn receive_chunk(&self, chunk: Hsetchunk) -> CommandResponse {
+        let progress = match self.inner.uploads.receive(chunk) {
+            Ok(progress) => progress,
+            Err(e) => return e.into(),
+        };
+        if let UploadProgress::Complete { table, key, data } = &progress {
+            if let Err(e) = self.inner.store.set(table, key.clone(), data.clone()) {
+                return e.into();
+            }
+            self.inner.watchers.notify(table, key);
+        }
+        progress.to_pairs().into()
+    }
+
+    // 等待 key 被写入，直到超时或者超过了请求的 deadline
+    async fn wait(
+        &self,
+        param: Hwait,
+        request_deadline: Option<std::time::Instant>,
+    ) -> Result<Value, KvError> {
+        let mut deadline = Instant::now() + Duration::from_millis(param.timeout_ms);
+        if let Some(d) = request_deadline {
+            deadline = deadline.min(Instant::from_std(d));
+        }
+        let watch = self.inner.watchers.watch(&param.table, &param.key);
+        loop {
+            // 先开始监听再检查 key，避免错过检查之后、开始等待之前的写入
+            let notified = watch.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if let Some(v) = self.inner.store.get(&param.table, &param.key)? {
+                return Ok(v);
+            }
+            if timeout_at(deadline, notified).await.is_err() {
+                return Err(KvError::Timeout(param.table, param.key));
+            }
+        }
+    }
+
+    // 不等待的 HWAIT，key 不存在时直接超时
+    fn wait_now(&self, param: Hwait) -> CommandResponse {
+        match self.inner.store.get(&param.table, &param.key) {
+            Ok(Some(v)) => v.into(),
+            Ok(None) => KvError::Timeout(param.table, param.key).into(),
+            Err(e) => e.into(),
+        }
+    }
+
+    /// 按照 Service 的日志配置（见 `ServiceInner::with_log_policy`）在日志中显示请求
+    pub fn format_request(&self, cmd: &CommandRequest) -> String {
+        self.inner.log.request(cmd)
+    }
+
+    /// 获取 Service 的统计数据
+    pub fn metrics(&self) -> &ServiceMetrics {
+        &self.inner.metrics
+    }
+
+    /// 证书指纹为 fingerprint 的客户端是否可以执行 PAUSETABLE 等控制命令和 DELMATCH。
+    /// 没有设置管理员时所有客户端都可以执行
+    pub fn is_admin(&self, fingerprint: Option<&str>) -> bool {
+        match &self.inner.admins {
+            Some(admins) => fingerprint.is_some_and(|f| admins.contains(f)),
+            None => true,
+        }
+    }
 }
 
 /// Service 内部数据结构
 pub struct ServiceInner<Store> {
     store: Store,
+    metrics: ServiceMetrics,
+    max_result_size: usize,
+    max_in_flight: Option<u64>,
+    table_limit: Option<TableLimit>,
+    paused: PausedTables,
+    versions: TableVersions,
+    admins: Option<HashSet<String>>,
+    uploads: UploadSessions,
+    watchers: KeyWatchers,
+    log: RequestLog,
+    middlewares: Vec<(CommandMatcher, Middleware)>,
     on_received: Vec<fn(&CommandRequest)>,
     on_executed: Vec<fn(&CommandResponse)>,
     on_before_send: Vec<fn(&mut CommandResponse)>,
@@ -83,6 +573,17 @@ impl<Store: Storage> ServiceInner<Store> {
     pub fn new(store: Store) -> Self {
         Self {
             store,
+            metrics: ServiceMetrics::default(),
+            max_result_size: DEFAULT_MAX_RESULT_SIZE,
+            max_in_flight: None,
+            table_limit: None,
+            paused: PausedTables::default(),
+            versions: TableVersions::default(),
+            admins: None,
+            uploads: UploadSessions::default(),
+            watchers: KeyWatchers::new(),
+            log: RequestLog::default(),
+            middlewares: Vec::new(),
             on_received: Vec::new(),
             on_executed: Vec::new(),
             on_before_send: Vec::new(),
@@ -90,6 +591,65 @@ impl<Store: Storage> ServiceInner<Store> {
         }
     }
 
+    /// 设置 HGETALL 等命令允许的最大结果大小，超过时返回 206 和不超过大小的部分结果
+    pub fn with_max_result_size(mut self, size: usize) -> Self {
+        self.max_result_size = size;
+        self
+    }
+
+    /// 限制同时执行的命令数，超过时新的命令直接返回 503，缺省不限制
+    pub fn with_max_in_flight(mut self, n: u64) -> Self {
+        self.max_in_flight = Some(n);
+        self
+    }
+
+    /// 限制 table 的总数，写入新 table 的命令超过限制时返回 507，已有 table 的写入不受影响。缺省不限制
+    pub fn with_max_tables(mut self, n: usize) -> Self {
+        self.table_limit = Some(TableLimit::new(n));
+        self
+    }
+
+    /// 只允许证书指纹（见 `PeerIdentity`）在 fingerprints 中的客户端通过连接执行 PAUSETABLE 等控制命令、DELMATCH 和 FLUSHTABLE，
+    /// 其它客户端返回 403。缺省所有客户端都可以执行
+    pub fn with_admins(
+        mut self,
+        fingerprints: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.admins = Some(fingerprints.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// 设置分块上传的超时时间，超过这个时间没有收到新 chunk 的上传会被丢弃
+    pub fn with_upload_timeout(mut self, timeout: Duration) -> Self {
+        self.uploads.set_timeout(timeout);
+        self
+    }
+
+    /// 设置分块上传的 chunk 数、数据大小和同时进行的上传个数的限制，缺省为 `UploadLimits::default()`
+    pub fn with_upload_limits(mut self, limits: UploadLimits) -> Self {
+        self.uploads.set_limits(limits);
+        self
+    }
+
+    /// 设置日志中记录请求和响应的详细程度，缺省截断超过 DEFAULT_LOG_VALUE_LEN 的 string 和 binary
+    pub fn with_log_policy(mut self, policy: LogPolicy) -> Self {
+        self.log.set_policy(policy);
+        self
+    }
+
+    /// redactor 选中的 key 的 value 在请求和响应的日志中都显示为 `***`，不受 LogPolicy 影响
+    pub fn with_log_redactor(mut self, redactor: Redactor) -> Self {
+        self.log.set_redactor(redactor);
+        self
+    }
+
+    /// 注册只作用于 matches 选中的命令的中间件，中间件在 fn_received 之后、命令执行之前按注册的顺序执行。
+    /// 中间件返回的 CommandResponse 和正常的结果一样会触发 fn_executed
+    pub fn fn_middleware(mut self, matches: CommandMatcher, f: Middleware) -> Self {
+        self.middlewares.push((matches, f));
+        self
+    }
+
     pub fn fn_received(mut self, f: fn(&CommandRequest)) -> Self {
         self.on_received.push(f);
         self
@@ -116,8 +676,117 @@ impl<Store: Storage> From<ServiceInner<Store>> for Service<Store> {
     }
 }
 
+// 请求的 deadline，从收到请求开始计算
+fn deadline_of(cmd: &CommandRequest) -> Option<std::time::Instant> {
+    let ms = cmd.deadline_ms?;
+    Some(std::time::Instant::now() + Duration::from_millis(ms))
+}
+
+fn is_success(res: &CommandResponse) -> bool {
+    StatusCode::from_u16(res.status as _).is_ok_and(|s| s.is_success())
+}
+
+// 需要连接的信息、改变连接状态，或者执行结果保存在 Service 中无法恢复的命令不能在 TRANSACTION 中执行
+fn check_batchable(cmd: &CommandRequest) -> Result<(), KvError> {
+    let name = match &cmd.request_data {
+        None => "empty command",
+        Some(RequestData::Multi(_)) => "MULTI",
+        Some(RequestData::Exec(_)) => "EXEC",
+        Some(RequestData::Discard(_)) => "DISCARD",
+        Some(RequestData::Quit(_)) => "QUIT",
+        Some(RequestData::Whoami(_)) => "WHOAMI",
+        Some(RequestData::Transaction(_)) => "TRANSACTION",
+        Some(RequestData::Pausetable(_)) => "PAUSETABLE",
+        Some(RequestData::Resumetable(_)) => "RESUMETABLE",
+        Some(RequestData::Hsetchunk(_)) => "HSETCHUNK",
+        // 等待的写入会被独占锁挡住
+        Some(RequestData::Hwait(_)) => "HWAIT",
+        // TRANSACTION 中的命令不经过权限检查
+        Some(RequestData::Delmatch(_)) => "DELMATCH",
+        Some(RequestData::Flushtable(_)) => "FLUSHTABLE",
+        Some(_) => return Ok(()),
+    };
+    Err(KvError::InvaildCommand(format!(
+        "{name} can not be used in a transaction"
+    )))
+}
+
+// 命令成功执行后会写入的 key，用于唤醒等待这些 key 的 HWAIT
+fn written_keys(data: &RequestData) -> Vec<(String, String)> {
+    let key = |table: &str, key: &str| (table.to_string(), key.to_string());
+    match data {
+        RequestData::Hset(v) => v.pair.iter().map(|p| key(&v.table, &p.key)).collect(),
+        RequestData::Hmset(v) => v.pairs.iter().map(|p| key(&v.table, &p.key)).collect(),
+        RequestData::Hmove(v) => vec![key(&v.to_table, &v.key)],
+        RequestData::Hrename(v) => vec![key(&v.table, &v.to)],
+        RequestData::Hmerge(v) => vec![key(&v.table, &v.key)],
+        RequestData::Hincrfield(v) => vec![key(&v.table, &v.key)],
+        RequestData::Fieldset(v) => vec![key(&v.table, &v.key)],
+        RequestData::Hsetif(v) => vec![key(&v.table, &v.key)],
+        RequestData::Hgetset(v) => vec![key(&v.table, &v.key)],
+        RequestData::Replacetable(v) => v.pairs.iter().map(|p| key(&v.table, &p.key)).collect(),
+        RequestData::Replacetableif(v) => v.pairs.iter().map(|p| key(&v.table, &p.key)).collect(),
+        RequestData::Fieldmerge(v) => vec![key(&v.table, &v.key)],
+        RequestData::Hmincr(v) => v.pairs.iter().map(|p| key(&v.table, &p.key)).collect(),
+        RequestData::Hincrinit(v) => vec![key(&v.table, &v.key)],
+        RequestData::Hincr(v) => vec![key(&v.table, &v.key)],
+        RequestData::Hincrfloat(v) => vec![key(&v.table, &v.key)],
+        RequestData::Lpush(v) => vec![key(&v.table, &v.key)],
+        RequestData::Rpush(v) => vec![key(&v.table, &v.key)],
+        RequestData::Hsetnx(v) => v.pair.iter().map(|p| key(&v.table, &p.key)).collect(),
+        RequestData::Hcas(v) => vec![key(&v.table, &v.key)],
+        RequestData::Lock(v) => vec![key(&v.table, &v.key)],
+        RequestData::Unlock(v) => vec![key(&v.table, &v.key)],
+        _ => Vec::new(),
+    }
+}
+
+// 命令会修改的 table，被暂停的 table 不能修改，修改成功后 table 的版本号加一
+fn modified_tables(data: &RequestData) -> Vec<&str> {
+    match data {
+        RequestData::Hset(v) => vec![&v.table],
+        RequestData::Hmset(v) => vec![&v.table],
+        RequestData::Hdel(v) => vec![&v.table],
+        RequestData::Hmdel(v) => vec![&v.table],
+        RequestData::Hmove(v) => vec![&v.from_table, &v.to_table],
+        RequestData::Hrename(v) => vec![&v.table],
+        RequestData::Copytable(v) => vec![&v.dst],
+        RequestData::Flushtable(v) => vec![&v.table],
+        RequestData::Hmerge(v) => vec![&v.table],
+        RequestData::Hdeleq(v) => vec![&v.table],
+        RequestData::Hincrfield(v) => vec![&v.table],
+        RequestData::Fieldset(v) => vec![&v.table],
+        RequestData::Hsetchunk(v) => vec![&v.table],
+        RequestData::Hsetif(v) => vec![&v.table],
+        RequestData::Hgetset(v) => vec![&v.table],
+        RequestData::Replacetable(v) => vec![&v.table],
+        RequestData::Replacetableif(v) => vec![&v.table],
+        RequestData::Fieldmerge(v) => vec![&v.table],
+        RequestData::Hmincr(v) => vec![&v.table],
+        RequestData::Lock(v) => vec![&v.table],
+        RequestData::Unlock(v) => vec![&v.table],
+        RequestData::Delmatch(v) => vec![&v.table],
+        RequestData::Hexpire(v) => vec![&v.table],
+        RequestData::Hincrinit(v) => vec![&v.table],
+        RequestData::Hincr(v) => vec![&v.table],
+        RequestData::Hincrfloat(v) => vec![&v.table],
+        RequestData::Lpush(v) => vec![&v.table],
+        RequestData::Rpush(v) => vec![&v.table],
+        RequestData::Hsetnx(v) => vec![&v.table],
+        RequestData::Hcas(v) => vec![&v.table],
+        RequestData::Transaction(v) => v
+            .commands
+            .iter()
+            .filter_map(|cmd| cmd.request_data.as_ref())
+            .flat_map(modified_tables)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
 pub fn dispatch(cmd: CommandRequest, store: &impl Storage) -> CommandResponse {
-    match cmd.request_data {
+    // 废弃的命令先转换成替代它的命令
+    match cmd.request_data.map(alias::resolve) {
         Some(RequestData::Hget(param)) => param.execute(store),
         Some(RequestData::Hset(param)) => param.execute(store),
         Some(RequestData::Hdel(param)) => param.execute(store),
@@ -127,6 +796,88 @@ pub fn dispatch(cmd: CommandRequest, store: &impl Storage) -> CommandResponse {
         Some(RequestData::Hmdel(param)) => param.execute(store),
         Some(RequestData::Hmexist(param)) => param.execute(store),
         Some(RequestData::Hgetall(param)) => param.execute(store),
+        Some(RequestData::Compact(param)) => param.execute(store),
+        Some(RequestData::Metrics(_)) => {
+            KvError::InvaildCommand("Metrics is only available through Service".into()).into()
+        }
+        Some(RequestData::Hmove(param)) => param.execute(store),
+        Some(RequestData::Hmerge(param)) => param.execute(store),
+        Some(RequestData::Hdeleq(param)) => param.execute(store),
+        Some(RequestData::Hmtime(param)) => param.execute(store),
+        Some(RequestData::Findbyvalue(param)) => param.execute(store),
+        Some(RequestData::Hincrfield(param)) => param.execute(store),
+        Some(RequestData::Fieldget(param)) => param.execute(store),
+        Some(RequestData::Fieldset(param)) => param.execute(store),
+        Some(RequestData::Memusage(param)) => param.execute(store),
+        Some(RequestData::Hsetchunk(_)) => {
+            KvError::InvaildCommand("Hsetchunk is only available through Service".into()).into()
+        }
+        Some(RequestData::Hwait(_)) => {
+            KvError::InvaildCommand("Hwait is only available through Service".into()).into()
+        }
+        Some(RequestData::Quit(param)) => param.execute(store),
+        Some(RequestData::Selftest(param)) => param.execute(store),
+        Some(RequestData::Whoami(_)) => {
+            KvError::InvaildCommand("Whoami is only available through a connection".into()).into()
+        }
+        Some(RequestData::Hsetif(param)) => param.execute(store),
+        Some(RequestData::Commands(param)) => param.execute(store),
+        Some(RequestData::Hgetset(param)) => param.execute(store),
+        Some(RequestData::Pausetable(_)) => {
+            KvError::InvaildCommand("Pausetable is only available through Service".into()).into()
+        }
+        Some(RequestData::Resumetable(_)) => {
+            KvError::InvaildCommand("Resumetable is only available through Service".into()).into()
+        }
+        Some(RequestData::Replacetable(param)) => param.execute(store),
+        Some(RequestData::Difftables(param)) => param.execute(store),
+        Some(RequestData::Fieldmerge(param)) => param.execute(store),
+        Some(RequestData::Hmatch(param)) => param.execute(store),
+        Some(RequestData::Tableversion(_)) => {
+            KvError::InvaildCommand("Tableversion is only available through Service".into()).into()
+        }
+        Some(RequestData::Replacetableif(_)) => {
+            KvError::InvaildCommand("Replacetableif is only available through Service".into())
+                .into()
+        }
+        Some(RequestData::Time(param)) => param.execute(store),
+        Some(RequestData::Multi(_)) => {
+            KvError::InvaildCommand("Multi is only available through a connection".into()).into()
+        }
+        Some(RequestData::Exec(_)) => {
+            KvError::InvaildCommand("Exec is only available through a connection".into()).into()
+        }
+        Some(RequestData::Discard(_)) => {
+            KvError::InvaildCommand("Discard is only available through a connection".into()).into()
+        }
+        Some(RequestData::Fieldmget(param)) => param.execute(store),
+        Some(RequestData::Hmincr(param)) => param.execute(store),
+        Some(RequestData::Lock(param)) => param.execute(store),
+        Some(RequestData::Unlock(param)) => param.execute(store),
+        Some(RequestData::Errorstats(_)) => {
+            KvError::InvaildCommand("Errorstats is only available through Service".into()).into()
+        }
+        Some(RequestData::Delmatch(param)) => param.execute(store),
+        Some(RequestData::Hexpire(param)) => param.execute(store),
+        Some(RequestData::Hincrinit(param)) => param.execute(store),
+        Some(RequestData::Hincr(param)) => param.execute(store),
+        Some(RequestData::Keyinfo(param)) => param.execute(store),
+        Some(RequestData::Hincrfloat(param)) => param.execute(store),
+        Some(RequestData::Lpush(param)) => param.execute(store),
+        Some(RequestData::Rpush(param)) => param.execute(store),
+        Some(RequestData::Lrange(param)) => param.execute(store),
+        Some(RequestData::Hkeys(param)) => param.execute(store),
+        Some(RequestData::Hvals(param)) => param.execute(store),
+        Some(RequestData::Hlen(param)) => param.execute(store),
+        Some(RequestData::Hsetnx(param)) => param.execute(store),
+        Some(RequestData::Hcas(param)) => param.execute(store),
+        Some(RequestData::Transaction(_)) => {
+            KvError::InvaildCommand("Transaction is only available through Service".into()).into()
+        }
+        Some(RequestData::Hrename(param)) => param.execute(store),
+        Some(RequestData::Copytable(param)) => param.execute(store),
+        Some(RequestData::Flushtable(param)) => param.execute(store),
+        Some(RequestData::Tables(param)) => param.execute(store),
         None => KvError::InvaildCommand("Request has no data".into()).into(),
     }
 }
@@ -135,11 +886,10 @@ pub fn dispatch(cmd: CommandRequest, store: &impl Storage) -> CommandResponse {
 mod tests {
     use std::thread;
 
-    use http::StatusCode;
     use tracing::info;
 
     use super::*;
-    use crate::{MemTable, Value};
+    use crate::{MemTable, Value, ERROR_CATEGORIES};
 
     #[test]
     fn service_should_work() {
@@ -192,10 +942,493 @@ mod tests {
         assert_eq!(res.message, "");
         assert_eq!(res.values, vec![Value::default()]);
     }
-}
 
-#[cfg(test)]
-use crate::{Kvpair, Value};
+    #[test]
+    fn middleware_should_only_apply_to_matched_commands() {
+        // 只允许 HSET 写入字符串
+        fn validate(cmd: &mut CommandRequest) -> Option<CommandResponse> {
+            let Some(RequestData::Hset(param)) = &cmd.request_data else {
+                return None;
+            };
+            let value = param.pair.as_ref().and_then(|p| p.value.as_ref());
+            match value.map(|v| v.type_name()) {
+                Some("string") => None,
+                _ => Some(KvError::InvaildCommand("HSET only accepts strings".into()).into()),
+            }
+        }
+        // 把 HGET 的 table 改成 users
+        fn rewrite(cmd: &mut CommandRequest) -> Option<CommandResponse> {
+            if let Some(RequestData::Hget(param)) = &mut cmd.request_data {
+                param.table = "users".into();
+            }
+            None
+        }
+        let service: Service = ServiceInner::new(MemTable::new())
+            .fn_middleware(|data| matches!(data, RequestData::Hset(_)), validate)
+            .fn_middleware(|data| matches!(data, RequestData::Hget(_)), rewrite)
+            .into();
+
+        let res = service.execute(CommandRequest::new_hset("users", "u1", 1));
+        assert_res_error(res, 400, "only accepts strings");
+        let res = service.execute(CommandRequest::new_hset("users", "u1", "tyr"));
+        assert_res_ok(res, &[Value::default()], &[]);
+        // 其它命令不受 validate 影响
+        let res = service.execute(CommandRequest::new_hmset(
+            "users",
+            vec![Kvpair::new("u2", 2)],
+        ));
+        assert_res_ok(res, &[Value::default()], &[]);
+
+        let res = service.execute(CommandRequest::new_hget("other", "u1"));
+        assert_res_ok(res, &["tyr".into()], &[]);
+    }
+
+    #[test]
+    fn transaction_should_work() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        service.execute(CommandRequest::new_hset("t", "a", 1));
+        let res = service.execute_transaction(vec![
+            CommandRequest::new_hset("t", "a", 2),
+            CommandRequest::new_hget("t", "missing"),
+            CommandRequest::new_replacetableif("t", 2, vec![Kvpair::new("b", 3)]),
+            CommandRequest::new_hgetall("t"),
+        ]);
+        assert_eq!(res.status, 200);
+        let [set, get, replace, all] = &res.responses[..] else {
+            panic!("expect 4 responses, got {:?}", res.responses);
+        };
+        assert_res_ok(set.clone(), &[1.into()], &[]);
+        // 失败的命令不影响后面的命令
+        assert_eq!(get.status, 404);
+        assert_eq!(replace.status, 200);
+        assert_res_ok(all.clone(), &[], &[Kvpair::new("b", 3)]);
+
+        // 事务中的每个写入都会增加版本号
+        let res = service.execute(CommandRequest::new_tableversion("t"));
+        assert_res_ok(res, &[3.into()], &[]);
+    }
+
+    #[test]
+    fn atomic_transaction_should_roll_back_on_failure() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        service.execute(CommandRequest::new_hset("t", "a", 1));
+        service.execute(CommandRequest::new_hset("t", "s", "string"));
+        service.execute(CommandRequest::new_hexpire("t", "a", 100));
+
+        let res = service.execute(CommandRequest::new_transaction(vec![
+            CommandRequest::new_hset("t", "b", 2),
+            CommandRequest::new_hincr("t", "n", 1),
+        ]));
+        assert_eq!((res.status, res.responses.len()), (200, 2));
+        assert_res_ok(res.responses[1].clone(), &[1.into()], &[]);
+
+        // 第三个命令失败，什么都没有写入，过期时间也保留
+        let res = service.execute(CommandRequest::new_transaction(vec![
+            CommandRequest::new_hset("t", "a", 2),
+            CommandRequest::new_hset("other", "c", 3),
+            CommandRequest::new_hincr("t", "s", 1),
+            CommandRequest::new_hset("t", "d", 4),
+        ]));
+        assert_eq!(res.responses.len(), 3);
+        assert_eq!(res.status, res.responses[2].status);
+        assert!(res.message.contains("Command 2 of the transaction failed"));
+        assert_eq!(res.pairs, &[Kvpair::new("failed_index", 2)]);
+        // 已经执行的命令的结果中也能看到暂存的修改
+        assert_res_ok(res.responses[0].clone(), &[1.into()], &[]);
+        let res = service.execute(CommandRequest::new_hgetall("t"));
+        let mut pairs = res.pairs;
+        pairs.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(
+            pairs,
+            &[
+                Kvpair::new("a", 1),
+                Kvpair::new("b", 2),
+                Kvpair::new("n", 1),
+                Kvpair::new("s", "string")
+            ]
+        );
+        let res = service.execute(CommandRequest::new_hget("other", "c"));
+        assert_eq!(res.status, 404);
+        let res = service.execute(CommandRequest::new_keyinfo("t", "a"));
+        assert!(res.pairs.iter().any(|p| p.key == "ttl_ms"));
+
+        // 不能在其中执行的命令使整个 TRANSACTION 都不执行
+        for cmd in [
+            CommandRequest::new_whoami(),
+            CommandRequest::new_transaction(vec![]),
+        ] {
+            let res = service.execute(CommandRequest::new_transaction(vec![
+                CommandRequest::new_hset("t", "b", 3),
+                cmd,
+            ]));
+            assert_res_error(res, 400, "can not be used in a transaction");
+        }
+        let res = service.execute(CommandRequest::new_hget("t", "b"));
+        assert_res_ok(res, &[2.into()], &[]);
+    }
+
+    #[tokio::test]
+    async fn atomic_transaction_should_notify_after_commit() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        service.execute(CommandRequest::new_hset("t", "s", "string"));
+        let cloned = service.clone();
+        let waiter = tokio::spawn(async move {
+            cloned
+                .execute_async(CommandRequest::new_hwait("t", "job", 1000))
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // 失败的 TRANSACTION 不唤醒 HWAIT，也不增加版本号
+        let res = service.execute(CommandRequest::new_transaction(vec![
+            CommandRequest::new_hset("t", "job", "lost"),
+            CommandRequest::new_hincr("t", "s", 1),
+        ]));
+        assert_eq!(res.pairs, &[Kvpair::new("failed_index", 1)]);
+        let res = service.execute(CommandRequest::new_tableversion("t"));
+        assert_res_ok(res, &[1.into()], &[]);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+
+        // 提交之后才唤醒，每个被修改的 table 的版本号只增加一次
+        let res = service.execute(CommandRequest::new_transaction(vec![
+            CommandRequest::new_replacetableif("t", 1, vec![Kvpair::new("a", 1)]),
+            CommandRequest::new_hset("t", "job", "work"),
+            CommandRequest::new_hget("t", "a"),
+        ]));
+        assert_eq!(res.status, 200);
+        assert_res_ok(res.responses[2].clone(), &[1.into()], &[]);
+        let res = waiter.await.unwrap();
+        assert_res_ok(res, &["work".into()], &[]);
+        let res = service.execute(CommandRequest::new_tableversion("t"));
+        assert_res_ok(res, &[2.into()], &[]);
+
+        // REPLACETABLEIF 比较的是 TRANSACTION 开始之前的版本号
+        let res = service.execute(CommandRequest::new_transaction(vec![
+            CommandRequest::new_hset("t", "b", 2),
+            CommandRequest::new_replacetableif("t", 3, Vec::<Kvpair>::new()),
+        ]));
+        assert_eq!(res.pairs, &[Kvpair::new("failed_index", 1)]);
+        let res = service.execute(CommandRequest::new_hget("t", "b"));
+        assert_res_error(res, 404, "Not found");
+    }
+
+    #[test]
+    fn atomic_transaction_should_take_one_in_flight_slot() {
+        let service: Service = ServiceInner::new(MemTable::new())
+            .with_max_in_flight(1)
+            .into();
+        let res = service.execute(CommandRequest::new_transaction(vec![
+            CommandRequest::new_hset("t", "a", 1),
+            CommandRequest::new_hdel("t", "a"),
+            CommandRequest::new_hset("t", "b", 2),
+        ]));
+        assert_eq!((res.status, res.responses.len()), (200, 3));
+
+        // 其中的命令不单独计数，METRICS 计入它自己
+        let res = service.execute(CommandRequest::new_metrics());
+        assert!(res.pairs.contains(&Kvpair::new("commands_total", 2)));
+        assert!(res.pairs.contains(&Kvpair::new("commands_shed_total", 0)));
+    }
+
+    #[test]
+    fn errorstats_should_count_errors_of_each_service() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        let other: Service = ServiceInner::new(MemTable::new()).into();
+        for _ in 0..3 {
+            service.execute(CommandRequest::new_hget("t", "missing"));
+        }
+        other.execute(CommandRequest::new_hget("t", "missing"));
+        let count = |service: &Service, reset| {
+            let res = service.execute(CommandRequest::new_errorstats(reset));
+            assert_eq!(res.status, 200);
+            let names: Vec<_> = res.pairs.iter().map(|p| p.key.as_str()).collect();
+            assert_eq!(names, ERROR_CATEGORIES);
+            let not_found = res.pairs.iter().find(|p| p.key == "NotFound").unwrap();
+            i64::try_from(not_found.value.clone().unwrap()).unwrap()
+        };
+        // 每个 Service 单独计数，清零也只影响自己
+        assert_eq!(count(&service, true), 3);
+        assert_eq!(count(&other, false), 1);
+        assert_eq!(count(&service, false), 0);
+    }
+
+    #[test]
+    fn unchanged_conditional_writes_should_not_bump_version() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        service.execute(CommandRequest::new_hsetnx("t", "k", "v1"));
+        // key 已经存在的 HSETNX 和比较失败的 HCAS 不改变版本号
+        let res = service.execute(CommandRequest::new_hsetnx("t", "k", "v2"));
+        assert_res_ok(res, &[false.into()], &[]);
+        let cmd = CommandRequest::new_hcas("t", "k", Some("v0".into()), "v2");
+        assert_res_ok(service.execute(cmd), &[false.into(), "v1".into()], &[]);
+        let res = service.execute(CommandRequest::new_tableversion("t"));
+        assert_res_ok(res, &[1.into()], &[]);
+
+        let cmd = CommandRequest::new_hcas("t", "k", Some("v1".into()), "v2");
+        assert_res_ok(service.execute(cmd), &[true.into(), "v1".into()], &[]);
+        let res = service.execute(CommandRequest::new_tableversion("t"));
+        assert_res_ok(res, &[2.into()], &[]);
+    }
+
+    #[test]
+    fn replace_table_if_should_check_version() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        let version = |table: &str| -> i64 {
+            let res = service.execute(CommandRequest::new_tableversion(table));
+            res.values[0].clone().try_into().unwrap()
+        };
+        assert_eq!(version("t"), 0);
+        service.execute(CommandRequest::new_hset("t", "a", 1));
+        let read = version("t");
+        assert_eq!(read, 1);
+        // 读取和其它 table 的修改不改变版本号
+        service.execute(CommandRequest::new_hget("t", "a"));
+        service.execute(CommandRequest::new_hset("other", "a", 1));
+        assert_eq!(version("t"), 1);
+
+        // 读取版本号之后有其它写入，替换失败，table 保持不变
+        service.execute(CommandRequest::new_hset("t", "b", 2));
+        let pairs = vec![Kvpair::new("c", 3)];
+        let cmd = CommandRequest::new_replacetableif("t", read as u64, pairs.clone());
+        assert_res_error(service.execute(cmd), 409, "is 2, expected 1");
+        let res = service.execute(CommandRequest::new_hget("t", "a"));
+        assert_res_ok(res, &[1.into()], &[]);
+
+        // 使用最新的版本号替换成功，返回新的版本号
+        let cmd = CommandRequest::new_replacetableif("t", 2, pairs);
+        assert_res_ok(
+            service.execute(cmd),
+            &[],
+            &[
+                Kvpair::new("inserted", 1),
+                Kvpair::new("removed", 2),
+                Kvpair::new("version", 3),
+            ],
+        );
+        assert_eq!(version("t"), 3);
+        let res = service.execute(CommandRequest::new_hgetall("t"));
+        assert_res_ok(res, &[], &[Kvpair::new("c", 3)]);
+    }
+
+    #[test]
+    fn max_result_size_should_work() {
+        let service: Service = ServiceInner::new(MemTable::new())
+            .with_max_result_size(64)
+            .into();
+        for i in 0..10 {
+            service.execute(CommandRequest::new_hset(
+                "table",
+                format!("key{i}"),
+                "value",
+            ));
+        }
+        // 超过限制的命令返回 206 和不超过限制的部分结果
+        let res = service.execute(CommandRequest::new_hgetall("table"));
+        assert_eq!((res.status, res.partial), (206, true));
+        assert!(res.message.contains("use HSCAN"));
+        assert!(!res.pairs.is_empty() && res.pairs.len() < 10);
+        // DIFFTABLES 按 key 排序，返回的是排在前面的 key
+        let res = service.execute(CommandRequest::new_difftables("table", "empty"));
+        assert_eq!((res.status, res.partial), (206, true));
+        assert!(res.message.contains("larger than 64 bytes"));
+        assert!(!res.pairs.is_empty() && res.pairs.len() < 10);
+        for (i, pair) in res.pairs.iter().enumerate() {
+            assert_eq!(pair.key, format!("key{i}"));
+        }
+        let res = service.execute(CommandRequest::new_hmatch("table", "key*", true));
+        assert_eq!((res.status, res.partial), (206, true));
+        assert!(!res.pairs.is_empty() && res.pairs.len() < 10);
+        // 没有超过限制时结果是完整的
+        let res = service.execute(CommandRequest::new_hmatch("table", "key1", true));
+        assert_res_ok(res, &[], &[Kvpair::new("key1", "value")]);
+    }
+
+    #[test]
+    fn chunked_upload_should_work() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        let cmd = CommandRequest::new_hsetchunk("t", "blob", "upload-1", 1, 2, "world");
+        let res = service.execute(cmd);
+        assert_res_ok(
+            res,
+            &[],
+            &[
+                Kvpair::new("committed", false),
+                Kvpair::new("received", 1),
+                Kvpair::new("total", 2),
+            ],
+        );
+        // 所有 chunk 到齐之前不会写入
+        let res = service.execute(CommandRequest::new_hexist("t", "blob"));
+        assert_res_ok(res, &[false.into()], &[]);
+
+        let cmd = CommandRequest::new_hsetchunk("t", "blob", "upload-1", 0, 2, "hello ");
+        let res = service.execute(cmd);
+        assert_res_ok(res, &[], &[Kvpair::new("committed", true)]);
+        let res = service.execute(CommandRequest::new_hget("t", "blob"));
+        assert_res_ok(res, &[b"hello world".into()], &[]);
+    }
+
+    #[tokio::test]
+    async fn hwait_should_wake_up_when_key_is_set() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        let cloned = service.clone();
+        let waiter = tokio::spawn(async move {
+            cloned
+                .execute_async(CommandRequest::new_hwait("queue", "job", 1000))
+                .await
+        });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        service.execute(CommandRequest::new_hset("queue", "job", "work"));
+        let res = waiter.await.unwrap();
+        assert_res_ok(res, &["work".into()], &[]);
+        // 等待结束后不再占用内存
+        assert!(service.inner.watchers.is_empty());
+
+        // key 已经存在时立即返回
+        let res = service
+            .execute_async(CommandRequest::new_hwait("queue", "job", 0))
+            .await;
+        assert_res_ok(res, &["work".into()], &[]);
+    }
+
+    #[tokio::test]
+    async fn hwait_should_time_out() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        let res = service
+            .execute_async(CommandRequest::new_hwait("queue", "job", 10))
+            .await;
+        assert_res_error(res, 504, "Timed out");
+
+        // 同步执行时不会等待
+        let res = service.execute(CommandRequest::new_hwait("queue", "job", 1000));
+        assert_res_error(res, 504, "Timed out");
+    }
+
+    #[tokio::test]
+    async fn request_deadline_should_work() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        // 已经超过 deadline 的命令不会执行
+        let cmd = CommandRequest::new_hset("t", "k", "v").with_deadline(Duration::ZERO);
+        assert_res_error(service.execute(cmd), 504, "Deadline");
+        let res = service.execute(CommandRequest::new_hget("t", "k"));
+        assert_res_error(res, 404, "Not found");
+
+        let cmd = CommandRequest::new_hset("t", "k", "v").with_deadline(Duration::from_secs(10));
+        assert_res_ok(service.execute(cmd), &[Value::default()], &[]);
+
+        // HWAIT 最多等到请求的 deadline
+        let cmd = CommandRequest::new_hwait("queue", "job", 10_000)
+            .with_deadline(Duration::from_millis(10));
+        let res = tokio::time::timeout(Duration::from_secs(5), service.execute_async(cmd)).await;
+        assert_res_error(res.unwrap(), 504, "Timed out");
+    }
+
+    #[tokio::test]
+    async fn commands_should_be_shed_when_overloaded() {
+        let service: Service = ServiceInner::new(MemTable::new())
+            .with_max_in_flight(1)
+            .into();
+        // 一个一直在等待的 HWAIT 占满了执行的名额
+        let cloned = service.clone();
+        let waiter = tokio::spawn(async move {
+            cloned
+                .execute_async(CommandRequest::new_hwait("queue", "job", 50))
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let res = service.execute(CommandRequest::new_hget("queue", "job"));
+        assert_res_error(res, 503, "overloaded");
+        // METRICS 不受限制，正在执行的命令包含 METRICS 本身
+        let res = service.execute(CommandRequest::new_metrics());
+        assert!(res.pairs.contains(&Kvpair::new("commands_shed_total", 1)));
+        assert!(res.pairs.contains(&Kvpair::new("commands_in_flight", 2)));
+
+        // HWAIT 结束后恢复
+        let res = waiter.await.unwrap();
+        assert_res_error(res, 504, "Timed out");
+        let res = service.execute(CommandRequest::new_hget("queue", "job"));
+        assert_res_error(res, 404, "Not found");
+    }
+
+    #[test]
+    fn max_tables_should_work() {
+        let service: Service = ServiceInner::new(MemTable::new()).with_max_tables(2).into();
+        let res = service.execute(CommandRequest::new_hset("t1", "k", "v"));
+        assert_eq!(res.status, 200);
+        let res = service.execute(CommandRequest::new_hset("t2", "k", "v"));
+        assert_eq!(res.status, 200);
+
+        // 达到上限后不能再创建新的 table，已有 table 依旧可以写入，读取也不受影响
+        let res = service.execute(CommandRequest::new_hset("t3", "k", "v"));
+        assert_res_error(res, 507, "exceeds the limit 2");
+        let res = service.execute(CommandRequest::new_hmove("t1", "t3", "k"));
+        assert_res_error(res, 507, "exceeds the limit 2");
+        let res = service.execute(CommandRequest::new_copytable("t1", "t3", true));
+        assert_res_error(res, 507, "exceeds the limit 2");
+        let res = service.execute(CommandRequest::new_hset("t1", "k", "v1"));
+        assert_eq!(res.status, 200);
+        let res = service.execute(CommandRequest::new_hget("t3", "k"));
+        assert_res_error(res, 404, "Not found");
+    }
+
+    #[test]
+    fn paused_table_should_reject_writes_only() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        service.execute(CommandRequest::new_hset("t1", "k", "v"));
+
+        let res = service.execute(CommandRequest::new_pausetable("t1"));
+        assert_res_ok(res, &["t1".into()], &[]);
+        let res = service.execute(CommandRequest::new_hset("t1", "k", "v1"));
+        assert_res_error(res, 503, "Table t1 is paused");
+        let res = service.execute(CommandRequest::new_hdel("t1", "k"));
+        assert_res_error(res, 503, "Table t1 is paused");
+        let res = service.execute(CommandRequest::new_hmove("t2", "t1", "k"));
+        assert_res_error(res, 503, "Table t1 is paused");
+
+        // 读取和其它 table 的写入不受影响
+        let res = service.execute(CommandRequest::new_hget("t1", "k"));
+        assert_res_ok(res, &["v".into()], &[]);
+        let res = service.execute(CommandRequest::new_hset("t2", "k", "v"));
+        assert_eq!(res.status, 200);
+        let res = service.execute(CommandRequest::new_metrics());
+        assert!(res.pairs.contains(&Kvpair::new("tables_paused", 1)));
+
+        let res = service.execute(CommandRequest::new_resumetable("t1"));
+        assert_res_ok(res, &[], &[]);
+        let res = service.execute(CommandRequest::new_hset("t1", "k", "v1"));
+        assert_res_ok(res, &["v".into()], &[]);
+    }
+
+    #[test]
+    fn is_admin_should_check_fingerprint() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        assert!(service.is_admin(None));
+
+        let service: Service = ServiceInner::new(MemTable::new())
+            .with_admins(["abcd"])
+            .into();
+        assert!(service.is_admin(Some("abcd")));
+        assert!(!service.is_admin(Some("ef01")));
+        assert!(!service.is_admin(None));
+    }
+
+    #[test]
+    fn metrics_should_work() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        service.execute(CommandRequest::new_hset("table", "key", "value"));
+        service.execute(CommandRequest::new_hget("table", "not exist key"));
+
+        let res = service.execute(CommandRequest::new_metrics());
+        assert_eq!(res.status, 200);
+        // 统计数据包含 METRICS 命令本身
+        assert!(res.pairs.contains(&Kvpair::new("commands_total", 3)));
+        assert!(res.pairs.contains(&Kvpair::new("errors_total", 1)));
+        assert!(res.pairs.contains(&Kvpair::new("connections_active", 0)));
+    }
+}
 
 // 测试成功的返回结果
 #[cfg(test)]
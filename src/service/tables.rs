@@ -0,0 +1,353 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+};
+
+use dashmap::DashMap;
+
+use super::is_success;
+use crate::{CommandResponse, KvError, Storage};
+
+/// 限制 table 的总数。
+///
+/// 第一次检查时从 Storage 读取已有的 table，之后记住每个被写入过的 table。
+/// 写入新 table 的命令在执行之前就占用名额，因此并发创建 table 也不会超过限制；
+/// 删除一个 table 中所有的 key 不会释放它的名额
+#[derive(Debug)]
+pub struct TableLimit {
+    max_tables: usize,
+    // None 表示还没有从 Storage 读取已有的 table
+    known: Mutex<Option<HashSet<String>>>,
+}
+
+impl TableLimit {
+    pub fn new(max_tables: usize) -> Self {
+        Self {
+            max_tables,
+            known: Mutex::new(None),
+        }
+    }
+
+    /// 为即将写入的 tables 占用名额，超过限制时一个都不占用，返回 507
+    pub fn reserve<'a>(
+        &self,
+        store: &impl Storage,
+        tables: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), KvError> {
+        let mut guard = self.known.lock().unwrap_or_else(|e| e.into_inner());
+        if guard.is_none() {
+            *guard = Some(store.tables()?.into_iter().collect());
+        }
+        let known = guard.as_mut().unwrap();
+
+        let mut new: Vec<_> = tables.into_iter().filter(|t| !known.contains(*t)).collect();
+        new.sort_unstable();
+        new.dedup();
+        if known.len() + new.len() > self.max_tables {
+            return Err(KvError::StorageFull(self.max_tables));
+        }
+        known.extend(new.into_iter().map(String::from));
+        Ok(())
+    }
+}
+
+/// 暂停写入的 table。
+///
+/// 写入命令在执行之前检查它要修改的 table 是否被暂停，已经开始执行的写入不受 PAUSETABLE 影响
+#[derive(Debug, Default)]
+pub struct PausedTables(RwLock<HashSet<String>>);
+
+impl PausedTables {
+    /// 暂停 table 的写入，返回 table 之前是否已经被暂停
+    pub fn pause(&self, table: &str) -> bool {
+        !self.write().insert(table.to_string())
+    }
+
+    /// 恢复 table 的写入，返回 table 之前是否被暂停
+    pub fn resume(&self, table: &str) -> bool {
+        self.write().remove(table)
+    }
+
+    /// 检查要修改的 tables 中是否有被暂停的 table，有的话返回 503
+    pub fn check<'a>(&self, tables: impl IntoIterator<Item = &'a str>) -> Result<(), KvError> {
+        let paused = self.0.read().unwrap_or_else(|e| e.into_inner());
+        match tables.into_iter().find(|t| paused.contains(*t)) {
+            Some(table) => Err(KvError::TablePaused(table.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// 所有被暂停的 table，按名字排序
+    pub fn names(&self) -> Vec<String> {
+        let paused = self.0.read().unwrap_or_else(|e| e.into_inner());
+        let mut names: Vec<_> = paused.iter().cloned().collect();
+        names.sort_unstable();
+        names
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, HashSet<String>> {
+        self.0.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 每个 table 的版本号，table 每被成功修改一次加一，没有被修改过的 table 版本号为 0。
+///
+/// 修改 table 的命令执行期间持有 table 的共享锁，互相之间不影响；按版本号替换 table 时持有独占锁，
+/// 检查版本号和替换之间不会有其它的写入。版本号只保存在内存中，重启后从 0 开始。
+///
+/// 记录的 table 超过 MAX_TABLE_VERSIONS 个时清理掉没有在使用的记录，这些 table 的版本号变成被清理的版本号中最大的那个。
+/// 版本号不会变小，使用旧版本号的 REPLACETABLEIF 可能多返回 409，但不会错误地替换成功
+#[derive(Debug, Default)]
+pub struct TableVersions {
+    versions: DashMap<String, Arc<TableVersion>>,
+    // 被清理掉的记录中最大的版本号，没有记录的 table 使用这个版本号
+    floor: AtomicU64,
+}
+
+const MAX_TABLE_VERSIONS: usize = 1024;
+
+#[derive(Debug)]
+struct TableVersion {
+    lock: RwLock<()>,
+    version: AtomicU64,
+}
+
+impl TableVersions {
+    /// table 当前的版本号
+    pub fn get(&self, table: &str) -> u64 {
+        match self.versions.get(table) {
+            Some(v) => v.version.load(Ordering::Acquire),
+            None => self.floor.load(Ordering::Acquire),
+        }
+    }
+
+    /// 持有 tables 的共享锁时执行修改它们的 f，changed 认为 f 的结果修改了数据时每个 table 的版本号加一
+    pub fn modify(
+        &self,
+        tables: &[&str],
+        f: impl FnOnce() -> CommandResponse,
+        changed: impl FnOnce(&CommandResponse) -> bool,
+    ) -> CommandResponse {
+        let entries = self.entries(tables);
+        let _guards: Vec<_> = entries
+            .iter()
+            .map(|v| v.lock.read().unwrap_or_else(|e| e.into_inner()))
+            .collect();
+        let res = f();
+        if changed(&res) {
+            for v in &entries {
+                v.version.fetch_add(1, Ordering::AcqRel);
+            }
+        }
+        res
+    }
+
+    /// 持有 tables 的独占锁时执行 f，f 执行期间其它命令不能修改这些 table。
+    /// f 中不能再调用 modify 或者 modify_if 修改这些 table（会死锁），而是在修改成功后调用 bump
+    pub fn exclusive<R>(&self, tables: &[&str], f: impl FnOnce() -> R) -> R {
+        let entries = self.entries(tables);
+        let _guards: Vec<_> = entries
+            .iter()
+            .map(|v| v.lock.write().unwrap_or_else(|e| e.into_inner()))
+            .collect();
+        f()
+    }
+
+    /// 增加 tables 的版本号，用于在 exclusive 中修改 table 之后
+    pub fn bump(&self, tables: &[&str]) {
+        for v in self.entries(tables) {
+            v.version.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+
+    /// 持有 table 的独占锁，版本号等于 expected 时才执行 f，否则返回 409。f 执行成功时版本号加一
+    pub fn modify_if(
+        &self,
+        table: &str,
+        expected: u64,
+        f: impl FnOnce() -> CommandResponse,
+    ) -> CommandResponse {
+        let entry = self.entry(table);
+        let _guard = entry.lock.write().unwrap_or_else(|e| e.into_inner());
+        self.modify_if_locked(table, expected, f)
+    }
+
+    /// 和 modify_if 一样，用于已经在 exclusive 中持有了 table 的独占锁的时候
+    pub fn modify_if_locked(
+        &self,
+        table: &str,
+        expected: u64,
+        f: impl FnOnce() -> CommandResponse,
+    ) -> CommandResponse {
+        let current = self.get(table);
+        if current != expected {
+            return KvError::VersionConflict(table.to_string(), expected, current).into();
+        }
+        let res = f();
+        if is_success(&res) {
+            self.bump(&[table]);
+        }
+        res
+    }
+
+    // 按名字排序并去重，所有的调用者都按同样的顺序加锁，避免死锁
+    fn entries(&self, tables: &[&str]) -> Vec<Arc<TableVersion>> {
+        let mut tables = tables.to_vec();
+        tables.sort_unstable();
+        tables.dedup();
+        tables.into_iter().map(|t| self.entry(t)).collect()
+    }
+
+    fn entry(&self, table: &str) -> Arc<TableVersion> {
+        if let Some(v) = self.versions.get(table) {
+            return Arc::clone(&v);
+        }
+        if self.versions.len() >= MAX_TABLE_VERSIONS {
+            self.prune();
+        }
+        let entry = self.versions.entry(table.to_string()).or_insert_with(|| {
+            Arc::new(TableVersion {
+                lock: RwLock::new(()),
+                version: AtomicU64::new(self.floor.load(Ordering::Acquire)),
+            })
+        });
+        Arc::clone(&entry)
+    }
+
+    // 删除没有被任何命令持有的记录，先把它们的版本号记到 floor 中再删除
+    fn prune(&self) {
+        self.versions.retain(|_, v| {
+            let idle = Arc::strong_count(v) == 1;
+            if idle {
+                let version = v.version.load(Ordering::Acquire);
+                self.floor.fetch_max(version, Ordering::AcqRel);
+            }
+            !idle
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemTable, Value};
+
+    #[test]
+    fn table_limit_should_work() {
+        let store = MemTable::new();
+        store.set("t1", "k", "v").unwrap();
+        let limit = TableLimit::new(2);
+
+        // 已有的 table 占用名额
+        assert!(limit.reserve(&store, ["t1", "t2"]).is_ok());
+        assert!(limit.reserve(&store, ["t2", "t1"]).is_ok());
+        assert!(limit.reserve(&store, ["t3"]).is_err());
+    }
+
+    #[test]
+    fn table_limit_should_reserve_all_or_nothing() {
+        let limit = TableLimit::new(2);
+        let store = MemTable::new();
+        assert!(limit.reserve(&store, ["t1", "t2", "t3"]).is_err());
+        assert!(limit.reserve(&store, ["t1", "t1", "t2"]).is_ok());
+    }
+
+    #[test]
+    fn paused_tables_should_work() {
+        let paused = PausedTables::default();
+        assert!(!paused.pause("t2"));
+        assert!(paused.pause("t2"));
+        assert!(!paused.pause("t1"));
+        assert_eq!(paused.names(), ["t1", "t2"]);
+
+        assert!(paused.check(["t3"]).is_ok());
+        assert!(matches!(
+            paused.check(["t3", "t1"]),
+            Err(KvError::TablePaused(t)) if t == "t1"
+        ));
+
+        assert!(paused.resume("t1"));
+        assert!(!paused.resume("t1"));
+        assert!(paused.check(["t1"]).is_ok());
+    }
+
+    #[test]
+    fn table_versions_should_work() {
+        let versions = TableVersions::default();
+        let ok = || CommandResponse::from(Value::default());
+        assert_eq!(versions.get("t1"), 0);
+
+        // 修改多个 table 时每个 table 都加一，重复的 table 只加一次
+        versions.modify(&["t1", "t2", "t1"], ok, is_success);
+        assert_eq!((versions.get("t1"), versions.get("t2")), (1, 1));
+        // 失败的修改不改变版本号
+        versions.modify(
+            &["t1"],
+            || KvError::NotFound("t1".into(), "k".into()).into(),
+            is_success,
+        );
+        assert_eq!(versions.get("t1"), 1);
+        // 没有修改数据时不改变版本号
+        versions.modify(&["t1"], ok, |_| false);
+        assert_eq!(versions.get("t1"), 1);
+
+        let res = versions.modify_if("t1", 0, || unreachable!());
+        assert_eq!(res.status, 409);
+        assert!(res.message.contains("is 1, expected 0"));
+        assert_eq!(versions.modify_if("t1", 1, ok).status, 200);
+        assert_eq!(versions.get("t1"), 2);
+
+        // exclusive 本身不改变版本号
+        versions.exclusive(&["t1", "t2"], || versions.bump(&["t2", "t2"]));
+        assert_eq!((versions.get("t1"), versions.get("t2")), (2, 2));
+    }
+
+    #[test]
+    fn table_versions_should_prune_idle_entries() {
+        let versions = TableVersions::default();
+        let ok = || CommandResponse::from(Value::default());
+        versions.modify(&["t0"], ok, is_success);
+        versions.modify(&["t0"], ok, is_success);
+        versions.exclusive(&["held"], || {
+            for i in 1..=MAX_TABLE_VERSIONS {
+                versions.modify(&[format!("t{i}").as_str()], ok, is_success);
+            }
+            // 持有中的记录不会被清理
+            assert!(versions.versions.contains_key("held"));
+        });
+        assert!(versions.versions.len() <= MAX_TABLE_VERSIONS);
+        // 被清理的 table 的版本号不会变小
+        assert!(!versions.versions.contains_key("t0"));
+        assert_eq!(versions.get("t0"), 2);
+        versions.modify(&["t0"], ok, is_success);
+        assert_eq!(versions.get("t0"), 3);
+    }
+
+    #[test]
+    fn exclusive_should_block_modify() {
+        let versions = TableVersions::default();
+        let order = Mutex::new(Vec::new());
+        std::thread::scope(|s| {
+            versions.exclusive(&["t1"], || {
+                s.spawn(|| {
+                    versions.modify(
+                        &["t1"],
+                        || {
+                            order.lock().unwrap().push("modify");
+                            CommandResponse::from(Value::default())
+                        },
+                        is_success,
+                    )
+                });
+                // 另一个线程的修改要等到 exclusive 结束之后才能执行
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                order.lock().unwrap().push("exclusive");
+            });
+        });
+        assert_eq!(*order.lock().unwrap(), ["exclusive", "modify"]);
+        assert_eq!(versions.get("t1"), 1);
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use bytes::{Bytes, BytesMut};
+
+use crate::{Hsetchunk, KvError, Kvpair};
+
+/// 分块上传的会话缺省的超时时间，超时未完成的上传会被丢弃
+pub const DEFAULT_UPLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 分块上传占用的内存的限制，在分配内存之前检查，超过限制的 chunk 直接拒绝
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadLimits {
+    /// 一次上传最多的 chunk 数，total_chunks 超过时返回 400
+    pub max_chunks: usize,
+    /// 一次上传拼接后的数据最大的字节数，超过时返回 400
+    pub max_size: usize,
+    /// 同时进行中的上传最多的个数，超过时新的上传返回 503
+    pub max_sessions: usize,
+    /// 所有进行中的上传已经收到的数据的总字节数，超过时返回 503
+    pub max_total_size: usize,
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        Self {
+            max_chunks: 4096,
+            max_size: 64 * 1024 * 1024,
+            max_sessions: 1024,
+            max_total_size: 256 * 1024 * 1024,
+        }
+    }
+}
+
+/// 正在进行中的分块上传，按 upload_id 索引
+#[derive(Debug)]
+pub struct UploadSessions {
+    sessions: Mutex<HashMap<String, Upload>>,
+    timeout: Duration,
+    limits: UploadLimits,
+}
+
+// 一个上传会话收到的数据
+#[derive(Debug)]
+struct Upload {
+    table: String,
+    key: String,
+    chunks: Vec<Option<Bytes>>,
+    received: usize,
+    // 已经收到的 chunk 的总字节数
+    size: usize,
+    last_active: Instant,
+}
+
+/// 收到一个 chunk 之后上传的进度
+#[derive(Debug, PartialEq)]
+pub enum UploadProgress {
+    /// 还在等待其它 chunk
+    Pending { received: usize, total: usize },
+    /// 所有 chunk 都已收到，拼接好的完整数据可以写入 table 中的 key
+    Complete {
+        table: String,
+        key: String,
+        data: Bytes,
+    },
+}
+
+impl UploadSessions {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            timeout,
+            limits: UploadLimits::default(),
+        }
+    }
+
+    /// 设置超时时间，超过这个时间没有收到新 chunk 的上传会被丢弃
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// 设置上传占用内存的限制，缺省为 `UploadLimits::default()`
+    pub fn set_limits(&mut self, limits: UploadLimits) {
+        self.limits = limits;
+    }
+
+    /// 保存一个 chunk。重复发送同一个 chunk 会覆盖之前的数据，因此断线后客户端可以重发缺失的 chunk 继续上传
+    pub fn receive(&self, chunk: Hsetchunk) -> Result<UploadProgress, KvError> {
+        let total = chunk.total_chunks as usize;
+        let index = chunk.chunk_index as usize;
+        if index >= total {
+            return Err(KvError::InvaildCommand(format!(
+                "Chunk index {index} is out of range, total chunks: {total}"
+            )));
+        }
+        let limits = self.limits;
+        if total > limits.max_chunks {
+            return Err(KvError::InvaildCommand(format!(
+                "Total chunks {total} exceeds the limit of {} chunks",
+                limits.max_chunks
+            )));
+        }
+
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        sessions.retain(|_, upload| now.duration_since(upload.last_active) < self.timeout);
+
+        // 所有检查都通过之后才创建会话、保存数据
+        let (size, replaced) = match sessions.get(&chunk.upload_id) {
+            Some(upload) => {
+                if upload.table != chunk.table
+                    || upload.key != chunk.key
+                    || upload.chunks.len() != total
+                {
+                    return Err(KvError::InvaildCommand(format!(
+                        "Chunk doesn't match upload {}",
+                        chunk.upload_id
+                    )));
+                }
+                (
+                    upload.size,
+                    upload.chunks[index].as_ref().map_or(0, Bytes::len),
+                )
+            }
+            None if sessions.len() >= limits.max_sessions => return Err(KvError::Overloaded),
+            None => (0, 0),
+        };
+        let size = size - replaced + chunk.data.len();
+        if size > limits.max_size {
+            return Err(KvError::InvaildCommand(format!(
+                "Upload {} is larger than the limit of {} bytes",
+                chunk.upload_id, limits.max_size
+            )));
+        }
+        let total_size: usize = sessions.values().map(|upload| upload.size).sum();
+        if total_size - replaced + chunk.data.len() > limits.max_total_size {
+            return Err(KvError::Overloaded);
+        }
+
+        let upload = sessions
+            .entry(chunk.upload_id.clone())
+            .or_insert_with(|| Upload {
+                table: chunk.table.clone(),
+                key: chunk.key.clone(),
+                chunks: vec![None; total],
+                received: 0,
+                size: 0,
+                last_active: now,
+            });
+        upload.last_active = now;
+        upload.size = size;
+        if upload.chunks[index].replace(chunk.data).is_none() {
+            upload.received += 1;
+        }
+        if upload.received < total {
+            return Ok(UploadProgress::Pending {
+                received: upload.received,
+                total,
+            });
+        }
+
+        let upload = sessions.remove(&chunk.upload_id).unwrap();
+        let mut data = BytesMut::new();
+        for chunk in upload.chunks.into_iter().flatten() {
+            data.extend_from_slice(&chunk);
+        }
+        Ok(UploadProgress::Complete {
+            table: upload.table,
+            key: upload.key,
+            data: data.freeze(),
+        })
+    }
+}
+
+impl Default for UploadSessions {
+    fn default() -> Self {
+        Self::new(DEFAULT_UPLOAD_TIMEOUT)
+    }
+}
+
+impl UploadProgress {
+    /// 返回给客户端的上传进度
+    pub fn to_pairs(&self) -> Vec<Kvpair> {
+        match self {
+            UploadProgress::Pending { received, total } => vec![
+                Kvpair::new("committed", false),
+                Kvpair::new("received", *received as i64),
+                Kvpair::new("total", *total as i64),
+            ],
+            UploadProgress::Complete { .. } => vec![Kvpair::new("committed", true)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn chunks_should_be_assembled_in_order() {
+        let sessions = UploadSessions::default();
+        let progress = sessions.receive(chunk("id", 1, 3, "world")).unwrap();
+        assert_eq!(
+            progress,
+            UploadProgress::Pending {
+                received: 1,
+                total: 3
+            }
+        );
+        // 重发的 chunk 不会重复计数
+        sessions.receive(chunk("id", 1, 3, "world")).unwrap();
+        sessions.receive(chunk("id", 0, 3, "hello ")).unwrap();
+        let progress = sessions.receive(chunk("id", 2, 3, "!")).unwrap();
+        assert_eq!(
+            progress,
+            UploadProgress::Complete {
+                table: "t".into(),
+                key: "k".into(),
+                data: Bytes::from("hello world!"),
+            }
+        );
+    }
+
+    #[test]
+    fn expired_upload_should_be_discarded() {
+        let sessions = UploadSessions::new(Duration::from_millis(10));
+        sessions.receive(chunk("id", 0, 2, "hello")).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        let progress = sessions.receive(chunk("id", 1, 2, "world")).unwrap();
+        assert_eq!(
+            progress,
+            UploadProgress::Pending {
+                received: 1,
+                total: 2
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_chunk_should_be_rejected() {
+        let sessions = UploadSessions::default();
+        assert!(sessions.receive(chunk("id", 2, 2, "hello")).is_err());
+        sessions.receive(chunk("id", 0, 2, "hello")).unwrap();
+        assert!(sessions.receive(chunk("id", 1, 3, "world")).is_err());
+    }
+
+    #[test]
+    fn oversized_upload_should_be_rejected_before_allocating() {
+        let sessions = UploadSessions::default();
+        // 不会分配 u32::MAX 个 chunk
+        let e = sessions
+            .receive(chunk("id", 0, u32::MAX, "hello"))
+            .unwrap_err();
+        assert!(matches!(e, KvError::InvaildCommand(_)));
+        let e = sessions.receive(chunk("id", u32::MAX - 1, u32::MAX, "hello"));
+        assert!(e.is_err());
+        assert!(sessions.sessions.lock().unwrap().is_empty());
+
+        let mut sessions = UploadSessions::default();
+        sessions.set_limits(UploadLimits {
+            max_chunks: 4,
+            max_size: 10,
+            max_sessions: 2,
+            max_total_size: 16,
+        });
+        let e = sessions.receive(chunk("id", 0, 5, "hello")).unwrap_err();
+        assert!(matches!(e, KvError::InvaildCommand(_)));
+
+        // 单个上传超过 max_size，重发的 chunk 按替换后的大小计算
+        sessions.receive(chunk("a", 0, 3, "hello")).unwrap();
+        let e = sessions.receive(chunk("a", 1, 3, "world!")).unwrap_err();
+        assert!(matches!(e, KvError::InvaildCommand(_)));
+        sessions.receive(chunk("a", 0, 3, "hi")).unwrap();
+        sessions.receive(chunk("a", 1, 3, "world!")).unwrap();
+
+        // 同时进行的上传个数和总大小超过限制
+        let e = sessions
+            .receive(chunk("b", 0, 2, "0123456789"))
+            .unwrap_err();
+        assert!(matches!(e, KvError::Overloaded));
+        sessions.receive(chunk("b", 0, 2, "01234")).unwrap();
+        let e = sessions.receive(chunk("c", 0, 2, "x")).unwrap_err();
+        assert!(matches!(e, KvError::Overloaded));
+
+        // 完成的上传不再占用限制
+        let progress = sessions.receive(chunk("a", 2, 3, "!")).unwrap();
+        assert!(matches!(progress, UploadProgress::Complete { .. }));
+        sessions.receive(chunk("c", 0, 2, "x")).unwrap();
+    }
+
+    fn chunk(upload_id: &str, index: u32, total: u32, data: &'static str) -> Hsetchunk {
+        Hsetchunk {
+            table: "t".into(),
+            key: "k".into(),
+            upload_id: upload_id.into(),
+            chunk_index: index,
+            total_chunks: total,
+            data: Bytes::from(data),
+        }
+    }
+}
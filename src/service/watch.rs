@@ -0,0 +1,130 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use tokio::sync::Notify;
+
+/// 等待 key 被写入的 HWAIT 命令，按 (table, key) 索引。
+///
+/// 写入一个 key 时会唤醒等待这个 key 的所有 HWAIT，而不是只唤醒其中一个；
+/// 没有人等待的 key 不会占用任何内存
+#[derive(Debug, Default)]
+pub struct KeyWatchers {
+    watchers: Mutex<HashMap<(String, String), Arc<Notify>>>,
+}
+
+/// 对一个 key 的等待，释放时如果已经没有其它人等待这个 key，会把它从 KeyWatchers 中移除
+#[derive(Debug)]
+pub struct KeyWatch<'a> {
+    watchers: &'a KeyWatchers,
+    id: (String, String),
+    notify: Option<Arc<Notify>>,
+}
+
+impl KeyWatchers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 开始等待 table 中的 key 被写入
+    pub fn watch(&self, table: &str, key: &str) -> KeyWatch<'_> {
+        let id = (table.to_string(), key.to_string());
+        let notify = self.lock().entry(id.clone()).or_default().clone();
+        KeyWatch {
+            watchers: self,
+            id,
+            notify: Some(notify),
+        }
+    }
+
+    /// table 中的 key 被写入了，唤醒所有等待它的 HWAIT
+    pub fn notify(&self, table: &str, key: &str) {
+        // 绝大多数写入都没有人等待，避免为了查找而分配 String
+        let watchers = self.lock();
+        if watchers.is_empty() {
+            return;
+        }
+        if let Some(notify) = watchers.get(&(table.to_string(), key.to_string())) {
+            notify.notify_waiters();
+        }
+    }
+
+    /// 正在被等待的 key 的数量
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<(String, String), Arc<Notify>>> {
+        self.watchers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl KeyWatch<'_> {
+    /// 下一次 key 被写入时被唤醒。
+    ///
+    /// 只有在返回的 future 第一次被 poll（或者被 enable）之后发生的写入才会唤醒它，
+    /// 所以应该先 enable，再检查 key 是否已经存在，最后等待，才不会错过检查之后的写入
+    pub fn notified(&self) -> tokio::sync::futures::Notified<'_> {
+        self.notify.as_ref().unwrap().notified()
+    }
+}
+
+impl Drop for KeyWatch<'_> {
+    fn drop(&mut self) {
+        let mut watchers = self.watchers.lock();
+        // 在锁内释放自己持有的引用，这样只剩 KeyWatchers 持有时就说明没有其它人等待了
+        drop(self.notify.take());
+        if watchers
+            .get(&self.id)
+            .is_some_and(|notify| Arc::strong_count(notify) == 1)
+        {
+            watchers.remove(&self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn all_watchers_should_be_notified() {
+        let watchers = KeyWatchers::new();
+        let w1 = watchers.watch("t", "k");
+        let w2 = watchers.watch("t", "k");
+        let other = watchers.watch("t", "other");
+        let (n1, n2, n3) = (w1.notified(), w2.notified(), other.notified());
+        tokio::pin!(n1, n2, n3);
+        n1.as_mut().enable();
+        n2.as_mut().enable();
+        n3.as_mut().enable();
+
+        watchers.notify("t", "k");
+        timeout(Duration::from_millis(100), n1).await.unwrap();
+        timeout(Duration::from_millis(100), n2).await.unwrap();
+        // 其它 key 的写入不会唤醒
+        assert!(timeout(Duration::from_millis(10), n3).await.is_err());
+    }
+
+    #[test]
+    fn watcher_should_be_removed_when_dropped() {
+        let watchers = KeyWatchers::new();
+        let w1 = watchers.watch("t", "k");
+        let w2 = watchers.watch("t", "k");
+        assert_eq!(watchers.len(), 1);
+
+        drop(w1);
+        assert_eq!(watchers.len(), 1);
+        drop(w2);
+        assert!(watchers.is_empty());
+    }
+}
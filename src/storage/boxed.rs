@@ -0,0 +1,361 @@
+use std::time::Duration;
+
+use crate::{KvError, Kvpair, MemTable, RocksDB, SledDb, Storage, Value, WriteOp};
+
+/// 类型擦除后的存储，可以在运行时（比如根据配置）选择具体的存储，而不需要为每种存储都单态化一份
+/// `Service`。需要单态化带来的性能时，依旧可以直接使用具体的存储类型
+pub struct BoxedStorage(Box<dyn DynStorage + Send + Sync>);
+
+impl BoxedStorage {
+    pub fn new(store: impl Storage + Send + Sync + 'static) -> Self {
+        Self(Box::new(store))
+    }
+
+    /// 根据配置打开存储：`memory`、`sled:<path>` 或者 `rocksdb:<path>`
+    pub fn open(spec: &str) -> Result<Self, KvError> {
+        match spec.split_once(':') {
+            None if spec == "memory" => Ok(Self::new(MemTable::new())),
+            Some(("sled", path)) if !path.is_empty() => Ok(Self::new(SledDb::new(path))),
+            Some(("rocksdb", path)) if !path.is_empty() => Ok(Self::new(RocksDB::new(path))),
+            _ => Err(KvError::InvaildCommand(format!(
+                "Unknown storage {spec}, expect memory, sled:<path> or rocksdb:<path>"
+            ))),
+        }
+    }
+}
+
+// Storage 的 object safe 版本：泛型参数换成具体类型，没有 get_iter。
+// 所有实现了 Storage 的类型都自动实现了 DynStorage
+trait DynStorage {
+    fn get(&self, table: &str, key: &str) -> Result<Option<Value>, KvError>;
+    fn get_batch(&self, table: &str, keys: &[&str]) -> Result<Vec<Option<Value>>, KvError>;
+    fn set(&self, table: &str, key: String, value: Value) -> Result<Option<Value>, KvError>;
+    fn contains(&self, table: &str, key: &str) -> Result<bool, KvError>;
+    fn del(&self, table: &str, key: &str) -> Result<Option<Value>, KvError>;
+    fn del_batch(&self, table: &str, keys: &[&str]) -> Result<Vec<Option<Value>>, KvError>;
+    fn mtime(&self, table: &str, key: &str) -> Result<Option<i64>, KvError>;
+    fn tables(&self) -> Result<Vec<String>, KvError>;
+    fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError>;
+    fn compact(&self) -> Result<u64, KvError>;
+    fn move_key(
+        &self,
+        from_table: &str,
+        to_table: &str,
+        key: &str,
+    ) -> Result<Option<Value>, KvError>;
+    fn rename_key(
+        &self,
+        table: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Option<Option<Value>>, KvError>;
+    fn update(
+        &self,
+        table: &str,
+        key: &str,
+        f: &mut dyn FnMut(Option<&Value>) -> Option<Value>,
+    ) -> Result<Option<Value>, KvError>;
+    fn find_by_value(&self, table: &str, value: &Value) -> Result<Vec<String>, KvError>;
+    fn len(&self, table: &str) -> Result<usize, KvError>;
+    fn approximate_size(&self, table: &str) -> Result<u64, KvError>;
+    fn replace_table(&self, table: &str, pairs: Vec<Kvpair>) -> Result<(u64, u64), KvError>;
+    fn clear_table(&self, table: &str) -> Result<u64, KvError>;
+    fn copy_table(&self, src: &str, dst: &str, overwrite: bool) -> Result<u64, KvError>;
+    fn stored_key_len(&self, table: &str, key: &str) -> usize;
+    fn expire(&self, table: &str, key: &str, ttl: Duration) -> Result<bool, KvError>;
+    fn set_with_ttl(
+        &self,
+        table: &str,
+        key: String,
+        value: Value,
+        ttl: Duration,
+    ) -> Result<Option<Value>, KvError>;
+    fn write_batch(&self, ops: Vec<WriteOp>) -> Result<(), KvError>;
+    fn ttl(&self, table: &str, key: &str) -> Result<Option<Duration>, KvError>;
+}
+
+impl<S: Storage> DynStorage for S {
+    fn get(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+        Storage::get(self, table, key)
+    }
+
+    fn get_batch(&self, table: &str, keys: &[&str]) -> Result<Vec<Option<Value>>, KvError> {
+        Storage::get_batch(self, table, keys)
+    }
+
+    fn set(&self, table: &str, key: String, value: Value) -> Result<Option<Value>, KvError> {
+        Storage::set(self, table, key, value)
+    }
+
+    fn contains(&self, table: &str, key: &str) -> Result<bool, KvError> {
+        Storage::contains(self, table, key)
+    }
+
+    fn del(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+        Storage::del(self, table, key)
+    }
+
+    fn del_batch(&self, table: &str, keys: &[&str]) -> Result<Vec<Option<Value>>, KvError> {
+        Storage::del_batch(self, table, keys)
+    }
+
+    fn mtime(&self, table: &str, key: &str) -> Result<Option<i64>, KvError> {
+        Storage::mtime(self, table, key)
+    }
+
+    fn tables(&self) -> Result<Vec<String>, KvError> {
+        Storage::tables(self)
+    }
+
+    fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError> {
+        Storage::get_all(self, table)
+    }
+
+    fn compact(&self) -> Result<u64, KvError> {
+        Storage::compact(self)
+    }
+
+    fn move_key(
+        &self,
+        from_table: &str,
+        to_table: &str,
+        key: &str,
+    ) -> Result<Option<Value>, KvError> {
+        Storage::move_key(self, from_table, to_table, key)
+    }
+
+    fn rename_key(
+        &self,
+        table: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Option<Option<Value>>, KvError> {
+        Storage::rename_key(self, table, from, to)
+    }
+
+    fn update(
+        &self,
+        table: &str,
+        key: &str,
+        f: &mut dyn FnMut(Option<&Value>) -> Option<Value>,
+    ) -> Result<Option<Value>, KvError> {
+        Storage::update(self, table, key, f)
+    }
+
+    fn find_by_value(&self, table: &str, value: &Value) -> Result<Vec<String>, KvError> {
+        Storage::find_by_value(self, table, value)
+    }
+
+    fn len(&self, table: &str) -> Result<usize, KvError> {
+        Storage::len(self, table)
+    }
+
+    fn approximate_size(&self, table: &str) -> Result<u64, KvError> {
+        Storage::approximate_size(self, table)
+    }
+
+    fn replace_table(&self, table: &str, pairs: Vec<Kvpair>) -> Result<(u64, u64), KvError> {
+        Storage::replace_table(self, table, pairs)
+    }
+
+    fn clear_table(&self, table: &str) -> Result<u64, KvError> {
+        Storage::clear_table(self, table)
+    }
+
+    fn copy_table(&self, src: &str, dst: &str, overwrite: bool) -> Result<u64, KvError> {
+        Storage::copy_table(self, src, dst, overwrite)
+    }
+
+    fn stored_key_len(&self, table: &str, key: &str) -> usize {
+        Storage::stored_key_len(self, table, key)
+    }
+
+    fn expire(&self, table: &str, key: &str, ttl: Duration) -> Result<bool, KvError> {
+        Storage::expire(self, table, key, ttl)
+    }
+
+    fn set_with_ttl(
+        &self,
+        table: &str,
+        key: String,
+        value: Value,
+        ttl: Duration,
+    ) -> Result<Option<Value>, KvError> {
+        Storage::set_with_ttl(self, table, key, value, ttl)
+    }
+
+    fn write_batch(&self, ops: Vec<WriteOp>) -> Result<(), KvError> {
+        Storage::write_batch(self, ops)
+    }
+
+    fn ttl(&self, table: &str, key: &str) -> Result<Option<Duration>, KvError> {
+        Storage::ttl(self, table, key)
+    }
+}
+
+impl Storage for BoxedStorage {
+    fn get(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+        self.0.get(table, key)
+    }
+
+    fn get_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        let keys: Vec<_> = keys.iter().map(|key| key.as_ref()).collect();
+        self.0.get_batch(table, &keys)
+    }
+
+    fn set(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Result<Option<Value>, KvError> {
+        self.0.set(table, key.into(), value.into())
+    }
+
+    fn contains(&self, table: &str, key: &str) -> Result<bool, KvError> {
+        self.0.contains(table, key)
+    }
+
+    fn del(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+        self.0.del(table, key)
+    }
+
+    fn del_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        let keys: Vec<_> = keys.iter().map(|key| key.as_ref()).collect();
+        self.0.del_batch(table, &keys)
+    }
+
+    fn mtime(&self, table: &str, key: &str) -> Result<Option<i64>, KvError> {
+        self.0.mtime(table, key)
+    }
+
+    fn tables(&self) -> Result<Vec<String>, KvError> {
+        self.0.tables()
+    }
+
+    fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError> {
+        self.0.get_all(table)
+    }
+
+    // 具体存储的 get_iter 返回的 Iterator 和 table 的生命周期绑在一起，没法放到 Box 里返回，
+    // 这里先把整个 table 读出来
+    fn get_iter(&self, table: &str) -> Result<impl Iterator<Item = Kvpair>, KvError> {
+        Ok(self.0.get_all(table)?.into_iter())
+    }
+
+    fn compact(&self) -> Result<u64, KvError> {
+        self.0.compact()
+    }
+
+    fn move_key(
+        &self,
+        from_table: &str,
+        to_table: &str,
+        key: &str,
+    ) -> Result<Option<Value>, KvError> {
+        self.0.move_key(from_table, to_table, key)
+    }
+
+    fn rename_key(
+        &self,
+        table: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Option<Option<Value>>, KvError> {
+        self.0.rename_key(table, from, to)
+    }
+
+    fn update<F>(&self, table: &str, key: &str, mut f: F) -> Result<Option<Value>, KvError>
+    where
+        F: FnMut(Option<&Value>) -> Option<Value>,
+    {
+        self.0.update(table, key, &mut f)
+    }
+
+    fn find_by_value(&self, table: &str, value: &Value) -> Result<Vec<String>, KvError> {
+        self.0.find_by_value(table, value)
+    }
+
+    fn len(&self, table: &str) -> Result<usize, KvError> {
+        self.0.len(table)
+    }
+
+    fn approximate_size(&self, table: &str) -> Result<u64, KvError> {
+        self.0.approximate_size(table)
+    }
+
+    fn replace_table(&self, table: &str, pairs: Vec<Kvpair>) -> Result<(u64, u64), KvError> {
+        self.0.replace_table(table, pairs)
+    }
+
+    fn clear_table(&self, table: &str) -> Result<u64, KvError> {
+        self.0.clear_table(table)
+    }
+
+    fn copy_table(&self, src: &str, dst: &str, overwrite: bool) -> Result<u64, KvError> {
+        self.0.copy_table(src, dst, overwrite)
+    }
+
+    fn stored_key_len(&self, table: &str, key: &str) -> usize {
+        self.0.stored_key_len(table, key)
+    }
+
+    fn expire(&self, table: &str, key: &str, ttl: Duration) -> Result<bool, KvError> {
+        self.0.expire(table, key, ttl)
+    }
+
+    fn set_with_ttl(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+        ttl: Duration,
+    ) -> Result<Option<Value>, KvError> {
+        self.0.set_with_ttl(table, key.into(), value.into(), ttl)
+    }
+
+    fn write_batch(&self, ops: Vec<WriteOp>) -> Result<(), KvError> {
+        self.0.write_batch(ops)
+    }
+
+    fn ttl(&self, table: &str, key: &str) -> Result<Option<Duration>, KvError> {
+        self.0.ttl(table, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::BoxedStorage;
+    use crate::Storage;
+
+    #[test]
+    fn boxed_storage_should_work() {
+        let dir = tempdir().unwrap();
+        let spec = format!("sled:{}", dir.path().display());
+        for store in [BoxedStorage::open("memory"), BoxedStorage::open(&spec)] {
+            let store = store.unwrap();
+            store.set("t1", "k1", "v1").unwrap();
+            assert_eq!(store.get("t1", "k1").unwrap(), Some("v1".into()));
+            let v = store.update("t1", "n", |_| Some(1.into())).unwrap();
+            assert_eq!(v, Some(1.into()));
+            assert_eq!(store.get_batch("t1", &["k1", "k2"]).unwrap().len(), 2);
+            assert_eq!(store.get_iter("t1").unwrap().count(), 2);
+        }
+    }
+
+    #[test]
+    fn unknown_storage_should_be_rejected() {
+        assert!(BoxedStorage::open("redis").is_err());
+        assert!(BoxedStorage::open("sled:").is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::BytesMut;
+use prost::Message;
+
+use super::{entry_header, now_millis, HEADER_LEN};
+use crate::{compress_with_level, decompress, CompressorType, KvError, Value};
+
+/// 持久化存储中 value 的编码方式，决定写入时是否压缩以及使用的算法和级别。
+///
+/// 不压缩的数据格式和之前完全相同：格式标记和 8 字节的修改时间后面是 protobuf 编码的 Value。
+/// 压缩的数据在修改时间之后多一个字节的压缩算法标记（1 gzip、2 lz4、3 zstd），
+/// protobuf 编码的 Value 不可能以这几个字节开头（字段编号为 0），所以读取时不需要知道写入时的配置，
+/// 也能正确读出压缩和不压缩混在一起的数据。设置了过期时间的数据在修改时间之后还有过期时间（见 `Storage::expire`）
+#[derive(Debug)]
+pub struct ValueCodec {
+    compressor: CompressorType,
+    level: Option<u32>,
+    threshold: usize,
+    bytes_in: AtomicU64,
+    bytes_stored: AtomicU64,
+}
+
+/// 写入的 value 压缩前后的总字节数，用于调整压缩的配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// protobuf 编码后的 value 的总字节数
+    pub bytes_in: u64,
+    /// 实际保存的 value 的总字节数（不包括修改时间）
+    pub bytes_stored: u64,
+}
+
+impl CompressionStats {
+    /// 压缩率：保存的字节数 / 编码后的字节数，没有写入过数据时为 1
+    pub fn ratio(&self) -> f64 {
+        if self.bytes_in == 0 {
+            return 1.0;
+        }
+        self.bytes_stored as f64 / self.bytes_in as f64
+    }
+}
+
+impl Default for ValueCodec {
+    fn default() -> Self {
+        Self::new(CompressorType::None)
+    }
+}
+
+impl ValueCodec {
+    /// 使用 compressor 压缩，CompressorType::None 表示不压缩
+    pub fn new(compressor: CompressorType) -> Self {
+        Self {
+            compressor,
+            level: None,
+            threshold: 0,
+            bytes_in: AtomicU64::new(0),
+            bytes_stored: AtomicU64::new(0),
+        }
+    }
+
+    /// 压缩级别，缺省使用算法自己的缺省级别
+    pub fn with_level(mut self, level: u32) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// 编码后小于 threshold 字节的 value 不压缩，缺省全部压缩
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// 到目前为止写入的 value 压缩前后的字节数
+    pub fn stats(&self) -> CompressionStats {
+        CompressionStats {
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_stored: self.bytes_stored.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 编码一个 value，压缩后没有变小时保存不压缩的数据
+    pub(crate) fn encode(&self, value: Value) -> Result<Vec<u8>, KvError> {
+        let mut buf = entry_header(now_millis());
+        let data = value.encode_to_vec();
+        let mut compressed = BytesMut::new();
+        if self.compressor != CompressorType::None && data.len() >= self.threshold {
+            compress_with_level(self.compressor, &data, &mut compressed, self.level)?;
+        }
+        if !compressed.is_empty() && compressed.len() + 1 < data.len() {
+            buf.push(self.compressor as u8);
+            buf.extend_from_slice(&compressed);
+        } else {
+            buf.extend_from_slice(&data);
+        }
+        self.bytes_in
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        let stored = buf.len() - HEADER_LEN;
+        self.bytes_stored
+            .fetch_add(stored as u64, Ordering::Relaxed);
+        Ok(buf)
+    }
+}
+
+// 解出 value，数据以压缩算法标记开头时先解压
+pub(super) fn decode_value_data(data: &[u8]) -> Result<Value, KvError> {
+    match data.first() {
+        Some(&tag @ 1..=3) => {
+            let mut decompressed = Vec::new();
+            decompress(
+                CompressorType::from(tag as usize),
+                &data[1..],
+                &mut decompressed,
+            )?;
+            Ok(Value::decode(&decompressed[..])?)
+        }
+        _ => Ok(Value::decode(data)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::decode_entry;
+
+    #[test]
+    fn value_codec_should_round_trip() {
+        let large = Value::from("compressible ".repeat(100));
+        let small = Value::from("tiny");
+        for compressor in [
+            CompressorType::None,
+            CompressorType::GZIP,
+            CompressorType::LZ4,
+            CompressorType::ZSTD,
+        ] {
+            let codec = ValueCodec::new(compressor).with_level(3).with_threshold(64);
+            let data = codec.encode(large.clone()).unwrap();
+            let compressed = compressor != CompressorType::None;
+            assert_eq!(data[HEADER_LEN] == compressor as u8, compressed);
+            assert_eq!(decode_entry(&data).unwrap().0, large);
+
+            // 小于 threshold 时不压缩，数据格式和不压缩时相同
+            let data = codec.encode(small.clone()).unwrap();
+            assert_eq!(&data[HEADER_LEN..], &small.encode_to_vec()[..]);
+            assert_eq!(decode_entry(&data).unwrap().0, small);
+
+            let stats = codec.stats();
+            assert_eq!(
+                stats.bytes_in,
+                (large.encoded_len() + small.encoded_len()) as u64
+            );
+            assert_eq!(stats.ratio() < 0.5, compressed);
+        }
+    }
+
+    #[test]
+    fn incompressible_value_should_be_stored_as_is() {
+        let value = Value::from(b"\x01\x7f\x33");
+        let data = ValueCodec::new(CompressorType::GZIP)
+            .encode(value.clone())
+            .unwrap();
+        assert_eq!(&data[HEADER_LEN..], &value.encode_to_vec()[..]);
+    }
+}
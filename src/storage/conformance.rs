@@ -0,0 +1,230 @@
+//! `Storage` 的一致性测试，检查一个存储是否遵循 trait 文档中约定的行为。
+//!
+//! 第三方的存储在 dev-dependencies 中打开 `test-util` feature 之后，在测试里调用
+//! [`storage_conformance_suite`]，传入创建一个空存储的函数：
+//!
+//! ```ignore
+//! [dev-dependencies]
+//! kv = { version = "0.1", features = ["test-util"] }
+//!
+//! #[test]
+//! fn my_store_should_conform() {
+//!     kv::storage_conformance_suite(MyStore::new);
+//! }
+//! ```
+//!
+//! 每个用例都在一个新创建的存储上执行，不满足约定时 panic，panic 的位置指出了是哪个用例失败。
+//! 遍历的顺序不在约定之内，用例比较遍历结果之前都会先排序
+use bytes::Bytes;
+
+use crate::{Kvpair, Storage, Value, WriteOp};
+
+/// 对 make_store 创建的存储执行所有一致性测试用例，make_store 每次都必须返回一个空的存储
+pub fn storage_conformance_suite<S: Storage>(make_store: impl Fn() -> S) {
+    let cases: [fn(&S); 11] = [
+        set_and_get,
+        set_should_return_overwritten,
+        del_should_return_removed,
+        del_batch_should_return_removed,
+        contains_should_work,
+        empty_table_should_be_empty,
+        iter_should_return_table_pairs,
+        special_keys_should_round_trip,
+        large_values_should_round_trip,
+        clear_table_should_remove_all_keys,
+        write_batch_should_apply_all_ops,
+    ];
+    for case in cases {
+        case(&make_store());
+    }
+}
+
+fn set_and_get(store: &impl Storage) {
+    assert_eq!(store.set("t", "k", "v").unwrap(), None);
+    assert_eq!(store.get("t", "k").unwrap(), Some("v".into()));
+    // 其它类型的 value 原样返回
+    for value in [
+        Value::from(1),
+        Value::from(1.5),
+        Value::from(true),
+        Value::from(b"\x00\xff"),
+        Value::default(),
+    ] {
+        store.set("t", "k", value.clone()).unwrap();
+        assert_eq!(store.get("t", "k").unwrap(), Some(value));
+    }
+    assert_eq!(store.get("t", "other").unwrap(), None);
+    assert_eq!(store.get("other", "k").unwrap(), None);
+}
+
+fn set_should_return_overwritten(store: &impl Storage) {
+    assert_eq!(store.set("t", "k", "v1").unwrap(), None);
+    assert_eq!(store.set("t", "k", "v2").unwrap(), Some("v1".into()));
+    assert_eq!(store.set("t", "k", 3).unwrap(), Some("v2".into()));
+    // 同名的 key 在另一个 table 中是不同的 key
+    assert_eq!(store.set("t2", "k", "v").unwrap(), None);
+    assert_eq!(store.get("t", "k").unwrap(), Some(3.into()));
+}
+
+fn del_should_return_removed(store: &impl Storage) {
+    assert_eq!(store.del("t", "k").unwrap(), None);
+    store.set("t", "k", "v").unwrap();
+    assert_eq!(store.del("other", "k").unwrap(), None);
+    assert_eq!(store.del("t", "k").unwrap(), Some("v".into()));
+    assert_eq!(store.del("t", "k").unwrap(), None);
+    assert_eq!(store.get("t", "k").unwrap(), None);
+    // 删除后可以重新写入
+    assert_eq!(store.set("t", "k", "v2").unwrap(), None);
+}
+
+fn del_batch_should_return_removed(store: &impl Storage) {
+    for i in 1..=3 {
+        store.set("t", format!("k{i}"), format!("v{i}")).unwrap();
+    }
+    store.set("t2", "k1", "other").unwrap();
+    // 按 keys 的顺序返回，不存在的和重复的 key 为 None
+    let removed = store
+        .del_batch("t", &["k1", "missing", "k2", "k1"])
+        .unwrap();
+    let expected: [Option<Value>; 4] = [Some("v1".into()), None, Some("v2".into()), None];
+    assert_eq!(removed, expected);
+    assert_eq!(store.get_all("t").unwrap(), [Kvpair::new("k3", "v3")]);
+    assert_eq!(store.get("t2", "k1").unwrap(), Some("other".into()));
+    assert!(store.del_batch("t", &[] as &[&str]).unwrap().is_empty());
+}
+
+fn contains_should_work(store: &impl Storage) {
+    assert!(!store.contains("t", "k").unwrap());
+    store.set("t", "k", "v").unwrap();
+    assert!(store.contains("t", "k").unwrap());
+    assert!(!store.contains("t", "k2").unwrap());
+    assert!(!store.contains("t2", "k").unwrap());
+    store.del("t", "k").unwrap();
+    assert!(!store.contains("t", "k").unwrap());
+}
+
+fn empty_table_should_be_empty(store: &impl Storage) {
+    assert!(store.get_all("t").unwrap().is_empty());
+    assert_eq!(store.get_iter("t").unwrap().count(), 0);
+    assert_eq!(store.len("t").unwrap(), 0);
+    // 删除了所有 key 的 table 和不存在的 table 一样
+    store.set("t", "k", "v").unwrap();
+    store.del("t", "k").unwrap();
+    assert!(store.get_all("t").unwrap().is_empty());
+    assert_eq!(store.get_iter("t").unwrap().count(), 0);
+    assert_eq!(store.len("t").unwrap(), 0);
+    assert!(store.tables().unwrap().is_empty());
+}
+
+fn iter_should_return_table_pairs(store: &impl Storage) {
+    for i in 0..10 {
+        store.set("t", format!("k{i}"), i).unwrap();
+    }
+    store.set("t", "k0", "new").unwrap();
+    store.del("t", "k9").unwrap();
+    store.set("t2", "k1", "other").unwrap();
+
+    let mut expected: Vec<_> = (1..9).map(|i| Kvpair::new(format!("k{i}"), i)).collect();
+    expected.insert(0, Kvpair::new("k0", "new"));
+    assert_eq!(sorted(store.get_all("t").unwrap()), expected);
+    assert_eq!(sorted(store.get_iter("t").unwrap().collect()), expected);
+    assert_eq!(store.len("t").unwrap(), expected.len());
+    assert_eq!(store.len("t2").unwrap(), 1);
+}
+
+fn special_keys_should_round_trip(store: &impl Storage) {
+    let keys = [
+        "", ":", "a:b", "a\\", "\\:", " ", "a b", "\0", "\n", "中文", "🦀", "%2F",
+    ];
+    // RocksDB 用 table 名作为 column family 的名字，不能包含 \0，也不能为空
+    let tables = [
+        ":", "a:b", "a\\", "\\:", " ", "a b", "\n", "中文", "🦀", "%2F",
+    ];
+    for table in tables {
+        for key in keys {
+            store.set(table, key, format!("{table}|{key}")).unwrap();
+        }
+    }
+    for table in tables {
+        for key in keys {
+            let value = Some(format!("{table}|{key}").into());
+            assert_eq!(store.get(table, key).unwrap(), value, "{table:?} {key:?}");
+        }
+        let mut expected: Vec<_> = keys
+            .iter()
+            .map(|key| Kvpair::new(*key, format!("{table}|{key}")))
+            .collect();
+        expected.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(sorted(store.get_iter(table).unwrap().collect()), expected);
+    }
+    let mut names: Vec<_> = tables.iter().map(|s| s.to_string()).collect();
+    names.sort();
+    assert_eq!(store.tables().unwrap(), names);
+}
+
+fn large_values_should_round_trip(store: &impl Storage) {
+    let string = Value::from("v".repeat(1024 * 1024));
+    let binary: Vec<u8> = (0..1024 * 1024).map(|i| i as u8).collect();
+    let binary = Value::from(Bytes::from(binary));
+    let key = "k".repeat(1024);
+    store.set("t", "string", string.clone()).unwrap();
+    store.set("t", key.as_str(), binary.clone()).unwrap();
+    assert_eq!(store.get("t", "string").unwrap(), Some(string.clone()));
+    assert_eq!(store.get("t", &key).unwrap(), Some(binary.clone()));
+    assert_eq!(
+        sorted(store.get_all("t").unwrap()),
+        [
+            Kvpair::new(key.as_str(), binary),
+            Kvpair::new("string", string)
+        ]
+    );
+}
+
+fn clear_table_should_remove_all_keys(store: &impl Storage) {
+    assert_eq!(store.clear_table("t").unwrap(), 0);
+    for i in 0..100 {
+        store.set("t", format!("k{i}"), i).unwrap();
+    }
+    store.set("t2", "k1", "other").unwrap();
+    assert_eq!(store.clear_table("t").unwrap(), 100);
+    assert!(store.get_all("t").unwrap().is_empty());
+    assert_eq!(store.get("t", "k1").unwrap(), None);
+    assert_eq!(store.tables().unwrap(), ["t2"]);
+    // 清空后可以重新写入
+    assert_eq!(store.set("t", "k1", "v").unwrap(), None);
+    assert_eq!(store.clear_table("t").unwrap(), 1);
+}
+
+fn write_batch_should_apply_all_ops(store: &impl Storage) {
+    store.set("t1", "a", 1).unwrap();
+    store.set("t1", "b", 2).unwrap();
+    let set = |table: &str, key: &str, value: i64| WriteOp::Set {
+        table: table.into(),
+        key: key.into(),
+        value: value.into(),
+        expires_at: None,
+    };
+    let del = |table: &str, key: &str| WriteOp::Del {
+        table: table.into(),
+        key: key.into(),
+    };
+    store
+        .write_batch(vec![
+            set("t1", "a", 10),
+            del("t1", "b"),
+            set("t2", "c", 3),
+            // 同一个 key 以最后一个写入为准，删除不存在的 key 什么都不做
+            set("t2", "d", 4),
+            del("t2", "d"),
+            del("t2", "missing"),
+        ])
+        .unwrap();
+    assert_eq!(sorted(store.get_all("t1").unwrap()), [Kvpair::new("a", 10)]);
+    assert_eq!(sorted(store.get_all("t2").unwrap()), [Kvpair::new("c", 3)]);
+    assert!(store.write_batch(Vec::new()).is_ok());
+}
+
+fn sorted(mut pairs: Vec<Kvpair>) -> Vec<Kvpair> {
+    pairs.sort_by(|a, b| a.key.cmp(&b.key));
+    pairs
+}
@@ -0,0 +1,530 @@
+use std::{
+    collections::{hash_map::Entry, BTreeSet, HashMap},
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use super::dedup_pairs;
+use crate::{KvError, Kvpair, Storage, Value, WriteOp};
+
+/// 给指定的 table 维护一个从 value 到 key 的反向索引，支持按 value 做等值查询。
+///
+/// 所有写操作都要经过 IndexedStore：写入底层存储和更新索引在同一把锁内完成，
+/// 查询不会看到只更新了一半的索引。每个被索引的 key 大约占用 key 的长度加上
+/// 编码后 value 的长度（相同的 value 只保存一份），为了避免内存无限增长，
+/// 每个 table 的索引都有容量限制，超过容量后这个 table 的索引就不再完整，查询会返回错误
+pub struct IndexedStore<S> {
+    inner: S,
+    indexes: HashMap<String, Mutex<ValueIndex>>,
+}
+
+// 一个 table 的反向索引
+#[derive(Debug, Default)]
+struct ValueIndex {
+    // 编码后的 value 到 key 的映射
+    keys: HashMap<Vec<u8>, BTreeSet<String>>,
+    // 索引中 key 的数量
+    len: usize,
+    // 最多索引多少个 key
+    capacity: usize,
+    // 曾经超过容量，索引已经不完整
+    overflowed: bool,
+}
+
+// 索引中使用的 value：只编码 value 的内容，和 find_by_value 一样忽略 content_type
+fn index_key(value: &Value) -> Vec<u8> {
+    let mut buf = Vec::new();
+    if let Some(v) = &value.value {
+        v.encode(&mut buf);
+    }
+    buf
+}
+
+impl ValueIndex {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    fn insert(&mut self, key: &str, value: &Value) {
+        let data = index_key(value);
+        if self.keys.get(&data).is_some_and(|keys| keys.contains(key)) {
+            return;
+        }
+        if self.len >= self.capacity {
+            self.overflowed = true;
+            return;
+        }
+        self.keys.entry(data).or_default().insert(key.to_string());
+        self.len += 1;
+    }
+
+    fn remove(&mut self, key: &str, value: &Value) {
+        let data = index_key(value);
+        if let Some(keys) = self.keys.get_mut(&data) {
+            if keys.remove(key) {
+                self.len -= 1;
+            }
+            if keys.is_empty() {
+                self.keys.remove(&data);
+            }
+        }
+    }
+
+    fn find(&self, table: &str, value: &Value) -> Result<Vec<String>, KvError> {
+        if self.overflowed {
+            return Err(KvError::IndexOverflow(table.into(), self.capacity));
+        }
+        let keys = self.keys.get(&index_key(value));
+        Ok(keys
+            .map(|k| k.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+}
+
+impl<S: Storage> IndexedStore<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            indexes: HashMap::new(),
+        }
+    }
+
+    /// 为 table 建立最多包含 capacity 个 key 的索引，底层存储中已有的数据会被加入索引
+    pub fn with_index(
+        mut self,
+        table: impl Into<String>,
+        capacity: usize,
+    ) -> Result<Self, KvError> {
+        let table = table.into();
+        let mut index = ValueIndex::new(capacity);
+        for pair in self.inner.get_iter(&table)? {
+            if let Some(v) = &pair.value {
+                index.insert(&pair.key, v);
+            }
+        }
+        self.indexes.insert(table, Mutex::new(index));
+        Ok(self)
+    }
+
+    // 锁住 table 的索引，table 没有索引时返回 None
+    fn lock(&self, table: &str) -> Option<MutexGuard<'_, ValueIndex>> {
+        self.indexes
+            .get(table)
+            .map(|index| index.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl<S: Storage> Storage for IndexedStore<S> {
+    fn get(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+        self.inner.get(table, key)
+    }
+
+    fn get_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        self.inner.get_batch(table, keys)
+    }
+
+    fn set(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Result<Option<Value>, KvError> {
+        let (key, value) = (key.into(), value.into());
+        let mut index = self.lock(table);
+        let old = self.inner.set(table, key.clone(), value.clone())?;
+        if let Some(index) = index.as_mut() {
+            if let Some(old) = &old {
+                index.remove(&key, old);
+            }
+            index.insert(&key, &value);
+        }
+        Ok(old)
+    }
+
+    fn contains(&self, table: &str, key: &str) -> Result<bool, KvError> {
+        self.inner.contains(table, key)
+    }
+
+    fn del(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+        let mut index = self.lock(table);
+        let old = self.inner.del(table, key)?;
+        if let (Some(index), Some(old)) = (index.as_mut(), &old) {
+            index.remove(key, old);
+        }
+        Ok(old)
+    }
+
+    fn del_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        let mut index = self.lock(table);
+        let removed = self.inner.del_batch(table, keys)?;
+        if let Some(index) = index.as_mut() {
+            for (key, old) in keys.iter().zip(&removed) {
+                if let Some(old) = old {
+                    index.remove(key.as_ref(), old);
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    fn mtime(&self, table: &str, key: &str) -> Result<Option<i64>, KvError> {
+        self.inner.mtime(table, key)
+    }
+
+    fn tables(&self) -> Result<Vec<String>, KvError> {
+        self.inner.tables()
+    }
+
+    fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError> {
+        self.inner.get_all(table)
+    }
+
+    fn get_iter(&self, table: &str) -> Result<impl Iterator<Item = Kvpair>, KvError> {
+        self.inner.get_iter(table)
+    }
+
+    fn compact(&self) -> Result<u64, KvError> {
+        self.inner.compact()
+    }
+
+    fn len(&self, table: &str) -> Result<usize, KvError> {
+        self.inner.len(table)
+    }
+
+    fn approximate_size(&self, table: &str) -> Result<u64, KvError> {
+        self.inner.approximate_size(table)
+    }
+
+    fn stored_key_len(&self, table: &str, key: &str) -> usize {
+        self.inner.stored_key_len(table, key)
+    }
+
+    fn expire(&self, table: &str, key: &str, ttl: Duration) -> Result<bool, KvError> {
+        self.inner.expire(table, key, ttl)
+    }
+
+    fn set_with_ttl(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+        ttl: Duration,
+    ) -> Result<Option<Value>, KvError> {
+        let (key, value) = (key.into(), value.into());
+        let mut index = self.lock(table);
+        let old = self
+            .inner
+            .set_with_ttl(table, key.clone(), value.clone(), ttl)?;
+        if let Some(index) = index.as_mut() {
+            if let Some(old) = &old {
+                index.remove(&key, old);
+            }
+            index.insert(&key, &value);
+        }
+        Ok(old)
+    }
+
+    fn write_batch(&self, ops: Vec<WriteOp>) -> Result<(), KvError> {
+        let indexed: Vec<_> = ops
+            .iter()
+            .filter(|op| self.indexes.contains_key(op.target().0))
+            .cloned()
+            .collect();
+        // 按 table 名字的顺序加锁，和 move_key 一样避免互相等待
+        let tables: BTreeSet<_> = indexed.iter().map(|op| op.target().0).collect();
+        let mut locks: HashMap<&str, MutexGuard<'_, ValueIndex>> = HashMap::new();
+        for table in tables {
+            locks.extend(self.lock(table).map(|index| (table, index)));
+        }
+        // 按顺序执行所有写入之前被索引的 key 的值
+        let mut current = HashMap::new();
+        for op in &indexed {
+            let (table, key) = op.target();
+            if let Entry::Vacant(e) = current.entry((table, key)) {
+                e.insert(self.inner.get(table, key)?);
+            }
+        }
+        self.inner.write_batch(ops)?;
+        for op in &indexed {
+            let (table, key) = op.target();
+            let index = locks.get_mut(table).unwrap();
+            let value = current.get_mut(&(table, key)).unwrap();
+            if let Some(old) = value.take() {
+                index.remove(key, &old);
+            }
+            if let WriteOp::Set { value: new, .. } = op {
+                index.insert(key, new);
+                *value = Some(new.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn ttl(&self, table: &str, key: &str) -> Result<Option<Duration>, KvError> {
+        self.inner.ttl(table, key)
+    }
+
+    fn move_key(
+        &self,
+        from_table: &str,
+        to_table: &str,
+        key: &str,
+    ) -> Result<Option<Value>, KvError> {
+        if from_table == to_table {
+            return self.inner.get(from_table, key);
+        }
+        // 按 table 名字的顺序加锁，避免两个方向相反的 HMOVE 互相等待
+        let (mut from, mut to) = if from_table < to_table {
+            let from = self.lock(from_table);
+            (from, self.lock(to_table))
+        } else {
+            let to = self.lock(to_table);
+            (self.lock(from_table), to)
+        };
+        // to_table 中原有的值会被覆盖，需要从索引中去掉
+        let replaced = match to {
+            Some(_) => self.inner.get(to_table, key)?,
+            None => None,
+        };
+        let value = self.inner.move_key(from_table, to_table, key)?;
+        if let Some(v) = &value {
+            if let Some(from) = from.as_mut() {
+                from.remove(key, v);
+            }
+            if let Some(to) = to.as_mut() {
+                if let Some(old) = &replaced {
+                    to.remove(key, old);
+                }
+                to.insert(key, v);
+            }
+        }
+        Ok(value)
+    }
+
+    fn rename_key(
+        &self,
+        table: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Option<Option<Value>>, KvError> {
+        let mut index = self.lock(table);
+        if index.is_none() || from == to {
+            return self.inner.rename_key(table, from, to);
+        }
+        let value = self.inner.get(table, from)?;
+        let result = self.inner.rename_key(table, from, to)?;
+        if let (Some(index), Some(v), Some(old)) = (index.as_mut(), &value, &result) {
+            if let Some(old) = old {
+                index.remove(to, old);
+            }
+            index.remove(from, v);
+            index.insert(to, v);
+        }
+        Ok(result)
+    }
+
+    fn update<F>(&self, table: &str, key: &str, mut f: F) -> Result<Option<Value>, KvError>
+    where
+        F: FnMut(Option<&Value>) -> Option<Value>,
+    {
+        let mut index = self.lock(table);
+        let mut old = None;
+        let value = self.inner.update(table, key, |v| {
+            old = v.cloned();
+            f(v)
+        })?;
+        if let Some(index) = index.as_mut() {
+            if let Some(old) = &old {
+                index.remove(key, old);
+            }
+            if let Some(v) = &value {
+                index.insert(key, v);
+            }
+        }
+        Ok(value)
+    }
+
+    fn find_by_value(&self, table: &str, value: &Value) -> Result<Vec<String>, KvError> {
+        let keys = match self.lock(table) {
+            Some(index) => index.find(table, value)?,
+            None => return self.inner.find_by_value(table, value),
+        };
+        // 过期的 key 不经过 IndexedStore 就消失了，还留在索引中，需要再检查一次
+        let mut found = Vec::with_capacity(keys.len());
+        for key in keys {
+            if self
+                .inner
+                .get(table, &key)?
+                .is_some_and(|v| v.deep_eq(value))
+            {
+                found.push(key);
+            }
+        }
+        Ok(found)
+    }
+
+    fn replace_table(&self, table: &str, pairs: Vec<Kvpair>) -> Result<(u64, u64), KvError> {
+        let mut index = self.lock(table);
+        let Some(index) = index.as_mut() else {
+            return self.inner.replace_table(table, pairs);
+        };
+        let result = self.inner.replace_table(table, pairs.clone())?;
+        // 整个 table 都被替换了，重新建立索引
+        **index = ValueIndex::new(index.capacity);
+        for (key, value) in dedup_pairs(pairs) {
+            index.insert(&key, &value);
+        }
+        Ok(result)
+    }
+
+    fn clear_table(&self, table: &str) -> Result<u64, KvError> {
+        let mut index = self.lock(table);
+        let removed = self.inner.clear_table(table)?;
+        // 清空之后索引又是完整的
+        if let Some(index) = index.as_mut() {
+            **index = ValueIndex::new(index.capacity);
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemTable;
+
+    #[test]
+    fn indexed_store_should_work() {
+        let store = MemTable::new();
+        store.set("user", "tyr", "admin").unwrap();
+        let store = IndexedStore::new(store).with_index("user", 100).unwrap();
+
+        store.set("user", "alice", "admin").unwrap();
+        store.set("user", "bob", "guest").unwrap();
+        assert_eq!(
+            store.find_by_value("user", &"admin".into()).unwrap(),
+            vec!["alice", "tyr"]
+        );
+
+        // 覆盖和删除都会更新索引
+        store.set("user", "alice", "guest").unwrap();
+        store.del("user", "tyr").unwrap();
+        assert!(store
+            .find_by_value("user", &"admin".into())
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store.find_by_value("user", &"guest".into()).unwrap(),
+            vec!["alice", "bob"]
+        );
+
+        // 移出和移入被索引的 table
+        store.move_key("user", "archived", "bob").unwrap();
+        assert_eq!(
+            store.find_by_value("user", &"guest".into()).unwrap(),
+            vec!["alice"]
+        );
+        store.move_key("archived", "user", "bob").unwrap();
+        assert_eq!(
+            store.find_by_value("user", &"guest".into()).unwrap(),
+            vec!["alice", "bob"]
+        );
+
+        // 重命名时去掉被覆盖的 key
+        store.set("user", "carol", "admin").unwrap();
+        store.rename_key("user", "bob", "carol").unwrap();
+        assert_eq!(
+            store.find_by_value("user", &"guest".into()).unwrap(),
+            vec!["alice", "carol"]
+        );
+        assert!(store
+            .find_by_value("user", &"admin".into())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn indexed_store_should_fail_when_index_overflowed() {
+        let store = IndexedStore::new(MemTable::new())
+            .with_index("user", 2)
+            .unwrap();
+        store.set("user", "alice", "admin").unwrap();
+        store.set("user", "bob", "admin").unwrap();
+        assert!(store.find_by_value("user", &"admin".into()).is_ok());
+
+        store.set("user", "tyr", "admin").unwrap();
+        assert!(store.find_by_value("user", &"admin".into()).is_err());
+        // 数据本身依旧写入成功
+        assert_eq!(store.get("user", "tyr").unwrap(), Some("admin".into()));
+    }
+
+    #[test]
+    fn index_should_ignore_content_type() {
+        let store = IndexedStore::new(MemTable::new())
+            .with_index("user", 100)
+            .unwrap();
+        let value = Value::from("admin").with_content_type("text/plain");
+        store.set("user", "alice", value).unwrap();
+        assert_eq!(
+            store.find_by_value("user", &"admin".into()).unwrap(),
+            vec!["alice"]
+        );
+    }
+
+    #[test]
+    fn replace_table_should_rebuild_index() {
+        let store = IndexedStore::new(MemTable::new())
+            .with_index("user", 100)
+            .unwrap();
+        store.set("user", "alice", "admin").unwrap();
+        let pairs = vec![Kvpair::new("bob", "admin"), Kvpair::new("tyr", "guest")];
+        store.replace_table("user", pairs).unwrap();
+        assert_eq!(
+            store.find_by_value("user", &"admin".into()).unwrap(),
+            vec!["bob"]
+        );
+    }
+
+    #[test]
+    fn clear_table_should_reset_index() {
+        let store = IndexedStore::new(MemTable::new())
+            .with_index("user", 1)
+            .unwrap();
+        store.set("user", "alice", "admin").unwrap();
+        store.set("user", "bob", "admin").unwrap();
+        assert!(store.find_by_value("user", &"admin".into()).is_err());
+
+        // 超过容量的索引在清空后重新可用
+        assert_eq!(store.clear_table("user").unwrap(), 2);
+        assert!(store
+            .find_by_value("user", &"admin".into())
+            .unwrap()
+            .is_empty());
+        store.set("user", "tyr", "admin").unwrap();
+        assert_eq!(
+            store.find_by_value("user", &"admin".into()).unwrap(),
+            vec!["tyr"]
+        );
+    }
+
+    #[test]
+    fn unindexed_table_should_fall_back_to_scan() {
+        let store = IndexedStore::new(MemTable::new());
+        store.set("user", "alice", "admin").unwrap();
+        assert_eq!(
+            store.find_by_value("user", &"admin".into()).unwrap(),
+            vec!["alice"]
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::time::Duration;
+
+use crate::{KvError, Kvpair, Storage, Value, WriteOp};
+
+/// LimitedStore 缺省允许的最大 key 长度（字节数）
+pub const DEFAULT_MAX_KEY_BYTES: usize = 64 * 1024;
+/// LimitedStore 缺省允许的最大 table 名长度（字节数）
+pub const DEFAULT_MAX_TABLE_BYTES: usize = 1024;
+
+/// 限制 table 名和 key 长度的存储，防止客户端写入很长的 key 占用大量内存。
+///
+/// 写入（set、update、move_key、rename_key、replace_table 和 copy_table）之前检查长度，超过限制时不做任何修改，返回 400。
+/// key 的长度是它在底层存储中实际占用的长度（见 `Storage::stored_key_len`），
+/// 比如 SledDb 中包括转义后的 table 名和分隔符。读取和删除不受限制
+pub struct LimitedStore<S> {
+    inner: S,
+    max_key_bytes: usize,
+    max_table_bytes: usize,
+}
+
+impl<S: Storage> LimitedStore<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            max_key_bytes: DEFAULT_MAX_KEY_BYTES,
+            max_table_bytes: DEFAULT_MAX_TABLE_BYTES,
+        }
+    }
+
+    /// 设置 key 在底层存储中允许的最大长度
+    pub fn with_max_key_bytes(mut self, n: usize) -> Self {
+        self.max_key_bytes = n;
+        self
+    }
+
+    /// 设置 table 名允许的最大长度
+    pub fn with_max_table_bytes(mut self, n: usize) -> Self {
+        self.max_table_bytes = n;
+        self
+    }
+
+    fn check(&self, table: &str, key: &str) -> Result<(), KvError> {
+        if table.len() > self.max_table_bytes {
+            let (len, max) = (table.len(), self.max_table_bytes);
+            return Err(KvError::KeyTooLong("Table name", len, max));
+        }
+        let len = self.inner.stored_key_len(table, key);
+        if len > self.max_key_bytes {
+            return Err(KvError::KeyTooLong("Key", len, self.max_key_bytes));
+        }
+        Ok(())
+    }
+}
+
+impl<S: Storage> Storage for LimitedStore<S> {
+    fn get(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+        self.inner.get(table, key)
+    }
+
+    fn get_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        self.inner.get_batch(table, keys)
+    }
+
+    fn set(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Result<Option<Value>, KvError> {
+        let key = key.into();
+        self.check(table, &key)?;
+        self.inner.set(table, key, value)
+    }
+
+    fn contains(&self, table: &str, key: &str) -> Result<bool, KvError> {
+        self.inner.contains(table, key)
+    }
+
+    fn del(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+        self.inner.del(table, key)
+    }
+
+    fn del_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        self.inner.del_batch(table, keys)
+    }
+
+    fn mtime(&self, table: &str, key: &str) -> Result<Option<i64>, KvError> {
+        self.inner.mtime(table, key)
+    }
+
+    fn tables(&self) -> Result<Vec<String>, KvError> {
+        self.inner.tables()
+    }
+
+    fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError> {
+        self.inner.get_all(table)
+    }
+
+    fn get_iter(&self, table: &str) -> Result<impl Iterator<Item = Kvpair>, KvError> {
+        self.inner.get_iter(table)
+    }
+
+    fn compact(&self) -> Result<u64, KvError> {
+        self.inner.compact()
+    }
+
+    fn move_key(
+        &self,
+        from_table: &str,
+        to_table: &str,
+        key: &str,
+    ) -> Result<Option<Value>, KvError> {
+        self.check(to_table, key)?;
+        self.inner.move_key(from_table, to_table, key)
+    }
+
+    fn rename_key(
+        &self,
+        table: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Option<Option<Value>>, KvError> {
+        self.check(table, to)?;
+        self.inner.rename_key(table, from, to)
+    }
+
+    fn update<F>(&self, table: &str, key: &str, f: F) -> Result<Option<Value>, KvError>
+    where
+        F: FnMut(Option<&Value>) -> Option<Value>,
+    {
+        self.check(table, key)?;
+        self.inner.update(table, key, f)
+    }
+
+    fn find_by_value(&self, table: &str, value: &Value) -> Result<Vec<String>, KvError> {
+        self.inner.find_by_value(table, value)
+    }
+
+    fn len(&self, table: &str) -> Result<usize, KvError> {
+        self.inner.len(table)
+    }
+
+    fn approximate_size(&self, table: &str) -> Result<u64, KvError> {
+        self.inner.approximate_size(table)
+    }
+
+    fn replace_table(&self, table: &str, pairs: Vec<Kvpair>) -> Result<(u64, u64), KvError> {
+        for pair in &pairs {
+            self.check(table, &pair.key)?;
+        }
+        self.inner.replace_table(table, pairs)
+    }
+
+    fn clear_table(&self, table: &str) -> Result<u64, KvError> {
+        self.inner.clear_table(table)
+    }
+
+    fn copy_table(&self, src: &str, dst: &str, overwrite: bool) -> Result<u64, KvError> {
+        for pair in self.inner.get_iter(src)? {
+            self.check(dst, &pair.key)?;
+        }
+        self.inner.copy_table(src, dst, overwrite)
+    }
+
+    fn stored_key_len(&self, table: &str, key: &str) -> usize {
+        self.inner.stored_key_len(table, key)
+    }
+
+    fn expire(&self, table: &str, key: &str, ttl: Duration) -> Result<bool, KvError> {
+        self.inner.expire(table, key, ttl)
+    }
+
+    fn set_with_ttl(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+        ttl: Duration,
+    ) -> Result<Option<Value>, KvError> {
+        let key = key.into();
+        self.check(table, &key)?;
+        self.inner.set_with_ttl(table, key, value, ttl)
+    }
+
+    fn write_batch(&self, ops: Vec<WriteOp>) -> Result<(), KvError> {
+        for op in &ops {
+            if let WriteOp::Set { table, key, .. } = op {
+                self.check(table, key)?;
+            }
+        }
+        self.inner.write_batch(ops)
+    }
+
+    fn ttl(&self, table: &str, key: &str) -> Result<Option<Duration>, KvError> {
+        self.inner.ttl(table, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{dispatch, CommandRequest, MemTable, SledDb};
+
+    #[test]
+    fn limited_store_should_check_key_length() {
+        let store = LimitedStore::new(MemTable::new())
+            .with_max_key_bytes(4)
+            .with_max_table_bytes(2);
+        assert!(store.set("t", "1234", "v").is_ok());
+        let e = store.set("t", "12345", "v").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Key is 5 bytes, longer than the limit of 4 bytes"
+        );
+        // 按字节计算长度
+        assert!(store.set("t", "中文", "v").is_err());
+        assert!(store.update("t", "12345", |_| Some(1.into())).is_err());
+        assert!(store.move_key("t", "t2", "1234").is_ok());
+        assert_eq!(store.get("t", "12345").unwrap(), None);
+
+        // 通过命令写入时返回 400
+        let res = dispatch(CommandRequest::new_hset("t", "12345", "v"), &store);
+        assert_eq!(res.status, 400);
+        assert_eq!(
+            res.message,
+            "Key is 5 bytes, longer than the limit of 4 bytes"
+        );
+
+        let e = store.set("abc", "k", "v").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Table name is 3 bytes, longer than the limit of 2 bytes"
+        );
+        assert!(store.move_key("t2", "abc", "1234").is_err());
+        assert!(store.get("t2", "1234").unwrap().is_some());
+
+        // 有一个 key 超过限制时整个 table 都不替换
+        let pairs = vec![Kvpair::new("k", "new"), Kvpair::new("12345", "new")];
+        assert!(store.replace_table("t2", pairs).is_err());
+        assert_eq!(store.get("t2", "k").unwrap(), None);
+        assert!(store.get("t2", "1234").unwrap().is_some());
+    }
+
+    #[test]
+    fn limited_sled_should_check_full_key_length() {
+        let dir = tempdir().unwrap();
+        let store = LimitedStore::new(SledDb::new(dir.path())).with_max_key_bytes(10);
+        // sled 中的 key 是 table:key，table 中的 : 会被转义
+        assert!(store.set("ab", "1234567", "v").is_ok());
+        assert!(store.set("ab", "12345678", "v").is_err());
+        assert!(store.set("a:", "123456", "v").is_ok());
+        let e = store.set("a:", "1234567", "v").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Key is 11 bytes, longer than the limit of 10 bytes"
+        );
+    }
+}
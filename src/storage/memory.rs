@@ -1,37 +1,234 @@
-use crate::{KvError, Kvpair, Storage, StorageIter, Value};
-use dashmap::{mapref::one::Ref, DashMap};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::Duration,
+};
 
-/// 使用 DashMap 构建的 MemTable，实现了 Storage trait
+use super::{dedup_pairs, expires_after, now_millis, remaining};
+use crate::{KvError, Kvpair, Storage, StorageIter, Value, WriteOp};
+use dashmap::DashMap;
+use prost::Message;
+
+/// table 中的一个 value，它最后一次被写入的时间和过期时间（都是毫秒级的 UNIX 时间戳）
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableEntry {
+    pub value: Value,
+    pub mtime: i64,
+    /// 为 None 时不过期
+    pub expires_at: Option<i64>,
+}
+
+impl TableEntry {
+    // 创建一个新的 entry，修改时间为当前时间，不过期
+    fn new(value: Value) -> Self {
+        Self {
+            value,
+            mtime: now_millis(),
+            expires_at: None,
+        }
+    }
+
+    /// 在 now 时是否已经过期
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    // 没有过期时返回 value
+    fn live(self, now: i64) -> Option<Value> {
+        (!self.is_expired(now)).then_some(self.value)
+    }
+}
+
+/// 一个 table 中的所有数据
+pub type Table = HashMap<String, TableEntry>;
+
+/// 存放所有 table 的容器，由实现者决定加锁的方式和粒度
+pub trait TableMap: Default {
+    /// 加读锁访问名为 name 的 table，table 不存在时 f 得到 None
+    fn read_table<R>(&self, name: &str, f: impl FnOnce(Option<&Table>) -> R) -> R;
+    /// 加写锁访问名为 name 的 table，table 不存在时先创建。f 执行期间一直持有写锁
+    fn write_table<R>(&self, name: &str, f: impl FnOnce(&mut Table) -> R) -> R;
+    /// 同时加写锁访问两个不同的 table，不存在时先创建，用于跨 table 的原子操作。调用者需保证 a != b
+    fn write_tables<R>(&self, a: &str, b: &str, f: impl FnOnce(&mut Table, &mut Table) -> R) -> R;
+    /// 同时加写锁访问 names 中的所有 table，不存在时先创建，f 得到的 table 和 names 的顺序相同。
+    /// 用于原子地写入多个 table，调用者需保证 names 中没有重复的名字
+    fn write_many<R>(&self, names: &[&str], f: impl FnOnce(Vec<&mut Table>) -> R) -> R;
+    /// 所有至少包含一个 key 的 table 的名字，没有顺序
+    fn table_names(&self) -> Vec<String>;
+}
+
+/// 使用一把全局的读写锁保护所有 table，所有写操作严格有序
+impl TableMap for RwLock<HashMap<String, Table>> {
+    fn read_table<R>(&self, name: &str, f: impl FnOnce(Option<&Table>) -> R) -> R {
+        f(read(self).get(name))
+    }
+
+    fn write_table<R>(&self, name: &str, f: impl FnOnce(&mut Table) -> R) -> R {
+        let mut tables = write(self);
+        match tables.get_mut(name) {
+            Some(table) => f(table),
+            None => f(tables.entry(name.to_string()).or_default()),
+        }
+    }
+
+    fn write_tables<R>(&self, a: &str, b: &str, f: impl FnOnce(&mut Table, &mut Table) -> R) -> R {
+        debug_assert_ne!(a, b);
+        let mut tables = write(self);
+        // 持有全局写锁时把 a 暂时取出来，这样才能同时拿到两个 table 的可变引用
+        let mut table_a = tables.remove(a).unwrap_or_default();
+        let result = f(&mut table_a, tables.entry(b.to_string()).or_default());
+        tables.insert(a.to_string(), table_a);
+        result
+    }
+
+    fn write_many<R>(&self, names: &[&str], f: impl FnOnce(Vec<&mut Table>) -> R) -> R {
+        let mut tables = write(self);
+        // 和 write_tables 一样先取出来，全部修改完再放回去
+        let mut taken: Vec<_> = names
+            .iter()
+            .map(|name| tables.remove(*name).unwrap_or_default())
+            .collect();
+        let result = f(taken.iter_mut().collect());
+        for (name, table) in names.iter().zip(taken) {
+            tables.insert(name.to_string(), table);
+        }
+        result
+    }
+
+    fn table_names(&self) -> Vec<String> {
+        read(self)
+            .iter()
+            .filter(|(_, t)| !t.is_empty())
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+/// 使用 DashMap 保存所有 table，每个 table 有自己的读写锁，不同 table 之间可以并发读写。
+/// 访问 table 时先拿到 table 并释放 DashMap 的分片锁，再对 table 加锁，因此不会同时持有两层锁
+impl TableMap for DashMap<String, Arc<RwLock<Table>>> {
+    fn read_table<R>(&self, name: &str, f: impl FnOnce(Option<&Table>) -> R) -> R {
+        let table = self.get(name).map(|t| t.value().clone());
+        match table {
+            Some(table) => f(Some(&*read(&table))),
+            None => f(None),
+        }
+    }
+
+    fn write_table<R>(&self, name: &str, f: impl FnOnce(&mut Table) -> R) -> R {
+        let table = get_or_create(self, name);
+        let mut table = write(&table);
+        f(&mut table)
+    }
+
+    fn write_tables<R>(&self, a: &str, b: &str, f: impl FnOnce(&mut Table, &mut Table) -> R) -> R {
+        debug_assert_ne!(a, b);
+        let (table_a, table_b) = (get_or_create(self, a), get_or_create(self, b));
+        // 按 table 名字的顺序加锁，避免两个方向相反的跨 table 操作互相等待
+        if a < b {
+            let mut table_a = write(&table_a);
+            let mut table_b = write(&table_b);
+            f(&mut table_a, &mut table_b)
+        } else {
+            let mut table_b = write(&table_b);
+            let mut table_a = write(&table_a);
+            f(&mut table_a, &mut table_b)
+        }
+    }
+
+    fn write_many<R>(&self, names: &[&str], f: impl FnOnce(Vec<&mut Table>) -> R) -> R {
+        let tables: Vec<_> = names.iter().map(|name| get_or_create(self, name)).collect();
+        // 和 write_tables 一样按 table 名字的顺序加锁
+        let mut order: Vec<_> = (0..names.len()).collect();
+        order.sort_unstable_by_key(|&i| names[i]);
+        let mut guards: Vec<_> = names.iter().map(|_| None).collect();
+        for i in order {
+            guards[i] = Some(write(&tables[i]));
+        }
+        f(guards.iter_mut().flatten().map(|t| &mut **t).collect())
+    }
+
+    fn table_names(&self) -> Vec<String> {
+        // 先取出所有 table 并释放 DashMap 的分片锁，再逐个加锁查看是否为空
+        let tables: Vec<_> = self
+            .iter()
+            .map(|t| (t.key().clone(), t.value().clone()))
+            .collect();
+        tables
+            .into_iter()
+            .filter(|(_, t)| !read(t).is_empty())
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+// 如果名为 name 的 table 不存在，则创建，否则返回
+fn get_or_create(tables: &DashMap<String, Arc<RwLock<Table>>>, name: &str) -> Arc<RwLock<Table>> {
+    match tables.get(name) {
+        Some(table) => table.value().clone(),
+        // entry()提供比insert()更多的灵活性，它返回一个枚举值Entry,该枚举代表了键在 DashMap 中的位置。
+        // 通过 Entry，可以决定如何处理该键的值，例如插入新值、更新已有值或执行其他操作。
+        None => tables.entry(name.to_string()).or_default().value().clone(),
+    }
+}
+
+// 加读锁，即便锁被 panic 的线程污染也继续使用其中的数据
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|e| e.into_inner())
+}
+
+// 加写锁，即便锁被 panic 的线程污染也继续使用其中的数据
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 内存存储，加锁的策略由 M 决定，实现了 Storage trait
 #[derive(Clone, Debug, Default)]
-pub struct MemTable {
-    tables: DashMap<String, DashMap<String, Value>>,
+pub struct LockedStore<M> {
+    tables: M,
 }
 
-impl MemTable {
-    // 创建一个缺省的MemTable
+/// 使用 DashMap 构建的内存存储，不同 table 之间可以并发读写。clone 后的 store 共享同一份数据
+pub type DashMapStore = LockedStore<DashMap<String, Arc<RwLock<Table>>>>;
+
+/// 使用全局读写锁构建的内存存储，所有写操作严格有序
+pub type RwLockStore = LockedStore<RwLock<HashMap<String, Table>>>;
+
+/// 缺省的内存存储
+pub type MemTable = DashMapStore;
+
+impl<M: TableMap> LockedStore<M> {
+    // 创建一个缺省的内存存储
     pub fn new() -> Self {
         Self::default()
     }
 
-    // 如果名为 name 的 hash table不存在，则创建，否则返回
-    fn get_or_create_table(&self, name: &str) -> Ref<String, DashMap<String, Value>> {
-        match self.tables.get(name) {
-            Some(table) => table,
-            None => {
-                // entry()提供比insert()更多的灵活性，它返回一个枚举值Entry,该枚举代表了键在 DashMap 中的位置。
-                // 通过 Entry，可以决定如何处理该键的值，例如插入新值、更新已有值或执行其他操作。
-                let entry = self.tables.entry(name.to_string()).or_default();
-                // downgrade() 方法将一个 Entry 转换为一个 Ref, 允许只读访问
-                entry.downgrade()
-            }
-        }
+    /// 如果名为 name 的 table 不存在，则创建
+    pub fn get_or_create_table(&self, name: &str) {
+        self.tables.write_table(name, |_| ());
     }
 }
 
-impl Storage for MemTable {
+impl<M: TableMap> Storage for LockedStore<M> {
     fn get(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
-        let table = self.get_or_create_table(table);
-        Ok(table.get(key).map(|v| v.value().clone()))
+        let now = now_millis();
+        Ok(self
+            .tables
+            .read_table(table, |t| live(t, key, now).map(|e| e.value.clone())))
+    }
+
+    fn get_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        let now = now_millis();
+        // 整个 batch 只加一次读锁
+        Ok(self.tables.read_table(table, |t| {
+            keys.iter()
+                .map(|key| live(t, key.as_ref(), now).map(|e| e.value.clone()))
+                .collect()
+        }))
     }
 
     fn set(
@@ -40,36 +237,302 @@ impl Storage for MemTable {
         key: impl Into<String>,
         value: impl Into<Value>,
     ) -> Result<Option<Value>, KvError> {
-        let table = self.get_or_create_table(table);
-        Ok(table.insert(key.into(), value.into()))
+        let (key, value) = (key.into(), value.into());
+        let entry = TableEntry::new(value);
+        let now = entry.mtime;
+        Ok(self
+            .tables
+            .write_table(table, |t| t.insert(key, entry).and_then(|e| e.live(now))))
     }
 
     fn contains(&self, table: &str, key: &str) -> Result<bool, KvError> {
-        let table = self.get_or_create_table(table);
-        Ok(table.contains_key(key))
+        let now = now_millis();
+        Ok(self
+            .tables
+            .read_table(table, |t| live(t, key, now).is_some()))
     }
 
     fn del(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
-        let table = self.get_or_create_table(table);
-        Ok(table.remove(key).map(|(_k, v)| v))
+        let now = now_millis();
+        Ok(self
+            .tables
+            .write_table(table, |t| t.remove(key).and_then(|e| e.live(now))))
+    }
+
+    fn del_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        let now = now_millis();
+        // 在 table 的写锁内删除所有 key
+        Ok(self.tables.write_table(table, |t| {
+            keys.iter()
+                .map(|key| t.remove(key.as_ref()).and_then(|e| e.live(now)))
+                .collect()
+        }))
     }
 
     fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError> {
-        let table = self.get_or_create_table(table);
-        Ok(table
-            .iter()
-            .map(|p| Kvpair::new(p.key(), p.value().clone()))
-            .collect())
+        let now = now_millis();
+        Ok(self.tables.read_table(table, |t| {
+            t.map(|t| {
+                t.iter()
+                    .filter(|(_, e)| !e.is_expired(now))
+                    .map(|(k, e)| Kvpair::new(k, e.value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+        }))
     }
 
     fn get_iter(&self, table: &str) -> Result<impl Iterator<Item = Kvpair>, KvError> {
-        let table = self.get_or_create_table(table).clone();
-        Ok(StorageIter::new(table.into_iter()))
+        let now = now_millis();
+        let table = self
+            .tables
+            .read_table(table, |t| t.cloned().unwrap_or_default());
+        Ok(StorageIter::new(
+            table
+                .into_iter()
+                .filter(move |(_, e)| !e.is_expired(now))
+                .map(|(k, e)| Kvpair::new(k, e.value)),
+        ))
+    }
+
+    fn compact(&self) -> Result<u64, KvError> {
+        let now = now_millis();
+        let mut freed = 0;
+        // 逐个 table 删除已经过期的 key，返回这些 key 和 value 的大致大小
+        for name in self.tables.table_names() {
+            freed += self.tables.write_table(&name, |t| {
+                let mut freed = 0;
+                t.retain(|k, e| {
+                    let expired = e.is_expired(now);
+                    if expired {
+                        freed += (k.len() + e.value.encoded_len()) as u64;
+                    }
+                    !expired
+                });
+                freed
+            });
+        }
+        Ok(freed)
+    }
+
+    fn move_key(
+        &self,
+        from_table: &str,
+        to_table: &str,
+        key: &str,
+    ) -> Result<Option<Value>, KvError> {
+        if from_table == to_table {
+            return self.get(from_table, key);
+        }
+        let now = now_millis();
+        Ok(self.tables.write_tables(from_table, to_table, |from, to| {
+            // 移动不算写入，保留原来的修改时间和过期时间。已经过期的 key 直接删除
+            let entry = from.remove(key).filter(|e| !e.is_expired(now))?;
+            let value = entry.value.clone();
+            to.insert(key.to_string(), entry);
+            Some(value)
+        }))
+    }
+
+    fn rename_key(
+        &self,
+        table: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Option<Option<Value>>, KvError> {
+        if from == to {
+            return Ok(self.contains(table, from)?.then_some(None));
+        }
+        let now = now_millis();
+        Ok(self.tables.write_table(table, |t| {
+            // 和 move_key 一样保留原来的修改时间和过期时间
+            let entry = t.remove(from).filter(|e| !e.is_expired(now))?;
+            let old = t.insert(to.to_string(), entry);
+            Some(old.filter(|e| !e.is_expired(now)).map(|e| e.value))
+        }))
+    }
+
+    fn update<F>(&self, table: &str, key: &str, mut f: F) -> Result<Option<Value>, KvError>
+    where
+        F: FnMut(Option<&Value>) -> Option<Value>,
+    {
+        let now = now_millis();
+        Ok(self.tables.write_table(table, |t| {
+            let old = t.get(key).filter(|e| !e.is_expired(now));
+            let expires_at = old.and_then(|e| e.expires_at);
+            let value = f(old.map(|e| &e.value));
+            if value.as_ref() == old.map(|e| &e.value) {
+                return value;
+            }
+            match &value {
+                Some(v) => t.insert(
+                    key.to_string(),
+                    TableEntry {
+                        expires_at,
+                        ..TableEntry::new(v.clone())
+                    },
+                ),
+                None => t.remove(key),
+            };
+            value
+        }))
+    }
+
+    fn mtime(&self, table: &str, key: &str) -> Result<Option<i64>, KvError> {
+        let now = now_millis();
+        Ok(self
+            .tables
+            .read_table(table, |t| live(t, key, now).map(|e| e.mtime)))
+    }
+
+    fn tables(&self) -> Result<Vec<String>, KvError> {
+        let now = now_millis();
+        // 所有 key 都已经过期的 table 和空的 table 一样
+        let mut names: Vec<_> = self
+            .tables
+            .table_names()
+            .into_iter()
+            .filter(|name| {
+                self.tables.read_table(name, |t| {
+                    t.is_some_and(|t| t.values().any(|e| !e.is_expired(now)))
+                })
+            })
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    fn replace_table(&self, table: &str, pairs: Vec<Kvpair>) -> Result<(u64, u64), KvError> {
+        let new: Table = dedup_pairs(pairs)
+            .into_iter()
+            .map(|(k, v)| (k, TableEntry::new(v)))
+            .collect();
+        let now = now_millis();
+        // 在 table 的写锁内整体替换
+        Ok(self.tables.write_table(table, |t| {
+            let removed = t.values().filter(|e| !e.is_expired(now)).count() as u64;
+            *t = new;
+            (removed, t.len() as u64)
+        }))
+    }
+
+    fn clear_table(&self, table: &str) -> Result<u64, KvError> {
+        let now = now_millis();
+        // 整个 table 换成空的，而不是从 TableMap 中删除：DashMapStore 中其它线程可能已经拿到了这个 table，正要写入
+        Ok(self.tables.write_table(table, |t| {
+            let removed = std::mem::take(t);
+            removed.values().filter(|e| !e.is_expired(now)).count() as u64
+        }))
+    }
+
+    fn copy_table(&self, src: &str, dst: &str, overwrite: bool) -> Result<u64, KvError> {
+        if src == dst {
+            return Ok(0);
+        }
+        let now = now_millis();
+        // 同时持有两个 table 的写锁，读者要么看不到复制的 key，要么看到全部
+        Ok(self.tables.write_tables(src, dst, |src, dst| {
+            let mut copied = 0;
+            for (key, entry) in src.iter().filter(|(_, e)| !e.is_expired(now)) {
+                if !overwrite && dst.get(key).is_some_and(|e| !e.is_expired(now)) {
+                    continue;
+                }
+                dst.insert(key.clone(), TableEntry::new(entry.value.clone()));
+                copied += 1;
+            }
+            copied
+        }))
+    }
+
+    fn len(&self, table: &str) -> Result<usize, KvError> {
+        let now = now_millis();
+        Ok(self.tables.read_table(table, |t| {
+            t.map_or(0, |t| t.values().filter(|e| !e.is_expired(now)).count())
+        }))
+    }
+
+    fn expire(&self, table: &str, key: &str, ttl: Duration) -> Result<bool, KvError> {
+        let now = now_millis();
+        Ok(self.tables.write_table(table, |t| match t.get_mut(key) {
+            Some(e) if !e.is_expired(now) => {
+                e.expires_at = Some(expires_after(now, ttl));
+                true
+            }
+            _ => false,
+        }))
+    }
+
+    fn set_with_ttl(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+        ttl: Duration,
+    ) -> Result<Option<Value>, KvError> {
+        let (key, value) = (key.into(), value.into());
+        let mut entry = TableEntry::new(value);
+        let now = entry.mtime;
+        entry.expires_at = Some(expires_after(now, ttl));
+        Ok(self
+            .tables
+            .write_table(table, |t| t.insert(key, entry).and_then(|e| e.live(now))))
+    }
+
+    fn write_batch(&self, ops: Vec<WriteOp>) -> Result<(), KvError> {
+        let mut names: Vec<String> = ops.iter().map(|op| op.target().0.to_string()).collect();
+        names.sort_unstable();
+        names.dedup();
+        let refs: Vec<_> = names.iter().map(String::as_str).collect();
+        let now = now_millis();
+        // 持有所有 table 的写锁时写入，读者看不到只写入了一部分的 batch
+        self.tables.write_many(&refs, |mut tables| {
+            for op in ops {
+                let i = refs.binary_search(&op.target().0).unwrap();
+                match op {
+                    WriteOp::Set {
+                        key,
+                        value,
+                        expires_at,
+                        ..
+                    } => {
+                        let entry = TableEntry {
+                            value,
+                            mtime: now,
+                            expires_at,
+                        };
+                        tables[i].insert(key, entry);
+                    }
+                    WriteOp::Del { key, .. } => {
+                        tables[i].remove(&key);
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn ttl(&self, table: &str, key: &str) -> Result<Option<Duration>, KvError> {
+        let now = now_millis();
+        Ok(self.tables.read_table(table, |t| {
+            let expires_at = live(t, key, now)?.expires_at?;
+            Some(remaining(expires_at, now))
+        }))
     }
 }
 
+// table 中没有过期的 key
+fn live<'a>(table: Option<&'a Table>, key: &str, now: i64) -> Option<&'a TableEntry> {
+    table?.get(key).filter(|e| !e.is_expired(now))
+}
+
 #[cfg(test)]
 mod tests {
+    use std::{sync::Arc, thread};
+
     use super::*;
 
     #[test]
@@ -79,4 +542,69 @@ mod tests {
         store.get_or_create_table("table");
         assert!(store.tables.contains_key("table"));
     }
+
+    #[test]
+    fn dashmap_store_concurrent_access_should_work() {
+        concurrent_access_should_work(DashMapStore::new());
+    }
+
+    #[test]
+    fn rwlock_store_concurrent_access_should_work() {
+        concurrent_access_should_work(RwLockStore::new());
+    }
+
+    fn concurrent_access_should_work<M>(store: LockedStore<M>)
+    where
+        M: TableMap + Send + Sync + 'static,
+    {
+        let store = Arc::new(store);
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let store = store.clone();
+                thread::spawn(move || {
+                    for j in 0..100i64 {
+                        let table = format!("table{}", j % 4);
+                        store.set(&table, format!("key{i}-{j}"), j).unwrap();
+                        assert_eq!(
+                            store.get(&table, &format!("key{i}-{j}")).unwrap(),
+                            Some(j.into())
+                        );
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let total: usize = (0..4)
+            .map(|i| store.get_all(&format!("table{i}")).unwrap().len())
+            .sum();
+        assert_eq!(total, 800);
+
+        // 并发地在两个 table 之间来回移动 key 不会死锁，也不会丢失数据
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let store = store.clone();
+                thread::spawn(move || {
+                    let (from, to) = if i % 2 == 0 {
+                        ("table0", "table1")
+                    } else {
+                        ("table1", "table0")
+                    };
+                    for j in 0..100 {
+                        store.move_key(from, to, &format!("key{i}-{j}")).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let total: usize = (0..4)
+            .map(|i| store.get_all(&format!("table{i}")).unwrap().len())
+            .sum();
+        assert_eq!(total, 800);
+    }
 }
@@ -1,18 +1,53 @@
+mod boxed;
+mod codec;
+#[cfg(any(test, feature = "test-util"))]
+mod conformance;
+mod indexed;
+mod limited;
 mod memory;
 mod rocksdb;
 mod sleddb;
+mod staged;
 
-pub use memory::MemTable;
+pub use boxed::BoxedStorage;
+pub use codec::{CompressionStats, ValueCodec};
+#[cfg(any(test, feature = "test-util"))]
+pub use conformance::storage_conformance_suite;
+pub use indexed::IndexedStore;
+pub use limited::{LimitedStore, DEFAULT_MAX_KEY_BYTES, DEFAULT_MAX_TABLE_BYTES};
+pub use memory::{DashMapStore, LockedStore, MemTable, RwLockStore, Table, TableEntry, TableMap};
 pub use rocksdb::RocksDB;
 pub use sleddb::SledDb;
+pub(crate) use staged::Staged;
 
 use crate::{KvError, Kvpair, Value};
+use prost::Message;
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 /// 对存储的抽象，我们不关心数据存在哪儿，但需要定义外界如何和存储打交道
+///
+/// 所有存储都遵循同样的键模型：table 和 key 都是任意的 UTF-8 字符串（包括 `:` 这样的分隔符），
+/// 只有 table 和 key 都相同时才是同一个 key，遍历 table 时得到的 key 和写入时完全一样。
+/// 各个存储之间唯一允许的差异是遍历的顺序：MemTable 等内存存储没有顺序，
+/// SledDb 和 RocksDB 按 key 的字节序返回，需要确定的顺序时调用者应当自己排序
 pub trait Storage {
     /// 从一个 HashTable 里获取一个 key 的 value
     fn get(&self, table: &str, key: &str) -> Result<Option<Value>, KvError>;
-    /// 从一个 HashTable 里设置一个 key 的 value，返回旧的 value
+    /// 从一个 HashTable 里获取一组 key 的 value，存储可以一次性读取所有的 key，减少加锁的次数
+    fn get_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        keys.iter()
+            .map(|key| self.get(table, key.as_ref()))
+            .collect()
+    }
+    /// 从一个 HashTable 里设置一个 key 的 value，返回旧的 value。
+    /// 返回的一定是被这次写入覆盖的 value，即使有其它并发的写入（HGETSET 依赖这一点）
     fn set(
         &self,
         table: &str,
@@ -23,12 +58,347 @@ pub trait Storage {
     fn contains(&self, table: &str, key: &str) -> Result<bool, KvError>;
     /// 从 HashTable 中删除一个 key
     fn del(&self, table: &str, key: &str) -> Result<Option<Value>, KvError>;
+    /// 获取 key 最后一次被写入的时间（毫秒级的 UNIX 时间戳），key 不存在时返回 None。
+    /// 缺省返回 None，不记录修改时间的存储不需要实现
+    fn mtime(&self, _table: &str, _key: &str) -> Result<Option<i64>, KvError> {
+        Ok(None)
+    }
+    /// 返回所有至少包含一个 key 的 table 的名字，按名字排序。缺省返回 KvError::Unsupported
+    fn tables(&self) -> Result<Vec<String>, KvError> {
+        Err(KvError::Unsupported("Listing tables"))
+    }
     /// 遍历 HashTable，返回所有 kv pair（这个接口不好）
     fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError>;
     /// 遍历 HashTable，返回 kv pair 的 Iterator
     fn get_iter(&self, table: &str) -> Result<impl Iterator<Item = Kvpair>, KvError>;
+    /// 整理存储，回收陈旧数据（包括已经过期的 key）占用的空间，返回回收的字节数。缺省返回 0
+    fn compact(&self) -> Result<u64, KvError> {
+        Ok(0)
+    }
+    /// 把 key 从 from_table 移动到 to_table，覆盖 to_table 中已有的值，返回被移动的 value，
+    /// from_table 中没有这个 key 时返回 None。缺省实现不是原子的，具体的存储应当提供原子的实现
+    fn move_key(
+        &self,
+        from_table: &str,
+        to_table: &str,
+        key: &str,
+    ) -> Result<Option<Value>, KvError> {
+        if from_table == to_table {
+            return self.get(from_table, key);
+        }
+        let value = self.del(from_table, key)?;
+        if let Some(v) = &value {
+            self.set(to_table, key, v.clone())?;
+        }
+        Ok(value)
+    }
+    /// 把 table 中 from 的 value 移动到 to 并删除 from，覆盖 to 中已有的值。from 不存在时返回 None，
+    /// 否则返回 to 中被覆盖的 value。from 和 to 相同时不做任何修改。和 move_key 一样，
+    /// 具体的存储应当原子地移动，并保留原来的修改时间和过期时间，缺省实现不是原子的
+    fn rename_key(
+        &self,
+        table: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Option<Option<Value>>, KvError> {
+        if from == to {
+            return Ok(self.contains(table, from)?.then_some(None));
+        }
+        match self.del(table, from)? {
+            Some(value) => Ok(Some(self.set(table, to, value)?)),
+            None => Ok(None),
+        }
+    }
+    /// 原子地修改 table 中的一个 key：f 得到 key 当前的 value，返回要写入的新 value，返回 None 时删除这个 key，
+    /// 最后返回写入的新 value。f 返回和当前相同的 value 时不写入，key 的修改时间不变。
+    /// f 可能会因为冲突重试而被调用多次。缺省实现不是原子的，具体的存储应当提供原子的实现
+    fn update<F>(&self, table: &str, key: &str, mut f: F) -> Result<Option<Value>, KvError>
+    where
+        F: FnMut(Option<&Value>) -> Option<Value>,
+    {
+        let old = self.get(table, key)?;
+        let value = f(old.as_ref());
+        if value == old {
+            return Ok(value);
+        }
+        match &value {
+            Some(v) => self.set(table, key, v.clone())?,
+            None => self.del(table, key)?,
+        };
+        Ok(value)
+    }
+    /// 查找 table 中所有 value 等于 value 的 key，按 key 排序返回。缺省实现会遍历整个 table
+    fn find_by_value(&self, table: &str, value: &Value) -> Result<Vec<String>, KvError> {
+        let mut keys: Vec<_> = self
+            .get_iter(table)?
+            .filter(|pair| pair.value.as_ref().is_some_and(|v| v.deep_eq(value)))
+            .map(|pair| pair.key)
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+    /// table 中 key 的个数，不存在的 table 为 0。缺省实现遍历整个 table
+    fn len(&self, table: &str) -> Result<usize, KvError> {
+        Ok(self.get_iter(table)?.count())
+    }
+    /// 估算 table 中所有 key 和 value 占用的字节数，只是一个近似值，不包含存储自身的开销。
+    /// 缺省实现遍历整个 table，累加 key 的长度和 value 编码后的长度
+    fn approximate_size(&self, table: &str) -> Result<u64, KvError> {
+        Ok(self
+            .get_iter(table)?
+            .map(|pair| (pair.key.len() + pair.value.map_or(0, |v| v.encoded_len())) as u64)
+            .sum())
+    }
+    /// 原子地删除 table 中的多个 key，按 keys 的顺序返回每个 key 被删除的 value，不存在的 key 为 None，
+    /// 重复的 key 只删除一次。读者要么看到所有的 key，要么一个也看不到。缺省实现不是原子的，具体的存储应当提供原子的实现
+    fn del_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        keys.iter()
+            .map(|key| self.del(table, key.as_ref()))
+            .collect()
+    }
+    /// 用 pairs 原子地替换 table 中的所有数据，读者要么看到替换前的全部数据，要么看到替换后的全部数据。
+    /// pairs 中重复的 key 以最后一个为准，返回 (删除的 key 数, 写入的 key 数)。
+    /// 缺省实现不是原子的，具体的存储应当提供原子的实现
+    fn replace_table(&self, table: &str, pairs: Vec<Kvpair>) -> Result<(u64, u64), KvError> {
+        let keys: Vec<_> = self.get_iter(table)?.map(|pair| pair.key).collect();
+        for key in &keys {
+            self.del(table, key)?;
+        }
+        let pairs = dedup_pairs(pairs);
+        let inserted = pairs.len() as u64;
+        for (key, value) in pairs {
+            self.set(table, key, value)?;
+        }
+        Ok((keys.len() as u64, inserted))
+    }
+    /// 原子地删除 table 中的所有 key，返回删除的 key 数（不包括已经过期的 key），不存在的 table 返回 0。
+    /// 缺省用空的 pairs 调用 replace_table，SledDb 和 RocksDB 在一个 batch 中删除所有 key
+    fn clear_table(&self, table: &str) -> Result<u64, KvError> {
+        Ok(self.replace_table(table, Vec::new())?.0)
+    }
+    /// 把 src 中的所有 key 写入 dst，返回写入的 key 数。overwrite 为 false 时跳过 dst 中已有的 key，
+    /// src 和 dst 相同时不做任何修改，返回 0。写入的 key 和 set 一样使用新的修改时间，没有过期时间。
+    /// 缺省实现逐个写入，不是原子的
+    fn copy_table(&self, src: &str, dst: &str, overwrite: bool) -> Result<u64, KvError> {
+        if src == dst {
+            return Ok(0);
+        }
+        let mut copied = 0;
+        for pair in self.get_iter(src)? {
+            if !overwrite && self.contains(dst, &pair.key)? {
+                continue;
+            }
+            self.set(dst, pair.key, pair.value.unwrap_or_default())?;
+            copied += 1;
+        }
+        Ok(copied)
+    }
+    /// 设置 key 在 ttl 之后过期，返回 key 是否存在。过期的 key 和不存在的 key 一样：读取、遍历都看不到它，
+    /// 之后由 compact 真正删除。set 覆盖 key 时去掉过期时间，update、move_key 和 rename_key 保留原来的过期时间。
+    /// ttl 为 0 时 key 立即过期。缺省返回 KvError::Unsupported
+    fn expire(&self, _table: &str, _key: &str, _ttl: Duration) -> Result<bool, KvError> {
+        Err(KvError::Unsupported("Key expiration"))
+    }
+    /// 和 set 一样写入 value 并返回旧的 value，同时设置 key 在 ttl 之后过期。value 和过期时间在同一次写入中保存，
+    /// 不会出现写入了 value 却没有过期时间的 key。缺省返回 KvError::Unsupported，不写入任何数据
+    fn set_with_ttl(
+        &self,
+        _table: &str,
+        _key: impl Into<String>,
+        _value: impl Into<Value>,
+        _ttl: Duration,
+    ) -> Result<Option<Value>, KvError> {
+        Err(KvError::Unsupported("Key expiration"))
+    }
+    /// key 剩余的存活时间，key 不存在或者没有设置过期时间时为 None。缺省为 None，不支持过期的存储中 key 都不会过期
+    fn ttl(&self, _table: &str, _key: &str) -> Result<Option<Duration>, KvError> {
+        Ok(None)
+    }
+    /// 原子地执行一组写入，读者要么看到全部写入，要么一个也看不到，出错时什么都不写入。
+    /// 同一个 key 的多个写入以最后一个为准，写入的 key 使用新的修改时间。TRANSACTION 用它提交所有的修改。
+    /// 缺省实现逐个写入，不是原子的，具体的存储应当提供原子的实现
+    fn write_batch(&self, ops: Vec<WriteOp>) -> Result<(), KvError> {
+        let now = now_millis();
+        for op in ops {
+            match op {
+                WriteOp::Set {
+                    table,
+                    key,
+                    value,
+                    expires_at: None,
+                } => self.set(&table, key, value)?,
+                WriteOp::Set {
+                    table,
+                    key,
+                    value,
+                    expires_at: Some(expires_at),
+                } => self.set_with_ttl(&table, key, value, remaining(expires_at, now))?,
+                WriteOp::Del { table, key } => self.del(&table, &key)?,
+            };
+        }
+        Ok(())
+    }
+    /// key 在存储中实际占用的字节数，LimitedStore 用它限制 key 的长度。缺省为 key 的长度，
+    /// 把 table 名和 key 拼在一起保存的存储（比如 SledDb）应当包括 table 名和分隔符的长度
+    fn stored_key_len(&self, _table: &str, key: &str) -> usize {
+        key.len()
+    }
+}
+
+/// write_batch 中的一个写入
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteOp {
+    /// 写入 key，expires_at 是过期时间（毫秒级的 UNIX 时间戳），为 None 时不过期。
+    /// 不支持过期的存储在 expires_at 不为 None 时返回 KvError::Unsupported
+    Set {
+        table: String,
+        key: String,
+        value: Value,
+        expires_at: Option<i64>,
+    },
+    /// 删除 key，key 不存在时什么都不做
+    Del { table: String, key: String },
+}
+
+impl WriteOp {
+    /// 写入的 table 和 key
+    pub fn target(&self) -> (&str, &str) {
+        match self {
+            WriteOp::Set { table, key, .. } | WriteOp::Del { table, key } => (table, key),
+        }
+    }
+}
+
+// 去掉 pairs 中重复的 key，保留最后一个 value，没有 value 的 pair 写入空的 value
+fn dedup_pairs(pairs: Vec<Kvpair>) -> Vec<(String, Value)> {
+    let mut index: HashMap<String, usize> = HashMap::with_capacity(pairs.len());
+    let mut result: Vec<(String, Value)> = Vec::with_capacity(pairs.len());
+    for pair in pairs {
+        let value = pair.value.unwrap_or_default();
+        match index.get(&pair.key) {
+            Some(&i) => result[i].1 = value,
+            None => {
+                index.insert(pair.key.clone(), result.len());
+                result.push((pair.key, value));
+            }
+        }
+    }
+    result
 }
 
+// 当前时间，毫秒级的 UNIX 时间戳
+pub(crate) fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+// 持久化存储中保存的数据格式：ENTRY_TAG 和 8 字节大端的最后修改时间，后面是 protobuf 编码的 Value
+fn encode_entry(value: Value) -> Result<Vec<u8>, KvError> {
+    let mut buf = entry_header(now_millis());
+    value.encode(&mut buf)?;
+    Ok(buf)
+}
+
+// 数据开头的格式标记和修改时间
+fn entry_header(mtime: i64) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN);
+    buf.push(ENTRY_TAG);
+    buf.extend_from_slice(&mtime.to_be_bytes());
+    buf
+}
+
+// 数据是否是带格式标记的格式，否则是旧版本直接保存的 protobuf 编码的 Value
+fn is_tagged(data: &[u8]) -> bool {
+    data.first() == Some(&ENTRY_TAG)
+}
+
+// 从持久化存储的数据中解出 Value 和它的修改时间，压缩过的 Value 会先解压，见 ValueCodec。
+// 旧版本写入的数据没有修改时间，返回 None
+fn decode_entry(data: &[u8]) -> Result<(Value, Option<i64>), KvError> {
+    if !is_tagged(data) {
+        return Ok((Value::decode(data)?, None));
+    }
+    if data.len() < HEADER_LEN {
+        return Err(KvError::Internal("Stored entry is corrupted".into()));
+    }
+    let (header, mut value) = data.split_at(HEADER_LEN);
+    let mtime = i64::from_be_bytes(header[1..].try_into().unwrap());
+    if entry_expires_at(data).is_some() {
+        value = &value[EXPIRES_LEN..];
+    }
+    Ok((codec::decode_value_data(value)?, Some(mtime)))
+}
+
+// 设置了过期时间的数据在修改时间之后是这个标记和 8 字节大端的过期时间（毫秒级的 UNIX 时间戳），
+// 然后才是 Value 的数据。和压缩算法的标记一样，protobuf 编码的 Value 不可能以这个字节开头
+const EXPIRES_TAG: u8 = 4;
+const EXPIRES_LEN: usize = 9;
+
+// 持久化存储的数据中保存的过期时间，没有设置时为 None
+fn entry_expires_at(data: &[u8]) -> Option<i64> {
+    if !is_tagged(data) {
+        return None;
+    }
+    match data.get(HEADER_LEN..HEADER_LEN + EXPIRES_LEN) {
+        Some([EXPIRES_TAG, expires_at @ ..]) => {
+            Some(i64::from_be_bytes(expires_at.try_into().unwrap()))
+        }
+        _ => None,
+    }
+}
+
+// 数据在 now 时是否已经过期
+fn entry_expired(data: &[u8], now: i64) -> bool {
+    entry_expires_at(data).is_some_and(|expires_at| expires_at <= now)
+}
+
+// 把数据的过期时间换成 expires_at，为 None 时去掉过期时间。修改时间和 Value 的数据保持不变，
+// 旧版本的数据会转成新的格式，修改时间为 0
+fn with_expires_at(data: &[u8], expires_at: Option<i64>) -> Vec<u8> {
+    if !is_tagged(data) {
+        let mut buf = entry_header(0);
+        buf.extend_from_slice(data);
+        return with_expires_at(&buf, expires_at);
+    }
+    let start = match entry_expires_at(data) {
+        Some(_) => HEADER_LEN + EXPIRES_LEN,
+        None => HEADER_LEN.min(data.len()),
+    };
+    let mut buf = data[..HEADER_LEN.min(data.len())].to_vec();
+    if let Some(expires_at) = expires_at {
+        buf.push(EXPIRES_TAG);
+        buf.extend_from_slice(&expires_at.to_be_bytes());
+    }
+    buf.extend_from_slice(&data[start..]);
+    buf
+}
+
+// 从现在开始 ttl 之后的时间，毫秒级的 UNIX 时间戳
+fn expires_after(now: i64, ttl: Duration) -> i64 {
+    now.saturating_add(ttl.as_millis().min(i64::MAX as u128) as i64)
+}
+
+// 从 now 到 expires_at 的时间，已经过期时为 0
+fn remaining(expires_at: i64, now: i64) -> Duration {
+    Duration::from_millis(expires_at.saturating_sub(now).max(0) as u64)
+}
+
+// 从持久化存储的数据中解出 Value
+fn decode_value(data: &[u8]) -> Result<Value, KvError> {
+    decode_entry(data).map(|(v, _)| v)
+}
+
+// 数据格式的标记。protobuf 编码的 Value 的第一个字节的低 3 位是 wire type，不可能是 7，
+// 所以能和旧版本直接保存的 Value 区分开
+const ENTRY_TAG: u8 = 0xff;
+// 格式标记和修改时间的长度
+const HEADER_LEN: usize = 1 + 8;
+
 //提供 Storage Iterator, 这样trait的实现者只需要把他们的Iterator, 提供给 StorageIter, 并且保证next()传出的类型实现了Into<Kvpair>
 pub struct StorageIter<T> {
     data: T,
@@ -76,6 +446,24 @@ mod tests {
         test_get_all(store);
     }
 
+    #[test]
+    fn rwlock_store_basic_interface_should_work() {
+        let store = RwLockStore::new();
+        test_basi_interface(store);
+    }
+
+    #[test]
+    fn rwlock_store_iter_should_work() {
+        let store = RwLockStore::new();
+        test_get_iter(store);
+    }
+
+    #[test]
+    fn rwlock_store_get_all_should_work() {
+        let store = RwLockStore::new();
+        test_get_all(store);
+    }
+
     #[test]
     fn selddb_basic_interface_should_work() {
         let dir = tempdir().unwrap();
@@ -118,6 +506,513 @@ mod tests {
         test_get_all(store);
     }
 
+    #[test]
+    fn memtable_compact_should_return_zero() {
+        let store = MemTable::new();
+        test_compact(&store);
+        assert_eq!(store.compact().unwrap(), 0);
+    }
+
+    #[test]
+    fn selddb_compact_should_work() {
+        let dir = tempdir().unwrap();
+        let store = SledDb::new(dir);
+        test_compact(&store);
+    }
+
+    #[test]
+    fn rocksdb_compact_should_work() {
+        let dir = tempdir().unwrap();
+        let store = RocksDB::new(dir);
+        test_compact(&store);
+    }
+
+    #[test]
+    fn memtable_move_key_should_work() {
+        test_move_key(MemTable::new());
+    }
+
+    #[test]
+    fn selddb_move_key_should_work() {
+        let dir = tempdir().unwrap();
+        test_move_key(SledDb::new(dir));
+    }
+
+    #[test]
+    fn rocksdb_move_key_should_work() {
+        let dir = tempdir().unwrap();
+        test_move_key(RocksDB::new(dir));
+    }
+
+    #[test]
+    fn memtable_rename_key_should_work() {
+        test_rename_key(MemTable::new());
+    }
+
+    #[test]
+    fn selddb_rename_key_should_work() {
+        let dir = tempdir().unwrap();
+        test_rename_key(SledDb::new(dir));
+    }
+
+    #[test]
+    fn rocksdb_rename_key_should_work() {
+        let dir = tempdir().unwrap();
+        test_rename_key(RocksDB::new(dir));
+    }
+
+    #[test]
+    fn memtable_copy_table_should_work() {
+        test_copy_table(MemTable::new());
+    }
+
+    #[test]
+    fn selddb_copy_table_should_work() {
+        let dir = tempdir().unwrap();
+        test_copy_table(SledDb::new(dir));
+    }
+
+    #[test]
+    fn rocksdb_copy_table_should_work() {
+        let dir = tempdir().unwrap();
+        test_copy_table(RocksDB::new(dir));
+    }
+
+    #[test]
+    fn memtable_update_should_work() {
+        test_update(MemTable::new());
+    }
+
+    #[test]
+    fn selddb_update_should_work() {
+        let dir = tempdir().unwrap();
+        test_update(SledDb::new(dir));
+    }
+
+    #[test]
+    fn rocksdb_update_should_work() {
+        let dir = tempdir().unwrap();
+        test_update(RocksDB::new(dir));
+    }
+
+    #[test]
+    fn memtable_mtime_should_work() {
+        test_mtime(MemTable::new());
+    }
+
+    #[test]
+    fn selddb_mtime_should_work() {
+        let dir = tempdir().unwrap();
+        test_mtime(SledDb::new(dir));
+    }
+
+    #[test]
+    fn rocksdb_mtime_should_work() {
+        let dir = tempdir().unwrap();
+        test_mtime(RocksDB::new(dir));
+    }
+
+    #[test]
+    fn selddb_should_read_legacy_entries() {
+        let dir = tempdir().unwrap();
+        // 旧版本直接保存 protobuf 编码的 Value，没有格式标记和修改时间
+        let db = sled::open(dir.path()).unwrap();
+        let old = Value::from("v1").encode_to_vec();
+        db.insert("t1:k", old).unwrap();
+        db.insert("t1:n", Value::from(10).encode_to_vec()).unwrap();
+        drop(db);
+
+        test_legacy_entries(SledDb::new(dir.path()));
+    }
+
+    #[test]
+    fn rocksdb_should_read_legacy_entries() {
+        let dir = tempdir().unwrap();
+        // 换成旧版本时只能打开缺省的 column family
+        let db = ::rocksdb::DB::open_default(dir.path()).unwrap();
+        let cf = db.cf_handle("default").unwrap();
+        db.put_cf(&cf, "k", Value::from("v1").encode_to_vec())
+            .unwrap();
+        db.put_cf(&cf, "n", Value::from(10).encode_to_vec())
+            .unwrap();
+        drop(cf);
+        drop(db);
+
+        let store = RocksDB::new(dir.path());
+        assert_eq!(store.get("default", "k").unwrap(), Some("v1".into()));
+        assert_eq!(store.mtime("default", "k").unwrap(), None);
+        let v = store.update("default", "n", |v| v.cloned()).unwrap();
+        assert_eq!(v, Some(10.into()));
+        store.set("default", "k", "v2").unwrap();
+        assert_eq!(store.get("default", "k").unwrap(), Some("v2".into()));
+        assert!(store.mtime("default", "k").unwrap().is_some());
+    }
+
+    #[test]
+    fn memtable_get_batch_should_work() {
+        test_get_batch(MemTable::new());
+    }
+
+    #[test]
+    fn rwlock_store_get_batch_should_work() {
+        test_get_batch(RwLockStore::new());
+    }
+
+    #[test]
+    fn selddb_get_batch_should_work() {
+        let dir = tempdir().unwrap();
+        test_get_batch(SledDb::new(dir));
+    }
+
+    #[test]
+    fn rocksdb_get_batch_should_work() {
+        let dir = tempdir().unwrap();
+        test_get_batch(RocksDB::new(dir));
+    }
+
+    #[test]
+    fn selddb_approximate_size_should_work() {
+        let dir = tempdir().unwrap();
+        let store = SledDb::new(dir);
+        assert_eq!(store.approximate_size("t1").unwrap(), 0);
+        store.set("t1", "k1", "v1").unwrap();
+        store.set("t2", "k1", "v1").unwrap();
+        let size = store.approximate_size("t1").unwrap();
+        assert!(size > 0);
+        assert_eq!(store.approximate_size("t2").unwrap(), size);
+    }
+
+    #[test]
+    fn memtable_key_model_should_work() {
+        test_key_model(MemTable::new());
+    }
+
+    #[test]
+    fn rwlock_store_key_model_should_work() {
+        test_key_model(RwLockStore::new());
+    }
+
+    #[test]
+    fn selddb_key_model_should_work() {
+        let dir = tempdir().unwrap();
+        test_key_model(SledDb::new(dir));
+    }
+
+    #[test]
+    fn rocksdb_key_model_should_work() {
+        let dir = tempdir().unwrap();
+        test_key_model(RocksDB::new(dir));
+    }
+
+    #[test]
+    fn memtable_tables_should_work() {
+        test_tables(MemTable::new());
+    }
+
+    #[test]
+    fn rwlock_store_tables_should_work() {
+        test_tables(RwLockStore::new());
+    }
+
+    #[test]
+    fn selddb_tables_should_work() {
+        let dir = tempdir().unwrap();
+        test_tables(SledDb::new(dir));
+    }
+
+    #[test]
+    fn rocksdb_tables_should_work() {
+        let dir = tempdir().unwrap();
+        test_tables(RocksDB::new(dir));
+    }
+
+    #[test]
+    fn selddb_should_read_mixed_compressed_data() {
+        let dir = tempdir().unwrap();
+        let large = Value::from("compressible ".repeat(100));
+        SledDb::new(dir.path())
+            .set("t", "plain", large.clone())
+            .unwrap();
+
+        // 重新打开并开启压缩，之前没有压缩的数据依旧可以读取
+        let codec = ValueCodec::new(crate::CompressorType::ZSTD).with_threshold(64);
+        let store = SledDb::new(dir.path()).with_codec(codec);
+        store.set("t", "zstd", large.clone()).unwrap();
+        assert_eq!(store.get("t", "plain").unwrap(), Some(large.clone()));
+        assert_eq!(store.get("t", "zstd").unwrap(), Some(large));
+        assert_eq!(store.get_iter("t").unwrap().count(), 2);
+        assert!(store.compression_stats().ratio() < 0.5);
+    }
+
+    #[test]
+    fn memtable_content_type_should_work() {
+        test_content_type(MemTable::new());
+    }
+
+    #[test]
+    fn selddb_content_type_should_work() {
+        let dir = tempdir().unwrap();
+        test_content_type(SledDb::new(dir));
+    }
+
+    #[test]
+    fn rocksdb_content_type_should_work() {
+        let dir = tempdir().unwrap();
+        test_content_type(RocksDB::new(dir));
+    }
+
+    #[test]
+    fn memtable_set_should_return_overwritten_value() {
+        test_set_returns_overwritten(MemTable::new());
+    }
+
+    #[test]
+    fn selddb_set_should_return_overwritten_value() {
+        let dir = tempdir().unwrap();
+        test_set_returns_overwritten(SledDb::new(dir));
+    }
+
+    #[test]
+    fn rocksdb_set_should_return_overwritten_value() {
+        let dir = tempdir().unwrap();
+        test_set_returns_overwritten(RocksDB::new(dir));
+    }
+
+    #[test]
+    fn memtable_replace_table_should_work() {
+        test_replace_table(MemTable::new());
+    }
+
+    #[test]
+    fn rwlock_store_replace_table_should_work() {
+        test_replace_table(RwLockStore::new());
+    }
+
+    #[test]
+    fn selddb_replace_table_should_work() {
+        let dir = tempdir().unwrap();
+        test_replace_table(SledDb::new(dir));
+    }
+
+    #[test]
+    fn rocksdb_replace_table_should_work() {
+        let dir = tempdir().unwrap();
+        test_replace_table(RocksDB::new(dir));
+    }
+
+    #[test]
+    fn memtable_should_conform() {
+        storage_conformance_suite(MemTable::new);
+    }
+
+    #[test]
+    fn rwlock_store_should_conform() {
+        storage_conformance_suite(RwLockStore::new);
+    }
+
+    #[test]
+    fn selddb_should_conform() {
+        storage_conformance_suite(|| SledDb::new(tempdir().unwrap()));
+    }
+
+    #[test]
+    fn compressed_selddb_should_conform() {
+        storage_conformance_suite(|| {
+            let codec = ValueCodec::new(crate::CompressorType::LZ4).with_threshold(64);
+            SledDb::new(tempdir().unwrap()).with_codec(codec)
+        });
+    }
+
+    #[test]
+    fn rocksdb_should_conform() {
+        storage_conformance_suite(|| RocksDB::new(tempdir().unwrap()));
+    }
+
+    #[test]
+    fn boxed_storage_should_conform() {
+        storage_conformance_suite(|| BoxedStorage::open("memory").unwrap());
+    }
+
+    #[test]
+    fn indexed_store_should_conform() {
+        storage_conformance_suite(|| IndexedStore::new(MemTable::new()));
+    }
+
+    #[test]
+    fn limited_store_should_conform() {
+        storage_conformance_suite(|| LimitedStore::new(MemTable::new()));
+    }
+
+    #[test]
+    fn memtable_expire_should_work() {
+        test_expire(MemTable::new());
+    }
+
+    #[test]
+    fn rwlock_store_expire_should_work() {
+        test_expire(RwLockStore::new());
+    }
+
+    #[test]
+    fn selddb_expire_should_work() {
+        let dir = tempdir().unwrap();
+        test_expire(SledDb::new(dir));
+
+        // 过期时间和压缩可以同时使用
+        let dir = tempdir().unwrap();
+        let store = SledDb::new(dir).with_codec(ValueCodec::new(crate::CompressorType::ZSTD));
+        let value = Value::from("compressible ".repeat(100));
+        store.set("t", "k", value.clone()).unwrap();
+        assert!(store.expire("t", "k", Duration::from_secs(60)).unwrap());
+        assert_eq!(store.get("t", "k").unwrap(), Some(value));
+    }
+
+    #[test]
+    fn rocksdb_expire_should_be_unsupported() {
+        let dir = tempdir().unwrap();
+        let store = RocksDB::new(dir);
+        store.set("t", "k", "v").unwrap();
+        let e = store.expire("t", "k", Duration::ZERO).unwrap_err();
+        assert!(matches!(e, KvError::Unsupported(_)));
+        assert_eq!(store.get("t", "k").unwrap(), Some("v".into()));
+        let e = store
+            .set_with_ttl("t", "k", "new", Duration::from_secs(60))
+            .unwrap_err();
+        assert!(matches!(e, KvError::Unsupported(_)));
+        assert_eq!(store.get("t", "k").unwrap(), Some("v".into()));
+    }
+
+    #[test]
+    fn memtable_set_with_ttl_should_work() {
+        test_set_with_ttl(MemTable::new());
+    }
+
+    #[test]
+    fn selddb_set_with_ttl_should_work() {
+        let dir = tempdir().unwrap();
+        test_set_with_ttl(SledDb::new(dir));
+    }
+
+    #[test]
+    fn indexed_store_set_with_ttl_should_work() {
+        let store = IndexedStore::new(MemTable::new())
+            .with_index("t", 16)
+            .unwrap();
+        test_set_with_ttl(store);
+    }
+
+    fn test_set_with_ttl(store: impl Storage) {
+        let ttl = Duration::from_millis(100);
+        assert_eq!(store.set_with_ttl("t", "k", "v1", ttl).unwrap(), None);
+        let remaining = store.ttl("t", "k").unwrap().unwrap();
+        assert!(remaining > Duration::ZERO && remaining <= ttl);
+        assert!(store.mtime("t", "k").unwrap().is_some());
+
+        // 覆盖时返回旧的 value，过期时间换成新的
+        let ttl = Duration::from_secs(60);
+        let old = store.set_with_ttl("t", "k", "v2", ttl).unwrap();
+        assert_eq!(old, Some("v1".into()));
+        assert!(store.ttl("t", "k").unwrap().unwrap() > Duration::from_secs(1));
+        assert_eq!(store.find_by_value("t", &"v2".into()).unwrap(), ["k"]);
+
+        // 过期的 key 和不存在的 key 一样，覆盖时返回 None
+        store.set_with_ttl("t", "k", "v3", Duration::ZERO).unwrap();
+        assert_eq!(store.get("t", "k").unwrap(), None);
+        assert_eq!(store.set_with_ttl("t", "k", "v4", ttl).unwrap(), None);
+        assert_eq!(store.get("t", "k").unwrap(), Some("v4".into()));
+    }
+
+    #[test]
+    fn memtable_write_batch_should_work() {
+        test_write_batch(MemTable::new());
+    }
+
+    #[test]
+    fn selddb_write_batch_should_work() {
+        let dir = tempdir().unwrap();
+        test_write_batch(SledDb::new(dir));
+    }
+
+    #[test]
+    fn indexed_store_write_batch_should_work() {
+        let store = IndexedStore::new(MemTable::new())
+            .with_index("t", 16)
+            .unwrap();
+        test_write_batch(store);
+    }
+
+    #[test]
+    fn rocksdb_write_batch_with_expiry_should_be_unsupported() {
+        let dir = tempdir().unwrap();
+        let store = RocksDB::new(dir);
+        store.set("t", "k", "v").unwrap();
+        let e = store
+            .write_batch(vec![
+                WriteOp::Del {
+                    table: "t".into(),
+                    key: "k".into(),
+                },
+                WriteOp::Set {
+                    table: "t".into(),
+                    key: "new".into(),
+                    value: "v".into(),
+                    expires_at: Some(now_millis() + 60_000),
+                },
+            ])
+            .unwrap_err();
+        assert!(matches!(e, KvError::Unsupported(_)));
+        // 出错时什么都没有写入
+        assert_eq!(store.get_all("t").unwrap(), [Kvpair::new("k", "v")]);
+    }
+
+    fn test_write_batch(store: impl Storage) {
+        store.set("t", "old", "v").unwrap();
+        store.set("t", "gone", "v").unwrap();
+        let expires_at = now_millis() + 60_000;
+        store
+            .write_batch(vec![
+                WriteOp::Set {
+                    table: "t".into(),
+                    key: "old".into(),
+                    value: "new".into(),
+                    expires_at: None,
+                },
+                WriteOp::Set {
+                    table: "t".into(),
+                    key: "ttl".into(),
+                    value: "v".into(),
+                    expires_at: Some(expires_at),
+                },
+                WriteOp::Del {
+                    table: "t".into(),
+                    key: "gone".into(),
+                },
+            ])
+            .unwrap();
+        assert_eq!(store.get("t", "old").unwrap(), Some("new".into()));
+        assert_eq!(store.get("t", "gone").unwrap(), None);
+        assert_eq!(store.ttl("t", "old").unwrap(), None);
+        let ttl = store.ttl("t", "ttl").unwrap().unwrap();
+        assert!(ttl > Duration::from_secs(1) && ttl <= Duration::from_secs(60));
+        assert!(store.mtime("t", "ttl").unwrap().is_some());
+        // 索引和写入保持一致
+        assert_eq!(store.find_by_value("t", &"new".into()).unwrap(), ["old"]);
+        let mut keys = store.find_by_value("t", &"v".into()).unwrap();
+        keys.sort();
+        assert_eq!(keys, ["ttl"]);
+
+        // 已经到期的写入和不存在的 key 一样
+        store
+            .write_batch(vec![WriteOp::Set {
+                table: "t".into(),
+                key: "old".into(),
+                value: "expired".into(),
+                expires_at: Some(now_millis() - 1),
+            }])
+            .unwrap();
+        assert_eq!(store.get("t", "old").unwrap(), None);
+    }
+
     fn test_basi_interface(store: impl Storage) {
         // 第一次set会创建table，插入key并返回None（之前没值）
         let v = store.set("table", "key", "value");
@@ -169,4 +1064,306 @@ mod tests {
             vec![Kvpair::new("key1", "1"), Kvpair::new("key2", "2")]
         );
     }
+
+    fn test_compact(store: &impl Storage) {
+        for i in 0..100 {
+            store.set("table", format!("key{i}"), "value").unwrap();
+        }
+        for i in 0..100 {
+            store.del("table", &format!("key{i}")).unwrap();
+        }
+        // 整理后数据保持不变
+        store.set("table", "key", "value").unwrap();
+        assert!(store.compact().is_ok());
+        assert_eq!(store.get("table", "key").unwrap(), Some("value".into()));
+    }
+
+    fn test_move_key(store: impl Storage) {
+        store.set("from", "key", "value").unwrap();
+        store.set("to", "key", "old").unwrap();
+
+        // 移动后返回原来的值，并覆盖目标 table 中的值
+        let v = store.move_key("from", "to", "key").unwrap();
+        assert_eq!(v, Some("value".into()));
+        assert_eq!(store.get("from", "key").unwrap(), None);
+        assert_eq!(store.get("to", "key").unwrap(), Some("value".into()));
+
+        // 源 table 中不存在的 key 返回 None，目标 table 不受影响
+        assert_eq!(store.move_key("from", "to", "key").unwrap(), None);
+        assert_eq!(store.get("to", "key").unwrap(), Some("value".into()));
+    }
+
+    fn test_rename_key(store: impl Storage) {
+        store.set("t", "from", "value").unwrap();
+        store.set("other", "from", "other").unwrap();
+
+        // to 不存在时返回 Some(None)，只影响同一个 table 中的 key
+        assert_eq!(store.rename_key("t", "from", "to").unwrap(), Some(None));
+        assert_eq!(store.get("t", "from").unwrap(), None);
+        assert_eq!(store.get("t", "to").unwrap(), Some("value".into()));
+        assert_eq!(store.get("other", "from").unwrap(), Some("other".into()));
+
+        // 覆盖 to 时返回被覆盖的值
+        store.set("t", "from", "new").unwrap();
+        let old = store.rename_key("t", "from", "to").unwrap();
+        assert_eq!(old, Some(Some("value".into())));
+        assert_eq!(store.get_all("t").unwrap(), [Kvpair::new("to", "new")]);
+
+        // from 不存在时返回 None，from 和 to 相同时不做修改
+        assert_eq!(store.rename_key("t", "from", "to").unwrap(), None);
+        assert_eq!(store.rename_key("t", "to", "to").unwrap(), Some(None));
+        assert_eq!(store.get("t", "to").unwrap(), Some("new".into()));
+    }
+
+    fn test_copy_table(store: impl Storage) {
+        // 超过 SledDb 一批提交的 key 数
+        for i in 0..1500 {
+            store.set("src", format!("key{i}"), i).unwrap();
+        }
+        store.set("dst", "key0", "old").unwrap();
+        store.set("dst", "other", "old").unwrap();
+
+        // 不覆盖时跳过 dst 中已有的 key
+        assert_eq!(store.copy_table("src", "dst", false).unwrap(), 1499);
+        assert_eq!(store.get("dst", "key0").unwrap(), Some("old".into()));
+        assert_eq!(store.get("dst", "key1499").unwrap(), Some(1499.into()));
+        assert_eq!(store.len("dst").unwrap(), 1501);
+
+        // 覆盖时写入所有的 key，dst 中 src 没有的 key 保持不变
+        assert_eq!(store.copy_table("src", "dst", true).unwrap(), 1500);
+        assert_eq!(store.get("dst", "key0").unwrap(), Some(0.into()));
+        assert_eq!(store.get("dst", "other").unwrap(), Some("old".into()));
+        assert_eq!(store.len("src").unwrap(), 1500);
+
+        assert_eq!(store.copy_table("src", "src", true).unwrap(), 0);
+        assert_eq!(store.copy_table("missing", "dst", true).unwrap(), 0);
+        assert_eq!(store.len("dst").unwrap(), 1501);
+    }
+
+    fn test_update(store: impl Storage) {
+        // key 不存在时 f 得到 None
+        let v = store
+            .update("t1", "counter", |old| {
+                assert!(old.is_none());
+                Some(1.into())
+            })
+            .unwrap();
+        assert_eq!(v, Some(1.into()));
+
+        let v = store
+            .update("t1", "counter", |old| {
+                let n: i64 = old.cloned().unwrap().try_into().unwrap();
+                Some((n + 1).into())
+            })
+            .unwrap();
+        assert_eq!(v, Some(2.into()));
+        assert_eq!(store.get("t1", "counter").unwrap(), Some(2.into()));
+
+        // 返回 None 删除 key
+        let v = store.update("t1", "counter", |_| None).unwrap();
+        assert_eq!(v, None);
+        assert_eq!(store.get("t1", "counter").unwrap(), None);
+    }
+
+    fn test_mtime(store: impl Storage) {
+        assert_eq!(store.mtime("t1", "k").unwrap(), None);
+
+        let before = now_millis();
+        store.set("t1", "k", "v1").unwrap();
+        let mtime = store.mtime("t1", "k").unwrap().unwrap();
+        assert!(mtime >= before && mtime <= now_millis());
+        // set 之后 get 依旧得到原来的值
+        assert_eq!(store.get("t1", "k").unwrap(), Some("v1".into()));
+
+        // 再次写入会更新修改时间
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        store.set("t1", "k", "v2").unwrap();
+        let updated = store.mtime("t1", "k").unwrap().unwrap();
+        assert!(updated > mtime);
+
+        // update 没有改变 value 时不写入，修改时间不变
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        store.update("t1", "k", |v| v.cloned()).unwrap();
+        assert_eq!(store.mtime("t1", "k").unwrap(), Some(updated));
+
+        store.del("t1", "k").unwrap();
+        assert_eq!(store.mtime("t1", "k").unwrap(), None);
+    }
+
+    fn test_legacy_entries(store: impl Storage) {
+        assert_eq!(store.get("t1", "k").unwrap(), Some("v1".into()));
+        assert_eq!(store.get("t1", "n").unwrap(), Some(10.into()));
+        assert_eq!(store.mtime("t1", "k").unwrap(), None);
+        assert_eq!(store.ttl("t1", "k").unwrap(), None);
+
+        // 设置过期时间后转成新的格式，value 不变
+        store.expire("t1", "n", Duration::from_secs(60)).unwrap();
+        assert_eq!(store.get("t1", "n").unwrap(), Some(10.into()));
+        assert!(store.ttl("t1", "n").unwrap().is_some());
+
+        // 重新写入后有修改时间
+        let old = store.set("t1", "k", "v2").unwrap();
+        assert_eq!(old, Some("v1".into()));
+        assert!(store.mtime("t1", "k").unwrap().is_some());
+    }
+
+    fn test_get_batch(store: impl Storage) {
+        store.set("t1", "k1", "v1").unwrap();
+        store.set("t1", "k3", "v3").unwrap();
+        let values = store.get_batch("t1", &["k1", "k2", "k3"]).unwrap();
+        assert_eq!(values, vec![Some("v1".into()), None, Some("v3".into())]);
+
+        // table 不存在
+        let values = store.get_batch("t2", &["k1"]).unwrap();
+        assert_eq!(values, vec![None]);
+    }
+
+    // 所有存储对同样的操作必须得到同样的结果
+    fn test_key_model(store: impl Storage) {
+        // 拼接之后看起来一样的 (table, key) 互不影响
+        store.set("a", "b:c", "1").unwrap();
+        store.set("a:b", "c", "2").unwrap();
+        store.set("a\\", ":c", "3").unwrap();
+        store.set("a", "", "4").unwrap();
+        assert_eq!(store.get("a", "b:c").unwrap(), Some("1".into()));
+        assert_eq!(store.get("a:b", "c").unwrap(), Some("2".into()));
+        assert_eq!(store.get("a\\", ":c").unwrap(), Some("3".into()));
+        assert_eq!(store.get("a", "").unwrap(), Some("4".into()));
+        assert!(!store.contains("a", "b").unwrap());
+        assert!(!store.contains("a:b", "").unwrap());
+
+        // 遍历只返回这个 table 的 key，并且 key 原样返回
+        let mut pairs = store.get_all("a").unwrap();
+        pairs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(pairs, vec![Kvpair::new("", "4"), Kvpair::new("b:c", "1")]);
+        let pairs: Vec<_> = store.get_iter("a:b").unwrap().collect();
+        assert_eq!(pairs, vec![Kvpair::new("c", "2")]);
+        let pairs: Vec<_> = store.get_iter("a\\").unwrap().collect();
+        assert_eq!(pairs, vec![Kvpair::new(":c", "3")]);
+
+        assert_eq!(store.del("a:b", "c").unwrap(), Some("2".into()));
+        assert_eq!(store.get("a", "b:c").unwrap(), Some("1".into()));
+    }
+
+    fn test_tables(store: impl Storage) {
+        assert!(store.tables().unwrap().is_empty());
+        store.set("t2", "k1", "v").unwrap();
+        store.set("t2", "k2", "v").unwrap();
+        store.set("t1:x", "k", "v").unwrap();
+        store.set("t1", "k", "v").unwrap();
+        // 读取和删除不存在的 key 不会创建 table
+        store.get("t3", "k").unwrap();
+        store.del("t4", "k").unwrap();
+        assert_eq!(store.tables().unwrap(), vec!["t1", "t1:x", "t2"]);
+
+        // 只返回有数据的 table
+        store.del("t1", "k").unwrap();
+        assert_eq!(store.tables().unwrap(), vec!["t1:x", "t2"]);
+    }
+
+    fn test_content_type(store: impl Storage) {
+        let json = Value::from(br#"{"a":1}"#).with_content_type("application/json");
+        store.set("t", "json", json.clone()).unwrap();
+        store.set("t", "plain", "v").unwrap();
+        // content_type 和 value 一起保存，没有 content_type 的 value 读出来是 None
+        let v = store.get("t", "json").unwrap().unwrap();
+        assert_eq!(v.content_type.as_deref(), Some("application/json"));
+        assert_eq!(v, json);
+        assert_eq!(store.get("t", "plain").unwrap().unwrap().content_type, None);
+        // 覆盖时返回之前的 content_type
+        let old = store.set("t", "json", "v").unwrap().unwrap();
+        assert_eq!(old.content_type.as_deref(), Some("application/json"));
+    }
+
+    fn test_set_returns_overwritten(store: impl Storage + Sync) {
+        // 并发写入同一个 key，每个被写入的 value 都恰好被覆盖一次，最后一个留在 key 中
+        let olds: Vec<_> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|t| {
+                    let store = &store;
+                    s.spawn(move || {
+                        (0..100)
+                            .filter_map(|i| store.set("t", "k", t * 1000 + i).unwrap())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+        let mut values: Vec<i64> = olds.into_iter().map(|v| v.try_into().unwrap()).collect();
+        values.push(store.get("t", "k").unwrap().unwrap().try_into().unwrap());
+        values.sort_unstable();
+        let expected: Vec<i64> = (0..4)
+            .flat_map(|t| (0..100).map(move |i| t * 1000 + i))
+            .collect();
+        assert_eq!(values, expected);
+    }
+
+    fn test_replace_table(store: impl Storage) {
+        store.set("t1", "a", 1).unwrap();
+        store.set("t1", "b", 2).unwrap();
+        store.set("t2", "a", 1).unwrap();
+
+        let pairs = vec![
+            Kvpair::new("b", 20),
+            Kvpair::new("c", 3),
+            Kvpair::new("c", 30),
+        ];
+        assert_eq!(store.replace_table("t1", pairs).unwrap(), (2, 2));
+        let mut pairs = store.get_all("t1").unwrap();
+        pairs.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(pairs, [Kvpair::new("b", 20), Kvpair::new("c", 30)]);
+        // 其它 table 不受影响
+        assert_eq!(store.get("t2", "a").unwrap(), Some(1.into()));
+
+        // 用空的 pairs 替换等于清空 table
+        assert_eq!(store.replace_table("t1", vec![]).unwrap(), (2, 0));
+        assert!(store.get_all("t1").unwrap().is_empty());
+    }
+
+    fn test_expire(store: impl Storage) {
+        store.set("t", "k1", "v1").unwrap();
+        store.set("t", "k2", "v2").unwrap();
+        assert!(!store.expire("t", "missing", Duration::ZERO).unwrap());
+
+        // 过期的 key 和不存在的 key 一样
+        assert!(store.expire("t", "k1", Duration::ZERO).unwrap());
+        assert_eq!(store.get("t", "k1").unwrap(), None);
+        assert!(!store.contains("t", "k1").unwrap());
+        assert_eq!(store.mtime("t", "k1").unwrap(), None);
+        assert_eq!(store.get_all("t").unwrap(), [Kvpair::new("k2", "v2")]);
+        assert_eq!(store.get_iter("t").unwrap().count(), 1);
+        assert_eq!(store.len("t").unwrap(), 1);
+        assert_eq!(store.rename_key("t", "k1", "k3").unwrap(), None);
+        assert!(!store.expire("t", "k1", Duration::from_secs(60)).unwrap());
+        assert_eq!(store.set("t", "k1", "new").unwrap(), None);
+        assert_eq!(store.ttl("t", "k1").unwrap(), None);
+        assert_eq!(store.ttl("t", "missing").unwrap(), None);
+
+        // set 去掉原来的过期时间，update 保留原来的过期时间
+        let ttl = Duration::from_millis(100);
+        assert!(store.expire("t", "k1", ttl).unwrap());
+        store.set("t", "k1", "v1").unwrap();
+        assert!(store.expire("t", "k2", ttl).unwrap());
+        store.update("t", "k2", |_| Some("v3".into())).unwrap();
+        let remaining = store.ttl("t", "k2").unwrap().unwrap();
+        assert!(
+            remaining > Duration::ZERO && remaining <= ttl,
+            "{remaining:?}"
+        );
+        assert_eq!(store.ttl("t", "k1").unwrap(), None);
+        std::thread::sleep(ttl * 2);
+        assert_eq!(store.get("t", "k1").unwrap(), Some("v1".into()));
+        assert_eq!(store.get("t", "k2").unwrap(), None);
+
+        // 所有 key 都过期的 table 不再出现，compact 之后过期的 key 被真正删除
+        assert!(store.expire("t", "k1", Duration::ZERO).unwrap());
+        assert!(store.tables().unwrap().is_empty());
+        store.compact().unwrap();
+        assert_eq!(store.del("t", "k1").unwrap(), None);
+        assert!(store.get_all("t").unwrap().is_empty());
+    }
 }
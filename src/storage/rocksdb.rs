@@ -1,13 +1,19 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
-use crate::{KvError, Kvpair, Storage, StorageIter, Value};
-use rocksdb::{BoundColumnFamily, Options, DB};
+use super::{decode_entry, decode_value, dedup_pairs, encode_entry};
+use crate::{KvError, Kvpair, Storage, StorageIter, Value, WriteOp};
+use rocksdb::{BoundColumnFamily, Options, WriteBatch, DB};
 
-pub struct RocksDB(DB);
+/// 第二个字段用来串行化先读后写的操作（返回旧值的 set/del、HMOVE、update 等），RocksDB 自身不提供这样的原子性
+pub struct RocksDB(DB, Mutex<()>);
 
 impl RocksDB {
     pub fn new(path: impl AsRef<Path>) -> Self {
-        Self(DB::open_default(path).unwrap())
+        Self(DB::open_default(path).unwrap(), Mutex::new(()))
     }
 
     pub fn get_or_create_table(&self, name: &str) -> Arc<BoundColumnFamily> {
@@ -16,15 +22,51 @@ impl RocksDB {
         }
         self.0.cf_handle(name).unwrap()
     }
+
+    /// 逐个 column family 做 compaction，每次只锁住一个 column family，
+    /// 其它 column family 的读写不受影响。返回整理前后 SST 文件大小的差值
+    pub fn compact(&self) -> Result<u64, KvError> {
+        let before = self.live_files_size()?;
+        for name in self.table_names()? {
+            if let Some(cf) = self.0.cf_handle(&name) {
+                self.0.compact_range_cf(&cf, None::<&[u8]>, None::<&[u8]>);
+            }
+        }
+        let after = self.live_files_size()?;
+        Ok(before.saturating_sub(after))
+    }
+
+    // 所有 column family 的名字，包括没有数据的
+    fn table_names(&self) -> Result<Vec<String>, KvError> {
+        Ok(DB::list_cf(&Default::default(), self.0.path())?)
+    }
+
+    fn live_files_size(&self) -> Result<u64, KvError> {
+        Ok(self.0.live_files()?.iter().map(|f| f.size as u64).sum())
+    }
 }
 
 impl Storage for RocksDB {
     fn get(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
         let cf = self.get_or_create_table(table);
-        let result = self.0.get_cf(&cf, key)?.map(|v| v.as_slice().try_into());
+        let result = self.0.get_cf(&cf, key)?.map(|v| decode_value(&v));
         result.transpose()
     }
 
+    fn get_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        let cf = self.get_or_create_table(table);
+        let keys = keys.iter().map(|key| (&cf, key.as_ref()));
+        self.0
+            .multi_get_cf(keys)
+            .into_iter()
+            .map(|v| v?.map(|v| decode_value(&v)).transpose())
+            .collect()
+    }
+
     fn set(
         &self,
         table: &str,
@@ -33,37 +75,218 @@ impl Storage for RocksDB {
     ) -> Result<Option<Value>, KvError> {
         let cf = self.get_or_create_table(table);
         let key = key.into();
-        let value: Vec<u8> = Into::<Value>::into(value).try_into()?;
+        let value = encode_entry(value.into())?;
+        let _guard = self.1.lock().unwrap_or_else(|e| e.into_inner());
         let old = self.get(table, &key);
-        let _ = self.0.put_cf(&cf, key, value);
-        old
+        let old = old?;
+        self.0.put_cf(&cf, key, value)?;
+        Ok(old)
     }
 
     fn contains(&self, table: &str, key: &str) -> Result<bool, KvError> {
         let cf = self.get_or_create_table(table);
-        Ok(self.0.key_may_exist_cf(&cf, key))
+        // key_may_exist_cf 可能会误报，需要真正读一次
+        Ok(self.0.get_pinned_cf(&cf, key)?.is_some())
     }
 
     fn del(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
         let cf = self.get_or_create_table(table);
+        let _guard = self.1.lock().unwrap_or_else(|e| e.into_inner());
         let old = self.get(table, key);
         self.0.delete_cf(&cf, key)?;
         old
     }
 
+    fn del_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        let cf = self.get_or_create_table(table);
+        let _guard = self.1.lock().unwrap_or_else(|e| e.into_inner());
+        // 持有写锁时读出旧的值，删除放在同一个 WriteBatch 中原子地提交
+        let mut batch = WriteBatch::default();
+        let mut seen = HashSet::new();
+        let mut removed = Vec::with_capacity(keys.len());
+        for key in keys {
+            let key = key.as_ref();
+            if !seen.insert(key) {
+                removed.push(None);
+                continue;
+            }
+            removed.push(self.get(table, key)?);
+            batch.delete_cf(&cf, key);
+        }
+        self.0.write(batch)?;
+        Ok(removed)
+    }
+
+    fn tables(&self) -> Result<Vec<String>, KvError> {
+        let mut names = Vec::new();
+        for name in self.table_names()? {
+            // 读操作也会创建 column family，只返回有数据的 table
+            let Some(cf) = self.0.cf_handle(&name) else {
+                continue;
+            };
+            let mut iter = self.0.iterator_cf(&cf, rocksdb::IteratorMode::Start);
+            if iter.next().transpose()?.is_some() {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
     fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError> {
         let cf = self.get_or_create_table(table);
         Ok(self
             .0
             .iterator_cf(&cf, rocksdb::IteratorMode::Start)
-            .map(|v| v.unwrap().into())
+            .map(|v| to_kvpair(v.unwrap()))
             .collect())
     }
 
     fn get_iter(&self, table: &str) -> Result<impl Iterator<Item = Kvpair>, KvError> {
         let cf = self.get_or_create_table(table);
         let iter = self.0.iterator_cf(&cf, rocksdb::IteratorMode::Start);
-        let iter = StorageIter::new(iter.map(|v| Into::<Kvpair>::into(v.unwrap())));
+        let iter = StorageIter::new(iter.map(|v| to_kvpair(v.unwrap())));
         Ok(iter)
     }
+
+    fn compact(&self) -> Result<u64, KvError> {
+        RocksDB::compact(self)
+    }
+
+    fn move_key(
+        &self,
+        from_table: &str,
+        to_table: &str,
+        key: &str,
+    ) -> Result<Option<Value>, KvError> {
+        if from_table == to_table {
+            return self.get(from_table, key);
+        }
+        let _guard = self.1.lock().unwrap_or_else(|e| e.into_inner());
+        let from = self.get_or_create_table(from_table);
+        let to = self.get_or_create_table(to_table);
+        let data = match self.0.get_cf(&from, key)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        // 写入新 key 和删除旧 key 放在同一个 WriteBatch 中原子地提交
+        let mut batch = WriteBatch::default();
+        batch.put_cf(&to, key, &data);
+        batch.delete_cf(&from, key);
+        self.0.write(batch)?;
+        Ok(Some(decode_value(&data)?))
+    }
+
+    fn rename_key(
+        &self,
+        table: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Option<Option<Value>>, KvError> {
+        if from == to {
+            return Ok(self.contains(table, from)?.then_some(None));
+        }
+        let _guard = self.1.lock().unwrap_or_else(|e| e.into_inner());
+        let cf = self.get_or_create_table(table);
+        let data = match self.0.get_cf(&cf, from)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let old = self.0.get_cf(&cf, to)?;
+        let mut batch = WriteBatch::default();
+        batch.put_cf(&cf, to, &data);
+        batch.delete_cf(&cf, from);
+        self.0.write(batch)?;
+        Ok(Some(old.as_deref().map(decode_value).transpose()?))
+    }
+
+    fn update<F>(&self, table: &str, key: &str, mut f: F) -> Result<Option<Value>, KvError>
+    where
+        F: FnMut(Option<&Value>) -> Option<Value>,
+    {
+        let _guard = self.1.lock().unwrap_or_else(|e| e.into_inner());
+        let cf = self.get_or_create_table(table);
+        let old = self.0.get_cf(&cf, key)?;
+        let old = old.as_deref().map(decode_value).transpose()?;
+        let value = f(old.as_ref());
+        if value == old {
+            return Ok(value);
+        }
+        match &value {
+            Some(v) => self.0.put_cf(&cf, key, encode_entry(v.clone())?)?,
+            None => self.0.delete_cf(&cf, key)?,
+        }
+        Ok(value)
+    }
+
+    fn mtime(&self, table: &str, key: &str) -> Result<Option<i64>, KvError> {
+        let cf = self.get_or_create_table(table);
+        let result = self.0.get_cf(&cf, key)?.map(|v| decode_entry(&v));
+        Ok(result.transpose()?.and_then(|(_, mtime)| mtime))
+    }
+
+    fn replace_table(&self, table: &str, pairs: Vec<Kvpair>) -> Result<(u64, u64), KvError> {
+        let pairs = dedup_pairs(pairs);
+        let inserted = pairs.len() as u64;
+        let _guard = self.1.lock().unwrap_or_else(|e| e.into_inner());
+        let cf = self.get_or_create_table(table);
+        // 删除和写入放在同一个 WriteBatch 中原子地提交
+        let mut batch = WriteBatch::default();
+        let mut removed = 0;
+        for entry in self.0.iterator_cf(&cf, rocksdb::IteratorMode::Start) {
+            let (key, _) = entry?;
+            batch.delete_cf(&cf, key);
+            removed += 1;
+        }
+        for (key, value) in pairs {
+            batch.put_cf(&cf, key, encode_entry(value)?);
+        }
+        self.0.write(batch)?;
+        Ok((removed, inserted))
+    }
+
+    fn write_batch(&self, ops: Vec<WriteOp>) -> Result<(), KvError> {
+        // 不支持过期时间，在写入任何数据之前返回
+        if ops.iter().any(|op| {
+            matches!(
+                op,
+                WriteOp::Set {
+                    expires_at: Some(_),
+                    ..
+                }
+            )
+        }) {
+            return Err(KvError::Unsupported("Key expiration"));
+        }
+        let _guard = self.1.lock().unwrap_or_else(|e| e.into_inner());
+        let mut batch = WriteBatch::default();
+        for op in ops {
+            match op {
+                WriteOp::Set {
+                    table, key, value, ..
+                } => {
+                    let cf = self.get_or_create_table(&table);
+                    batch.put_cf(&cf, key, encode_entry(value)?);
+                }
+                WriteOp::Del { table, key } => {
+                    let cf = self.get_or_create_table(&table);
+                    batch.delete_cf(&cf, key);
+                }
+            }
+        }
+        self.0.write(batch)?;
+        Ok(())
+    }
+}
+
+// 把 RocksDB 中保存的 key 和数据转换成 Kvpair
+fn to_kvpair((key, data): (Box<[u8]>, Box<[u8]>)) -> Kvpair {
+    Kvpair::new(
+        std::str::from_utf8(&key).unwrap(),
+        decode_value(&data).unwrap(),
+    )
 }
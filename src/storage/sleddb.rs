@@ -1,28 +1,70 @@
-use crate::{KvError, Kvpair, Storage, StorageIter, Value};
-use sled::{Db, IVec};
-use std::{convert::TryInto, path::Path, str};
+use super::{
+    decode_entry, decode_value, dedup_pairs, entry_expired, entry_expires_at, expires_after,
+    now_millis, remaining, with_expires_at,
+};
+use crate::{CompressionStats, KvError, Kvpair, Storage, StorageIter, Value, ValueCodec, WriteOp};
+use sled::{
+    transaction::{ConflictableTransactionError, TransactionError},
+    Db, IVec,
+};
+use std::{borrow::Cow, path::Path, str, time::Duration};
 
-pub struct SledDb(Db);
+pub struct SledDb(Db, ValueCodec);
+
+// copy_table 每次提交的 key 数，复制很大的 table 时不需要把所有数据都放在内存中
+const COPY_BATCH_SIZE: usize = 1024;
 
 impl SledDb {
     pub fn new(path: impl AsRef<Path>) -> Self {
-        Self(sled::open(path).unwrap())
+        Self(sled::open(path).unwrap(), ValueCodec::default())
+    }
+
+    /// 设置写入 value 时的编码方式，比如 `ValueCodec::new(CompressorType::ZSTD).with_threshold(256)`。
+    /// 只影响之后写入的数据，已有的数据不论是否压缩都能正确读取
+    pub fn with_codec(mut self, codec: ValueCodec) -> Self {
+        self.1 = codec;
+        self
     }
 
+    /// 打开之后写入的 value 压缩前后的字节数
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.1.stats()
+    }
+
+    // sled 中保存的 key 是 table:key。table 里的 : 和 \ 会被转义，
+    // 第一个没有被转义的 : 就是 table 和 key 的分隔符，不同的 (table, key) 不会得到同样的 key
     fn get_full_key(table: &str, key: &str) -> String {
-        format!("{}:{}", table, key)
+        format!("{}:{}", escape_table(table), key)
     }
 
     fn get_table_prefix(table: &str) -> String {
-        format!("{}:", table)
+        format!("{}:", escape_table(table))
+    }
+
+    /// 整理磁盘空间。先删除已经过期的 key，再把缓存中的数据刷到磁盘，
+    /// sled 会在后台回收不再包含有效数据的 segment，整个过程不会阻塞其它读写操作。返回整理前后的磁盘占用差值
+    pub fn compact(&self) -> Result<u64, KvError> {
+        let before = self.0.size_on_disk()?;
+        let now = now_millis();
+        for entry in self.0.iter() {
+            let (key, data) = entry?;
+            if entry_expired(&data, now) {
+                // 扫描之后被重新写入的 key 不删除
+                let _ = self
+                    .0
+                    .compare_and_swap(key, Some(data), None as Option<IVec>)?;
+            }
+        }
+        self.0.flush()?;
+        let after = self.0.size_on_disk()?;
+        Ok(before.saturating_sub(after))
     }
 }
 
 impl Storage for SledDb {
     fn get(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
         let name = SledDb::get_full_key(table, key);
-        let result = self.0.get(name.as_bytes())?.map(|v| v.as_ref().try_into());
-        result.transpose()
+        decode_live(self.0.get(name.as_bytes())?.as_deref())
     }
 
     fn set(
@@ -33,39 +75,327 @@ impl Storage for SledDb {
     ) -> Result<Option<Value>, KvError> {
         let key = key.into();
         let name = SledDb::get_full_key(table, &key);
-        let data: Vec<u8> = value.into().try_into()?;
-        let result = self.0.insert(name, data)?.map(|v| v.as_ref().try_into());
-        result.transpose()
+        let data = self.1.encode(value.into())?;
+        decode_live(self.0.insert(name, data)?.as_deref())
     }
 
     fn contains(&self, table: &str, key: &str) -> Result<bool, KvError> {
         let name = SledDb::get_full_key(table, &key);
-        Ok(self.0.contains_key(name)?)
+        let data = self.0.get(name)?;
+        Ok(data.is_some_and(|v| !entry_expired(&v, now_millis())))
     }
 
     fn del(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
         let name = SledDb::get_full_key(table, &key);
-        let result = self.0.remove(name)?.map(|v| v.as_ref().try_into());
-        result.transpose()
+        decode_live(self.0.remove(name)?.as_deref())
+    }
+
+    fn del_batch(
+        &self,
+        table: &str,
+        keys: &[impl AsRef<str>],
+    ) -> Result<Vec<Option<Value>>, KvError> {
+        let names: Vec<_> = keys
+            .iter()
+            .map(|key| SledDb::get_full_key(table, key.as_ref()))
+            .collect();
+        // 在一个事务中删除所有 key
+        let removed = self
+            .0
+            .transaction(|tx| {
+                let mut removed = Vec::with_capacity(names.len());
+                for name in &names {
+                    removed.push(tx.remove(name.as_bytes())?);
+                }
+                Ok::<_, ConflictableTransactionError<KvError>>(removed)
+            })
+            .map_err(transaction_error)?;
+        removed.iter().map(|v| decode_live(v.as_deref())).collect()
     }
 
     fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError> {
         let prefix = SledDb::get_table_prefix(table);
-        let result = self.0.scan_prefix(prefix).map(|v| v.into()).collect();
-        Ok(result)
+        let iter = self.0.scan_prefix(prefix).filter(not_expired(now_millis()));
+        Ok(iter.map(|v| v.into()).collect())
     }
 
     fn get_iter(&self, table: &str) -> Result<impl Iterator<Item = Kvpair>, KvError> {
         let prefix = SledDb::get_table_prefix(table);
-        let iter = StorageIter::new(self.0.scan_prefix(prefix));
-        Ok(iter)
+        let iter = self.0.scan_prefix(prefix).filter(not_expired(now_millis()));
+        Ok(StorageIter::new(iter))
+    }
+
+    fn compact(&self) -> Result<u64, KvError> {
+        SledDb::compact(self)
+    }
+
+    fn move_key(
+        &self,
+        from_table: &str,
+        to_table: &str,
+        key: &str,
+    ) -> Result<Option<Value>, KvError> {
+        if from_table == to_table {
+            return self.get(from_table, key);
+        }
+        let from = SledDb::get_full_key(from_table, key);
+        let to = SledDb::get_full_key(to_table, key);
+        let now = now_millis();
+        // 在一个事务中删除旧 key 并写入新 key，读者不会同时看到或者同时看不到两个 key。
+        // 数据原样移动，保留修改时间和过期时间，已经过期的 key 只删除不移动
+        let result = self
+            .0
+            .transaction(|tx| {
+                let value = tx.remove(from.as_bytes())?;
+                if let Some(v) = value.as_ref().filter(|v| !entry_expired(v, now)) {
+                    tx.insert(to.as_bytes(), v.clone())?;
+                }
+                Ok::<_, ConflictableTransactionError<KvError>>(value)
+            })
+            .map_err(transaction_error)?;
+        decode_live(result.as_deref())
+    }
+
+    fn rename_key(
+        &self,
+        table: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Option<Option<Value>>, KvError> {
+        if from == to {
+            return Ok(self.contains(table, from)?.then_some(None));
+        }
+        let from = SledDb::get_full_key(table, from);
+        let to = SledDb::get_full_key(table, to);
+        let now = now_millis();
+        // 和 move_key 一样在一个事务中删除旧 key 并写入新 key，数据原样移动
+        let result = self
+            .0
+            .transaction(|tx| {
+                let value = tx.remove(from.as_bytes())?;
+                match value.filter(|v| !entry_expired(v, now)) {
+                    Some(v) => Ok(Some(tx.insert(to.as_bytes(), v)?)),
+                    None => Ok::<_, ConflictableTransactionError<KvError>>(None),
+                }
+            })
+            .map_err(transaction_error)?;
+        result.map(|old| decode_live(old.as_deref())).transpose()
+    }
+
+    fn update<F>(&self, table: &str, key: &str, mut f: F) -> Result<Option<Value>, KvError>
+    where
+        F: FnMut(Option<&Value>) -> Option<Value>,
+    {
+        let name = SledDb::get_full_key(table, key);
+        // 乐观地读取、计算、再用 compare_and_swap 写回，期间 key 被别人修改过就重试
+        loop {
+            let now = now_millis();
+            let old = self.0.get(&name)?;
+            let live = old.as_deref().filter(|v| !entry_expired(v, now));
+            let value = live.map(decode_value).transpose()?;
+            let new = f(value.as_ref());
+            if new == value {
+                return Ok(new);
+            }
+            let data = new.clone().map(|v| self.1.encode(v)).transpose()?;
+            // 保留原来的过期时间
+            let data = match live.and_then(entry_expires_at) {
+                Some(expires_at) => data.map(|d| with_expires_at(&d, Some(expires_at))),
+                None => data,
+            };
+            if self.0.compare_and_swap(&name, old, data)?.is_ok() {
+                return Ok(new);
+            }
+        }
+    }
+
+    fn expire(&self, table: &str, key: &str, ttl: Duration) -> Result<bool, KvError> {
+        let name = SledDb::get_full_key(table, key);
+        // 过期时间保存在数据中，和 update 一样用 compare_and_swap 修改，不改变修改时间
+        loop {
+            let now = now_millis();
+            let Some(old) = self.0.get(&name)? else {
+                return Ok(false);
+            };
+            if entry_expired(&old, now) {
+                return Ok(false);
+            }
+            let data = with_expires_at(&old, Some(expires_after(now, ttl)));
+            if self
+                .0
+                .compare_and_swap(&name, Some(old), Some(data))?
+                .is_ok()
+            {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn set_with_ttl(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+        ttl: Duration,
+    ) -> Result<Option<Value>, KvError> {
+        let key = key.into();
+        let name = SledDb::get_full_key(table, &key);
+        let data = self.1.encode(value.into())?;
+        let data = with_expires_at(&data, Some(expires_after(now_millis(), ttl)));
+        decode_live(self.0.insert(name, data)?.as_deref())
+    }
+
+    fn write_batch(&self, ops: Vec<WriteOp>) -> Result<(), KvError> {
+        // 所有 table 都在同一棵树中，一个 Batch 可以原子地提交所有写入
+        let mut batch = sled::Batch::default();
+        for op in ops {
+            match op {
+                WriteOp::Set {
+                    table,
+                    key,
+                    value,
+                    expires_at,
+                } => {
+                    let data = self.1.encode(value)?;
+                    let name = SledDb::get_full_key(&table, &key);
+                    batch.insert(name.as_bytes(), with_expires_at(&data, expires_at));
+                }
+                WriteOp::Del { table, key } => {
+                    batch.remove(SledDb::get_full_key(&table, &key).as_bytes());
+                }
+            }
+        }
+        self.0.apply_batch(batch)?;
+        Ok(())
+    }
+
+    fn ttl(&self, table: &str, key: &str) -> Result<Option<Duration>, KvError> {
+        let name = SledDb::get_full_key(table, key);
+        let now = now_millis();
+        let expires_at = self
+            .0
+            .get(name.as_bytes())?
+            .and_then(|v| entry_expires_at(&v));
+        Ok(expires_at
+            .filter(|&expires_at| expires_at > now)
+            .map(|expires_at| remaining(expires_at, now)))
+    }
+
+    // 和 get_full_key 的长度相同
+    fn stored_key_len(&self, table: &str, key: &str) -> usize {
+        escape_table(table).len() + 1 + key.len()
+    }
+
+    fn mtime(&self, table: &str, key: &str) -> Result<Option<i64>, KvError> {
+        let name = SledDb::get_full_key(table, key);
+        let data = self.0.get(name.as_bytes())?;
+        let result = data
+            .filter(|v| !entry_expired(v, now_millis()))
+            .map(|v| decode_entry(&v));
+        Ok(result.transpose()?.and_then(|(_, mtime)| mtime))
+    }
+
+    fn tables(&self) -> Result<Vec<String>, KvError> {
+        let now = now_millis();
+        let mut names: Vec<String> = Vec::new();
+        for entry in self.0.iter() {
+            let (key, data) = entry?;
+            if entry_expired(&data, now) {
+                continue;
+            }
+            let (table, _) = split_full_key(str::from_utf8(&key).unwrap());
+            // 不同 table 的前缀互不包含，sled 按字节序遍历时同一个 table 的 key 是连续的
+            if names.last().map(String::as_str) != Some(&*table) {
+                names.push(table.into_owned());
+            }
+        }
+        // 转义后的顺序和 table 名字的顺序不一定相同
+        names.sort();
+        Ok(names)
+    }
+
+    fn len(&self, table: &str) -> Result<usize, KvError> {
+        let prefix = SledDb::get_table_prefix(table);
+        let now = now_millis();
+        let mut n = 0;
+        for entry in self.0.scan_prefix(prefix) {
+            let (_, data) = entry?;
+            if !entry_expired(&data, now) {
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+
+    fn approximate_size(&self, table: &str) -> Result<u64, KvError> {
+        // sled 没有按前缀估算大小的接口，直接累加磁盘上保存的 key 和 value 的长度
+        let prefix = SledDb::get_table_prefix(table);
+        let mut size = 0;
+        for entry in self.0.scan_prefix(prefix) {
+            let (k, v) = entry?;
+            size += (k.len() + v.len()) as u64;
+        }
+        Ok(size)
+    }
+
+    fn replace_table(&self, table: &str, pairs: Vec<Kvpair>) -> Result<(u64, u64), KvError> {
+        let pairs = dedup_pairs(pairs);
+        let inserted = pairs.len() as u64;
+        // 删除和写入放在同一个 Batch 中原子地提交。在扫描之后、提交之前并发写入的新 key 会被保留
+        let mut batch = sled::Batch::default();
+        let now = now_millis();
+        let mut removed = 0;
+        for entry in self.0.scan_prefix(SledDb::get_table_prefix(table)) {
+            let (key, data) = entry?;
+            // 已经过期的 key 也一起删除，但不计入删除的 key 数
+            if !entry_expired(&data, now) {
+                removed += 1;
+            }
+            batch.remove(key);
+        }
+        for (key, value) in pairs {
+            batch.insert(
+                SledDb::get_full_key(table, &key).as_bytes(),
+                self.1.encode(value)?,
+            );
+        }
+        self.0.apply_batch(batch)?;
+        Ok((removed, inserted))
+    }
+
+    fn copy_table(&self, src: &str, dst: &str, overwrite: bool) -> Result<u64, KvError> {
+        if src == dst {
+            return Ok(0);
+        }
+        let now = now_millis();
+        let (mut batch, mut pending, mut copied) = (sled::Batch::default(), 0, 0);
+        for entry in self.0.scan_prefix(SledDb::get_table_prefix(src)) {
+            let (key, data) = entry?;
+            if entry_expired(&data, now) {
+                continue;
+            }
+            let name = SledDb::get_full_key(dst, ivec_to_key(&key));
+            if !overwrite && decode_live(self.0.get(&name)?.as_deref())?.is_some() {
+                continue;
+            }
+            batch.insert(name.as_bytes(), self.1.encode(decode_value(&data)?)?);
+            pending += 1;
+            copied += 1;
+            // 分批提交，每一批是原子的，整个复制不是
+            if pending == COPY_BATCH_SIZE {
+                self.0.apply_batch(std::mem::take(&mut batch))?;
+                pending = 0;
+            }
+        }
+        self.0.apply_batch(batch)?;
+        Ok(copied)
     }
 }
 
 impl From<Result<(IVec, IVec), sled::Error>> for Kvpair {
     fn from(v: Result<(IVec, IVec), sled::Error>) -> Self {
         match v {
-            Ok((k, v)) => match TryInto::<Value>::try_into(v.as_ref()) {
+            Ok((k, v)) => match decode_value(&v) {
                 Ok(v) => Kvpair::new(ivec_to_key(k.as_ref()), v),
                 Err(_) => Kvpair::default(),
             },
@@ -74,9 +404,69 @@ impl From<Result<(IVec, IVec), sled::Error>> for Kvpair {
     }
 }
 
+// 解出没有过期的 value，已经过期的数据和不存在一样
+fn decode_live(data: Option<&[u8]>) -> Result<Option<Value>, KvError> {
+    match data {
+        Some(v) if !entry_expired(v, now_millis()) => decode_value(v).map(Some),
+        _ => Ok(None),
+    }
+}
+
+// 遍历时跳过已经过期的数据，读取出错的留给 Kvpair::from 处理
+fn not_expired(now: i64) -> impl Fn(&Result<(IVec, IVec), sled::Error>) -> bool {
+    move |entry| !matches!(entry, Ok((_, v)) if entry_expired(v, now))
+}
+
 fn ivec_to_key(ivec: &[u8]) -> &str {
-    let s = str::from_utf8(ivec).unwrap();
-    let mut iter = s.split(":");
-    iter.next();
-    iter.next().unwrap()
+    split_full_key(str::from_utf8(ivec).unwrap()).1
+}
+
+// 把 sled 中保存的 key 拆分成 table 和 key，第一个没有被转义的 : 之前是转义过的 table，之后都是 key
+fn split_full_key(s: &str) -> (Cow<'_, str>, &str) {
+    let (mut escaped, mut has_escape) = (false, false);
+    let mut end = s.len();
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => (escaped, has_escape) = (true, true),
+            ':' => {
+                end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+    let (table, key) = (&s[..end], s.get(end + 1..).unwrap_or(""));
+    if !has_escape {
+        return (Cow::Borrowed(table), key);
+    }
+    let mut unescaped = String::with_capacity(table.len());
+    let mut chars = table.chars();
+    while let Some(c) = chars.next() {
+        unescaped.extend(if c == '\\' { chars.next() } else { Some(c) });
+    }
+    (Cow::Owned(unescaped), key)
+}
+
+// 给 table 中的 \ 和 : 加上转义，没有这两个字符的 table 保持不变
+fn escape_table(table: &str) -> Cow<'_, str> {
+    if !table.contains(['\\', ':']) {
+        return Cow::Borrowed(table);
+    }
+    let mut escaped = String::with_capacity(table.len() + 2);
+    for c in table.chars() {
+        if c == '\\' || c == ':' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    Cow::Owned(escaped)
+}
+
+// 把 sled 事务的错误转换成 KvError
+fn transaction_error(e: TransactionError<KvError>) -> KvError {
+    match e {
+        TransactionError::Abort(e) => e,
+        TransactionError::Storage(e) => e.into(),
+    }
 }
@@ -0,0 +1,400 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use super::{dedup_pairs, expires_after, now_millis, remaining};
+use crate::{KvError, Kvpair, Storage, Value, WriteOp};
+
+/// 暂存在内存中的一组修改，读取时先看暂存的修改，再看底层的存储。
+///
+/// TRANSACTION 中的命令在 Staged 上执行，提交之前底层的存储不会被修改，其它读者也看不到这些修改；
+/// 所有命令都成功后 commit 用一次 write_batch 写入所有修改，有命令失败时直接丢弃
+pub(crate) struct Staged<'a, S> {
+    inner: &'a S,
+    tables: Mutex<BTreeMap<String, StagedTable>>,
+}
+
+// 一个 table 中暂存的修改
+#[derive(Debug, Default)]
+struct StagedTable {
+    // 为 true 时底层存储中这个 table 原有的 key 都被删除了，只有 keys 中的 key 存在
+    cleared: bool,
+    // 被修改过的 key，None 表示被删除
+    keys: BTreeMap<String, Option<StagedEntry>>,
+}
+
+#[derive(Debug, Clone)]
+struct StagedEntry {
+    value: Value,
+    mtime: Option<i64>,
+    expires_at: Option<i64>,
+}
+
+impl StagedEntry {
+    // 新写入的 value，修改时间为当前时间，不过期
+    fn new(value: Value) -> Self {
+        Self {
+            value,
+            mtime: Some(now_millis()),
+            expires_at: None,
+        }
+    }
+
+    fn is_expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+impl<'a, S: Storage> Staged<'a, S> {
+    pub(crate) fn new(inner: &'a S) -> Self {
+        Self {
+            inner,
+            tables: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// 用一次 write_batch 把暂存的修改写入底层的存储，被清空过的 table 中其它的 key 一起删除。
+    /// 调用者需要持有这些 table 的独占锁，保证暂存之后底层的存储没有被其它命令修改
+    pub(crate) fn commit(self) -> Result<(), KvError> {
+        let tables = self.tables.into_inner().unwrap_or_else(|e| e.into_inner());
+        let mut ops = Vec::new();
+        for (table, staged) in tables {
+            if staged.cleared {
+                for pair in self.inner.get_iter(&table)? {
+                    if !staged.keys.contains_key(&pair.key) {
+                        let table = table.clone();
+                        ops.push(WriteOp::Del {
+                            table,
+                            key: pair.key,
+                        });
+                    }
+                }
+            }
+            for (key, entry) in staged.keys {
+                let table = table.clone();
+                ops.push(match entry {
+                    Some(e) => WriteOp::Set {
+                        table,
+                        key,
+                        value: e.value,
+                        expires_at: e.expires_at,
+                    },
+                    None => WriteOp::Del { table, key },
+                });
+            }
+        }
+        if ops.is_empty() {
+            return Ok(());
+        }
+        self.inner.write_batch(ops)
+    }
+
+    // 暂存的修改中 key 的状态，没有暂存过时为 None，需要读取底层的存储。被删除或者已经过期时为 Some(None)
+    fn staged(&self, table: &str, key: &str) -> Option<Option<StagedEntry>> {
+        let tables = self.lock();
+        let staged = tables.get(table)?;
+        match staged.keys.get(key) {
+            Some(entry) => Some(entry.clone().filter(|e| !e.is_expired(now_millis()))),
+            None => staged.cleared.then_some(None),
+        }
+    }
+
+    // key 当前的 value、修改时间和过期时间
+    fn entry(&self, table: &str, key: &str) -> Result<Option<StagedEntry>, KvError> {
+        if let Some(entry) = self.staged(table, key) {
+            return Ok(entry);
+        }
+        let Some(value) = self.inner.get(table, key)? else {
+            return Ok(None);
+        };
+        let now = now_millis();
+        Ok(Some(StagedEntry {
+            value,
+            mtime: self.inner.mtime(table, key)?,
+            expires_at: self
+                .inner
+                .ttl(table, key)?
+                .map(|ttl| expires_after(now, ttl)),
+        }))
+    }
+
+    fn put(&self, table: &str, key: &str, entry: Option<StagedEntry>) {
+        let mut tables = self.lock();
+        let staged = tables.entry(table.to_string()).or_default();
+        staged.keys.insert(key.to_string(), entry);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, StagedTable>> {
+        self.tables.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<S: Storage> Storage for Staged<'_, S> {
+    fn get(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+        match self.staged(table, key) {
+            Some(entry) => Ok(entry.map(|e| e.value)),
+            None => self.inner.get(table, key),
+        }
+    }
+
+    fn set(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Result<Option<Value>, KvError> {
+        let key = key.into();
+        let old = self.get(table, &key)?;
+        self.put(table, &key, Some(StagedEntry::new(value.into())));
+        Ok(old)
+    }
+
+    fn contains(&self, table: &str, key: &str) -> Result<bool, KvError> {
+        Ok(self.get(table, key)?.is_some())
+    }
+
+    fn del(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+        let old = self.get(table, key)?;
+        self.put(table, key, None);
+        Ok(old)
+    }
+
+    fn mtime(&self, table: &str, key: &str) -> Result<Option<i64>, KvError> {
+        match self.staged(table, key) {
+            Some(entry) => Ok(entry.and_then(|e| e.mtime)),
+            None => self.inner.mtime(table, key),
+        }
+    }
+
+    fn tables(&self) -> Result<Vec<String>, KvError> {
+        let mut names: BTreeSet<_> = self.inner.tables()?.into_iter().collect();
+        let staged: Vec<_> = self.lock().keys().cloned().collect();
+        for table in staged {
+            match self.len(&table)? {
+                0 => names.remove(&table),
+                _ => names.insert(table),
+            };
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError> {
+        let (cleared, keys) = match self.lock().get(table) {
+            Some(staged) => (staged.cleared, staged.keys.clone()),
+            None => return self.inner.get_all(table),
+        };
+        let mut pairs = Vec::new();
+        if !cleared {
+            for pair in self.inner.get_iter(table)? {
+                if !keys.contains_key(&pair.key) {
+                    pairs.push(pair);
+                }
+            }
+        }
+        let now = now_millis();
+        for (key, entry) in keys {
+            if let Some(e) = entry.filter(|e| !e.is_expired(now)) {
+                pairs.push(Kvpair::new(key, e.value));
+            }
+        }
+        Ok(pairs)
+    }
+
+    fn get_iter(&self, table: &str) -> Result<impl Iterator<Item = Kvpair>, KvError> {
+        Ok(self.get_all(table)?.into_iter())
+    }
+
+    // 只回收空间，不改变数据，直接在底层的存储上执行
+    fn compact(&self) -> Result<u64, KvError> {
+        self.inner.compact()
+    }
+
+    // 和具体的存储一样保留 key 的修改时间和过期时间
+    fn move_key(
+        &self,
+        from_table: &str,
+        to_table: &str,
+        key: &str,
+    ) -> Result<Option<Value>, KvError> {
+        if from_table == to_table {
+            return self.get(from_table, key);
+        }
+        let entry = self.entry(from_table, key)?;
+        if let Some(e) = &entry {
+            self.put(from_table, key, None);
+            self.put(to_table, key, Some(e.clone()));
+        }
+        Ok(entry.map(|e| e.value))
+    }
+
+    fn rename_key(
+        &self,
+        table: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<Option<Option<Value>>, KvError> {
+        if from == to {
+            return Ok(self.contains(table, from)?.then_some(None));
+        }
+        let Some(entry) = self.entry(table, from)? else {
+            return Ok(None);
+        };
+        let old = self.get(table, to)?;
+        self.put(table, from, None);
+        self.put(table, to, Some(entry));
+        Ok(Some(old))
+    }
+
+    fn update<F>(&self, table: &str, key: &str, mut f: F) -> Result<Option<Value>, KvError>
+    where
+        F: FnMut(Option<&Value>) -> Option<Value>,
+    {
+        let entry = self.entry(table, key)?;
+        let old = entry.as_ref().map(|e| &e.value);
+        let value = f(old);
+        if value.as_ref() == old {
+            return Ok(value);
+        }
+        // 保留原来的过期时间
+        let staged = value.clone().map(|v| StagedEntry {
+            expires_at: entry.and_then(|e| e.expires_at),
+            ..StagedEntry::new(v)
+        });
+        self.put(table, key, staged);
+        Ok(value)
+    }
+
+    fn replace_table(&self, table: &str, pairs: Vec<Kvpair>) -> Result<(u64, u64), KvError> {
+        let removed = self.len(table)? as u64;
+        let pairs = dedup_pairs(pairs);
+        let inserted = pairs.len() as u64;
+        let keys = pairs
+            .into_iter()
+            .map(|(key, value)| (key, Some(StagedEntry::new(value))))
+            .collect();
+        let staged = StagedTable {
+            cleared: true,
+            keys,
+        };
+        self.lock().insert(table.to_string(), staged);
+        Ok((removed, inserted))
+    }
+
+    // 底层的存储是否支持过期时间在提交时才知道，不支持时提交失败
+    fn expire(&self, table: &str, key: &str, ttl: Duration) -> Result<bool, KvError> {
+        let Some(mut entry) = self.entry(table, key)? else {
+            return Ok(false);
+        };
+        entry.expires_at = Some(expires_after(now_millis(), ttl));
+        self.put(table, key, Some(entry));
+        Ok(true)
+    }
+
+    fn set_with_ttl(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+        ttl: Duration,
+    ) -> Result<Option<Value>, KvError> {
+        let key = key.into();
+        let old = self.get(table, &key)?;
+        let entry = StagedEntry::new(value.into());
+        let entry = StagedEntry {
+            expires_at: entry.mtime.map(|now| expires_after(now, ttl)),
+            ..entry
+        };
+        self.put(table, &key, Some(entry));
+        Ok(old)
+    }
+
+    fn ttl(&self, table: &str, key: &str) -> Result<Option<Duration>, KvError> {
+        let now = now_millis();
+        let expires_at = self.entry(table, key)?.and_then(|e| e.expires_at);
+        Ok(expires_at.map(|expires_at| remaining(expires_at, now)))
+    }
+
+    fn stored_key_len(&self, table: &str, key: &str) -> usize {
+        self.inner.stored_key_len(table, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemTable;
+
+    #[test]
+    fn staged_writes_should_be_invisible_until_commit() {
+        let store = MemTable::new();
+        store.set("t", "a", 1).unwrap();
+        store.set("t", "b", 2).unwrap();
+
+        let staged = Staged::new(&store);
+        assert_eq!(staged.set("t", "a", 10).unwrap(), Some(1.into()));
+        assert_eq!(staged.del("t", "b").unwrap(), Some(2.into()));
+        staged.set("other", "c", 3).unwrap();
+        assert_eq!(staged.get("t", "a").unwrap(), Some(10.into()));
+        assert!(!staged.contains("t", "b").unwrap());
+        assert_eq!(staged.tables().unwrap(), ["other", "t"]);
+
+        // 底层的存储没有变化
+        assert_eq!(store.get("t", "a").unwrap(), Some(1.into()));
+        assert_eq!(store.get("t", "b").unwrap(), Some(2.into()));
+        assert_eq!(store.get("other", "c").unwrap(), None);
+
+        staged.commit().unwrap();
+        assert_eq!(store.get_all("t").unwrap(), [Kvpair::new("a", 10)]);
+        assert_eq!(store.get("other", "c").unwrap(), Some(3.into()));
+    }
+
+    #[test]
+    fn staged_replace_table_should_hide_old_keys() {
+        let store = MemTable::new();
+        store.set("t", "a", 1).unwrap();
+        store.set("t", "b", 2).unwrap();
+
+        let staged = Staged::new(&store);
+        let pairs = vec![Kvpair::new("b", 20), Kvpair::new("c", 30)];
+        assert_eq!(staged.replace_table("t", pairs).unwrap(), (2, 2));
+        assert_eq!(staged.get("t", "a").unwrap(), None);
+        assert_eq!(staged.len("t").unwrap(), 2);
+        staged.set("t", "d", 40).unwrap();
+
+        staged.commit().unwrap();
+        let mut pairs = store.get_all("t").unwrap();
+        pairs.sort_by(|a, b| a.key.cmp(&b.key));
+        let expected = [
+            Kvpair::new("b", 20),
+            Kvpair::new("c", 30),
+            Kvpair::new("d", 40),
+        ];
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    fn staged_update_should_keep_ttl() {
+        let store = MemTable::new();
+        store.set("t", "n", 1).unwrap();
+        store.expire("t", "n", Duration::from_secs(60)).unwrap();
+
+        let staged = Staged::new(&store);
+        staged.update("t", "n", |_| Some(2.into())).unwrap();
+        staged.move_key("t", "other", "n").unwrap();
+        assert!(staged.ttl("other", "n").unwrap().is_some());
+        assert_eq!(staged.get("t", "n").unwrap(), None);
+        staged.expire("other", "n", Duration::ZERO).unwrap();
+        assert_eq!(staged.get("other", "n").unwrap(), None);
+        staged
+            .set_with_ttl("t", "m", 3, Duration::from_secs(60))
+            .unwrap();
+
+        staged.commit().unwrap();
+        assert_eq!(store.get("t", "n").unwrap(), None);
+        assert_eq!(store.get("other", "n").unwrap(), None);
+        assert_eq!(store.get("t", "m").unwrap(), Some(3.into()));
+        assert!(store.ttl("t", "m").unwrap().is_some());
+    }
+}